@@ -0,0 +1,105 @@
+//! Compares buffer pool hit rates of the eviction policies on a workload that
+//! mixes skewed point lookups with periodic full scans.
+//!
+//! Run with `cargo bench --bench eviction`.
+
+use nimbus::storage::buffer::{BufferPool, EvictionPolicy};
+use nimbus::storage::disk::FileManager;
+use nimbus::storage::page::base::PageKind;
+use nimbus::storage::page_locator::locator::DirectoryPageLocator;
+use std::pin::Pin;
+use std::time::Instant;
+
+const TABLE_PAGES: usize = 1024;
+const HOT_PAGES: usize = 64;
+const ROUNDS: usize = 20;
+const LOOKUPS_PER_ROUND: usize = 2000;
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn touch(bp: &mut Pin<Box<BufferPool>>, offset: u64) {
+    let fid = bp.as_mut().fetch_page_at_offset(offset).unwrap().fid();
+    bp.as_mut().unpin_frame(fid).unwrap();
+}
+
+fn run(policy: EvictionPolicy) {
+    let mut path = std::env::temp_dir();
+    path.push(format!("nimbus_bench_eviction_{}.db", policy));
+    let _ = std::fs::remove_file(&path);
+
+    let fm = FileManager::new(path.to_str().unwrap().to_string()).unwrap();
+    let mut bp = Box::pin(BufferPool::new(
+        Box::new(fm),
+        policy.evictor(),
+        Box::new(DirectoryPageLocator::new()),
+    ));
+
+    let mut offsets = Vec::with_capacity(TABLE_PAGES);
+    for i in 0..TABLE_PAGES {
+        let frame = bp
+            .as_mut()
+            .alloc_new_page(PageKind::SlottedData, i as u32 + 1)
+            .unwrap();
+        offsets.push(frame.file_offset());
+        let fid = frame.fid();
+        bp.as_mut().unpin_frame(fid).unwrap();
+    }
+
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    let start_stats = bp.stats();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        // 90% of lookups go to the hot set
+        for _ in 0..LOOKUPS_PER_ROUND {
+            let r = rng.next() as usize;
+            let idx = if r % 10 == 0 {
+                r / 10 % TABLE_PAGES
+            } else {
+                r / 10 % HOT_PAGES
+            };
+            touch(&mut bp, offsets[idx]);
+        }
+        for &offset in &offsets {
+            touch(&mut bp, offset);
+        }
+    }
+    let elapsed = start.elapsed();
+
+    let stats = bp.stats();
+    let hits = stats.hits - start_stats.hits;
+    let misses = stats.misses - start_stats.misses;
+    println!(
+        "{:<8} hits {:>8}  misses {:>8}  hit rate {:>6.2}%  {:>8.2?}",
+        policy.to_string(),
+        hits,
+        misses,
+        100.0 * hits as f64 / (hits + misses) as f64,
+        elapsed
+    );
+
+    drop(bp);
+    let _ = std::fs::remove_file(&path);
+}
+
+fn main() {
+    println!(
+        "{} pages, {} hot, {} rounds of {} lookups + full scan",
+        TABLE_PAGES, HOT_PAGES, ROUNDS, LOOKUPS_PER_ROUND
+    );
+    for policy in [
+        EvictionPolicy::Fifo,
+        EvictionPolicy::LruK(2),
+        EvictionPolicy::Clock,
+    ] {
+        run(policy);
+    }
+}
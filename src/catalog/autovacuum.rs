@@ -0,0 +1,269 @@
+use crate::catalog::stats::StatsRegistry;
+use crate::storage::buffer::BufferPool;
+use crate::storage::heap::heap_file::{HeapFile, VacuumStats};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// When autovacuum steps in.
+#[derive(Clone, Copy, Debug)]
+pub struct AutovacuumConfig {
+    /// Pause between looks at the statistics
+    pub interval: Duration,
+    /// Share of a table's tuples that must be dead before it is vacuumed
+    pub dead_fraction: f64,
+    /// Tables with fewer dead tuples are left alone, however small they are
+    pub min_dead_tuples: u64,
+}
+
+impl Default for AutovacuumConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            dead_fraction: 0.2,
+            min_dead_tuples: 50,
+        }
+    }
+}
+
+/// Vacuums tables from a background thread once enough of their tuples are dead, and saves
+/// the statistics to the catalog. The pool is locked for one page at a time, so queries
+/// keep running while a table is being vacuumed. The thread stops when this is dropped.
+pub struct Autovacuum {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Autovacuum {
+    pub fn start(
+        bp: Arc<Mutex<BufferPool>>,
+        stats: StatsRegistry,
+        config: AutovacuumConfig,
+    ) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            // Both a stop message and a dropped sender end the loop
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(config.interval) {
+                if !run_round(&bp, &stats, &config, &stopped) {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+/// Vacuums every table that is due, then trims the file and saves the statistics.
+/// Returns false when the worker should stop.
+fn run_round(
+    bp: &Arc<Mutex<BufferPool>>,
+    stats: &StatsRegistry,
+    config: &AutovacuumConfig,
+    stopped: &Receiver<()>,
+) -> bool {
+    let mut pages_freed = 0;
+    'tables: for (oid, root, dead) in
+        stats.needing_vacuum(config.dead_fraction, config.min_dead_tuples)
+    {
+        let heap = HeapFile::new(root, 0);
+        let mut table_stats = VacuumStats::default();
+        let (mut prev_page_id, mut page_id) = (0, root);
+        while page_id != 0 {
+            if !matches!(stopped.try_recv(), Err(TryRecvError::Empty)) {
+                return false;
+            }
+            let Ok(mut bp_guard) = bp.lock() else {
+                return false;
+            };
+            // Dropped while we were away from the pool
+            if stats.root_page(oid) != Some(root) {
+                continue 'tables;
+            }
+            let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            let before = (table_stats.pages_freed, table_stats.bytes_reclaimed);
+            match heap.vacuum_page(pinned_bp, prev_page_id, page_id, &mut table_stats) {
+                Ok((next, prev)) => (page_id, prev_page_id) = (next, prev),
+                Err(e) => {
+                    eprintln!("Autovacuum of table {} failed: {:?}", oid, e);
+                    continue 'tables;
+                }
+            }
+            // The page inserts would start at may be the one just freed, or this one may
+            // have room again
+            if (table_stats.pages_freed, table_stats.bytes_reclaimed) != before {
+                stats.set_insert_page(oid, 0);
+            }
+        }
+        // Tuples deleted during the pass may have been reclaimed too, but are only
+        // counted off by the next one
+        stats.record_vacuum(oid, dead);
+        pages_freed += table_stats.pages_freed;
+    }
+
+    let Ok(mut bp_guard) = bp.lock() else {
+        return false;
+    };
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    if pages_freed > 0
+        && let Err(e) = pinned_bp.as_mut().trim_free_pages()
+    {
+        eprintln!("Autovacuum failed to trim the file: {}", e);
+    }
+    if let Err(e) = stats.persist(pinned_bp) {
+        eprintln!("Autovacuum failed to save statistics: {}", e);
+    }
+    true
+}
+
+impl Drop for Autovacuum {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::manager::Catalog;
+    use crate::catalog::stats::TableStats;
+    use crate::rt_type::primitives::{
+        AttributeKind, AttributeValue, TableAttribute, TableLayout, TableType,
+    };
+    use crate::storage::buffer::AccessStrategy;
+    use crate::storage::buffer::fifo_evictor::FifoEvictor;
+    use crate::storage::disk::FileManager;
+    use crate::storage::heap::iterator::HeapIterator;
+    use crate::storage::heap::tuple::Tuple;
+    use crate::storage::page_locator::locator::DirectoryPageLocator;
+    use std::fs;
+    use std::time::Instant;
+
+    fn open(path: &str) -> (Arc<Mutex<BufferPool>>, Catalog) {
+        let bp = Arc::new(Mutex::new(BufferPool::new(
+            Box::new(FileManager::new(path.to_string()).unwrap()),
+            Box::new(FifoEvictor::new()),
+            Box::new(DirectoryPageLocator::new()),
+        )));
+        let catalog = Catalog::new(bp.clone());
+        (bp, catalog)
+    }
+
+    #[test]
+    fn test_autovacuum_compacts_and_saves_stats() {
+        let db_file = "test_db/test_autovacuum.db";
+        let _ = fs::create_dir_all("test_db");
+        let _ = FileManager::remove_database(db_file);
+
+        let schema = TableType {
+            attributes: vec![
+                TableAttribute {
+                    name: "id".into(),
+                    kind: AttributeKind::U32,
+                    nullable: false,
+                    is_internal: false,
+                },
+                TableAttribute {
+                    name: "payload".into(),
+                    kind: AttributeKind::Varchar,
+                    nullable: false,
+                    is_internal: false,
+                },
+            ],
+            layout: TableLayout {
+                size: 0,
+                attr_layouts: vec![],
+            },
+        };
+
+        {
+            let (bp, mut catalog) = open(db_file);
+            let oid = catalog.create_table("events", schema.clone()).unwrap();
+            let root = catalog.get_table_root_page(oid).unwrap();
+            {
+                let mut bp_guard = bp.lock().unwrap();
+                let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+                for i in 0..300 {
+                    let row = Tuple::new(vec![
+                        AttributeValue::U32(i),
+                        AttributeValue::Varchar(format!("event number {:04}", i)),
+                    ]);
+                    catalog
+                        .insert_tuple(oid, &row, &schema, pinned_bp.as_mut())
+                        .unwrap();
+                }
+                let mut rids = Vec::new();
+                let mut iter = HeapIterator::new(pinned_bp.as_mut(), root);
+                while let Some(Ok((rid, _))) = iter.next() {
+                    rids.push(rid);
+                }
+                for rid in rids.into_iter().skip(50) {
+                    catalog.delete_tuple(oid, rid, pinned_bp.as_mut()).unwrap();
+                }
+            }
+            let expected = TableStats {
+                live_tuples: 50,
+                dead_tuples: 250,
+            };
+            assert_eq!(catalog.get_table_stats(oid), Some(expected));
+
+            let config = AutovacuumConfig {
+                interval: Duration::from_millis(5),
+                dead_fraction: 0.2,
+                min_dead_tuples: 10,
+            };
+            let worker = Autovacuum::start(bp.clone(), catalog.stats_registry(), config);
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while catalog.get_table_stats(oid).unwrap().dead_tuples > 0 {
+                assert!(Instant::now() < deadline, "Autovacuum did not run");
+                thread::sleep(Duration::from_millis(5));
+            }
+            drop(worker);
+
+            let mut bp_guard = bp.lock().unwrap();
+            let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            let mut pages = 0;
+            let mut page_id = root;
+            while page_id != 0 {
+                let frame = pinned_bp
+                    .as_mut()
+                    .fetch_page(page_id, AccessStrategy::Normal)
+                    .unwrap();
+                let fid = frame.fid();
+                page_id = frame.page_view().header().next_page_id();
+                pinned_bp.as_mut().unpin_frame(fid).unwrap();
+                pages += 1;
+            }
+            assert_eq!(pages, 1, "Emptied pages should leave the heap");
+            let mut iter = HeapIterator::new(pinned_bp.as_mut(), root);
+            let mut ids = Vec::new();
+            while let Some(Ok((_, bytes))) = iter.next() {
+                let tuple = Tuple::from_bytes(&bytes, &schema).unwrap();
+                ids.push(tuple.values[0].clone());
+            }
+            assert_eq!(ids, (0..50).map(AttributeValue::U32).collect::<Vec<_>>());
+            pinned_bp.flush_all().unwrap();
+        }
+
+        // The worker saved the counters before it stopped
+        let (_bp, catalog) = open(db_file);
+        let oid = catalog.get_table_oid("events").unwrap();
+        let expected = TableStats {
+            live_tuples: 50,
+            dead_tuples: 0,
+        };
+        assert_eq!(catalog.get_table_stats(oid), Some(expected));
+
+        let _ = FileManager::remove_database(db_file);
+    }
+}
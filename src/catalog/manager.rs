@@ -1,32 +1,144 @@
 use crate::catalog::schema::SYSTEM_INDEXES_ID;
-use crate::rt_type::primitives::{AttributeKind, AttributeValue, TableAttribute, TableType};
-use crate::storage::bplus_tree::BPlusTree;
-use crate::storage::buffer::BufferPool;
-
-use crate::storage::heap::heap_file::HeapFile;
-use crate::storage::heap::iterator::HeapIterator;
+use crate::catalog::stats::{StatsRegistry, TableStats};
+use crate::rt_type::primitives::{
+    AttributeKind, AttributeValue, TableAttribute, TableLayout, TableType,
+};
+use crate::storage::bplus_tree::{BPlusTree, VerifyReport};
+use crate::storage::buffer::{AccessStrategy, BufferPool, EvictionPolicy};
+use crate::storage::disk::{self, Compression};
+use crate::storage::hash_index::HashIndex;
+use crate::storage::hash_index::extendible::hash_key;
+
+use crate::storage::heap::heap_file::{HeapFile, UpdatePlacement, VacuumStats};
+use crate::storage::heap::iterator::{BTreeIterator, HeapIterator};
+use crate::storage::heap::row::RowId;
 use crate::storage::heap::tuple::Tuple;
+use crate::storage::page::BPlusLeaf;
 use crate::storage::page::base::PageKind;
+use crate::storage::page_locator::locator::DirectoryPageLocator;
 use std::collections::HashMap;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 
 use super::schema::{
-    SYSTEM_COLUMNS_ID, SYSTEM_TABLES_ID, get_system_columns_schema, get_system_indexes_schema,
-    get_system_tables_schema,
+    SYSTEM_COLUMNS_ID, SYSTEM_SEGMENTS_ID, SYSTEM_STATS_ID, SYSTEM_TABLES_ID,
+    SYSTEM_TABLESPACES_ID, get_system_columns_schema, get_system_indexes_schema,
+    get_system_segments_schema, get_system_stats_schema, get_system_tables_schema,
+    get_system_tablespaces_schema,
 };
 
 // Fixed Page IDs for system tables
 const SYSTEM_TABLES_PAGE_ID: u32 = 1;
 const SYSTEM_COLUMNS_PAGE_ID: u32 = 2;
 const SYSTEM_INDEXES_PAGE_ID: u32 = 3;
+const SYSTEM_STATS_PAGE_ID: u32 = 4;
+const SYSTEM_TABLESPACES_PAGE_ID: u32 = 5;
+const SYSTEM_SEGMENTS_PAGE_ID: u32 = 6;
+
+/// Access method backing an index, persisted as a U8 in `system_indexes`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexMethod {
+    #[default]
+    BTree = 0,
+    Hash = 1,
+}
+
+impl IndexMethod {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(IndexMethod::BTree),
+            1 => Some(IndexMethod::Hash),
+            _ => None,
+        }
+    }
+}
+
+/// Expression an index key is computed with, applied to `IndexMeta::column_idx`.
+/// Persisted as a U8 in `system_indexes`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyExpr {
+    #[default]
+    Column = 0,
+    Lower = 1,
+    Upper = 2,
+}
+
+impl KeyExpr {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(KeyExpr::Column),
+            1 => Some(KeyExpr::Lower),
+            2 => Some(KeyExpr::Upper),
+            _ => None,
+        }
+    }
+
+    /// Evaluates the expression on a column value, or `None` if it does not apply to its type.
+    pub fn apply(&self, value: &AttributeValue) -> Option<AttributeValue> {
+        match (self, value) {
+            (KeyExpr::Column, v) => Some(v.clone()),
+            (KeyExpr::Lower, AttributeValue::Varchar(s)) => {
+                Some(AttributeValue::Varchar(s.to_lowercase()))
+            }
+            (KeyExpr::Upper, AttributeValue::Varchar(s)) => {
+                Some(AttributeValue::Varchar(s.to_uppercase()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// One `expr(column) = value` conjunct of a WHERE clause, resolved against a table schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EqualityTerm {
+    pub column_idx: usize,
+    pub key_expr: KeyExpr,
+    pub value: AttributeValue,
+}
+
+impl EqualityTerm {
+    pub fn matches(&self, tuple: &Tuple) -> bool {
+        tuple
+            .values
+            .get(self.column_idx)
+            .and_then(|v| self.key_expr.apply(v))
+            .is_some_and(|v| v == self.value)
+    }
+}
+
+/// Everything about an index besides its name, table and key column.
+#[derive(Clone, Debug, Default)]
+pub struct IndexOptions {
+    pub method: IndexMethod,
+    pub key_expr: KeyExpr,
+    pub include_columns: Vec<String>,
+    /// Partial index: only rows where each column equals its value are indexed
+    pub predicate: Vec<(String, AttributeValue)>,
+    /// Percent of each B+ tree leaf filled when the index is built, 100 if unset
+    pub fill_factor: Option<u8>,
+    /// Tablespace to put the index in, next to the database file if unset
+    pub tablespace: Option<String>,
+}
+
+/// How a table is stored
+#[derive(Clone, Debug, Default)]
+pub struct TableOptions {
+    /// Tablespace to put the table in, next to the database file if unset
+    pub tablespace: Option<String>,
+    /// Compression of the pages of the table, which readers never notice
+    pub compression: Compression,
+}
 
 #[derive(Clone, Debug)]
 pub struct IndexMeta {
     pub table_oid: u32,
     pub column_idx: usize,
-    pub root_page_id: u32,
+    pub root_page_id: u32, // B+ tree root, or the directory page of a hash index
+    pub include_cols: Vec<usize>, // Columns stored in the leaves for index-only scans
+    pub method: IndexMethod,
+    pub key_expr: KeyExpr,
+    pub predicate: Vec<(usize, AttributeValue)>, // Empty unless this is a partial index
 }
 
 pub struct Catalog {
@@ -37,11 +149,22 @@ pub struct Catalog {
     index_name_cache: HashMap<String, u32>, // IndexName -> IndexOID
     index_meta_cache: HashMap<u32, IndexMeta>, // IndexOID -> Metadata
     table_indexes: HashMap<u32, Vec<u32>>,
+    stats: StatsRegistry, // Live and dead tuple counts of the user tables
+    tablespaces: HashMap<String, (u32, String)>, // Name -> (OID, directory)
+    segments: HashMap<u32, (u32, Compression)>, // Segment OID -> (tablespace OID, compression)
     next_oid: AtomicU32,
+    // The last page id handed out: unlike oids, a new page takes the one after it
+    last_page_id: AtomicU32,
 }
 
 impl Catalog {
     pub fn new(bp: Arc<Mutex<BufferPool>>) -> Self {
+        Self::try_new(bp).unwrap_or_else(|e| panic!("Failed to open the catalog: {}", e))
+    }
+
+    /// Like `new`, but a database file this build cannot read is an error instead of a
+    /// panic.
+    pub fn try_new(bp: Arc<Mutex<BufferPool>>) -> Result<Self, String> {
         let mut catalog = Self {
             bp,
             table_cache: HashMap::new(),
@@ -50,14 +173,55 @@ impl Catalog {
             index_name_cache: HashMap::new(),
             index_meta_cache: HashMap::new(),
             table_indexes: HashMap::new(),
+            stats: StatsRegistry::new(),
+            tablespaces: HashMap::new(),
+            segments: HashMap::new(),
             next_oid: AtomicU32::new(100),
+            // Like oids, the ones below 100 are kept for the system tables
+            last_page_id: AtomicU32::new(99),
         };
 
-        catalog.init_system_tables();
-        catalog
+        catalog.init_system_tables()?;
+        Ok(catalog)
+    }
+
+    /// Opens the database at `path` with a default buffer pool. `MEMORY_PATH` opens a
+    /// database that lives in memory and is gone once the pool is dropped.
+    pub fn open(path: &str) -> Result<(Arc<Mutex<BufferPool>>, Self), String> {
+        let device =
+            disk::open_device(path).map_err(|e| format!("Failed to open {}: {:?}", path, e))?;
+        Self::open_on(device)
     }
 
-    fn init_system_tables(&mut self) {
+    /// Opens the database at `path` with its pages encrypted under `passphrase`, creating
+    /// it encrypted if it does not exist yet. See `EncryptedDevice`.
+    pub fn open_encrypted(
+        path: &str,
+        passphrase: &str,
+    ) -> Result<(Arc<Mutex<BufferPool>>, Self), String> {
+        let device = disk::open_encrypted_device(path, passphrase)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        Self::open_on(device)
+    }
+
+    fn open_on(
+        device: Box<dyn disk::BlockDevice>,
+    ) -> Result<(Arc<Mutex<BufferPool>>, Self), String> {
+        let bp = Arc::new(Mutex::new(BufferPool::new(
+            device,
+            EvictionPolicy::default().evictor(),
+            Box::new(DirectoryPageLocator::new()),
+        )));
+        let catalog = Self::try_new(bp.clone())?;
+        Ok((bp, catalog))
+    }
+
+    /// Opens a fresh database that never touches disk.
+    pub fn open_in_memory() -> (Arc<Mutex<BufferPool>>, Self) {
+        Self::open(disk::MEMORY_PATH).expect("Memory devices cannot fail to open")
+    }
+
+    fn init_system_tables(&mut self) -> Result<(), String> {
         // Register Tables
         self.table_cache
             .insert("system_tables".to_string(), SYSTEM_TABLES_ID);
@@ -65,6 +229,12 @@ impl Catalog {
             .insert("system_columns".to_string(), SYSTEM_COLUMNS_ID);
         self.table_cache
             .insert("system_indexes".to_string(), SYSTEM_INDEXES_ID);
+        self.table_cache
+            .insert("system_stats".to_string(), SYSTEM_STATS_ID);
+        self.table_cache
+            .insert("system_tablespaces".to_string(), SYSTEM_TABLESPACES_ID);
+        self.table_cache
+            .insert("system_segments".to_string(), SYSTEM_SEGMENTS_ID);
 
         self.root_page_cache
             .insert(SYSTEM_TABLES_ID, SYSTEM_TABLES_PAGE_ID);
@@ -72,6 +242,12 @@ impl Catalog {
             .insert(SYSTEM_COLUMNS_ID, SYSTEM_COLUMNS_PAGE_ID);
         self.root_page_cache
             .insert(SYSTEM_INDEXES_ID, SYSTEM_INDEXES_PAGE_ID);
+        self.root_page_cache
+            .insert(SYSTEM_STATS_ID, SYSTEM_STATS_PAGE_ID);
+        self.root_page_cache
+            .insert(SYSTEM_TABLESPACES_ID, SYSTEM_TABLESPACES_PAGE_ID);
+        self.root_page_cache
+            .insert(SYSTEM_SEGMENTS_ID, SYSTEM_SEGMENTS_PAGE_ID);
 
         self.schema_cache
             .insert(SYSTEM_TABLES_ID, get_system_tables_schema());
@@ -79,22 +255,80 @@ impl Catalog {
             .insert(SYSTEM_COLUMNS_ID, get_system_columns_schema());
         self.schema_cache
             .insert(SYSTEM_INDEXES_ID, get_system_indexes_schema());
+        self.schema_cache
+            .insert(SYSTEM_STATS_ID, get_system_stats_schema());
+        self.schema_cache
+            .insert(SYSTEM_TABLESPACES_ID, get_system_tablespaces_schema());
+        self.schema_cache
+            .insert(SYSTEM_SEGMENTS_ID, get_system_segments_schema());
+
+        // Before anything else is read: this also refuses files this build would misread
+        let superblock = {
+            let mut bp_guard = self.bp.lock().map_err(|_| "Lock poisoned")?;
+            let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            let superblock = pinned_bp.as_mut().open_superblock()?;
+            // Files that do not record the page id counter yet have it recovered from the
+            // directory, and recorded before any page is allocated
+            let last_page_id = match superblock.next_page_id {
+                0 => pinned_bp.as_mut().max_page_id()?,
+                next_page_id => next_page_id - 1,
+            };
+            self.last_page_id.fetch_max(last_page_id, Ordering::SeqCst);
+            pinned_bp
+                .core()
+                .reserve_page_id(self.last_page_id.load(Ordering::SeqCst))
+                .map_err(|e| format!("Failed to write the superblock: {:?}", e))?;
+            superblock
+        };
+        self.next_oid
+            .fetch_max(superblock.next_oid, Ordering::SeqCst);
 
-        if let Err(_) = self.load_state() {
+        // An existing database that cannot be read is an error: bootstrapping over it
+        // would lose it
+        if !self.load_state()? {
             self.bootstrap_new_db();
             self.bootstrap_system_metadata();
         }
+        self.rebuild_file_indexes()?;
+
+        // New and upgraded files have an oid counter to catch up on
+        if self.next_oid.load(Ordering::SeqCst) != superblock.next_oid {
+            let mut bp_guard = self.bp.lock().map_err(|_| "Lock poisoned")?;
+            let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            self.save_next_oid(pinned_bp)?;
+        }
+        Ok(())
+    }
+
+    /// Records the oid counter in the superblock, so no oid handed out so far is handed out
+    /// again after a restart.
+    fn save_next_oid(&self, mut bpm: Pin<&mut BufferPool>) -> Result<(), String> {
+        let mut superblock = bpm
+            .as_mut()
+            .core()
+            .read_superblock()
+            .map_err(|e| format!("Failed to read the superblock: {:?}", e))?;
+        superblock.next_oid = self.next_oid.load(Ordering::SeqCst);
+        bpm.write_superblock(&superblock)
     }
     fn bootstrap_new_db(&self) {
         let mut bp_guard = self.bp.lock().expect("Lock poisoned");
         let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
 
-        if pinned_bp.as_mut().fetch_page_at_offset(0).is_err() {
-            // 1. Directory
-            pinned_bp
+        // The root directory page only has to exist, nobody keeps it pinned
+        let root = pinned_bp.page_locator.root_directory();
+        if let Ok(frame) = pinned_bp.as_mut().fetch_page_at_offset(root) {
+            let fid = frame.fid();
+            pinned_bp.as_mut().unpin_frame(fid).ok();
+        } else {
+            // 1. Directory, right where the superblock expects it
+            let frame = pinned_bp
                 .as_mut()
                 .alloc_new_page(PageKind::Directory, 0)
                 .expect("Bootstrap dir");
+            assert_eq!(frame.file_offset(), root, "Bootstrap dir");
+            let fid = frame.fid();
+            pinned_bp.as_mut().unpin_frame(fid).ok();
 
             // 2. System Tables
             let frame = pinned_bp
@@ -210,6 +444,96 @@ impl Catalog {
                 pinned_bp.as_mut(),
             );
         }
+
+        self.create_system_stats(pinned_bp.as_mut())
+            .expect("Bootstrap stats");
+        self.create_system_tablespaces(pinned_bp.as_mut())
+            .expect("Bootstrap tablespaces");
+    }
+
+    /// Creates "system_stats". It came after the other system tables, so files written
+    /// before it get it the first time they are opened.
+    fn create_system_stats(&self, bpm: Pin<&mut BufferPool>) -> Result<(), String> {
+        self.create_system_table(
+            SYSTEM_STATS_ID,
+            "system_stats",
+            SYSTEM_STATS_PAGE_ID,
+            &get_system_stats_schema(),
+            bpm,
+        )
+    }
+
+    /// Creates "system_tablespaces" and "system_segments", which came after
+    /// "system_stats", the same way.
+    fn create_system_tablespaces(&self, mut bpm: Pin<&mut BufferPool>) -> Result<(), String> {
+        self.create_system_table(
+            SYSTEM_TABLESPACES_ID,
+            "system_tablespaces",
+            SYSTEM_TABLESPACES_PAGE_ID,
+            &get_system_tablespaces_schema(),
+            bpm.as_mut(),
+        )?;
+        self.create_system_table(
+            SYSTEM_SEGMENTS_ID,
+            "system_segments",
+            SYSTEM_SEGMENTS_PAGE_ID,
+            &get_system_segments_schema(),
+            bpm,
+        )
+    }
+
+    fn create_system_table(
+        &self,
+        oid: u32,
+        name: &str,
+        page_id: u32,
+        schema: &TableType,
+        mut bpm: Pin<&mut BufferPool>,
+    ) -> Result<(), String> {
+        let frame = bpm
+            .as_mut()
+            .alloc_new_page(PageKind::SlottedData, page_id)
+            .map_err(|e| format!("{:?}", e))?;
+        let offset = frame.file_offset();
+        let fid = frame.fid();
+        bpm.as_mut().unpin_frame(fid).ok();
+        bpm.as_mut()
+            .register_page_in_directory(page_id, offset, 4000)
+            .map_err(|e| format!("{:?}", e))?;
+
+        let row = Tuple::new(vec![
+            AttributeValue::U32(oid),
+            AttributeValue::Varchar(name.to_string()),
+            AttributeValue::U32(page_id),
+        ]);
+        self.insert_tuple(
+            SYSTEM_TABLES_ID,
+            &row,
+            &get_system_tables_schema(),
+            bpm.as_mut(),
+        )?;
+        for col in &schema.attributes {
+            self.insert_column_metadata(oid, col, &get_system_columns_schema(), bpm.as_mut());
+        }
+        Ok(())
+    }
+
+    /// Adds the counters row of a table to system_stats and starts tracking them.
+    fn track_table_stats(
+        &self,
+        table_oid: u32,
+        root_page_id: u32,
+        stats: TableStats,
+        bpm: Pin<&mut BufferPool>,
+    ) -> Result<(), String> {
+        let bytes = stats
+            .to_tuple(table_oid)
+            .to_bytes(&get_system_stats_schema())?;
+        let rid = HeapFile::new(SYSTEM_STATS_PAGE_ID, SYSTEM_STATS_PAGE_ID)
+            .insert(bpm, &self.last_page_id, &bytes, AccessStrategy::Normal)
+            .map_err(|e| format!("{:?}", e))?;
+        self.stats.track(table_oid, root_page_id, stats, rid);
+        Ok(())
     }
 
     fn insert_column_metadata(
@@ -233,17 +557,21 @@ impl Catalog {
             .unwrap();
     }
 
-    fn load_state(&mut self) -> Result<(), String> {
+    /// Loads the catalog of an existing database, or returns false for a new one.
+    fn load_state(&mut self) -> Result<bool, String> {
         let mut bp_guard = self.bp.lock().map_err(|_| "Lock poisoned")?;
         let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
 
-        if pinned_bp.as_mut().fetch_page_at_offset(0).is_err() {
-            return Err("Uninitialized".to_string());
+        let root = pinned_bp.page_locator.root_directory();
+        if pinned_bp.as_mut().fetch_page_at_offset(root).is_err() {
+            return Ok(false);
         }
 
         // 1. Load Tables
         let mut iter = HeapIterator::new(pinned_bp.as_mut(), SYSTEM_TABLES_PAGE_ID);
         let mut max_oid = 99;
+        let mut has_stats = false;
+        let mut has_tablespaces = false;
         while let Some(res) = iter.next() {
             let (_, bytes) = res.map_err(|e| format!("{:?}", e))?;
             let t = Tuple::from_bytes(&bytes, &get_system_tables_schema())?;
@@ -262,11 +590,13 @@ impl Catalog {
 
             self.table_cache.insert(name, oid);
             self.root_page_cache.insert(oid, root);
+            has_stats |= oid == SYSTEM_STATS_ID;
+            has_tablespaces |= oid == SYSTEM_TABLESPACES_ID;
             if oid > max_oid {
                 max_oid = oid;
             }
         }
-        self.next_oid.store(max_oid + 1, Ordering::SeqCst);
+        self.next_oid.fetch_max(max_oid + 1, Ordering::SeqCst);
 
         // 2. Load Columns
         let mut iter = HeapIterator::new(pinned_bp.as_mut(), SYSTEM_COLUMNS_PAGE_ID);
@@ -315,47 +645,215 @@ impl Catalog {
         let mut iter = HeapIterator::new(pinned_bp.as_mut(), SYSTEM_INDEXES_PAGE_ID);
         while let Some(res) = iter.next() {
             let (_, bytes) = res.map_err(|e| format!("{:?}", e))?;
-            let t = Tuple::from_bytes(&bytes, &get_system_indexes_schema())?;
-
-            let idx_oid = match t.values.get(0) {
-                Some(AttributeValue::U32(v)) => *v,
-                _ => continue,
-            };
-            let idx_name = match t.values.get(1) {
-                Some(AttributeValue::Varchar(v)) => v.clone(),
-                _ => continue,
-            };
-            let tbl_oid = match t.values.get(2) {
-                Some(AttributeValue::U32(v)) => *v,
-                _ => continue,
-            };
-            let col_idx = match t.values.get(3) {
-                Some(AttributeValue::U8(v)) => *v,
-                _ => continue,
-            };
-            let root = match t.values.get(4) {
-                Some(AttributeValue::U32(v)) => *v,
-                _ => continue,
-            };
+            let (idx_oid, idx_name, meta) = decode_index_row(&bytes)?;
+            let tbl_oid = meta.table_oid;
 
             self.index_name_cache.insert(idx_name, idx_oid);
-            self.index_meta_cache.insert(
-                idx_oid,
-                IndexMeta {
-                    table_oid: tbl_oid,
-                    column_idx: col_idx as usize,
-                    root_page_id: root,
-                },
-            );
+            self.index_meta_cache.insert(idx_oid, meta);
 
             // UPDATE: Populate the table_indexes cache
             self.table_indexes.entry(tbl_oid).or_default().push(idx_oid);
 
-            if idx_oid > max_oid {
-                self.next_oid.store(idx_oid + 1, Ordering::SeqCst);
+            self.next_oid.fetch_max(idx_oid + 1, Ordering::SeqCst);
+        }
+
+        // 4. Load Tablespaces, and tell the pool where their segments are before any of
+        // those pages is read
+        if !has_tablespaces {
+            self.create_system_tablespaces(pinned_bp.as_mut())?;
+        }
+        let mut iter = HeapIterator::new(pinned_bp.as_mut(), SYSTEM_TABLESPACES_PAGE_ID);
+        while let Some(res) = iter.next() {
+            let (_, bytes) = res.map_err(|e| format!("{:?}", e))?;
+            let t = Tuple::from_bytes(&bytes, &get_system_tablespaces_schema())?;
+            if let [
+                AttributeValue::U32(oid),
+                AttributeValue::Varchar(name),
+                AttributeValue::Varchar(location),
+            ] = &t.values[..]
+            {
+                self.tablespaces
+                    .insert(name.clone(), (*oid, location.clone()));
+                self.next_oid.fetch_max(oid + 1, Ordering::SeqCst);
+            }
+        }
+        let mut iter = HeapIterator::new(pinned_bp.as_mut(), SYSTEM_SEGMENTS_PAGE_ID);
+        while let Some(res) = iter.next() {
+            let (_, bytes) = res.map_err(|e| format!("{:?}", e))?;
+            let t = Tuple::from_bytes(&bytes, &get_system_segments_schema())?;
+            if let [
+                AttributeValue::U32(segment),
+                AttributeValue::U32(tablespace),
+                AttributeValue::U8(compression),
+            ] = t.values[..]
+            {
+                let compression = Compression::from_u8(compression)
+                    .ok_or(format!("Segment {} has unknown compression", segment))?;
+                self.segments.insert(segment, (tablespace, compression));
+            }
+        }
+        for (&segment, &(tablespace, compression)) in &self.segments {
+            if tablespace != 0 {
+                let (_, location) = self
+                    .tablespaces
+                    .values()
+                    .find(|(oid, _)| *oid == tablespace)
+                    .ok_or(format!(
+                        "Segment {} is in unknown tablespace {}",
+                        segment, tablespace
+                    ))?;
+                pinned_bp.as_mut().place_segment(segment, location);
+            }
+            if compression != Compression::None {
+                pinned_bp.as_mut().compress_segment(segment)?;
+            }
+        }
+
+        // 5. Load Stats
+        if !has_stats {
+            self.create_system_stats(pinned_bp.as_mut())?;
+        }
+        let mut saved: HashMap<u32, (TableStats, RowId)> = HashMap::new();
+        let mut iter = HeapIterator::new(pinned_bp.as_mut(), SYSTEM_STATS_PAGE_ID);
+        while let Some(res) = iter.next() {
+            let (rid, bytes) = res.map_err(|e| format!("{:?}", e))?;
+            let t = Tuple::from_bytes(&bytes, &get_system_stats_schema())?;
+            if let [
+                AttributeValue::U32(oid),
+                AttributeValue::U64(live),
+                AttributeValue::U64(dead),
+            ] = t.values[..]
+            {
+                let stats = TableStats {
+                    live_tuples: live,
+                    dead_tuples: dead,
+                };
+                saved.insert(oid, (stats, rid));
+            }
+        }
+        let user_tables: Vec<(u32, u32)> = self
+            .root_page_cache
+            .iter()
+            .filter(|(oid, _)| **oid >= 100)
+            .map(|(oid, root)| (*oid, *root))
+            .collect();
+        for (oid, root) in user_tables {
+            match saved.get(&oid) {
+                Some(&(stats, rid)) => self.stats.track(oid, root, stats, rid),
+                None => {
+                    // No counters saved yet, so start from what is in the heap
+                    let mut live_tuples = 0;
+                    let mut iter = HeapIterator::new(pinned_bp.as_mut(), root);
+                    while let Some(Ok(_)) = iter.next() {
+                        live_tuples += 1;
+                    }
+                    let stats = TableStats {
+                        live_tuples,
+                        dead_tuples: 0,
+                    };
+                    self.track_table_stats(oid, root, stats, pinned_bp.as_mut())?;
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Builds the B+ tree indexes that live in the database file again, from their tables.
+    /// Those are from before indexes had segments of their own: their keys were unique, so
+    /// rows with equal values were missing, and their leaves have the plain layout. Each one
+    /// gets a new oid and segment, and a `system_indexes` row in the current schema. The old
+    /// index is only forgotten once its replacement is in place, and the old pages are only
+    /// freed once every index has been replaced, so a failure leaves them to a later open.
+    fn rebuild_file_indexes(&mut self) -> Result<(), String> {
+        let bp = self.bp.clone();
+        let mut old_indexes = Vec::new();
+        {
+            let mut bp_guard = bp.lock().map_err(|_| "Lock poisoned")?;
+            let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            let mut index_oids: Vec<u32> = self.index_meta_cache.keys().copied().collect();
+            index_oids.sort_unstable();
+            for index_oid in index_oids {
+                let meta = &self.index_meta_cache[&index_oid];
+                if meta.method != IndexMethod::BTree
+                    || pinned_bp.as_mut().segment_of(meta.root_page_id) != Ok(disk::MAIN_FILE_ID)
+                {
+                    continue;
+                }
+                // None if a crash came between replacing the index and forgetting it
+                let name = self
+                    .index_name_cache
+                    .iter()
+                    .find(|(_, oid)| **oid == index_oid)
+                    .map(|(name, _)| name.clone());
+                old_indexes.push((index_oid, name, meta.clone()));
             }
         }
+        if old_indexes.is_empty() {
+            return Ok(());
+        }
+
+        for (old_oid, name, meta) in old_indexes {
+            let Some(name) = name else {
+                let mut bp_guard = bp.lock().map_err(|_| "Lock poisoned")?;
+                let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+                self.forget_index(old_oid, pinned_bp)?;
+                continue;
+            };
+            let table_name = self
+                .table_cache
+                .iter()
+                .find(|(_, oid)| **oid == meta.table_oid)
+                .map(|(name, _)| name.clone())
+                .ok_or("Table of index missing")?;
+            let schema = self
+                .get_table_schema(meta.table_oid)
+                .ok_or("Schema not found")?;
+            let column_name = |idx: usize| {
+                schema
+                    .attributes
+                    .get(idx)
+                    .map(|attr| attr.name.clone())
+                    .ok_or(format!("Index {} is on a missing column", name))
+            };
+            let options = IndexOptions {
+                method: meta.method,
+                key_expr: meta.key_expr,
+                include_columns: meta
+                    .include_cols
+                    .iter()
+                    .map(|&idx| column_name(idx))
+                    .collect::<Result<_, _>>()?,
+                predicate: meta
+                    .predicate
+                    .iter()
+                    .map(|(idx, value)| Ok((column_name(*idx)?, value.clone())))
+                    .collect::<Result<_, String>>()?,
+                ..Default::default()
+            };
+            let column = column_name(meta.column_idx)?;
+            self.create_index_replacing(&name, &table_name, &column, &options, Some(old_oid))?;
+            let mut bp_guard = bp.lock().map_err(|_| "Lock poisoned")?;
+            let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            self.forget_index(old_oid, pinned_bp)?;
+        }
 
+        // The recorded root of such a tree can be stale, so rather than walking the trees,
+        // free every tree page of the file: they all belonged to the old indexes
+        let mut bp_guard = bp.lock().map_err(|_| "Lock poisoned")?;
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for page_id in pinned_bp.as_mut().file_page_ids(disk::MAIN_FILE_ID)? {
+            let frame = pinned_bp
+                .as_mut()
+                .fetch_page(page_id, AccessStrategy::Normal)
+                .map_err(|e| format!("Failed to read page {}: {:?}", page_id, e))?;
+            let fid = frame.fid();
+            let kind = frame.page_view().header().page_kind();
+            pinned_bp.as_mut().unpin_frame(fid).ok();
+            if matches!(kind, PageKind::BPlusInner | PageKind::BPlusLeaf) {
+                pinned_bp.as_mut().free_page(page_id)?;
+            }
+        }
         Ok(())
     }
 
@@ -371,6 +869,23 @@ impl Catalog {
         self.schema_cache.get(&oid).cloned()
     }
 
+    /// Live and dead tuple counts of a user table.
+    pub fn get_table_stats(&self, oid: u32) -> Option<TableStats> {
+        self.stats.get(oid)
+    }
+
+    /// A handle to the tuple counters, for the autovacuum worker.
+    pub fn stats_registry(&self) -> StatsRegistry {
+        self.stats.clone()
+    }
+
+    /// Writes the tuple counters changed since they were last saved to system_stats.
+    pub fn save_stats(&self) -> Result<usize, String> {
+        let mut bp_guard = self.bp.lock().map_err(|_| "Lock poisoned")?;
+        let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        self.stats.persist(pinned_bp)
+    }
+
     pub fn get_index_oid(&self, index_name: &str) -> Option<u32> {
         self.index_name_cache.get(index_name).copied()
     }
@@ -387,32 +902,232 @@ impl Catalog {
             .iter()
             .position(|attr| attr.name == col_name)?;
 
-        // Find an index that points to this table and this column index
+        // Find an index that points to this table and this column index.
+        // Lookups through here are always equality, so a hash index wins over a B+ tree.
         let table_indexes = self.table_indexes.get(&table_oid)?;
 
+        let mut found = None;
         for idx_oid in table_indexes {
             let meta = self.index_meta_cache.get(idx_oid)?;
-            if meta.column_idx == col_idx {
-                return Some(*idx_oid);
+            if meta.column_idx == col_idx
+                && meta.key_expr == KeyExpr::Column
+                && meta.predicate.is_empty()
+            {
+                if meta.method == IndexMethod::Hash {
+                    return Some(*idx_oid);
+                }
+                found.get_or_insert(*idx_oid);
+            }
+        }
+
+        found
+    }
+
+    /// Finds an index that can answer the equality `terms`: its key expression matches one
+    /// term and, for a partial index, every predicate term is among the others. Hash indexes
+    /// win since every term is an equality. Returns the index and the position of its key term.
+    pub fn find_index(&self, table_name: &str, terms: &[EqualityTerm]) -> Option<(u32, usize)> {
+        let table_oid = self.get_table_oid(table_name)?;
+        let mut found = None;
+        for &idx_oid in self.table_indexes.get(&table_oid)? {
+            let Some(meta) = self.index_meta_cache.get(&idx_oid) else {
+                continue;
+            };
+            if let Some(pos) = key_term_position(meta, terms) {
+                if meta.method == IndexMethod::Hash {
+                    return Some((idx_oid, pos));
+                }
+                found.get_or_insert((idx_oid, pos));
             }
         }
+        found
+    }
+
+    /// Like `find_index`, but only returns an index whose key and INCLUDE columns contain
+    /// every column in `columns` and that fully decides `terms`, so a lookup can be answered
+    /// without reading the heap.
+    pub fn find_covering_index(
+        &self,
+        table_name: &str,
+        terms: &[EqualityTerm],
+        columns: &[usize],
+    ) -> Option<(u32, usize)> {
+        let table_oid = self.get_table_oid(table_name)?;
+
+        self.table_indexes
+            .get(&table_oid)?
+            .iter()
+            .find_map(|&idx_oid| {
+                let meta = self.index_meta_cache.get(&idx_oid)?;
+                // Only plain B+ tree keys decode back to the column value
+                if meta.method != IndexMethod::BTree || meta.key_expr != KeyExpr::Column {
+                    return None;
+                }
+                let pos = key_term_position(meta, terms)?;
+                let decided = terms.iter().enumerate().all(|(i, t)| {
+                    i == pos
+                        || (t.key_expr == KeyExpr::Column
+                            && meta.predicate.contains(&(t.column_idx, t.value.clone())))
+                });
+                let covered = columns
+                    .iter()
+                    .all(|c| *c == meta.column_idx || meta.include_cols.contains(c));
+                (decided && covered).then_some((idx_oid, pos))
+            })
+    }
+
+    /// Finds a B+ tree index whose key order is the order of column `column_idx`, so
+    /// `ORDER BY` on it can walk the leaves instead of sorting. Only unsigned integer keys
+    /// qualify: their big-endian bytes sort like the numbers, while signed and string keys don't.
+    /// A partial index is skipped since it does not hold every row. Every other index has an
+    /// entry per row, because its keys end with the RowId and equal values never collide.
+    pub fn find_ordered_index(&self, table_name: &str, column_idx: usize) -> Option<u32> {
+        let table_oid = self.get_table_oid(table_name)?;
+        let schema = self.schema_cache.get(&table_oid)?;
+        if !matches!(
+            schema.attributes.get(column_idx)?.kind,
+            AttributeKind::U32 | AttributeKind::U64
+        ) {
+            return None;
+        }
+
+        self.table_indexes
+            .get(&table_oid)?
+            .iter()
+            .copied()
+            .find(|idx_oid| {
+                self.index_meta_cache.get(idx_oid).is_some_and(|meta| {
+                    meta.method == IndexMethod::BTree
+                        && meta.key_expr == KeyExpr::Column
+                        && meta.column_idx == column_idx
+                        && meta.predicate.is_empty()
+                })
+            })
+    }
+
+    /// Registers directory `location`, which must exist, as tablespace `name`.
+    pub fn create_tablespace(&mut self, name: &str, location: &str) -> Result<u32, String> {
+        if self.tablespaces.contains_key(name) {
+            return Err(format!("Tablespace '{}' already exists", name));
+        }
+        let path = std::path::Path::new(location);
+        if !path.is_absolute() {
+            return Err("Tablespace location must be an absolute path".to_string());
+        }
+        if !path.is_dir() {
+            return Err(format!("Directory '{}' does not exist", location));
+        }
+        let oid = self.next_oid.fetch_add(1, Ordering::SeqCst);
+
+        let mut bp_guard = self.bp.lock().map_err(|_| "Lock")?;
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        let row = Tuple::new(vec![
+            AttributeValue::U32(oid),
+            AttributeValue::Varchar(name.to_string()),
+            AttributeValue::Varchar(location.to_string()),
+        ]);
+        self.insert_tuple(
+            SYSTEM_TABLESPACES_ID,
+            &row,
+            &get_system_tablespaces_schema(),
+            pinned_bp.as_mut(),
+        )?;
+        self.tablespaces
+            .insert(name.to_string(), (oid, location.to_string()));
+        self.save_next_oid(pinned_bp)?;
+        Ok(oid)
+    }
+
+    /// The directory of tablespace `name`
+    pub fn get_tablespace_location(&self, name: &str) -> Option<&str> {
+        self.tablespaces
+            .get(name)
+            .map(|(_, location)| location.as_str())
+    }
+
+    fn lookup_tablespace(&self, name: Option<&str>) -> Result<Option<(u32, String)>, String> {
+        name.map(|name| {
+            self.tablespaces
+                .get(name)
+                .cloned()
+                .ok_or(format!("Tablespace '{}' not found", name))
+        })
+        .transpose()
+    }
+
+    /// Records in system_segments that `segment` was created in `tablespace` (0 for next
+    /// to the database file) with its pages stored as `compression` says.
+    fn record_segment(
+        &mut self,
+        segment: u32,
+        tablespace: u32,
+        compression: Compression,
+        bpm: Pin<&mut BufferPool>,
+    ) -> Result<(), String> {
+        let row = Tuple::new(vec![
+            AttributeValue::U32(segment),
+            AttributeValue::U32(tablespace),
+            AttributeValue::U8(compression as u8),
+        ]);
+        self.insert_tuple(SYSTEM_SEGMENTS_ID, &row, &get_system_segments_schema(), bpm)?;
+        self.segments.insert(segment, (tablespace, compression));
+        Ok(())
+    }
 
-        None
+    /// Deletes the system_segments row of a segment being dropped, if it has one.
+    fn forget_segment(
+        &mut self,
+        segment: u32,
+        mut bpm: Pin<&mut BufferPool>,
+    ) -> Result<(), String> {
+        if self.segments.remove(&segment).is_none() {
+            return Ok(());
+        }
+        let mut iter = HeapIterator::new(bpm.as_mut(), SYSTEM_SEGMENTS_PAGE_ID);
+        while let Some(Ok((rid, bytes))) = iter.next() {
+            if let Ok(tuple) = Tuple::from_bytes(&bytes, &get_system_segments_schema())
+                && tuple.values[0] == AttributeValue::U32(segment)
+            {
+                return HeapFile::new(0, 0)
+                    .delete(bpm.as_mut(), rid)
+                    .map_err(|e| format!("Failed to delete segment row: {:?}", e));
+            }
+        }
+        Ok(())
     }
 
     pub fn create_table(&mut self, name: &str, schema: TableType) -> Result<u32, String> {
+        self.create_table_with(name, schema, &TableOptions::default())
+    }
+
+    /// Creates a table stored as `options` say.
+    pub fn create_table_with(
+        &mut self,
+        name: &str,
+        schema: TableType,
+        options: &TableOptions,
+    ) -> Result<u32, String> {
         if self.table_cache.contains_key(name) {
             return Err("Exists".into());
         }
+        let tablespace = self.lookup_tablespace(options.tablespace.as_deref())?;
         let oid = self.next_oid.fetch_add(1, Ordering::SeqCst);
 
+        // The table gets a segment of its own, named after its oid
         let root_page_id = {
             let mut bp_guard = self.bp.lock().map_err(|_| "Lock")?;
             let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
-            let new_pid = self.next_oid.fetch_add(1, Ordering::SeqCst);
+            let location = tablespace.as_ref().map(|(_, location)| location.as_str());
+            // Recorded first, so a segment left behind by a crash is never taken for a new one
+            self.save_next_oid(pinned_bp.as_mut())?;
+            pinned_bp.as_mut().create_segment(oid, location)?;
+            if options.compression != Compression::None {
+                pinned_bp.as_mut().compress_segment(oid)?;
+            }
+            let new_pid = self.last_page_id.fetch_add(1, Ordering::SeqCst) + 1;
             let frame = pinned_bp
                 .as_mut()
-                .alloc_new_page(PageKind::SlottedData, new_pid)
+                .alloc_new_page_in(PageKind::SlottedData, new_pid, oid, AccessStrategy::Normal)
                 .map_err(|e| format!("{:?}", e))?;
             let offset = frame.file_offset();
             let fid = frame.fid();
@@ -427,7 +1142,8 @@ impl Catalog {
         self.table_cache.insert(name.to_string(), oid);
         self.root_page_cache.insert(oid, root_page_id);
         self.schema_cache.insert(oid, schema.clone());
-        let mut bp_guard = self.bp.lock().map_err(|_| "Lock")?;
+        let bp = self.bp.clone();
+        let mut bp_guard = bp.lock().map_err(|_| "Lock")?;
         let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
 
         let row = Tuple::new(vec![
@@ -445,6 +1161,11 @@ impl Catalog {
         for col in &schema.attributes {
             self.insert_column_metadata(oid, col, &get_system_columns_schema(), pinned_bp.as_mut());
         }
+        if tablespace.is_some() || options.compression != Compression::None {
+            let tablespace_oid = tablespace.map_or(0, |(oid, _)| oid);
+            self.record_segment(oid, tablespace_oid, options.compression, pinned_bp.as_mut())?;
+        }
+        self.track_table_stats(oid, root_page_id, TableStats::default(), pinned_bp)?;
         Ok(oid)
     }
 
@@ -455,6 +1176,62 @@ impl Catalog {
         table_name: &str,
         column_name: &str,
     ) -> Result<u32, String> {
+        self.create_covering_index(index_name, table_name, column_name, &[])
+    }
+
+    /// Creates an index that also stores the `include_columns` values in its leaves,
+    /// so queries reading only those columns and the key never touch the heap.
+    pub fn create_covering_index(
+        &mut self,
+        index_name: &str,
+        table_name: &str,
+        column_name: &str,
+        include_columns: &[String],
+    ) -> Result<u32, String> {
+        let options = IndexOptions {
+            include_columns: include_columns.to_vec(),
+            ..Default::default()
+        };
+        self.create_index_with(index_name, table_name, column_name, &options)
+    }
+
+    /// Creates an index on `options.key_expr` applied to `column_name`. Hash indexes only
+    /// answer equality lookups and cannot store INCLUDE columns. With a predicate, only
+    /// qualifying rows are indexed and the planner only uses the index for queries that
+    /// repeat the predicate. Predicate values must have the type of their column.
+    pub fn create_index_with(
+        &mut self,
+        index_name: &str,
+        table_name: &str,
+        column_name: &str,
+        options: &IndexOptions,
+    ) -> Result<u32, String> {
+        self.create_index_replacing(index_name, table_name, column_name, options, None)
+    }
+
+    /// `create_index_with`, where the name may still belong to index `replaces`. The name
+    /// is taken over by the new index, and the old one is left for the caller to forget.
+    fn create_index_replacing(
+        &mut self,
+        index_name: &str,
+        table_name: &str,
+        column_name: &str,
+        options: &IndexOptions,
+        replaces: Option<u32>,
+    ) -> Result<u32, String> {
+        let method = options.method;
+        let include_columns = &options.include_columns;
+        if method == IndexMethod::Hash && !include_columns.is_empty() {
+            return Err("Hash indexes do not support INCLUDE columns".into());
+        }
+        let fill_factor = options.fill_factor.unwrap_or(100);
+        if !(10..=100).contains(&fill_factor) {
+            return Err(format!(
+                "Fill factor {} must be between 10 and 100",
+                fill_factor
+            ));
+        }
+
         let table_oid = *self.table_cache.get(table_name).ok_or("Table not found")?;
         let schema = self
             .schema_cache
@@ -468,49 +1245,134 @@ impl Catalog {
             .find(|(_, attr)| attr.name == column_name)
             .ok_or("Column not found")?;
 
+        if let Some(&oid) = self.index_name_cache.get(index_name)
+            && Some(oid) != replaces
+        {
+            return Err(format!("Index '{}' already exists", index_name));
+        }
+
+        let value_key_size = match (options.key_expr, col_attr.kind) {
+            (KeyExpr::Column, AttributeKind::U32 | AttributeKind::I32) => 4,
+            (KeyExpr::Column, AttributeKind::U64 | AttributeKind::I64) => 8,
+            (KeyExpr::Column, _) => return Err("Index only supports integers".into()),
+            // String expressions are keyed by their hash, see `index_key`
+            (_, AttributeKind::Varchar) => 8,
+            (_, _) => return Err("LOWER/UPPER indexes require a VARCHAR column".into()),
+        };
+        let key_size = match method {
+            IndexMethod::BTree => value_key_size + ROW_ID_KEY_SIZE as u32,
+            IndexMethod::Hash => value_key_size,
+        };
+
+        let mut predicate = Vec::new();
+        for (name, value) in &options.predicate {
+            let (idx, attr) = schema
+                .attributes
+                .iter()
+                .enumerate()
+                .find(|(_, attr)| &attr.name == name)
+                .ok_or(format!("Column {} not found", name))?;
+            if !value_has_kind(value, attr.kind) {
+                return Err(format!(
+                    "Predicate value {:?} for column {} has the wrong type",
+                    value, name
+                ));
+            }
+            predicate.push((idx, value.clone()));
+        }
+
+        let mut include_cols = Vec::new();
+        let mut payload_size = 0;
+        for name in include_columns {
+            let (idx, attr) = schema
+                .attributes
+                .iter()
+                .enumerate()
+                .find(|(_, attr)| &attr.name == name)
+                .ok_or(format!("Column {} not found", name))?;
+            if !is_fixed_width(attr.kind) {
+                return Err(format!("INCLUDE column {} must be fixed-width", name));
+            }
+            if idx != col_idx && !include_cols.contains(&idx) {
+                include_cols.push(idx);
+                payload_size += attr.kind.size_of();
+            }
+        }
+        if payload_size > u16::MAX as usize
+            || BPlusLeaf::max_keys_for(key_size, payload_size as u16) < 4
+        {
+            return Err("INCLUDE columns are too wide".into());
+        }
+
+        let table_root = *self
+            .root_page_cache
+            .get(&table_oid)
+            .ok_or("Table root missing")?;
+
+        let meta = IndexMeta {
+            table_oid,
+            column_idx: col_idx,
+            root_page_id: 0,
+            include_cols,
+            method,
+            key_expr: options.key_expr,
+            predicate,
+        };
+
+        let tablespace = self.lookup_tablespace(options.tablespace.as_deref())?;
         let index_oid = self.next_oid.fetch_add(1, Ordering::SeqCst);
-        let mut bp_guard = self.bp.lock().map_err(|_| "Lock")?;
+        let bp = self.bp.clone();
+        let mut bp_guard = bp.lock().map_err(|_| "Lock")?;
         let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        let location = tablespace.as_ref().map(|(_, location)| location.as_str());
+        self.save_next_oid(pinned_bp.as_mut())?;
 
-        // Allocate B+ Tree
-        let root_page_id = {
-            let new_pid = self.next_oid.fetch_add(1, Ordering::SeqCst);
+        // Backfill: collect (key, rid) for every qualifying row and build the index
+        let mut rows_to_index = Vec::new();
+        {
+            let mut heap_iter = HeapIterator::new(pinned_bp.as_mut(), table_root);
 
-            let frame = pinned_bp
-                .as_mut()
-                .alloc_new_page(PageKind::BPlusLeaf, new_pid)
-                .map_err(|e| format!("{:?}", e))?;
-            {
-                let mut view = frame.page_view();
-                if let crate::storage::page::base::Page::BPlusLeaf(leaf) = &mut view {
-                    let key_size = match col_attr.kind {
-                        AttributeKind::U32 | AttributeKind::I32 => 4,
-                        AttributeKind::U64 | AttributeKind::I64 => 8,
-                        _ => return Err("Index only supports integers".into()),
+            while let Some(Ok((rid, bytes))) = heap_iter.next() {
+                if let Ok(tuple) = Tuple::from_bytes(&bytes, schema)
+                    && let Some(key_bytes) = index_entry_key(&meta, &tuple)
+                {
+                    let payload = index_payload(&tuple, &meta.include_cols, schema)?;
+                    let key_bytes = match method {
+                        IndexMethod::BTree => btree_entry_key(&key_bytes, rid.to_u64()),
+                        IndexMethod::Hash => key_bytes,
                     };
-                    leaf.init(new_pid, key_size);
+                    rows_to_index.push((key_bytes, rid.to_u64(), payload));
                 }
             }
-            let offset = frame.file_offset();
-            let fid = frame.fid();
-            pinned_bp.as_mut().unpin_frame(fid).ok();
-            pinned_bp
-                .as_mut()
-                .register_page_in_directory(new_pid, offset, 4000)
-                .unwrap();
-            new_pid
+        }
+        // B+ tree keys end with the RowId, so no two are equal
+        rows_to_index.sort_by(|a, b| a.0.cmp(&b.0));
+
+        // The segment only exists once the rows are in hand; if building the index fails
+        // it is dropped again along with whatever pages the build allocated
+        pinned_bp.as_mut().create_segment(index_oid, location)?;
+        let root_page_id = match self.build_index(
+            pinned_bp.as_mut(),
+            index_oid,
+            options,
+            key_size,
+            payload_size as u16,
+            &rows_to_index,
+        ) {
+            Ok(root_page_id) => root_page_id,
+            Err(e) => {
+                pinned_bp.as_mut().drop_segment(index_oid)?;
+                return Err(e);
+            }
         };
 
+        let meta = IndexMeta {
+            root_page_id,
+            ..meta
+        };
         self.index_name_cache
             .insert(index_name.to_string(), index_oid);
-        self.index_meta_cache.insert(
-            index_oid,
-            IndexMeta {
-                table_oid,
-                column_idx: col_idx,
-                root_page_id,
-            },
-        );
+        self.index_meta_cache.insert(index_oid, meta.clone());
 
         // UPDATE: Add to table_indexes
         self.table_indexes
@@ -524,6 +1386,16 @@ impl Catalog {
             AttributeValue::U32(table_oid),
             AttributeValue::U8(col_idx as u8),
             AttributeValue::U32(root_page_id),
+            AttributeValue::Varchar(
+                meta.include_cols
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            AttributeValue::U8(method as u8),
+            AttributeValue::U8(meta.key_expr as u8),
+            AttributeValue::Varchar(encode_predicate(&meta.predicate)),
         ]);
         self.insert_tuple(
             SYSTEM_INDEXES_ID,
@@ -531,44 +1403,232 @@ impl Catalog {
             &get_system_indexes_schema(),
             pinned_bp.as_mut(),
         )?;
+        if let Some((tablespace_oid, _)) = tablespace {
+            self.record_segment(
+                index_oid,
+                tablespace_oid,
+                Compression::None,
+                pinned_bp.as_mut(),
+            )?;
+        }
 
-        // Backfill
-        let table_root = *self
-            .root_page_cache
-            .get(&table_oid)
-            .ok_or("Table root missing")?;
-        let mut rows_to_index = Vec::new();
+        Ok(index_oid)
+    }
+
+    /// Builds a new index in segment `index_oid` out of `rows`, sorted by key, and
+    /// returns its root (or directory) page.
+    fn build_index(
+        &self,
+        mut bpm: Pin<&mut BufferPool>,
+        index_oid: u32,
+        options: &IndexOptions,
+        key_size: u32,
+        payload_size: u16,
+        rows: &[(Vec<u8>, u64, Vec<u8>)],
+    ) -> Result<u32, String> {
+        match options.method {
+            IndexMethod::BTree => {
+                let mut tree = BPlusTree::in_segment(bpm.as_mut(), 0, index_oid);
+                tree.bulk_load_with_payload(
+                    key_size,
+                    payload_size,
+                    rows,
+                    options.fill_factor.unwrap_or(100),
+                    &self.last_page_id,
+                )
+                .map_err(|e| format!("{:?}", e))?;
+                Ok(tree.root_page_id())
+            }
+            IndexMethod::Hash => {
+                let mut index =
+                    HashIndex::create(bpm.as_mut(), key_size, index_oid, &self.last_page_id)
+                        .map_err(|e| format!("{:?}", e))?;
+                for (key, rid, _) in rows {
+                    index
+                        .insert(key, *rid, &self.last_page_id)
+                        .map_err(|e| format!("{:?}", e))?;
+                }
+                Ok(index.directory_page_id)
+            }
+        }
+    }
+
+    pub fn drop_index(&mut self, index_name: &str) -> Result<(), String> {
+        let index_oid = self
+            .index_name_cache
+            .get(index_name)
+            .copied()
+            .ok_or(format!("Index '{}' not found", index_name))?;
+
+        let bp = self.bp.clone();
+        let mut bp_guard = bp.lock().map_err(|_| "Lock poisoned")?;
+        let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+
+        self.remove_index(index_oid, pinned_bp)
+    }
 
+    /// Deletes the `system_indexes` row, forgets the index in every cache and frees its pages.
+    fn remove_index(
+        &mut self,
+        index_oid: u32,
+        mut bpm: Pin<&mut BufferPool>,
+    ) -> Result<(), String> {
+        let meta = self.forget_index(index_oid, bpm.as_mut())?;
+
+        if let Some(meta) = meta {
+            // Indexes made before segments existed live in the database file
+            let segment = bpm.as_mut().segment_of(meta.root_page_id)?;
+            if segment != disk::MAIN_FILE_ID {
+                self.forget_segment(segment, bpm.as_mut())?;
+                return bpm.drop_segment(segment);
+            }
+            match meta.method {
+                IndexMethod::BTree => BPlusTree::new(bpm.as_mut(), meta.root_page_id)
+                    .destroy()
+                    .map_err(|e| format!("Failed to free index pages: {:?}", e))?,
+                IndexMethod::Hash => HashIndex::new(bpm.as_mut(), meta.root_page_id)
+                    .destroy()
+                    .map_err(|e| format!("Failed to free index pages: {:?}", e))?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the `system_indexes` row and forgets the index in every cache, but leaves
+    /// its pages alone.
+    fn forget_index(
+        &mut self,
+        index_oid: u32,
+        mut bpm: Pin<&mut BufferPool>,
+    ) -> Result<Option<IndexMeta>, String> {
+        let mut iter = HeapIterator::new(bpm.as_mut(), SYSTEM_INDEXES_PAGE_ID);
+        while let Some(Ok((rid, bytes))) = iter.next() {
+            if let Ok((oid, _, _)) = decode_index_row(&bytes)
+                && oid == index_oid
+            {
+                HeapFile::new(0, 0)
+                    .delete(bpm.as_mut(), rid)
+                    .map_err(|e| format!("Failed to delete index metadata: {:?}", e))?;
+                break;
+            }
+        }
+
+        self.index_name_cache.retain(|_, oid| *oid != index_oid);
+        let meta = self.index_meta_cache.remove(&index_oid);
+        if let Some(meta) = &meta
+            && let Some(indexes) = self.table_indexes.get_mut(&meta.table_oid)
         {
-            let mut heap_iter = HeapIterator::new(pinned_bp.as_mut(), table_root);
+            indexes.retain(|oid| *oid != index_oid);
+        }
+        Ok(meta)
+    }
 
-            while let Some(Ok((rid, bytes))) = heap_iter.next() {
-                if let Ok(tuple) = Tuple::from_bytes(&bytes, schema) {
-                    let key_val = &tuple.values[col_idx];
-                    let key_bytes = match key_val {
-                        AttributeValue::U32(v) => v.to_be_bytes().to_vec(),
-                        AttributeValue::I32(v) => v.to_be_bytes().to_vec(),
-                        AttributeValue::U64(v) => v.to_be_bytes().to_vec(),
-                        AttributeValue::I64(v) => v.to_be_bytes().to_vec(),
-                        _ => vec![],
-                    };
-                    if !key_bytes.is_empty() {
-                        rows_to_index.push((key_bytes, rid.to_u64()));
-                    }
+    /// Runs `BPlusTree::verify` on an index and also checks that every entry points at a
+    /// live row of the table whose key (and partial index predicate) still matches.
+    pub fn verify_index(&self, index_name: &str) -> Result<VerifyReport, String> {
+        let index_oid = self
+            .index_name_cache
+            .get(index_name)
+            .copied()
+            .ok_or(format!("Index '{}' not found", index_name))?;
+        let meta = self
+            .index_meta_cache
+            .get(&index_oid)
+            .ok_or("Index metadata missing")?;
+        if meta.method != IndexMethod::BTree {
+            return Err("Only B+ tree indexes can be verified".into());
+        }
+        let schema = self
+            .get_table_schema(meta.table_oid)
+            .ok_or("Schema not found")?;
+
+        let mut bp_guard = self.bp.lock().map_err(|_| "Lock poisoned")?;
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+
+        let mut report = BPlusTree::new(pinned_bp.as_mut(), meta.root_page_id)
+            .verify()
+            .map_err(|e| format!("{:?}", e))?;
+
+        // Capped at what the walk counted, so a looping leaf chain cannot hang us
+        let mut entries = Vec::new();
+        if meta.root_page_id != 0 {
+            let tree = BPlusTree::new(pinned_bp.as_mut(), meta.root_page_id);
+            let mut iter = BTreeIterator::new(tree, None);
+            while entries.len() < report.entries
+                && let Some(entry) = iter.next()
+            {
+                entries.push(entry);
+            }
+        }
+
+        for (key, packed_rid) in entries {
+            let rid = RowId::from_u64(packed_rid);
+            let row = HeapFile::get(pinned_bp.as_mut(), rid)
+                .ok()
+                .and_then(|bytes| Tuple::from_bytes(&bytes, &schema).ok());
+            match row {
+                None => report.problems.push(format!(
+                    "entry for row {}:{} points at no live row",
+                    rid.page_id(),
+                    rid.slot_num()
+                )),
+                Some(tuple)
+                    if index_entry_key(meta, &tuple).map(|k| btree_entry_key(&k, packed_rid))
+                        != Some(key) =>
+                {
+                    report.problems.push(format!(
+                        "entry for row {}:{} does not match the row's key",
+                        rid.page_id(),
+                        rid.slot_num()
+                    ))
                 }
+                Some(_) => {}
             }
         }
 
-        {
-            let mut tree = BPlusTree::new(pinned_bp.as_mut(), root_page_id);
+        Ok(report)
+    }
+
+    /// Compacts the heap of `table_name`, or of every table including the system ones,
+    /// then returns the free pages at the end of the file to the file system.
+    pub fn vacuum(&self, table_name: Option<&str>) -> Result<VacuumStats, String> {
+        let tables: Vec<(u32, u32)> = match table_name {
+            Some(name) => {
+                let oid = self
+                    .table_cache
+                    .get(name)
+                    .ok_or(format!("Table '{}' not found", name))?;
+                vec![(*oid, *self.root_page_cache.get(oid).ok_or("Unknown table")?)]
+            }
+            None => self
+                .root_page_cache
+                .iter()
+                .map(|(oid, root)| (*oid, *root))
+                .collect(),
+        };
+
+        let mut bp_guard = self.bp.lock().map_err(|_| "Lock poisoned")?;
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
 
-            for (key, val) in rows_to_index {
-                tree.insert(&key, val, &self.next_oid)
-                    .map_err(|e| format!("{:?}", e))?;
+        let mut stats = VacuumStats::default();
+        for (oid, root) in tables {
+            let dead_tuples = self.stats.get(oid).map_or(0, |s| s.dead_tuples);
+            let table_stats = HeapFile::new(root, 0)
+                .vacuum(pinned_bp.as_mut())
+                .map_err(|e| format!("Vacuum failed: {:?}", e))?;
+            // Room may have opened up before the page inserts would start at
+            if table_stats.pages_freed > 0 || table_stats.bytes_reclaimed > 0 {
+                self.stats.set_insert_page(oid, 0);
             }
+            self.stats.record_vacuum(oid, dead_tuples);
+            stats.pages_scanned += table_stats.pages_scanned;
+            stats.bytes_reclaimed += table_stats.bytes_reclaimed;
+            stats.pages_freed += table_stats.pages_freed;
         }
+        stats.pages_truncated = pinned_bp.as_mut().trim_free_pages()?;
 
-        Ok(index_oid)
+        Ok(stats)
     }
 
     pub fn list_user_tables(&self) -> Vec<(u32, String)> {
@@ -596,41 +1656,14 @@ impl Catalog {
             return Err("Cannot drop system tables".to_string());
         }
 
-        let mut bp_guard = self.bp.lock().map_err(|_| "Lock poisoned")?;
+        let bp = self.bp.clone();
+        let mut bp_guard = bp.lock().map_err(|_| "Lock poisoned")?;
         let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
 
         // 2. Drop all indexes associated with this table
         if let Some(index_oids) = self.table_indexes.get(&table_oid).cloned() {
             for index_oid in index_oids {
-                // Get index name for deletion
-                let index_name = self
-                    .index_name_cache
-                    .iter()
-                    .find(|(_, oid)| **oid == index_oid) // Fixed: dereference oid twice
-                    .map(|(name, _)| name.clone());
-
-                if let Some(idx_name) = index_name {
-                    // Delete from system_indexes table
-                    let mut iter = HeapIterator::new(pinned_bp.as_mut(), SYSTEM_INDEXES_PAGE_ID);
-                    while let Some(Ok((rid, bytes))) = iter.next() {
-                        if let Ok(tuple) = Tuple::from_bytes(&bytes, &get_system_indexes_schema()) {
-                            if let AttributeValue::U32(oid) = tuple.values[0] {
-                                if oid == index_oid {
-                                    HeapFile::new(0, 0)
-                                        .delete(pinned_bp.as_mut(), rid)
-                                        .map_err(|e| {
-                                            format!("Failed to delete index metadata: {:?}", e)
-                                        })?;
-                                    break;
-                                }
-                            }
-                        }
-                    }
-
-                    // Remove from caches
-                    self.index_name_cache.remove(&idx_name);
-                    self.index_meta_cache.remove(&index_oid);
-                }
+                self.remove_index(index_oid, pinned_bp.as_mut())?;
             }
         }
 
@@ -672,7 +1705,27 @@ impl Catalog {
             }
         }
 
-        // 5. Remove from runtime caches
+        // 5. Delete its statistics from system_stats
+        if let Some(rid) = self.stats.forget(table_oid) {
+            HeapFile::new(0, 0)
+                .delete(pinned_bp.as_mut(), rid)
+                .map_err(|e| format!("Failed to delete table stats: {:?}", e))?;
+        }
+
+        // 6. Free the table's heap pages, all at once if it has a segment of its own
+        if let Some(&root_page_id) = self.root_page_cache.get(&table_oid) {
+            let segment = pinned_bp.as_mut().segment_of(root_page_id)?;
+            if segment != disk::MAIN_FILE_ID {
+                self.forget_segment(segment, pinned_bp.as_mut())?;
+                pinned_bp.as_mut().drop_segment(segment)?;
+            } else {
+                HeapFile::new(root_page_id, 0)
+                    .free_pages(pinned_bp.as_mut())
+                    .map_err(|e| format!("Failed to free table pages: {:?}", e))?;
+            }
+        }
+
+        // 7. Remove from runtime caches
         self.table_cache.remove(table_name);
         self.schema_cache.remove(&table_oid);
         self.root_page_cache.remove(&table_oid);
@@ -682,11 +1735,25 @@ impl Catalog {
     }
 
     pub fn insert_tuple(
+        &self,
+        table_oid: u32,
+        tuple: &Tuple,
+        schema: &TableType,
+        bpm: Pin<&mut BufferPool>,
+    ) -> Result<(), String> {
+        self.insert_tuple_with(table_oid, tuple, schema, bpm, AccessStrategy::Normal)
+    }
+
+    /// Like `insert_tuple`, with the heap pages read and written through `strategy`.
+    /// Index maintenance always uses the normal policy: those pages are the ones
+    /// lookups need next.
+    pub fn insert_tuple_with(
         &self,
         table_oid: u32,
         tuple: &Tuple,
         schema: &TableType,
         mut bpm: Pin<&mut BufferPool>,
+        strategy: AccessStrategy,
     ) -> Result<(), String> {
         let start_page = if table_oid == SYSTEM_TABLES_ID {
             SYSTEM_TABLES_PAGE_ID
@@ -701,33 +1768,29 @@ impl Catalog {
                 .ok_or("Unknown table")?
         };
 
-        let mut heap = HeapFile::new(start_page, start_page);
+        // Start where the last row went instead of walking the whole chain
+        let insert_page = match self.stats.insert_page(table_oid) {
+            0 => start_page,
+            page_id => page_id,
+        };
+        let mut heap = HeapFile::new(start_page, insert_page);
         let bytes = tuple.to_bytes(schema)?;
 
         // 1. Insert into Heap
         let rid = heap
-            .insert(bpm.as_mut(), &self.next_oid, &bytes)
+            .insert(bpm.as_mut(), &self.last_page_id, &bytes, strategy)
             .map_err(|e| format!("{:?}", e))?;
+        self.stats.record_insert(table_oid);
+        self.stats.set_insert_page(table_oid, rid.page_id());
 
         // 2. Update Indexes
         if let Some(indexes) = self.table_indexes.get(&table_oid) {
             for &index_oid in indexes {
                 if let Some(meta) = self.index_meta_cache.get(&index_oid) {
-                    if meta.column_idx < tuple.values.len() {
-                        let key_val = &tuple.values[meta.column_idx];
-                        let key_bytes = match key_val {
-                            AttributeValue::U32(v) => v.to_be_bytes().to_vec(),
-                            AttributeValue::I32(v) => v.to_be_bytes().to_vec(),
-                            AttributeValue::U64(v) => v.to_be_bytes().to_vec(),
-                            AttributeValue::I64(v) => v.to_be_bytes().to_vec(),
-                            _ => continue,
-                        };
-
-                        if !key_bytes.is_empty() {
-                            let mut tree = BPlusTree::new(bpm.as_mut(), meta.root_page_id);
-                            tree.insert(&key_bytes, rid.to_u64(), &self.next_oid)
-                                .map_err(|e| format!("Index insert failed: {:?}", e))?;
-                        }
+                    // Rows outside a partial index's predicate are skipped
+                    if let Some(key_bytes) = index_entry_key(meta, tuple) {
+                        let payload = index_payload(tuple, &meta.include_cols, schema)?;
+                        self.index_insert(meta, &key_bytes, rid, &payload, bpm.as_mut())?;
                     }
                 }
             }
@@ -736,6 +1799,65 @@ impl Catalog {
         Ok(())
     }
 
+    /// Replaces the row at `rid` with `tuple`. The row keeps its RowId, so only the
+    /// indexes whose key or payload changed are touched.
+    pub fn update_tuple(
+        &self,
+        table_oid: u32,
+        rid: RowId,
+        tuple: &Tuple,
+        schema: &TableType,
+        mut bpm: Pin<&mut BufferPool>,
+    ) -> Result<(), String> {
+        let root_page_id = *self
+            .root_page_cache
+            .get(&table_oid)
+            .ok_or("Unknown table")?;
+        let old_bytes = HeapFile::get(bpm.as_mut(), rid)
+            .map_err(|e| format!("Failed to fetch tuple for update: {:?}", e))?;
+        let old_tuple = Tuple::from_bytes(&old_bytes, schema)?;
+        let bytes = tuple.to_bytes(schema)?;
+
+        // 1. Update the Heap. A tuple that moves goes where inserts would put it.
+        let insert_page = match self.stats.insert_page(table_oid) {
+            0 => root_page_id,
+            page_id => page_id,
+        };
+        let mut heap = HeapFile::new(root_page_id, insert_page);
+        let placement = heap
+            .update(bpm.as_mut(), &self.last_page_id, rid, &bytes)
+            .map_err(|e| format!("Heap update failed: {:?}", e))?;
+        self.stats.set_insert_page(table_oid, heap.last_page_id);
+        if placement == UpdatePlacement::Relocated {
+            self.stats.record_relocation(table_oid);
+        }
+
+        // 2. Update the Indexes that see a change
+        let Some(indexes) = self.table_indexes.get(&table_oid) else {
+            return Ok(());
+        };
+        for &index_oid in indexes {
+            let Some(meta) = self.index_meta_cache.get(&index_oid) else {
+                continue;
+            };
+            let old_key = index_entry_key(meta, &old_tuple);
+            let new_key = index_entry_key(meta, tuple);
+            let old_payload = index_payload(&old_tuple, &meta.include_cols, schema)?;
+            let payload = index_payload(tuple, &meta.include_cols, schema)?;
+            if old_key == new_key && old_payload == payload {
+                continue;
+            }
+            if let Some(key) = old_key {
+                self.index_delete(meta, &key, rid, bpm.as_mut())?;
+            }
+            if let Some(key) = new_key {
+                self.index_insert(meta, &key, rid, &payload, bpm.as_mut())?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn delete_tuple(
         &self,
         table_oid: u32,
@@ -753,44 +1875,351 @@ impl Catalog {
         let mut heap = HeapFile::new(0, 0);
         heap.delete(bpm.as_mut(), rid)
             .map_err(|e| format!("Heap delete failed: {:?}", e))?;
+        self.stats.record_delete(table_oid);
 
         // 3. Delete from Indexes
         if let Some(indexes) = self.table_indexes.get(&table_oid) {
             for &index_oid in indexes {
-                if let Some(meta) = self.index_meta_cache.get(&index_oid) {
-                    if meta.column_idx < tuple.values.len() {
-                        let key_val = &tuple.values[meta.column_idx];
-                        let key_bytes = match key_val {
-                            AttributeValue::U32(v) => v.to_be_bytes().to_vec(),
-                            AttributeValue::I32(v) => v.to_be_bytes().to_vec(),
-                            AttributeValue::U64(v) => v.to_be_bytes().to_vec(),
-                            AttributeValue::I64(v) => v.to_be_bytes().to_vec(),
-                            _ => continue,
-                        };
-
-                        if !key_bytes.is_empty() {
-                            let mut tree = BPlusTree::new(bpm.as_mut(), meta.root_page_id);
-                            // Ignore error if key not found (idempotent)
-                            let _ = tree.delete(&key_bytes);
-                        }
-                    }
+                if let Some(meta) = self.index_meta_cache.get(&index_oid)
+                    && let Some(key_bytes) = index_entry_key(meta, &tuple)
+                {
+                    self.index_delete(meta, &key_bytes, rid, bpm.as_mut())?;
                 }
             }
         }
 
         Ok(())
     }
+
+    /// Adds the entry of row `rid` under value key `key` to an index.
+    fn index_insert(
+        &self,
+        meta: &IndexMeta,
+        key: &[u8],
+        rid: RowId,
+        payload: &[u8],
+        mut bpm: Pin<&mut BufferPool>,
+    ) -> Result<(), String> {
+        match meta.method {
+            IndexMethod::BTree => BPlusTree::new(bpm.as_mut(), meta.root_page_id)
+                .insert_with_payload(
+                    &btree_entry_key(key, rid.to_u64()),
+                    rid.to_u64(),
+                    payload,
+                    &self.last_page_id,
+                )
+                .map_err(|e| format!("Index insert failed: {:?}", e)),
+            IndexMethod::Hash => HashIndex::new(bpm.as_mut(), meta.root_page_id)
+                .insert(key, rid.to_u64(), &self.last_page_id)
+                .map_err(|e| format!("Index insert failed: {:?}", e)),
+        }
+    }
+
+    /// Removes the entry of row `rid` under value key `key` from an index. Other rows with
+    /// the same key keep theirs. An entry that is not there is not an error.
+    fn index_delete(
+        &self,
+        meta: &IndexMeta,
+        key: &[u8],
+        rid: RowId,
+        bpm: Pin<&mut BufferPool>,
+    ) -> Result<(), String> {
+        match meta.method {
+            IndexMethod::BTree => BPlusTree::new(bpm, meta.root_page_id)
+                .delete(&btree_entry_key(key, rid.to_u64()))
+                .map_err(|e| format!("{:?}", e)),
+            IndexMethod::Hash => HashIndex::new(bpm, meta.root_page_id)
+                .delete(key, rid.to_u64())
+                .map_err(|e| format!("{:?}", e)),
+        }
+    }
+
+    /// Rows an index holds under the key of `value`. String keys are only a hash of the
+    /// value, so the rows have to be checked against it.
+    pub fn index_lookup(
+        &self,
+        index_oid: u32,
+        value: &AttributeValue,
+        mut bpm: Pin<&mut BufferPool>,
+    ) -> Result<Vec<RowId>, String> {
+        let meta = self
+            .index_meta_cache
+            .get(&index_oid)
+            .ok_or("Index not found")?;
+        let key = index_key(value).ok_or("Unsupported index key type")?;
+        let rids = match meta.method {
+            IndexMethod::BTree => {
+                let tree = BPlusTree::new(bpm.as_mut(), meta.root_page_id);
+                let mut iter = BTreeIterator::new(tree, Some(&btree_entry_key(&key, 0)));
+                let mut rids = Vec::new();
+                while let Some((entry, rid)) = iter.next()
+                    && entry.starts_with(&key)
+                {
+                    rids.push(rid);
+                }
+                rids
+            }
+            IndexMethod::Hash => HashIndex::new(bpm.as_mut(), meta.root_page_id)
+                .get_values(&key)
+                .map_err(|e| format!("{:?}", e))?,
+        };
+        Ok(rids.into_iter().map(RowId::from_u64).collect())
+    }
+}
+
+/// Bytes of the RowId that ends every B+ tree index key, see `btree_entry_key`.
+pub const ROW_ID_KEY_SIZE: usize = 8;
+
+/// Key of the entry for row `rid` in a B+ tree index: the key of its value followed by the
+/// RowId. Rows with equal values are entries of their own, in RowId order, and the rows
+/// with a value are the entries from `btree_entry_key(key, 0)` on that start with its key.
+pub fn btree_entry_key(key: &[u8], rid: u64) -> Vec<u8> {
+    let mut entry = key.to_vec();
+    entry.extend_from_slice(&rid.to_be_bytes());
+    entry
+}
+
+/// Encodes an indexable value as an index key, or `None` for unsupported types.
+/// Strings are keyed by their 64-bit hash, so a lookup through such a key must recheck the row.
+pub fn index_key(value: &AttributeValue) -> Option<Vec<u8>> {
+    match value {
+        AttributeValue::U32(v) => Some(v.to_be_bytes().to_vec()),
+        AttributeValue::I32(v) => Some(v.to_be_bytes().to_vec()),
+        AttributeValue::U64(v) => Some(v.to_be_bytes().to_vec()),
+        AttributeValue::I64(v) => Some(v.to_be_bytes().to_vec()),
+        AttributeValue::Varchar(s) => Some(hash_key(s.as_bytes()).to_be_bytes().to_vec()),
+        _ => None,
+    }
+}
+
+/// Key `tuple` is stored under in an index, or `None` if the row falls outside a partial
+/// index or its key cannot be encoded.
+fn index_entry_key(meta: &IndexMeta, tuple: &Tuple) -> Option<Vec<u8>> {
+    let qualifies = meta
+        .predicate
+        .iter()
+        .all(|(c, v)| tuple.values.get(*c) == Some(v));
+    if !qualifies {
+        return None;
+    }
+    let value = meta.key_expr.apply(tuple.values.get(meta.column_idx)?)?;
+    index_key(&value)
+}
+
+/// Position of the term an index's key expression answers, if the index is usable for `terms`:
+/// a partial index is only usable when every predicate term is also in `terms`.
+fn key_term_position(meta: &IndexMeta, terms: &[EqualityTerm]) -> Option<usize> {
+    let implied = meta.predicate.iter().all(|(c, v)| {
+        terms
+            .iter()
+            .any(|t| t.column_idx == *c && t.key_expr == KeyExpr::Column && t.value == *v)
+    });
+    if !implied {
+        return None;
+    }
+    terms
+        .iter()
+        .position(|t| t.column_idx == meta.column_idx && t.key_expr == meta.key_expr)
+}
+
+/// Columns of the `system_indexes` rows written before INCLUDE columns, hash indexes and
+/// expression indexes, which describe a plain B+ tree index on a column.
+const BASE_INDEX_COLUMNS: usize = 5;
+
+/// Reads a `system_indexes` row into the index oid, name and metadata. A row this build
+/// does not understand is an error rather than an index that silently goes missing.
+fn decode_index_row(bytes: &[u8]) -> Result<(u32, String, IndexMeta), String> {
+    let mut schema = get_system_indexes_schema();
+    let t = match Tuple::from_bytes(bytes, &schema) {
+        Ok(t) => t,
+        Err(_) => {
+            schema.attributes.truncate(BASE_INDEX_COLUMNS);
+            Tuple::from_bytes(bytes, &schema)?
+        }
+    };
+    let malformed = || format!("Unreadable system_indexes row: {:?}", t.values);
+
+    let (base, rest) = t.values.split_at(BASE_INDEX_COLUMNS.min(t.values.len()));
+    let [
+        AttributeValue::U32(oid),
+        AttributeValue::Varchar(name),
+        AttributeValue::U32(table_oid),
+        AttributeValue::U8(column_idx),
+        AttributeValue::U32(root_page_id),
+    ] = base
+    else {
+        return Err(malformed());
+    };
+    let mut meta = IndexMeta {
+        table_oid: *table_oid,
+        column_idx: *column_idx as usize,
+        root_page_id: *root_page_id,
+        include_cols: Vec::new(),
+        method: IndexMethod::BTree,
+        key_expr: KeyExpr::Column,
+        predicate: Vec::new(),
+    };
+    match rest {
+        [] => {}
+        [
+            AttributeValue::Varchar(include),
+            AttributeValue::U8(method),
+            AttributeValue::U8(key_expr),
+            AttributeValue::Varchar(predicate),
+        ] => {
+            meta.include_cols = include
+                .split(',')
+                .filter(|c| !c.is_empty())
+                .map(|c| c.parse().map_err(|_| malformed()))
+                .collect::<Result<_, _>>()?;
+            meta.method = IndexMethod::from_u8(*method).ok_or_else(malformed)?;
+            meta.key_expr = KeyExpr::from_u8(*key_expr).ok_or_else(malformed)?;
+            meta.predicate = decode_predicate(predicate).ok_or_else(malformed)?;
+        }
+        _ => return Err(malformed()),
+    }
+    Ok((*oid, name.clone(), meta))
+}
+
+/// Whether `value` can be stored in a column of type `kind`.
+fn value_has_kind(value: &AttributeValue, kind: AttributeKind) -> bool {
+    matches!(
+        (kind, value),
+        (AttributeKind::U8, AttributeValue::U8(_))
+            | (AttributeKind::U16, AttributeValue::U16(_))
+            | (AttributeKind::U32, AttributeValue::U32(_))
+            | (AttributeKind::U64, AttributeValue::U64(_))
+            | (AttributeKind::U128, AttributeValue::U128(_))
+            | (AttributeKind::I8, AttributeValue::I8(_))
+            | (AttributeKind::I16, AttributeValue::I16(_))
+            | (AttributeKind::I32, AttributeValue::I32(_))
+            | (AttributeKind::I64, AttributeValue::I64(_))
+            | (AttributeKind::I128, AttributeValue::I128(_))
+            | (AttributeKind::F32, AttributeValue::F32(_))
+            | (AttributeKind::F64, AttributeValue::F64(_))
+            | (AttributeKind::Bool, AttributeValue::Bool(_))
+            | (AttributeKind::Char(_), AttributeValue::Char(_))
+            | (AttributeKind::Varchar, AttributeValue::Varchar(_))
+    )
+}
+
+/// Serializes a partial index predicate for `system_indexes` as comma separated
+/// `col=<tag><value>` terms. The tag is one letter per type (`u` for U32, `s` for
+/// Varchar, see `decode_predicate`); strings are written as hex bytes so they need no
+/// escaping and numbers in decimal.
+fn encode_predicate(predicate: &[(usize, AttributeValue)]) -> String {
+    let hex = |s: &str| s.bytes().map(|b| format!("{:02x}", b)).collect::<String>();
+    predicate
+        .iter()
+        .map(|(c, v)| {
+            let value = match v {
+                AttributeValue::U8(n) => format!("b{}", n),
+                AttributeValue::U16(n) => format!("w{}", n),
+                AttributeValue::U32(n) => format!("u{}", n),
+                AttributeValue::U64(n) => format!("l{}", n),
+                AttributeValue::U128(n) => format!("q{}", n),
+                AttributeValue::I8(n) => format!("B{}", n),
+                AttributeValue::I16(n) => format!("W{}", n),
+                AttributeValue::I32(n) => format!("i{}", n),
+                AttributeValue::I64(n) => format!("L{}", n),
+                AttributeValue::I128(n) => format!("Q{}", n),
+                // Bit patterns, so every value comes back exactly
+                AttributeValue::F32(n) => format!("f{}", n.to_bits()),
+                AttributeValue::F64(n) => format!("d{}", n.to_bits()),
+                AttributeValue::Bool(b) => format!("t{}", *b as u8),
+                AttributeValue::Char(s) => format!("c{}", hex(s)),
+                AttributeValue::Varchar(s) => format!("s{}", hex(s)),
+            };
+            format!("{}={}", c, value)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn decode_predicate(encoded: &str) -> Option<Vec<(usize, AttributeValue)>> {
+    encoded
+        .split(',')
+        .filter(|t| !t.is_empty())
+        .map(|term| {
+            let (col, value) = term.split_once('=')?;
+            let col = col.parse().ok()?;
+            let string = |hex: &str| {
+                let bytes = (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+                    .collect::<Option<Vec<u8>>>()?;
+                String::from_utf8(bytes).ok()
+            };
+            let value = match value.split_at_checked(1)? {
+                ("b", n) => AttributeValue::U8(n.parse().ok()?),
+                ("w", n) => AttributeValue::U16(n.parse().ok()?),
+                ("u", n) => AttributeValue::U32(n.parse().ok()?),
+                ("l", n) => AttributeValue::U64(n.parse().ok()?),
+                ("q", n) => AttributeValue::U128(n.parse().ok()?),
+                ("B", n) => AttributeValue::I8(n.parse().ok()?),
+                ("W", n) => AttributeValue::I16(n.parse().ok()?),
+                ("i", n) => AttributeValue::I32(n.parse().ok()?),
+                ("L", n) => AttributeValue::I64(n.parse().ok()?),
+                ("Q", n) => AttributeValue::I128(n.parse().ok()?),
+                ("f", n) => AttributeValue::F32(f32::from_bits(n.parse().ok()?)),
+                ("d", n) => AttributeValue::F64(f64::from_bits(n.parse().ok()?)),
+                ("t", "0") => AttributeValue::Bool(false),
+                ("t", "1") => AttributeValue::Bool(true),
+                ("c", hex) => AttributeValue::Char(string(hex)?),
+                ("s", hex) => AttributeValue::Varchar(string(hex)?),
+                _ => return None,
+            };
+            Some((col, value))
+        })
+        .collect()
+}
+
+/// Only fixed-width columns can be stored in leaf entries.
+fn is_fixed_width(kind: AttributeKind) -> bool {
+    !matches!(
+        kind,
+        AttributeKind::Varchar | AttributeKind::U128 | AttributeKind::I128 | AttributeKind::F32
+    )
+}
+
+/// Serializes the INCLUDE columns of `tuple` into the payload stored next to its key.
+fn index_payload(
+    tuple: &Tuple,
+    include_cols: &[usize],
+    schema: &TableType,
+) -> Result<Vec<u8>, String> {
+    let sub_schema = TableType {
+        attributes: include_cols
+            .iter()
+            .map(|&c| schema.attributes[c].clone())
+            .collect(),
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let values = include_cols
+        .iter()
+        .map(|&c| tuple.values[c].clone())
+        .collect();
+    Tuple::new(values).to_bytes(&sub_schema)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::catalog::manager::Catalog;
-    use crate::rt_type::primitives::{AttributeKind, TableAttribute, TableLayout, TableType};
-    use crate::storage::buffer::BufferPool;
+    use crate::catalog::manager::{Catalog, IndexMethod, KeyExpr};
+    use crate::catalog::schema::get_system_indexes_schema;
+    use crate::rt_type::primitives::{
+        AttributeKind, AttributeValue, TableAttribute, TableLayout, TableType,
+    };
     use crate::storage::buffer::fifo_evictor::FifoEvictor;
-    use crate::storage::disk::FileManager;
+    use crate::storage::buffer::{AccessStrategy, BufferPool};
+    use crate::storage::disk::{self, FileManager};
+    use crate::storage::heap::iterator::HeapIterator;
+    use crate::storage::heap::tuple::Tuple;
     use crate::storage::page_locator::locator::DirectoryPageLocator;
     use std::fs;
+    use std::pin::Pin;
     use std::sync::{Arc, Mutex};
 
     #[test]
@@ -798,11 +2227,11 @@ mod tests {
         let db_file = "test_db/test_catalog_persist.db";
         // Ensure directory exists
         let _ = fs::create_dir_all("test_db");
-        let _ = fs::remove_file(db_file);
+        let _ = FileManager::remove_database(db_file);
 
         let fm = FileManager::new(db_file.to_string()).unwrap();
         let bp = Arc::new(Mutex::new(BufferPool::new(
-            fm,
+            Box::new(fm),
             Box::new(FifoEvictor::new()),
             Box::new(DirectoryPageLocator::new()),
         )));
@@ -829,19 +2258,58 @@ mod tests {
         assert!(oid >= 100);
         assert_eq!(catalog.get_table_oid("users"), Some(oid));
 
-        fs::remove_file(db_file).unwrap();
+        FileManager::remove_database(db_file).unwrap();
+    }
+
+    #[test]
+    fn test_in_memory_database() {
+        let (bp, mut catalog) = Catalog::open_in_memory();
+        let schema = TableType {
+            attributes: vec![TableAttribute {
+                name: "n".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            }],
+            layout: TableLayout {
+                size: 0,
+                attr_layouts: vec![],
+            },
+        };
+        let oid = catalog.create_table("numbers", schema.clone()).unwrap();
+
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for i in 0..2000 {
+            let row = Tuple::new(vec![AttributeValue::U32(i)]);
+            catalog
+                .insert_tuple(oid, &row, &schema, pinned_bp.as_mut())
+                .unwrap();
+        }
+        // Flushing and checkpointing work without a file behind the pool
+        pinned_bp.as_mut().checkpoint().unwrap();
+        assert!(pinned_bp.last_checkpoint().unwrap().is_none());
+
+        let root = catalog.get_table_root_page(oid).unwrap();
+        let mut iter = HeapIterator::new(pinned_bp.as_mut(), root);
+        let mut count = 0;
+        while let Some(Ok(_)) = iter.next() {
+            count += 1;
+        }
+        assert_eq!(count, 2000);
+        assert!(!std::path::Path::new(disk::MEMORY_PATH).exists());
     }
 
     #[test]
     fn test_catalog_bootstrap() {
         let db_file = "test_db/test_bootstrap.db";
         let _ = fs::create_dir_all("test_db");
-        let _ = fs::remove_file(db_file);
+        let _ = FileManager::remove_database(db_file);
 
         {
             let fm = FileManager::new(db_file.to_string()).unwrap();
             let bp = Arc::new(Mutex::new(BufferPool::new(
-                fm,
+                Box::new(fm),
                 Box::new(FifoEvictor::new()),
                 Box::new(DirectoryPageLocator::new()),
             )));
@@ -870,7 +2338,7 @@ mod tests {
         {
             let fm = FileManager::new(db_file.to_string()).unwrap();
             let bp = Arc::new(Mutex::new(BufferPool::new(
-                fm,
+                Box::new(fm),
                 Box::new(FifoEvictor::new()),
                 Box::new(DirectoryPageLocator::new()),
             )));
@@ -883,7 +2351,7 @@ mod tests {
             );
         }
 
-        let _ = fs::remove_file(db_file);
+        let _ = FileManager::remove_database(db_file);
         // FIX: Ignore error if directory not empty (used by other tests)
         let _ = fs::remove_dir("test_db");
     }
@@ -891,11 +2359,11 @@ mod tests {
     fn test_create_index_metadata() {
         let db_file = "test_db/test_index_meta.db";
         let _ = fs::create_dir_all("test_db");
-        let _ = fs::remove_file(db_file);
+        let _ = FileManager::remove_database(db_file);
 
         let fm = FileManager::new(db_file.to_string()).unwrap();
         let bp = Arc::new(Mutex::new(BufferPool::new(
-            fm,
+            Box::new(fm),
             Box::new(FifoEvictor::new()),
             Box::new(DirectoryPageLocator::new()),
         )));
@@ -945,7 +2413,153 @@ mod tests {
             "Index metadata lost after restart"
         );
 
-        let _ = fs::remove_file(db_file);
+        let _ = FileManager::remove_database(db_file);
+        let _ = fs::remove_dir("test_db");
+    }
+
+    #[test]
+    fn test_create_index_backfills_and_drop_index() {
+        let db_file = "test_db/test_index_backfill.db";
+        let _ = fs::create_dir_all("test_db");
+        let _ = FileManager::remove_database(db_file);
+
+        let fm = FileManager::new(db_file.to_string()).unwrap();
+        let bp = Arc::new(Mutex::new(BufferPool::new(
+            Box::new(fm),
+            Box::new(FifoEvictor::new()),
+            Box::new(DirectoryPageLocator::new()),
+        )));
+        let mut catalog = Catalog::new(bp.clone());
+
+        let schema = TableType {
+            attributes: vec![TableAttribute {
+                name: "id".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            }],
+            layout: TableLayout {
+                size: 0,
+                attr_layouts: vec![],
+            },
+        };
+        let table_oid = catalog.create_table("items", schema.clone()).unwrap();
+
+        // 1. Rows exist before the index does
+        let n = 300u32;
+        {
+            let mut bp_guard = bp.lock().unwrap();
+            let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            for i in 0..n {
+                let row = Tuple::new(vec![AttributeValue::U32(i * 3)]);
+                catalog
+                    .insert_tuple(table_oid, &row, &schema, pinned_bp.as_mut())
+                    .unwrap();
+            }
+        }
+
+        // 2. Index picks them up
+        let idx_oid = catalog.create_index("idx_items_id", "items", "id").unwrap();
+        assert!(catalog.create_index("idx_items_id", "items", "id").is_err());
+        let root = catalog.get_index_meta(idx_oid).unwrap().root_page_id;
+        {
+            let mut bp_guard = bp.lock().unwrap();
+            let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            for i in 0..n {
+                let rids = catalog
+                    .index_lookup(idx_oid, &AttributeValue::U32(i * 3), pinned_bp.as_mut())
+                    .unwrap();
+                assert_eq!(rids.len(), 1, "Row {} missing from backfilled index", i * 3);
+            }
+            let rids = catalog
+                .index_lookup(idx_oid, &AttributeValue::U32(1), pinned_bp.as_mut())
+                .unwrap();
+            assert!(rids.is_empty());
+        }
+
+        // 3. Drop removes metadata and pages
+        catalog.drop_index("idx_items_id").unwrap();
+        assert_eq!(catalog.get_index_oid("idx_items_id"), None);
+        assert_eq!(catalog.find_index_for_column("items", "id"), None);
+        assert!(catalog.drop_index("idx_items_id").is_err());
+        {
+            let mut bp_guard = bp.lock().unwrap();
+            let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            assert!(pinned_bp.fetch_page(root, AccessStrategy::Normal).is_err());
+        }
+
+        drop(catalog);
+        let catalog_2 = Catalog::new(bp.clone());
+        assert_eq!(catalog_2.get_index_oid("idx_items_id"), None);
+
+        let _ = FileManager::remove_database(db_file);
         let _ = fs::remove_dir("test_db");
     }
+
+    #[test]
+    fn test_predicate_encoding_roundtrip() {
+        use super::{decode_predicate, encode_predicate};
+
+        let predicate = vec![
+            (2, AttributeValue::Varchar("open, =really".into())),
+            (0, AttributeValue::U32(7)),
+            (1, AttributeValue::U64(u64::MAX)),
+            (3, AttributeValue::I16(-3)),
+            (4, AttributeValue::Bool(true)),
+            (5, AttributeValue::F64(-0.1)),
+            (6, AttributeValue::Char("ab".into())),
+        ];
+        let encoded = encode_predicate(&predicate);
+        assert_eq!(decode_predicate(&encoded), Some(predicate));
+        assert_eq!(decode_predicate(""), Some(vec![]));
+        assert_eq!(decode_predicate("1=x5"), None);
+    }
+
+    #[test]
+    fn test_decode_index_rows_of_every_version() {
+        use super::{BASE_INDEX_COLUMNS, decode_index_row};
+
+        let mut values = vec![
+            AttributeValue::U32(120),
+            AttributeValue::Varchar("idx_old".into()),
+            AttributeValue::U32(100),
+            AttributeValue::U8(2),
+            AttributeValue::U32(57),
+            AttributeValue::Varchar("0,3".into()),
+            AttributeValue::U8(IndexMethod::Hash as u8),
+            AttributeValue::U8(KeyExpr::Lower as u8),
+            AttributeValue::Varchar(String::new()),
+        ];
+        let mut schema = get_system_indexes_schema();
+        let (oid, name, meta) =
+            decode_index_row(&Tuple::new(values.clone()).to_bytes(&schema).unwrap()).unwrap();
+        assert_eq!((oid, name.as_str()), (120, "idx_old"));
+        assert_eq!(meta.include_cols, vec![0, 3]);
+        assert_eq!(meta.method, IndexMethod::Hash);
+        assert_eq!(meta.key_expr, KeyExpr::Lower);
+
+        // Rows from before index options describe a B+ tree on the column itself
+        values.truncate(BASE_INDEX_COLUMNS);
+        schema.attributes.truncate(BASE_INDEX_COLUMNS);
+        let (oid, _, meta) =
+            decode_index_row(&Tuple::new(values.clone()).to_bytes(&schema).unwrap()).unwrap();
+        assert_eq!(oid, 120);
+        assert_eq!(
+            (meta.table_oid, meta.column_idx, meta.root_page_id),
+            (100, 2, 57)
+        );
+        assert_eq!(meta.method, IndexMethod::BTree);
+        assert_eq!(meta.key_expr, KeyExpr::Column);
+        assert!(meta.include_cols.is_empty() && meta.predicate.is_empty());
+
+        // An access method this build does not know is refused, not skipped
+        values.extend([
+            AttributeValue::Varchar(String::new()),
+            AttributeValue::U8(9),
+            AttributeValue::U8(0),
+            AttributeValue::Varchar(String::new()),
+        ]);
+        let schema = get_system_indexes_schema();
+        assert!(decode_index_row(&Tuple::new(values).to_bytes(&schema).unwrap()).is_err());
+    }
 }
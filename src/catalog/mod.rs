@@ -1,2 +1,4 @@
+pub mod autovacuum;
 pub mod manager;
 pub mod schema;
+pub mod stats;
@@ -2,6 +2,9 @@ use crate::rt_type::primitives::{AttributeKind, TableAttribute, TableLayout, Tab
 pub const SYSTEM_TABLES_ID: u32 = 1;
 pub const SYSTEM_COLUMNS_ID: u32 = 2;
 pub const SYSTEM_INDEXES_ID: u32 = 3;
+pub const SYSTEM_STATS_ID: u32 = 4;
+pub const SYSTEM_TABLESPACES_ID: u32 = 5;
+pub const SYSTEM_SEGMENTS_ID: u32 = 6;
 
 /// Defines the schema for "system_tables"
 /// Columns: [oid (U32), table_name (Varchar), root_page (U32)]
@@ -73,7 +76,8 @@ pub fn get_system_columns_schema() -> TableType {
 }
 
 /// Defines the schema for "system_indexes"
-/// Columns: [index_oid, index_name, table_oid, column_idx, root_page]
+/// Columns: [index_oid, index_name, table_oid, column_idx, root_page, include_cols, method,
+///           key_expr, predicate]
 pub fn get_system_indexes_schema() -> TableType {
     TableType {
         attributes: vec![
@@ -102,13 +106,137 @@ pub fn get_system_indexes_schema() -> TableType {
                 nullable: false,
                 is_internal: true,
             },
-            // The root page ID of the B+ Tree
+            // The root page ID of the B+ Tree, or the directory page of a hash index
             TableAttribute {
                 name: "root_page".to_string(),
                 kind: AttributeKind::U32,
                 nullable: false,
                 is_internal: true,
             },
+            // Comma separated column indices stored in the leaves (INCLUDE), empty if none
+            TableAttribute {
+                name: "include_cols".to_string(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: true,
+            },
+            // Access method: 0 = B+ Tree, 1 = Hash
+            TableAttribute {
+                name: "method".to_string(),
+                kind: AttributeKind::U8,
+                nullable: false,
+                is_internal: true,
+            },
+            // Expression applied to column_idx: 0 = the column itself, 1 = LOWER, 2 = UPPER
+            TableAttribute {
+                name: "key_expr".to_string(),
+                kind: AttributeKind::U8,
+                nullable: false,
+                is_internal: true,
+            },
+            // Partial index predicate (ANDed column equalities), empty if none
+            TableAttribute {
+                name: "predicate".to_string(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: true,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    }
+}
+
+/// Defines the schema for "system_stats"
+/// Columns: [table_oid (U32), live_tuples (U64), dead_tuples (U64)]
+/// Every column is fixed width, so a row can be rewritten in place.
+pub fn get_system_stats_schema() -> TableType {
+    TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "table_oid".to_string(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: true,
+            },
+            TableAttribute {
+                name: "live_tuples".to_string(),
+                kind: AttributeKind::U64,
+                nullable: false,
+                is_internal: true,
+            },
+            TableAttribute {
+                name: "dead_tuples".to_string(),
+                kind: AttributeKind::U64,
+                nullable: false,
+                is_internal: true,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    }
+}
+
+/// Defines the schema for "system_tablespaces"
+/// Columns: [oid (U32), name (Varchar), location (Varchar)]
+pub fn get_system_tablespaces_schema() -> TableType {
+    TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "oid".to_string(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: true,
+            },
+            TableAttribute {
+                name: "name".to_string(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: true,
+            },
+            TableAttribute {
+                name: "location".to_string(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: true,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    }
+}
+
+/// Defines the schema for "system_segments"
+/// Columns: [segment_oid (U32), tablespace_oid (U32), compression (U8)]
+/// Only segments placed in a tablespace or stored compressed have a row. A tablespace_oid of
+/// 0 is the directory of the database file.
+pub fn get_system_segments_schema() -> TableType {
+    TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "segment_oid".to_string(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: true,
+            },
+            TableAttribute {
+                name: "tablespace_oid".to_string(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: true,
+            },
+            TableAttribute {
+                name: "compression".to_string(),
+                kind: AttributeKind::U8,
+                nullable: false,
+                is_internal: true,
+            },
         ],
         layout: TableLayout {
             size: 0,
@@ -0,0 +1,203 @@
+use crate::catalog::schema::get_system_stats_schema;
+use crate::rt_type::primitives::AttributeValue;
+use crate::storage::buffer::BufferPool;
+use crate::storage::heap::heap_file::HeapFile;
+use crate::storage::heap::row::RowId;
+use crate::storage::heap::tuple::Tuple;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// Tuple counts of a table, kept up to date by the catalog as rows come and go.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TableStats {
+    pub live_tuples: u64,
+    /// Deleted tuples whose space no VACUUM has reclaimed yet
+    pub dead_tuples: u64,
+}
+
+impl TableStats {
+    /// Share of the table's tuples that are dead, 0 for an empty table.
+    pub fn dead_fraction(&self) -> f64 {
+        let total = self.live_tuples + self.dead_tuples;
+        if total == 0 {
+            0.0
+        } else {
+            self.dead_tuples as f64 / total as f64
+        }
+    }
+
+    pub(crate) fn to_tuple(self, table_oid: u32) -> Tuple {
+        Tuple::new(vec![
+            AttributeValue::U32(table_oid),
+            AttributeValue::U64(self.live_tuples),
+            AttributeValue::U64(self.dead_tuples),
+        ])
+    }
+}
+
+struct TrackedTable {
+    root_page_id: u32,
+    stats: TableStats,
+    row: RowId, // Where the counters live in system_stats
+    dirty: bool,
+    // Page the last row went to, so inserts need not walk the heap from its first page
+    insert_page: u32,
+}
+
+/// Per-table statistics, shared between the catalog and the autovacuum worker.
+/// Clones are handles to the same counters.
+#[derive(Clone, Default)]
+pub struct StatsRegistry {
+    tables: Arc<Mutex<HashMap<u32, TrackedTable>>>,
+}
+
+impl StatsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, table_oid: u32) -> Option<TableStats> {
+        let tables = self.tables.lock().expect("Lock poisoned");
+        tables.get(&table_oid).map(|t| t.stats)
+    }
+
+    /// Root page of the table's heap, or None once the table has been dropped.
+    pub fn root_page(&self, table_oid: u32) -> Option<u32> {
+        let tables = self.tables.lock().expect("Lock poisoned");
+        tables.get(&table_oid).map(|t| t.root_page_id)
+    }
+
+    pub(crate) fn track(&self, table_oid: u32, root_page_id: u32, stats: TableStats, row: RowId) {
+        let mut tables = self.tables.lock().expect("Lock poisoned");
+        tables.insert(
+            table_oid,
+            TrackedTable {
+                root_page_id,
+                stats,
+                row,
+                dirty: false,
+                insert_page: 0,
+            },
+        );
+    }
+
+    /// Stops tracking the table and returns its row in system_stats.
+    pub(crate) fn forget(&self, table_oid: u32) -> Option<RowId> {
+        let mut tables = self.tables.lock().expect("Lock poisoned");
+        tables.remove(&table_oid).map(|t| t.row)
+    }
+
+    /// Page of the table's heap to start looking for room at, 0 when unknown.
+    pub(crate) fn insert_page(&self, table_oid: u32) -> u32 {
+        let tables = self.tables.lock().expect("Lock poisoned");
+        tables.get(&table_oid).map_or(0, |t| t.insert_page)
+    }
+
+    /// Remembers the page a row of the table just went to. Pass 0 once a VACUUM may
+    /// have freed it.
+    pub(crate) fn set_insert_page(&self, table_oid: u32, page_id: u32) {
+        let mut tables = self.tables.lock().expect("Lock poisoned");
+        if let Some(table) = tables.get_mut(&table_oid) {
+            table.insert_page = page_id;
+        }
+    }
+
+    pub(crate) fn record_insert(&self, table_oid: u32) {
+        self.update(table_oid, |stats| stats.live_tuples += 1);
+    }
+
+    pub(crate) fn record_delete(&self, table_oid: u32) {
+        self.update(table_oid, |stats| {
+            stats.live_tuples = stats.live_tuples.saturating_sub(1);
+            stats.dead_tuples += 1;
+        });
+    }
+
+    /// An update that left the old version of a tuple behind as garbage.
+    pub(crate) fn record_relocation(&self, table_oid: u32) {
+        self.update(table_oid, |stats| stats.dead_tuples += 1);
+    }
+
+    /// Takes `reclaimed` dead tuples off the count once a VACUUM has compacted them away.
+    pub(crate) fn record_vacuum(&self, table_oid: u32, reclaimed: u64) {
+        self.update(table_oid, |stats| {
+            stats.dead_tuples = stats.dead_tuples.saturating_sub(reclaimed)
+        });
+    }
+
+    fn update(&self, table_oid: u32, f: impl FnOnce(&mut TableStats)) {
+        let mut tables = self.tables.lock().expect("Lock poisoned");
+        if let Some(table) = tables.get_mut(&table_oid) {
+            f(&mut table.stats);
+            table.dirty = true;
+        }
+    }
+
+    /// Tables with at least `min_dead` dead tuples that make up more than `fraction` of
+    /// the table, as (oid, root page, dead tuples).
+    pub fn needing_vacuum(&self, fraction: f64, min_dead: u64) -> Vec<(u32, u32, u64)> {
+        let tables = self.tables.lock().expect("Lock poisoned");
+        let mut due: Vec<(u32, u32, u64)> = tables
+            .iter()
+            .filter(|(_, t)| t.stats.dead_tuples >= min_dead && t.stats.dead_fraction() > fraction)
+            .map(|(oid, t)| (*oid, t.root_page_id, t.stats.dead_tuples))
+            .collect();
+        due.sort();
+        due
+    }
+
+    /// Writes the counters changed since the last call back to system_stats.
+    /// Returns how many rows were rewritten.
+    pub fn persist(&self, mut bpm: Pin<&mut BufferPool>) -> Result<usize, String> {
+        let schema = get_system_stats_schema();
+        let mut tables = self.tables.lock().map_err(|_| "Lock poisoned")?;
+        let mut written = 0;
+        for (oid, table) in tables.iter_mut().filter(|(_, t)| t.dirty) {
+            let bytes = table.stats.to_tuple(*oid).to_bytes(&schema)?;
+            HeapFile::overwrite(bpm.as_mut(), table.row, &bytes)
+                .map_err(|e| format!("Failed to save stats of table {}: {:?}", oid, e))?;
+            table.dirty = false;
+            written += 1;
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_and_vacuum_candidates() {
+        let registry = StatsRegistry::new();
+        registry.track(100, 101, TableStats::default(), RowId::new(4, 0));
+        registry.track(102, 103, TableStats::default(), RowId::new(4, 1));
+
+        for _ in 0..10 {
+            registry.record_insert(100);
+            registry.record_insert(102);
+        }
+        for _ in 0..6 {
+            registry.record_delete(100);
+        }
+        registry.record_delete(102);
+        // Untracked tables are ignored
+        registry.record_delete(7);
+
+        let stats = registry.get(100).unwrap();
+        assert_eq!(stats.live_tuples, 4);
+        assert_eq!(stats.dead_tuples, 6);
+        assert!((stats.dead_fraction() - 0.6).abs() < 1e-9);
+        assert_eq!(registry.needing_vacuum(0.2, 5), vec![(100, 101, 6)]);
+        assert!(registry.needing_vacuum(0.2, 7).is_empty());
+
+        registry.record_vacuum(100, 6);
+        assert_eq!(registry.get(100).unwrap().dead_tuples, 0);
+        assert!(registry.needing_vacuum(0.05, 1).iter().all(|t| t.0 == 102));
+
+        assert_eq!(registry.forget(102), Some(RowId::new(4, 1)));
+        assert_eq!(registry.root_page(102), None);
+        assert!(registry.get(7).is_none());
+    }
+}
@@ -50,7 +50,15 @@ pub fn display_query_result<'a>(
     let mut is_select = false;
     let mut header_set = false;
 
-    while let Some(tuple) = plan.next(pinned_bp.as_mut()) {
+    loop {
+        let tuple = match plan.next(pinned_bp.as_mut()) {
+            Ok(Some(tuple)) => tuple,
+            Ok(None) => break,
+            Err(e) => {
+                println!("\x1B[1;31mError:\x1B[0m {}", e);
+                return;
+            }
+        };
         if row_count == 0 {
             // Check if this is a SELECT or a DML operation (which returns count)
             if let AstStatement::Select {
@@ -29,15 +29,15 @@ impl<'a> Executor for DeleteExecutor<'a> {
         self.executed = false;
     }
 
-    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Option<Tuple> {
+    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String> {
         // Added bpm
         if self.executed {
-            return None;
+            return Ok(None);
         }
 
         let mut count = 0;
 
-        while let Some(tuple) = self.child.next(bpm.as_mut()) {
+        while let Some(tuple) = self.child.next(bpm.as_mut())? {
             // Pass bpm to child
             if let Some(rid) = tuple.rid {
                 if self
@@ -51,6 +51,6 @@ impl<'a> Executor for DeleteExecutor<'a> {
         }
 
         self.executed = true;
-        Some(Tuple::new(vec![AttributeValue::U32(count)]))
+        Ok(Some(Tuple::new(vec![AttributeValue::U32(count)])))
     }
 }
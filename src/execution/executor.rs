@@ -5,7 +5,8 @@ use std::pin::Pin;
 pub trait Executor {
     /// init prepares the executor for execution.
     fn init(&mut self);
-    /// next returns the next tuple from the executor.
+    /// next returns the next tuple from the executor, or None once there are no more.
     /// It takes the BufferPool as an argument, which it passes to its children.
-    fn next(&mut self, bpm: Pin<&mut BufferPool>) -> Option<Tuple>;
+    /// An error ends the query; it is passed up through every parent.
+    fn next(&mut self, bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String>;
 }
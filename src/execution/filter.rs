@@ -30,14 +30,14 @@ where
         self.child.init();
     }
 
-    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Option<Tuple> {
+    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String> {
         // Pull from child until we find a match or run out
-        while let Some(tuple) = self.child.next(bpm.as_mut()) {
+        while let Some(tuple) = self.child.next(bpm.as_mut())? {
             // Pass bpm
             if (self.predicate)(&tuple) {
-                return Some(tuple);
+                return Ok(Some(tuple));
             }
         }
-        None
+        Ok(None)
     }
 }
@@ -0,0 +1,131 @@
+use super::executor::Executor;
+use crate::catalog::manager::{Catalog, ROW_ID_KEY_SIZE, btree_entry_key};
+use crate::rt_type::primitives::{TableLayout, TableType};
+use crate::storage::bplus_tree::BPlusTree;
+use crate::storage::buffer::BufferPool;
+use crate::storage::heap::iterator::BTreeIterator;
+use crate::storage::heap::row::RowId;
+use crate::storage::heap::tuple::Tuple;
+use crate::storage::page::base::PageId;
+use std::pin::Pin;
+
+/// Answers a query straight from the leaves of a covering index.
+/// Each leaf entry holds the key and the INCLUDE columns, so the heap is never read.
+pub struct IndexOnlyScanExecutor<'a> {
+    catalog: &'a Catalog,
+    index_oid: u32,
+    key: Option<Vec<u8>>, // Point lookup of this value key if set, full index scan otherwise
+    covered_schema: TableType,
+    output: Vec<usize>, // Positions in the covered row (key, include cols...) to emit
+    position: Option<(PageId, u16)>,
+    done: bool,
+}
+
+impl<'a> IndexOnlyScanExecutor<'a> {
+    /// `columns` are table column indices, emitted in that order. All of them must be covered
+    /// by the index key or its INCLUDE columns.
+    pub fn new(
+        catalog: &'a Catalog,
+        index_oid: u32,
+        key: Option<Vec<u8>>,
+        columns: &[usize],
+    ) -> Result<Self, String> {
+        let meta = catalog.get_index_meta(index_oid).ok_or("Index not found")?;
+        let schema = catalog
+            .get_table_schema(meta.table_oid)
+            .ok_or("Table schema missing")?;
+
+        // The key bytes followed by the payload bytes decode as one tuple of this shape
+        let covered: Vec<usize> = std::iter::once(meta.column_idx)
+            .chain(meta.include_cols.iter().copied())
+            .collect();
+        let covered_schema = TableType {
+            attributes: covered
+                .iter()
+                .map(|&c| schema.attributes[c].clone())
+                .collect(),
+            layout: TableLayout {
+                size: 0,
+                attr_layouts: vec![],
+            },
+        };
+
+        let output = columns
+            .iter()
+            .map(|c| {
+                covered
+                    .iter()
+                    .position(|x| x == c)
+                    .ok_or(format!("Column {} is not covered by the index", c))
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self {
+            catalog,
+            index_oid,
+            key,
+            covered_schema,
+            output,
+            position: None,
+            done: false,
+        })
+    }
+}
+
+impl<'a> Executor for IndexOnlyScanExecutor<'a> {
+    fn init(&mut self) {
+        self.position = None;
+        self.done = false;
+    }
+
+    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let mut iter = match self.position {
+            Some(pos) => BTreeIterator::resume(bpm.as_mut(), pos),
+            None => {
+                let meta = self
+                    .catalog
+                    .get_index_meta(self.index_oid)
+                    .ok_or("Index not found")?;
+                let tree = BPlusTree::new(bpm.as_mut(), meta.root_page_id);
+                let start = self.key.as_ref().map(|key| btree_entry_key(key, 0));
+                BTreeIterator::new(tree, start.as_deref())
+            }
+        };
+
+        let entry = iter.next_with_payload();
+        self.position = Some(iter.position());
+
+        let (mut key, rid, payload) = match entry {
+            Some(e) => e,
+            None => {
+                self.done = true;
+                return Ok(None);
+            }
+        };
+
+        // Every row with the value is an entry of its own, and they are next to each other
+        if let Some(target) = &self.key
+            && !key.starts_with(target)
+        {
+            self.done = true;
+            return Ok(None);
+        }
+
+        // The key ends with the RowId, which is not a column
+        key.truncate(key.len() - ROW_ID_KEY_SIZE);
+        let mut bytes = key;
+        bytes.extend_from_slice(&payload);
+        let covered = Tuple::from_bytes(&bytes, &self.covered_schema)?;
+
+        let values = self
+            .output
+            .iter()
+            .map(|&i| covered.values[i].clone())
+            .collect();
+        Ok(Some(Tuple::new_with_rid(values, RowId::from_u64(rid))))
+    }
+}
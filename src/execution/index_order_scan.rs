@@ -0,0 +1,91 @@
+use super::executor::Executor;
+use crate::catalog::manager::Catalog;
+use crate::rt_type::primitives::TableType;
+use crate::storage::bplus_tree::BPlusTree;
+use crate::storage::buffer::BufferPool;
+use crate::storage::heap::heap_file::HeapFile;
+use crate::storage::heap::iterator::BTreeIterator;
+use crate::storage::heap::row::RowId;
+use crate::storage::heap::tuple::Tuple;
+use crate::storage::page::base::PageId;
+use std::pin::Pin;
+
+/// Reads every row of a table in the key order of a B+ tree index, ascending or descending.
+/// Answers `ORDER BY` without sorting, and `LIMIT` on top of it stops after a few leaves.
+pub struct IndexOrderScanExecutor<'a> {
+    catalog: &'a Catalog,
+    index_oid: u32,
+    descending: bool,
+    schema: TableType,
+    position: Option<(PageId, u16)>,
+    done: bool,
+}
+
+impl<'a> IndexOrderScanExecutor<'a> {
+    pub fn new(catalog: &'a Catalog, index_oid: u32, descending: bool) -> Result<Self, String> {
+        let meta = catalog.get_index_meta(index_oid).ok_or("Index not found")?;
+        let schema = catalog
+            .get_table_schema(meta.table_oid)
+            .ok_or("Table schema missing")?;
+
+        Ok(Self {
+            catalog,
+            index_oid,
+            descending,
+            schema,
+            position: None,
+            done: false,
+        })
+    }
+}
+
+impl<'a> Executor for IndexOrderScanExecutor<'a> {
+    fn init(&mut self) {
+        self.position = None;
+        self.done = false;
+    }
+
+    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String> {
+        while !self.done {
+            let entry = {
+                let mut iter = match self.position {
+                    Some(pos) => BTreeIterator::resume(bpm.as_mut(), pos),
+                    None => {
+                        let meta = self
+                            .catalog
+                            .get_index_meta(self.index_oid)
+                            .ok_or("Index not found")?;
+                        let tree = BPlusTree::new(bpm.as_mut(), meta.root_page_id);
+                        if self.descending {
+                            BTreeIterator::last(tree)
+                        } else {
+                            BTreeIterator::new(tree, None)
+                        }
+                    }
+                };
+                let entry = if self.descending {
+                    iter.prev()
+                } else {
+                    iter.next()
+                };
+                self.position = Some(iter.position());
+                entry
+            };
+
+            let Some((_, rid_val)) = entry else {
+                self.done = true;
+                return Ok(None);
+            };
+
+            // Skip entries whose row cannot be read rather than ending the scan early
+            let rid = RowId::from_u64(rid_val);
+            if let Ok(bytes) = HeapFile::get(bpm.as_mut(), rid)
+                && let Ok(mut tuple) = Tuple::from_bytes(&bytes, &self.schema)
+            {
+                tuple.rid = Some(rid);
+                return Ok(Some(tuple));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -1,23 +1,30 @@
 use super::executor::Executor;
 use crate::catalog::manager::Catalog;
-use crate::rt_type::primitives::TableType;
-use crate::storage::bplus_tree::BPlusTree;
+use crate::rt_type::primitives::{AttributeValue, TableType};
 use crate::storage::buffer::BufferPool;
 use crate::storage::heap::heap_file::HeapFile;
 use crate::storage::heap::row::RowId;
 use crate::storage::heap::tuple::Tuple;
 use std::pin::Pin;
 
+/// Returns the rows whose indexed value (the key expression applied to the key column)
+/// equals `value`. Every row the index points at is checked against the value itself, so
+/// a string key whose hash matches is never taken for a match.
 pub struct IndexScanExecutor<'a> {
     catalog: &'a Catalog,
     index_oid: u32,
-    key: Vec<u8>,
+    value: AttributeValue,
     schema: TableType,
-    done: bool,
+    // Looked up on the first call, so rows changed through this scan are not seen again
+    rids: Option<std::vec::IntoIter<RowId>>,
 }
 
 impl<'a> IndexScanExecutor<'a> {
-    pub fn new(catalog: &'a Catalog, index_oid: u32, key: Vec<u8>) -> Result<Self, String> {
+    pub fn new(
+        catalog: &'a Catalog,
+        index_oid: u32,
+        value: AttributeValue,
+    ) -> Result<Self, String> {
         let idx_meta = catalog.get_index_meta(index_oid).ok_or("Index not found")?;
         let schema = catalog
             .get_table_schema(idx_meta.table_oid)
@@ -26,51 +33,49 @@ impl<'a> IndexScanExecutor<'a> {
         Ok(Self {
             catalog,
             index_oid,
-            key,
+            value,
             schema,
-            done: false,
+            rids: None,
         })
     }
 }
 
 impl<'a> Executor for IndexScanExecutor<'a> {
     fn init(&mut self) {
-        self.done = false;
+        self.rids = None;
     }
 
-    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Option<Tuple> {
-        // Added bpm
-        if self.done {
-            return None;
+    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String> {
+        let meta = self
+            .catalog
+            .get_index_meta(self.index_oid)
+            .ok_or("Index not found")?;
+        if self.rids.is_none() {
+            let rids = self
+                .catalog
+                .index_lookup(self.index_oid, &self.value, bpm.as_mut())?;
+            self.rids = Some(rids.into_iter());
         }
-        self.done = true;
-
-        let meta = self.catalog.get_index_meta(self.index_oid)?;
-        // Use passed-in bpm
-
-        // 1. Look up RowId in B+ Tree
-        let rid_val = {
-            let mut tree = BPlusTree::new(bpm.as_mut(), meta.root_page_id);
-            match tree.get_value(&self.key) {
-                Ok(Some(v)) => v,
-                _ => return None, // Key not found or error
-            }
+        let Some(rids) = self.rids.as_mut() else {
+            return Ok(None);
         };
 
-        let rid = RowId::from_u64(rid_val);
-
-        // 2. Fetch Tuple from Heap
-
-        match HeapFile::get(bpm.as_mut(), rid) {
-            Ok(bytes) => {
-                if let Ok(mut tuple) = Tuple::from_bytes(&bytes, &self.schema) {
-                    tuple.rid = Some(rid);
-                    return Some(tuple);
-                }
+        for rid in rids {
+            let Ok(bytes) = HeapFile::get(bpm.as_mut(), rid) else {
+                continue;
+            };
+            let Ok(mut tuple) = Tuple::from_bytes(&bytes, &self.schema) else {
+                continue;
+            };
+            let value = tuple
+                .values
+                .get(meta.column_idx)
+                .and_then(|v| meta.key_expr.apply(v));
+            if value.as_ref() == Some(&self.value) {
+                tuple.rid = Some(rid);
+                return Ok(Some(tuple));
             }
-            Err(_) => return None,
         }
-
-        None
+        Ok(None)
     }
 }
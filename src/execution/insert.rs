@@ -1,16 +1,21 @@
 use super::executor::Executor;
 use crate::catalog::manager::Catalog;
 use crate::rt_type::primitives::{AttributeValue, TableType};
-use crate::storage::buffer::BufferPool;
+use crate::storage::buffer::{AccessStrategy, BufferPool, BufferRing};
 use crate::storage::heap::tuple::Tuple;
 use std::pin::Pin;
 
+/// Rows after which an insert counts as a bulk load and moves its heap pages to a ring.
+/// Below it the pages written are likely to be read again soon.
+const BULK_INSERT_ROWS: u32 = 64;
+
 pub struct InsertExecutor<'a> {
     child: Box<dyn Executor + 'a>,
     catalog: &'a Catalog,
     table_oid: u32,
     schema: TableType,
     executed: bool,
+    ring: BufferRing,
 }
 
 impl<'a> InsertExecutor<'a> {
@@ -29,6 +34,7 @@ impl<'a> InsertExecutor<'a> {
             table_oid,
             schema,
             executed: false,
+            ring: BufferRing::default(),
         })
     }
 }
@@ -39,29 +45,28 @@ impl<'a> Executor for InsertExecutor<'a> {
         self.executed = false;
     }
 
-    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Option<Tuple> {
+    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String> {
         // Added bpm
         if self.executed {
-            return None;
+            return Ok(None);
         }
 
         let mut count = 0;
-        while let Some(tuple) = self.child.next(bpm.as_mut()) {
-            // Pass bpm
-            // Panic on error for now (Prototype phase)
-            if let Err(e) =
-                self.catalog
-                    .insert_tuple(self.table_oid, &tuple, &self.schema, bpm.as_mut())
-            // Pass bpm
-            {
-                panic!("Insert failed: {}", e);
-            }
+        while let Some(tuple) = self.child.next(bpm.as_mut())? {
+            let strategy = if count < BULK_INSERT_ROWS {
+                AccessStrategy::Normal
+            } else {
+                AccessStrategy::Ring(&mut self.ring)
+            };
+            self.catalog
+                .insert_tuple_with(self.table_oid, &tuple, &self.schema, bpm.as_mut(), strategy)
+                .map_err(|e| format!("Insert failed: {}", e))?;
             count += 1;
         }
 
         self.executed = true;
         // Return the number of inserted rows as a single tuple
-        Some(Tuple::new(vec![AttributeValue::U32(count)]))
+        Ok(Some(Tuple::new(vec![AttributeValue::U32(count)])))
     }
 }
 
@@ -75,27 +80,12 @@ mod tests {
     use crate::rt_type::primitives::{
         AttributeKind, AttributeValue, TableAttribute, TableLayout, TableType,
     };
-    use crate::storage::buffer::BufferPool;
-    use crate::storage::buffer::fifo_evictor::FifoEvictor;
-    use crate::storage::disk::FileManager;
     use crate::storage::heap::tuple::Tuple;
-    use crate::storage::page_locator::locator::DirectoryPageLocator;
-    use std::fs;
     use std::pin::Pin;
-    use std::sync::{Arc, Mutex};
 
     #[test]
     fn test_insert_execution() {
-        let db_file = "test_insert_exec.db";
-        let _ = fs::remove_file(db_file);
-
-        let fm = FileManager::new(db_file.to_string()).unwrap();
-        let bp = Arc::new(Mutex::new(BufferPool::new(
-            fm,
-            Box::new(FifoEvictor::new()),
-            Box::new(DirectoryPageLocator::new()),
-        )));
-        let mut catalog = Catalog::new(bp.clone());
+        let (bp, mut catalog) = Catalog::open_in_memory();
 
         // 1. Create Table "items"
         let schema = TableType {
@@ -128,6 +118,7 @@ mod tests {
 
         let result = insert_exec
             .next(pinned_bp.as_mut())
+            .unwrap()
             .expect("Should return count"); // Pass bpm
 
         // Verify count = 3
@@ -143,13 +134,11 @@ mod tests {
         scan_exec.init();
 
         let mut fetched_count = 0;
-        while let Some(tuple) = scan_exec.next(pinned_bp.as_mut()) {
+        while let Some(tuple) = scan_exec.next(pinned_bp.as_mut()).unwrap() {
             // Pass bpm
             println!("Scanned: {:?}", tuple);
             fetched_count += 1;
         }
         assert_eq!(fetched_count, 3, "SeqScan should find 3 rows");
-
-        fs::remove_file(db_file).unwrap();
     }
 }
@@ -0,0 +1,39 @@
+use super::executor::Executor;
+use crate::storage::buffer::BufferPool;
+use crate::storage::heap::tuple::Tuple;
+use std::pin::Pin;
+
+/// Passes through the first `limit` tuples of its child, then stops pulling from it.
+pub struct LimitExecutor<'a> {
+    child: Box<dyn Executor + 'a>,
+    limit: usize,
+    emitted: usize,
+}
+
+impl<'a> LimitExecutor<'a> {
+    pub fn new(child: Box<dyn Executor + 'a>, limit: usize) -> Self {
+        Self {
+            child,
+            limit,
+            emitted: 0,
+        }
+    }
+}
+
+impl<'a> Executor for LimitExecutor<'a> {
+    fn init(&mut self) {
+        self.child.init();
+        self.emitted = 0;
+    }
+
+    fn next(&mut self, bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String> {
+        if self.emitted >= self.limit {
+            return Ok(None);
+        }
+        let Some(tuple) = self.child.next(bpm)? else {
+            return Ok(None);
+        };
+        self.emitted += 1;
+        Ok(Some(tuple))
+    }
+}
@@ -1,8 +1,11 @@
 pub mod delete;
 pub mod executor;
 pub mod filter;
+pub mod index_only_scan;
+pub mod index_order_scan;
 pub mod index_scan;
 pub mod insert;
+pub mod limit;
 pub mod projection;
 pub mod seq_scan;
 pub mod update;
@@ -23,8 +23,8 @@ impl<'a> Executor for ProjectionExecutor<'a> {
         self.child.init();
     }
 
-    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Option<Tuple> {
-        if let Some(tuple) = self.child.next(bpm.as_mut()) {
+    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String> {
+        if let Some(tuple) = self.child.next(bpm.as_mut())? {
             let mut new_values = Vec::new();
 
             for &idx in &self.column_indices {
@@ -35,9 +35,9 @@ impl<'a> Executor for ProjectionExecutor<'a> {
                 }
             }
 
-            Some(Tuple::new(new_values))
+            Ok(Some(Tuple::new(new_values)))
         } else {
-            None
+            Ok(None)
         }
     }
 }
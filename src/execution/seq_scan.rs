@@ -1,11 +1,13 @@
 use super::executor::Executor;
 use crate::catalog::manager::Catalog;
 use crate::rt_type::primitives::TableType;
-use crate::storage::buffer::BufferPool;
+use crate::storage::buffer::{AccessStrategy, BufferPool, BufferRing};
+use crate::storage::heap::heap_file::HeapFile;
 use crate::storage::heap::row::RowId;
 use crate::storage::heap::tuple::Tuple;
 use crate::storage::page::base::DiskPage;
 use crate::storage::page::base::{Page, PageId};
+use crate::storage::page::slotted_data::SlotKind;
 use std::pin::Pin;
 
 pub struct SeqScanExecutor<'a> {
@@ -16,6 +18,8 @@ pub struct SeqScanExecutor<'a> {
     current_page_id: PageId,
     current_slot_index: u16,
     done: bool,
+    // A full scan reads through its own few frames instead of the whole pool
+    ring: BufferRing,
 }
 
 impl<'a> SeqScanExecutor<'a> {
@@ -31,6 +35,7 @@ impl<'a> SeqScanExecutor<'a> {
             current_page_id: 0, // Will be set in init
             current_slot_index: 0,
             done: false,
+            ring: BufferRing::default(),
         })
     }
 }
@@ -46,29 +51,32 @@ impl<'a> Executor for SeqScanExecutor<'a> {
         self.done = self.current_page_id == 0;
     }
 
-    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Option<Tuple> {
+    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String> {
         if self.done {
-            return None;
+            return Ok(None);
         }
 
         loop {
             // Loop structure from your old HeapIterator
             if self.current_page_id == 0 {
                 self.done = true;
-                return None;
+                return Ok(None);
             }
 
-            let frame_result = bpm.as_mut().fetch_page(self.current_page_id);
+            let frame_result = bpm
+                .as_mut()
+                .fetch_page(self.current_page_id, AccessStrategy::Ring(&mut self.ring));
             if frame_result.is_err() {
                 self.done = true;
-                return None;
+                return Ok(None);
             }
 
             let frame = frame_result.unwrap();
             let frame_id = frame.fid();
 
             let mut next_page_id_to_scan = 0;
-            let mut found_data: Option<(RowId, Vec<u8>)> = None;
+            // The data is None for a redirect, whose tuple lives on another page
+            let mut found_data: Option<(RowId, Option<Vec<u8>>)> = None;
 
             {
                 let mut page_view = frame.page_view();
@@ -80,11 +88,15 @@ impl<'a> Executor for SeqScanExecutor<'a> {
                         let idx = self.current_slot_index as usize;
                         self.current_slot_index += 1; // Advance for next call
 
-                        if let Some(data) = slotted.slot_data(idx) {
-                            let rid = RowId::new(self.current_page_id, idx as u32);
-                            found_data = Some((rid, data.to_vec()));
-                            break; // Found a tuple
-                        }
+                        let data = match slotted.slot_kind(idx) {
+                            Some(SlotKind::Tuple) => slotted.slot_data(idx).map(|d| d.to_vec()),
+                            Some(SlotKind::Redirect) => None,
+                            // Dead, or moved here and found through its redirect
+                            _ => continue,
+                        };
+                        let rid = RowId::new(self.current_page_id, idx as u32);
+                        found_data = Some((rid, data));
+                        break; // Found a tuple
                     }
 
                     if found_data.is_none() {
@@ -95,17 +107,24 @@ impl<'a> Executor for SeqScanExecutor<'a> {
                     // Invalid page type
                     bpm.as_mut().unpin_frame(frame_id).ok();
                     self.done = true;
-                    return None;
+                    return Ok(None);
                 }
             } // page_view borrow ends
 
             bpm.as_mut().unpin_frame(frame_id).ok();
 
             if let Some((rid, tuple_bytes)) = found_data {
+                let tuple_bytes = match tuple_bytes {
+                    Some(bytes) => bytes,
+                    None => match HeapFile::get(bpm.as_mut(), rid) {
+                        Ok(bytes) => bytes,
+                        Err(_) => continue,
+                    },
+                };
                 // Found data, deserialize and return it
                 if let Ok(mut tuple) = Tuple::from_bytes(&tuple_bytes, &self.schema) {
                     tuple.rid = Some(rid); // Attach RID
-                    return Some(tuple);
+                    return Ok(Some(tuple));
                 }
                 // if deserialize fails, continue loop
             } else if next_page_id_to_scan != 0 {
@@ -115,7 +134,7 @@ impl<'a> Executor for SeqScanExecutor<'a> {
             } else {
                 // No data and no next page
                 self.done = true;
-                return None;
+                return Ok(None);
             }
         }
     }
@@ -5,7 +5,7 @@ use crate::storage::buffer::BufferPool;
 use crate::storage::heap::tuple::Tuple;
 use std::pin::Pin;
 
-/// Executes an Update operation (Delete + Insert).
+/// Executes an Update operation. Rows are rewritten where they are and keep their RowId.
 /// `F` is a closure that takes the Old Tuple and returns the New Tuple.
 pub struct UpdateExecutor<'a, F>
 where
@@ -53,37 +53,36 @@ where
         self.executed = false;
     }
 
-    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Option<Tuple> {
+    fn next(&mut self, mut bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String> {
         if self.executed {
-            return None;
+            return Ok(None);
+        }
+
+        // Collect the targets first: an updated row can move in the index being scanned,
+        // and the scan would find it again
+        let mut targets = Vec::new();
+        while let Some(old_tuple) = self.child.next(bpm.as_mut())? {
+            targets.push(old_tuple);
         }
 
         let mut count = 0;
-        while let Some(old_tuple) = self.child.next(bpm.as_mut()) {
-            // Pass bpm
+        for old_tuple in targets {
             if let Some(rid) = old_tuple.rid {
                 // 1. Calculate New Tuple
                 let new_tuple = (self.update_fn)(&old_tuple);
 
-                // 2. Delete Old Tuple
+                // 2. Write it over the Old Tuple (Updates Heap + Changed Indexes)
                 if self
                     .catalog
-                    .delete_tuple(self.table_oid, rid, bpm.as_mut()) // Pass bpm
+                    .update_tuple(self.table_oid, rid, &new_tuple, &self.schema, bpm.as_mut())
                     .is_ok()
                 {
-                    // 3. Insert New Tuple (Updates Heap + All Indexes)
-                    if self
-                        .catalog
-                        .insert_tuple(self.table_oid, &new_tuple, &self.schema, bpm.as_mut()) // Pass bpm
-                        .is_ok()
-                    {
-                        count += 1;
-                    }
+                    count += 1;
                 }
             }
         }
 
         self.executed = true;
-        Some(Tuple::new(vec![AttributeValue::U32(count)]))
+        Ok(Some(Tuple::new(vec![AttributeValue::U32(count)])))
     }
 }
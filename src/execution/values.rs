@@ -20,13 +20,13 @@ impl Executor for ValuesExecutor {
         self.cursor = 0;
     }
 
-    fn next(&mut self, _bpm: Pin<&mut BufferPool>) -> Option<Tuple> {
+    fn next(&mut self, _bpm: Pin<&mut BufferPool>) -> Result<Option<Tuple>, String> {
         if self.cursor < self.tuples.len() {
             let tuple = self.tuples[self.cursor].clone();
             self.cursor += 1;
-            Some(tuple)
+            Ok(Some(tuple))
         } else {
-            None
+            Ok(None)
         }
     }
 }
@@ -1,10 +1,15 @@
-use nimbus::catalog::manager::Catalog;
+use nimbus::catalog::autovacuum::{Autovacuum, AutovacuumConfig};
+use nimbus::catalog::manager::{Catalog, IndexMethod, IndexOptions, KeyExpr, TableOptions};
 use nimbus::parser;
 use nimbus::planner::Planner;
-use nimbus::rt_type::primitives::{AttributeKind, TableAttribute, TableLayout, TableType};
+use nimbus::rt_type::primitives::{
+    AttributeKind, AttributeValue, TableAttribute, TableLayout, TableType,
+};
 use nimbus::storage::buffer::BufferPool;
-use nimbus::storage::buffer::fifo_evictor::FifoEvictor;
-use nimbus::storage::disk::FileManager;
+use nimbus::storage::buffer::EvictionPolicy;
+use nimbus::storage::buffer::buffer_pool::{DEFAULT_FRAME_COUNT, MAX_FRAME_COUNT, MIN_FRAME_COUNT};
+use nimbus::storage::buffer::{BackgroundWriter, WriterConfig, checkpoint_in_batches};
+use nimbus::storage::disk;
 use nimbus::storage::page_locator::locator::DirectoryPageLocator;
 use rustyline::DefaultEditor;
 use rustyline::error::ReadlineError;
@@ -23,7 +28,20 @@ fn main() {
     let _ = fs::create_dir_all("test_db");
     let mut current_db_path = format!("test_db/{}", default_db);
 
-    let (mut bp, mut catalog) = init_database(current_db_path.clone());
+    let mut options = pool_options_from_args();
+    let (mut bp, mut catalog) = match init_database(current_db_path.clone(), options) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("\x1B[1;31mError:\x1B[0m {}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut writer = BackgroundWriter::start(bp.clone(), WriterConfig::default());
+    let mut autovacuum = Autovacuum::start(
+        bp.clone(),
+        catalog.stats_registry(),
+        AutovacuumConfig::default(),
+    );
     let mut rl = DefaultEditor::new().unwrap();
 
     loop {
@@ -62,6 +80,11 @@ fn main() {
                     continue;
                 }
 
+                if let Some(index_name) = trimmed.strip_prefix(".verify ") {
+                    verify_index(&catalog, index_name.trim());
+                    continue;
+                }
+
                 if trimmed.is_empty() {
                     continue;
                 }
@@ -107,15 +130,74 @@ fn main() {
                     Err(e) => println!("\x1B[1;31mError:\x1B[0m {}", e),
                 }
             }
+            parser::AstStatement::DropIndex { index_name } => {
+                match catalog.drop_index(&index_name) {
+                    Ok(_) => println!(
+                        "\x1B[1;32mIndex '{}' dropped successfully\x1B[0m",
+                        index_name
+                    ),
+                    Err(e) => println!("\x1B[1;31mError:\x1B[0m {}", e),
+                }
+            }
+            parser::AstStatement::Checkpoint => {
+                match checkpoint_in_batches(&bp, WriterConfig::default().pages_per_round) {
+                    Ok(stats) => println!(
+                        "\x1B[1;32mCheckpoint {} complete: {} pages written, {} skipped\x1B[0m",
+                        stats.id, stats.pages_written, stats.pages_skipped
+                    ),
+                    Err(e) => println!("\x1B[1;31mError:\x1B[0m Checkpoint failed: {:?}", e),
+                }
+            }
+            parser::AstStatement::Vacuum { table_name } => {
+                match catalog.vacuum(table_name.as_deref()) {
+                    Ok(stats) => println!(
+                        "\x1B[1;32mVacuum complete: {} pages scanned, {} bytes reclaimed, \
+                         {} pages freed, {} truncated\x1B[0m",
+                        stats.pages_scanned,
+                        stats.bytes_reclaimed,
+                        stats.pages_freed,
+                        stats.pages_truncated
+                    ),
+                    Err(e) => println!("\x1B[1;31mError:\x1B[0m {}", e),
+                }
+            }
+            parser::AstStatement::Pragma { name, value } => {
+                match run_pragma(&name, value, &bp, &mut options) {
+                    Ok(msg) => println!("\x1B[1;32m{}\x1B[0m", msg),
+                    Err(e) => println!("\x1B[1;31mError:\x1B[0m {}", e),
+                }
+            }
             parser::AstStatement::UseDatabase { path } => {
-                match use_database(path.clone(), &mut bp, &mut catalog, &mut current_db_path) {
-                    Ok(_) => println!("\x1B[1;32mSwitched to database: {}\x1B[0m", path),
+                match use_database(
+                    path.clone(),
+                    options,
+                    &mut bp,
+                    &mut catalog,
+                    &mut current_db_path,
+                ) {
+                    Ok(_) => {
+                        writer = BackgroundWriter::start(bp.clone(), WriterConfig::default());
+                        autovacuum = Autovacuum::start(
+                            bp.clone(),
+                            catalog.stats_registry(),
+                            AutovacuumConfig::default(),
+                        );
+                        println!("\x1B[1;32mSwitched to database: {}\x1B[0m", path);
+                    }
+                    Err(e) => println!("\x1B[1;31mError:\x1B[0m {}", e),
+                }
+            }
+            parser::AstStatement::CreateTablespace { name, location } => {
+                match catalog.create_tablespace(&name, &location) {
+                    Ok(_) => println!("\x1B[1;32mTablespace '{}' created\x1B[0m", name),
                     Err(e) => println!("\x1B[1;31mError:\x1B[0m {}", e),
                 }
             }
             parser::AstStatement::CreateTable {
                 table_name,
                 columns,
+                tablespace,
+                compression,
             } => {
                 let mut attributes = Vec::new();
                 for (name, data_type) in columns {
@@ -139,7 +221,17 @@ fn main() {
                     },
                 };
 
-                match catalog.create_table(&table_name, schema) {
+                let options = match compression.map(|c| c.parse()).transpose() {
+                    Ok(compression) => TableOptions {
+                        tablespace,
+                        compression: compression.unwrap_or_default(),
+                    },
+                    Err(e) => {
+                        println!("\x1B[1;31mError:\x1B[0m {}", e);
+                        continue;
+                    }
+                };
+                match catalog.create_table_with(&table_name, schema, &options) {
                     Ok(_) => println!("\x1B[1;32mTable '{}' created\x1B[0m", table_name),
                     Err(e) => println!("\x1B[1;31mError:\x1B[0m {}", e),
                 }
@@ -147,40 +239,200 @@ fn main() {
             parser::AstStatement::CreateIndex {
                 index_name,
                 table_name,
-                column_name,
-            } => match catalog.create_index(&index_name, &table_name, &column_name) {
-                Ok(_) => println!(
-                    "\x1B[1;32mIndex '{}' created on {}.{}\x1B[0m",
-                    index_name, table_name, column_name
-                ),
-                Err(e) => println!("\x1B[1;31mError:\x1B[0m {}", e),
-            },
+                key,
+                include_columns,
+                using_hash,
+                predicate,
+                tablespace,
+            } => {
+                let result = index_options(key.clone(), include_columns, using_hash, predicate)
+                    .and_then(|(column_name, mut options)| {
+                        options.tablespace = tablespace;
+                        catalog.create_index_with(&index_name, &table_name, &column_name, &options)
+                    });
+                match result {
+                    Ok(_) => println!(
+                        "\x1B[1;32mIndex '{}' created on {}.{}\x1B[0m",
+                        index_name, table_name, key
+                    ),
+                    Err(e) => println!("\x1B[1;31mError:\x1B[0m {}", e),
+                }
+            }
             other => {
                 execute_dml_query(&catalog, &bp, other);
             }
         }
     }
 
+    drop(autovacuum);
+    drop(writer);
     println!("\x1B[1;34mFlushing data to disk...\x1B[0m");
+    if let Err(e) = catalog.save_stats() {
+        println!("\x1B[1;31mError:\x1B[0m {}", e);
+    }
     let mut bp_guard = bp.lock().unwrap();
     let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
     pinned_bp.flush_all().expect("Failed to flush all pages.");
     println!("\x1B[1;32mAll data flushed to {}.\x1B[0m", current_db_path);
 }
 
-fn init_database(path: String) -> (Arc<Mutex<BufferPool>>, Catalog) {
-    let fm = FileManager::new(path.clone()).unwrap();
-    let bp = Arc::new(Mutex::new(BufferPool::new(
-        fm,
-        Box::new(FifoEvictor::new()),
+/// Translates the parsed parts of a CREATE INDEX into the key column and catalog options.
+/// SQL literals are integers (U32) or strings (VARCHAR), so a predicate written in SQL can
+/// only compare columns of those two types; the catalog rejects any other column.
+fn index_options(
+    key: parser::AstExpr,
+    include_columns: Vec<String>,
+    using_hash: bool,
+    predicate: Vec<(parser::AstExpr, parser::AstValue)>,
+) -> Result<(String, IndexOptions), String> {
+    let key_expr = match &key {
+        parser::AstExpr::Column(_) => KeyExpr::Column,
+        parser::AstExpr::Lower(_) => KeyExpr::Lower,
+        parser::AstExpr::Upper(_) => KeyExpr::Upper,
+    };
+    let predicate = predicate
+        .into_iter()
+        .map(|(expr, value)| match expr {
+            parser::AstExpr::Column(column) => Ok((
+                column,
+                match value {
+                    parser::AstValue::U32(v) => AttributeValue::U32(v),
+                    parser::AstValue::Varchar(v) => AttributeValue::Varchar(v),
+                },
+            )),
+            other => Err(format!(
+                "Index predicate must compare plain columns, got {}",
+                other
+            )),
+        })
+        .collect::<Result<_, String>>()?;
+
+    let options = IndexOptions {
+        method: if using_hash {
+            IndexMethod::Hash
+        } else {
+            IndexMethod::BTree
+        },
+        key_expr,
+        include_columns,
+        predicate,
+        fill_factor: None,
+        tablespace: None,
+    };
+    Ok((key.column().to_string(), options))
+}
+
+/// How buffer pools are opened. Carried over when `USE` switches databases.
+#[derive(Clone, Copy)]
+struct PoolOptions {
+    policy: EvictionPolicy,
+    frames: usize,
+}
+
+/// Reads `--flag <value>` (or `--flag=<value>`) from the command line.
+fn arg_value(flag: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let prefix = format!("{}=", flag);
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if arg == flag {
+            args.get(i + 1).cloned()
+        } else {
+            arg.strip_prefix(&prefix).map(str::to_string)
+        }
+    })
+}
+
+/// Reads `--eviction <policy>` and `--buffer-pool-size <frames>`, falling back to the
+/// defaults for missing or invalid values.
+fn pool_options_from_args() -> PoolOptions {
+    let policy = match arg_value("--eviction").map(|v| v.parse::<EvictionPolicy>()) {
+        None => EvictionPolicy::default(),
+        Some(Ok(policy)) => policy,
+        Some(Err(e)) => {
+            eprintln!("{}, using {}", e, EvictionPolicy::default());
+            EvictionPolicy::default()
+        }
+    };
+    let frames = match arg_value("--buffer-pool-size").map(|v| parse_frame_count(&v)) {
+        None => DEFAULT_FRAME_COUNT,
+        Some(Ok(frames)) => frames,
+        Some(Err(e)) => {
+            eprintln!("{}, using {} frames", e, DEFAULT_FRAME_COUNT);
+            DEFAULT_FRAME_COUNT
+        }
+    };
+    PoolOptions { policy, frames }
+}
+
+fn parse_frame_count(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if (MIN_FRAME_COUNT..=MAX_FRAME_COUNT).contains(&n) => Ok(n),
+        _ => Err(format!(
+            "Buffer pool size must be between {} and {} frames, got '{}'",
+            MIN_FRAME_COUNT, MAX_FRAME_COUNT, value
+        )),
+    }
+}
+
+/// `PRAGMA buffer_pool_size` shows the pool size in frames, `PRAGMA buffer_pool_size = n`
+/// resizes the open pool and keeps the size for databases opened later.
+fn run_pragma(
+    name: &str,
+    value: Option<parser::AstValue>,
+    bp: &Arc<Mutex<BufferPool>>,
+    options: &mut PoolOptions,
+) -> Result<String, String> {
+    if name != "buffer_pool_size" {
+        return Err(format!("Unknown pragma: {}", name));
+    }
+
+    let mut bp_guard = bp.lock().map_err(|_| "Lock poisoned")?;
+    let frames = match value {
+        None => return Ok(format!("buffer_pool_size = {}", bp_guard.frame_count())),
+        Some(parser::AstValue::U32(n)) => parse_frame_count(&n.to_string())?,
+        Some(parser::AstValue::Varchar(v)) => parse_frame_count(&v)?,
+    };
+    let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    pinned_bp
+        .resize(frames)
+        .map_err(|e| format!("Failed to resize buffer pool: {:?}", e))?;
+    options.frames = frames;
+    Ok(format!("buffer_pool_size = {}", frames))
+}
+
+/// Opens the database at `path`, encrypted under `NIMBUS_PASSPHRASE` when that is set.
+fn init_database(
+    path: String,
+    options: PoolOptions,
+) -> Result<(Arc<Mutex<BufferPool>>, Catalog), String> {
+    let device = match std::env::var("NIMBUS_PASSPHRASE") {
+        Ok(passphrase) => disk::open_encrypted_device(&path, &passphrase),
+        Err(_) => disk::open_device(&path),
+    }
+    .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let bp = Arc::new(Mutex::new(BufferPool::with_frame_count(
+        device,
+        options.policy.evictor(),
         Box::new(DirectoryPageLocator::new()),
+        options.frames,
     )));
-    let catalog = Catalog::new(bp.clone());
-    (bp, catalog)
+    if let Ok(Some(record)) = bp.lock().unwrap().last_checkpoint()
+        && !record.complete
+    {
+        eprintln!(
+            "Checkpoint {} of {} did not finish; {} pages may be out of date",
+            record.id,
+            path,
+            record.dirty_pages.len()
+        );
+    }
+    let catalog = Catalog::try_new(bp.clone())?;
+    Ok((bp, catalog))
 }
 
 fn use_database(
     path: String,
+    options: PoolOptions,
     bp: &mut Arc<Mutex<BufferPool>>,
     catalog: &mut Catalog,
     current_path: &mut String,
@@ -195,6 +447,7 @@ fn use_database(
         fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
     }
 
+    catalog.save_stats()?;
     {
         let mut bp_guard = bp.lock().map_err(|_| "Lock poisoned")?;
         let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
@@ -203,7 +456,7 @@ fn use_database(
             .map_err(|e| format!("Failed to flush: {:?}", e))?;
     }
 
-    let (new_bp, new_catalog) = init_database(full_path.clone());
+    let (new_bp, new_catalog) = init_database(full_path.clone(), options)?;
     *bp = new_bp;
     *catalog = new_catalog;
     *current_path = full_path;
@@ -310,7 +563,16 @@ fn print_help() {
     println!("  \x1B[1;33m.clear\x1B[0m                   Clear terminal screen");
     println!("  \x1B[1;33m.tables\x1B[0m                  List all tables");
     println!("  \x1B[1;33m.describe <table>\x1B[0m        Show table structure");
-    println!("  \x1B[1;33m.desc <table>\x1B[0m            Short form of .describe\n");
+    println!("  \x1B[1;33m.desc <table>\x1B[0m            Short form of .describe");
+    println!("  \x1B[1;33m.verify <index>\x1B[0m          Check a B+ tree index for corruption\n");
+
+    println!("\x1B[1;36mSettings:\x1B[0m");
+    println!(
+        "  \x1B[1;33mPRAGMA buffer_pool_size\x1B[0m  Show or set the buffer pool size in frames"
+    );
+    println!("    \x1B[2mExample: PRAGMA buffer_pool_size = 1024;\x1B[0m");
+    println!("  \x1B[1;33mCHECKPOINT\x1B[0m               Write dirty pages to disk now");
+    println!("  \x1B[1;33mVACUUM [table]\x1B[0m           Reclaim space left by deleted rows\n");
 
     println!("\x1B[1;36mSQL Statements:\x1B[0m");
     println!("  \x1B[1;33mSHOW TABLES\x1B[0m              List all tables");
@@ -323,6 +585,15 @@ fn print_help() {
     println!();
     println!("  \x1B[1;33mCREATE INDEX\x1B[0m             Create an index on a column");
     println!("    \x1B[2mExample: CREATE INDEX idx_id ON users(id);\x1B[0m");
+    println!("    \x1B[2mExample: CREATE INDEX idx_id ON users(id) INCLUDE (age);\x1B[0m");
+    println!("    \x1B[2mExample: CREATE INDEX idx_id ON users USING HASH (id);\x1B[0m");
+    println!("    \x1B[2mExample: CREATE INDEX idx_email ON users(LOWER(email));\x1B[0m");
+    println!(
+        "    \x1B[2mExample: CREATE INDEX idx_open ON tickets(id) WHERE status = 'open';\x1B[0m"
+    );
+    println!();
+    println!("  \x1B[1;33mDROP INDEX\x1B[0m               Delete an index");
+    println!("    \x1B[2mExample: DROP INDEX idx_id;\x1B[0m");
     println!();
     println!("  \x1B[1;33mINSERT\x1B[0m                   Insert rows into a table");
     println!("    \x1B[2mExample: INSERT INTO users (id, name) VALUES (1, 'Alice');\x1B[0m");
@@ -330,6 +601,7 @@ fn print_help() {
     println!("  \x1B[1;33mSELECT\x1B[0m                   Query data from a table");
     println!("    \x1B[2mExample: SELECT * FROM users;\x1B[0m");
     println!("    \x1B[2mExample: SELECT name FROM users WHERE id = 1;\x1B[0m");
+    println!("    \x1B[2mExample: SELECT * FROM users ORDER BY id DESC LIMIT 10;\x1B[0m");
     println!();
     println!("  \x1B[1;33mUPDATE\x1B[0m                   Update rows in a table");
     println!("    \x1B[2mExample: UPDATE users SET name = 'Bob' WHERE id = 1;\x1B[0m");
@@ -340,6 +612,29 @@ fn print_help() {
     println!("\x1B[1;35m═══════════════════════════════════════════════════════════════\x1B[0m\n");
 }
 
+fn verify_index(catalog: &Catalog, index_name: &str) {
+    match catalog.verify_index(index_name) {
+        Ok(report) => {
+            println!(
+                "\n\x1B[1;36mIndex:\x1B[0m \x1B[1;33m{}\x1B[0m ({} levels, {} inner pages, {} leaf pages, {} entries)",
+                index_name, report.height, report.inner_pages, report.leaf_pages, report.entries
+            );
+            if report.is_ok() {
+                println!("\x1B[1;32mNo problems found\x1B[0m\n");
+            } else {
+                for problem in &report.problems {
+                    println!("  \x1B[1;31m✗\x1B[0m {}", problem);
+                }
+                println!(
+                    "\x1B[1;31m{} problem(s) found\x1B[0m\n",
+                    report.problems.len()
+                );
+            }
+        }
+        Err(e) => println!("\x1B[1;31mError:\x1B[0m {}\n", e),
+    }
+}
+
 fn describe_table(catalog: &Catalog, table_name: &str) {
     match catalog.get_table_oid(table_name) {
         Some(oid) => match catalog.get_table_schema(oid) {
@@ -1,8 +1,10 @@
 use sqlparser::ast::{
-    BinaryOperator, Expr, SetExpr, Statement, TableFactor, Value,
+    BinaryOperator, Expr, FunctionArg, FunctionArgExpr, SetExpr, Statement, TableFactor, Value,
 };
 use sqlparser::dialect::GenericDialect;
 use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Token, Tokenizer};
+use std::fmt;
 
 #[derive(Debug, Clone)]
 pub enum AstStatement {
@@ -14,34 +16,95 @@ pub enum AstStatement {
     Select {
         table_name: String,
         selection: Vec<String>,
-        filter: Option<(String, AstValue)>,
+        filter: Vec<(AstExpr, AstValue)>, // ANDed equalities, empty if there is no WHERE
+        order_by: Option<AstOrderBy>,
+        limit: Option<usize>,
     },
     Update {
         table_name: String,
         assignments: Vec<(String, AstValue)>,
-        filter: Option<(String, AstValue)>,
+        filter: Vec<(AstExpr, AstValue)>, // ANDed equalities, empty if there is no WHERE
     },
     Delete {
         table_name: String,
-        filter: Option<(String, AstValue)>,
+        filter: Vec<(AstExpr, AstValue)>, // ANDed equalities, empty if there is no WHERE
     },
     CreateTable {
         table_name: String,
         columns: Vec<(String, AstDataType)>,
+        tablespace: Option<String>,  // CREATE TABLE ... TABLESPACE name
+        compression: Option<String>, // CREATE TABLE ... WITH (compression = 'lz4')
     },
     CreateIndex {
         index_name: String,
         table_name: String,
-        column_name: String,
+        key: AstExpr,
+        include_columns: Vec<String>,
+        using_hash: bool,                    // CREATE INDEX ... USING HASH
+        predicate: Vec<(AstExpr, AstValue)>, // Partial index: CREATE INDEX ... WHERE
+        tablespace: Option<String>,          // CREATE INDEX ... TABLESPACE name
+    },
+    /// `CREATE TABLESPACE name LOCATION '/path'`: a directory to put tables and indexes in
+    CreateTablespace {
+        name: String,
+        location: String,
     },
     ShowTables,
     DropTable {
         table_name: String,
     },
+    DropIndex {
+        index_name: String,
+    },
     Clear,
     UseDatabase {
         path: String,
     },
+    /// Write dirty pages back and record a checkpoint
+    Checkpoint,
+    /// Compact one table, or all of them, and shrink the file
+    Vacuum {
+        table_name: Option<String>,
+    },
+    /// `PRAGMA name` reads a setting, `PRAGMA name = value` changes it
+    Pragma {
+        name: String,
+        value: Option<AstValue>,
+    },
+}
+
+/// `ORDER BY column [ASC | DESC]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstOrderBy {
+    pub column: String,
+    pub descending: bool,
+}
+
+/// Left-hand side of a WHERE equality or an index key: a column, optionally wrapped
+/// in one of the supported string functions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AstExpr {
+    Column(String),
+    Lower(String),
+    Upper(String),
+}
+
+impl AstExpr {
+    pub fn column(&self) -> &str {
+        match self {
+            AstExpr::Column(c) | AstExpr::Lower(c) | AstExpr::Upper(c) => c,
+        }
+    }
+}
+
+impl fmt::Display for AstExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AstExpr::Column(c) => write!(f, "{}", c),
+            AstExpr::Lower(c) => write!(f, "LOWER({})", c),
+            AstExpr::Upper(c) => write!(f, "UPPER({})", c),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -67,8 +130,57 @@ pub fn parse(sql: &str) -> Result<AstStatement, String> {
         return Ok(AstStatement::ShowTables);
     }
 
+    if trimmed
+        .trim_end_matches(';')
+        .trim_end()
+        .eq_ignore_ascii_case("checkpoint")
+    {
+        return Ok(AstStatement::Checkpoint);
+    }
+
+    let mut words = trimmed.trim_end_matches(';').split_whitespace();
+    if words
+        .next()
+        .is_some_and(|w| w.eq_ignore_ascii_case("vacuum"))
+    {
+        let table_name = words.next().map(|w| w.to_string());
+        if words.next().is_some() {
+            return Err("Expected VACUUM [table]".to_string());
+        }
+        return Ok(AstStatement::Vacuum { table_name });
+    }
+
     let dialect = GenericDialect {};
-    let mut ast = Parser::parse_sql(&dialect, sql).map_err(|e| e.to_string())?;
+    let mut tokens = Tokenizer::new(&dialect, sql)
+        .tokenize()
+        .map_err(|e| e.to_string())?;
+    let words: Vec<&Token> = tokens
+        .iter()
+        .filter(|t| !matches!(t, Token::Whitespace(_) | Token::SemiColon))
+        .collect();
+    let is_create = words.first().is_some_and(|t| is_keyword(t, "create"));
+    if is_create && words.get(1).is_some_and(|t| is_keyword(t, "tablespace")) {
+        return match words[2..] {
+            [
+                Token::Word(name),
+                keyword,
+                Token::SingleQuotedString(location),
+            ] if is_keyword(keyword, "location") => Ok(AstStatement::CreateTablespace {
+                name: name.value.clone(),
+                location: location.clone(),
+            }),
+            _ => Err("Expected CREATE TABLESPACE name LOCATION '/path'".to_string()),
+        };
+    }
+
+    // The SQL parser knows no TABLESPACE clause, so it is taken out beforehand
+    let tablespace = if is_create {
+        take_tablespace_clause(&mut tokens)?
+    } else {
+        None
+    };
+    let sql: String = tokens.iter().map(|t| t.to_string()).collect();
+    let mut ast = Parser::parse_sql(&dialect, &sql).map_err(|e| e.to_string())?;
 
     if ast.len() != 1 {
         return Err("Expected exactly one SQL statement.".to_string());
@@ -88,7 +200,15 @@ pub fn parse(sql: &str) -> Result<AstStatement, String> {
                         .ok_or("DROP TABLE requires a table name")?;
                     Ok(AstStatement::DropTable { table_name })
                 }
-                _ => Err("Only DROP TABLE is supported".to_string()),
+                ObjectType::Index => {
+                    let index_name = names
+                        .first()
+                        .and_then(|obj_name| obj_name.0.first())
+                        .map(|ident| ident.value.clone())
+                        .ok_or("DROP INDEX requires an index name")?;
+                    Ok(AstStatement::DropIndex { index_name })
+                }
+                _ => Err("Only DROP TABLE and DROP INDEX are supported".to_string()),
             }
         }
         Statement::Use { db_name } => {
@@ -152,10 +272,33 @@ pub fn parse(sql: &str) -> Result<AstStatement, String> {
 
                 let filter = parse_optional_filter(select.selection)?;
 
+                let order_by = match query.order_by.as_slice() {
+                    [] => None,
+                    [item] => match &item.expr {
+                        Expr::Identifier(ident) => Some(AstOrderBy {
+                            column: ident.value.clone(),
+                            descending: item.asc == Some(false),
+                        }),
+                        other => return Err(format!("Unsupported ORDER BY expression: {}", other)),
+                    },
+                    _ => return Err("ORDER BY supports a single column".to_string()),
+                };
+
+                let limit = match query.limit {
+                    None => None,
+                    Some(Expr::Value(Value::Number(n, _))) => Some(
+                        n.parse::<usize>()
+                            .map_err(|_| format!("Invalid LIMIT: {}", n))?,
+                    ),
+                    Some(other) => return Err(format!("Unsupported LIMIT: {}", other)),
+                };
+
                 Ok(AstStatement::Select {
                     table_name,
                     selection,
                     filter,
+                    order_by,
+                    limit,
                 })
             } else {
                 Err("Unsupported query type (must be SELECT)".to_string())
@@ -224,7 +367,12 @@ pub fn parse(sql: &str) -> Result<AstStatement, String> {
 
             Ok(AstStatement::Delete { table_name, filter })
         }
-        Statement::CreateTable { name, columns, .. } => {
+        Statement::CreateTable {
+            name,
+            columns,
+            with_options,
+            ..
+        } => {
             let table_name = name.0.get(0).unwrap().value.clone();
             let cols = columns
                 .into_iter()
@@ -235,15 +383,30 @@ pub fn parse(sql: &str) -> Result<AstStatement, String> {
                 })
                 .collect::<Result<Vec<_>, _>>()?;
 
+            let mut compression = None;
+            for option in with_options {
+                match (option.name.value.to_lowercase().as_str(), option.value) {
+                    ("compression", Expr::Value(Value::SingleQuotedString(s))) => {
+                        compression = Some(s)
+                    }
+                    (name, _) => return Err(format!("Unsupported table option: {}", name)),
+                }
+            }
+
             Ok(AstStatement::CreateTable {
                 table_name,
                 columns: cols,
+                tablespace,
+                compression,
             })
         }
         Statement::CreateIndex {
             name,
             table_name,
             columns,
+            include,
+            using,
+            predicate,
             ..
         } => {
             let index_name = name
@@ -254,32 +417,120 @@ pub fn parse(sql: &str) -> Result<AstStatement, String> {
                 .value
                 .clone();
             let table = table_name.0.get(0).unwrap().value.clone();
-            let col_name = columns.get(0).unwrap().expr.to_string();
+            let key = match columns.as_slice() {
+                [column] => parse_operand(column.expr.clone())?,
+                _ => return Err("Index must have exactly one key column".to_string()),
+            };
+            let include_columns = include.into_iter().map(|ident| ident.value).collect();
+            let using_hash = match using {
+                None => false,
+                Some(ident) if ident.value.eq_ignore_ascii_case("btree") => false,
+                Some(ident) if ident.value.eq_ignore_ascii_case("hash") => true,
+                Some(ident) => return Err(format!("Unsupported index method: {}", ident.value)),
+            };
             Ok(AstStatement::CreateIndex {
                 index_name,
                 table_name: table,
-                column_name: col_name,
+                key,
+                include_columns,
+                using_hash,
+                predicate: parse_optional_filter(predicate)?,
+                tablespace,
             })
         }
+        Statement::Pragma { name, value, .. } => {
+            let name = name
+                .0
+                .last()
+                .map(|ident| ident.value.to_lowercase())
+                .ok_or("PRAGMA requires a name")?;
+            let value = value.map(convert_sql_value).transpose()?;
+            Ok(AstStatement::Pragma { name, value })
+        }
         _ => Err("Unsupported SQL statement type.".to_string()),
     }
 }
 
-fn parse_optional_filter(expr: Option<Expr>) -> Result<Option<(String, AstValue)>, String> {
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    matches!(token, Token::Word(w)
+        if w.quote_style.is_none() && w.value.eq_ignore_ascii_case(keyword))
+}
+
+/// Removes `TABLESPACE name` from the tokens of a CREATE statement and returns the name.
+fn take_tablespace_clause(tokens: &mut Vec<Token>) -> Result<Option<String>, String> {
+    let Some(start) = tokens.iter().position(|t| is_keyword(t, "tablespace")) else {
+        return Ok(None);
+    };
+    let end = tokens[start + 1..]
+        .iter()
+        .position(|t| !matches!(t, Token::Whitespace(_)))
+        .map(|i| start + 1 + i);
+    let name = match end.map(|i| &tokens[i]) {
+        Some(Token::Word(w)) => w.value.clone(),
+        _ => return Err("Expected a name after TABLESPACE".to_string()),
+    };
+    tokens.drain(start..=end.unwrap());
+    Ok(Some(name))
+}
+
+fn parse_optional_filter(expr: Option<Expr>) -> Result<Vec<(AstExpr, AstValue)>, String> {
+    let mut terms = Vec::new();
     if let Some(expr) = expr {
-        if let Expr::BinaryOp { left, op, right } = expr {
-            if op == BinaryOperator::Eq {
-                let col = left.to_string();
-                let val = convert_sql_value(match *right {
-                    Expr::Value(v) => v,
-                    _ => return Err("Filter value must be a literal".to_string()),
-                })?;
-                return Ok(Some((col, val)));
+        collect_filter_terms(expr, &mut terms)?;
+    }
+    Ok(terms)
+}
+
+/// Flattens `a = 1 AND f(b) = 'x' AND ...` into its equality terms.
+fn collect_filter_terms(expr: Expr, terms: &mut Vec<(AstExpr, AstValue)>) -> Result<(), String> {
+    match expr {
+        Expr::Nested(inner) => collect_filter_terms(*inner, terms),
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => {
+            collect_filter_terms(*left, terms)?;
+            collect_filter_terms(*right, terms)
+        }
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::Eq,
+            right,
+        } => {
+            let operand = parse_operand(*left)?;
+            let val = convert_sql_value(match *right {
+                Expr::Value(v) => v,
+                _ => return Err("Filter value must be a literal".to_string()),
+            })?;
+            terms.push((operand, val));
+            Ok(())
+        }
+        _ => Err("Unsupported WHERE clause (must be equalities joined by AND)".to_string()),
+    }
+}
+
+fn parse_operand(expr: Expr) -> Result<AstExpr, String> {
+    match expr {
+        Expr::Identifier(ident) => Ok(AstExpr::Column(ident.value)),
+        Expr::Function(func) => {
+            let name = func.name.to_string();
+            let column = match func.args.as_slice() {
+                [FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Identifier(ident)))] => {
+                    ident.value.clone()
+                }
+                _ => return Err(format!("{} expects a single column argument", name)),
+            };
+            if name.eq_ignore_ascii_case("lower") {
+                Ok(AstExpr::Lower(column))
+            } else if name.eq_ignore_ascii_case("upper") {
+                Ok(AstExpr::Upper(column))
+            } else {
+                Err(format!("Unsupported function: {}", name))
             }
         }
-        return Err("Unsupported WHERE clause (must be simple equality)".to_string());
+        other => Err(format!("Unsupported expression: {}", other)),
     }
-    Ok(None)
 }
 
 fn convert_sql_value(sql_val: Value) -> Result<AstValue, String> {
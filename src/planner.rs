@@ -1,17 +1,18 @@
-use crate::catalog::manager::Catalog;
+use crate::catalog::manager::{Catalog, EqualityTerm, KeyExpr, index_key};
 use crate::execution::delete::DeleteExecutor;
 use crate::execution::executor::Executor;
 use crate::execution::filter::FilterExecutor;
+use crate::execution::index_only_scan::IndexOnlyScanExecutor;
+use crate::execution::index_order_scan::IndexOrderScanExecutor;
 use crate::execution::index_scan::IndexScanExecutor;
 use crate::execution::insert::InsertExecutor;
+use crate::execution::limit::LimitExecutor;
 use crate::execution::projection::ProjectionExecutor;
 use crate::execution::seq_scan::SeqScanExecutor;
 use crate::execution::update::UpdateExecutor;
 use crate::execution::values::ValuesExecutor;
-use crate::parser::{AstStatement, AstValue};
-use crate::rt_type::primitives::{
-    AttributeValue, TableType,
-};
+use crate::parser::{AstExpr, AstOrderBy, AstStatement, AstValue};
+use crate::rt_type::primitives::{AttributeValue, TableType};
 use crate::storage::heap::tuple::Tuple;
 use std::collections::HashMap;
 
@@ -35,7 +36,9 @@ impl<'a> Planner<'a> {
                 table_name,
                 selection,
                 filter,
-            } => self.plan_select(table_name, selection, filter),
+                order_by,
+                limit,
+            } => self.plan_select(table_name, selection, filter, order_by, limit),
             AstStatement::Delete { table_name, filter } => self.plan_delete(table_name, filter),
             AstStatement::Update {
                 table_name,
@@ -48,14 +51,23 @@ impl<'a> Planner<'a> {
             AstStatement::CreateIndex { .. } => {
                 Err("CREATE INDEX not supported in query plan".to_string())
             }
+            AstStatement::CreateTablespace { .. } => {
+                Err("CREATE TABLESPACE not supported in query plan".to_string())
+            }
             AstStatement::ShowTables => Err("SHOW TABLES not supported in query plan".to_string()),
             AstStatement::DropTable { .. } => {
                 Err("DROP TABLE not supported in query plan".to_string())
             }
+            AstStatement::DropIndex { .. } => {
+                Err("DROP INDEX not supported in query plan".to_string())
+            }
             AstStatement::Clear => Err("CLEAR not supported in query plan".to_string()),
             AstStatement::UseDatabase { .. } => {
                 Err("USE DATABASE not supported in query plan".to_string())
             }
+            AstStatement::Checkpoint => Err("CHECKPOINT not supported in query plan".to_string()),
+            AstStatement::Vacuum { .. } => Err("VACUUM not supported in query plan".to_string()),
+            AstStatement::Pragma { .. } => Err("PRAGMA not supported in query plan".to_string()),
         }
     }
 
@@ -120,7 +132,9 @@ impl<'a> Planner<'a> {
         &self,
         table_name: String,
         selection: Vec<String>,
-        filter: Option<(String, AstValue)>,
+        filter: Vec<(AstExpr, AstValue)>,
+        order_by: Option<AstOrderBy>,
+        limit: Option<usize>,
     ) -> Result<Box<dyn Executor + 'a>, String> {
         let table_oid = self
             .catalog
@@ -132,12 +146,7 @@ impl<'a> Planner<'a> {
             .get_table_schema(table_oid)
             .ok_or(format!("Schema not found for OID: {}", table_oid))?;
 
-        let scan_exec: Box<dyn Executor + 'a> =
-            self.build_scan_with_filter(table_oid, &table_name, &schema, filter)?;
-
-        if selection.len() == 1 && selection[0] == "*" {
-            return Ok(scan_exec);
-        }
+        let select_all = selection.len() == 1 && selection[0] == "*";
 
         let schema_col_map: HashMap<_, _> = schema
             .attributes
@@ -146,15 +155,75 @@ impl<'a> Planner<'a> {
             .map(|(i, attr)| (attr.name.as_str(), i))
             .collect();
 
-        let col_indices: Vec<usize> = selection
-            .iter()
-            .map(|col_name| {
-                schema_col_map
-                    .get(col_name.as_str())
-                    .copied()
-                    .ok_or_else(|| format!("Column {} not found in table {}", col_name, table_name))
-            })
-            .collect::<Result<_, String>>()?;
+        let col_indices: Vec<usize> = if select_all {
+            (0..schema.attributes.len()).collect()
+        } else {
+            selection
+                .iter()
+                .map(|col_name| {
+                    schema_col_map
+                        .get(col_name.as_str())
+                        .copied()
+                        .ok_or_else(|| {
+                            format!("Column {} not found in table {}", col_name, table_name)
+                        })
+                })
+                .collect::<Result<_, String>>()?
+        };
+
+        let terms = resolve_filter(&schema, filter)?;
+
+        let mut scan_exec: Box<dyn Executor + 'a> = if let Some(order) = order_by {
+            // There is no sort operator, so the order has to come from an index
+            let column_idx = *schema_col_map
+                .get(order.column.as_str())
+                .ok_or_else(|| format!("Column {} not found in ORDER BY", order.column))?;
+            let oid = self
+                .catalog
+                .find_ordered_index(&table_name, column_idx)
+                .ok_or(format!(
+                    "ORDER BY {} needs a B+ tree index on it (unsigned integer columns only)",
+                    order.column
+                ))?;
+            let scan = Box::new(IndexOrderScanExecutor::new(
+                self.catalog,
+                oid,
+                order.descending,
+            )?);
+            if terms.is_empty() {
+                scan
+            } else {
+                Box::new(FilterExecutor::new(scan, move |t: &Tuple| {
+                    terms.iter().all(|term| term.matches(t))
+                }))
+            }
+        } else if let Some((oid, key_term)) =
+            self.catalog
+                .find_covering_index(&table_name, &terms, &col_indices)
+        {
+            // Index-only scan: the lookup index also stores every selected column
+            let key_bytes = convert_value_to_key(&terms[key_term].value)?;
+            let scan = Box::new(IndexOnlyScanExecutor::new(
+                self.catalog,
+                oid,
+                Some(key_bytes),
+                &col_indices,
+            )?);
+            return Ok(match limit {
+                Some(n) => Box::new(LimitExecutor::new(scan, n)),
+                None => scan,
+            });
+        } else {
+            self.build_scan_with_filter(table_oid, &table_name, terms)?
+        };
+
+        if let Some(n) = limit {
+            scan_exec = Box::new(LimitExecutor::new(scan_exec, n));
+        }
+
+        if select_all {
+            return Ok(scan_exec);
+        }
 
         let proj_exec = Box::new(ProjectionExecutor::new(scan_exec, col_indices));
         Ok(proj_exec)
@@ -163,7 +232,7 @@ impl<'a> Planner<'a> {
     fn plan_delete(
         &self,
         table_name: String,
-        filter: Option<(String, AstValue)>,
+        filter: Vec<(AstExpr, AstValue)>,
     ) -> Result<Box<dyn Executor + 'a>, String> {
         let table_oid = self
             .catalog
@@ -175,8 +244,9 @@ impl<'a> Planner<'a> {
             .get_table_schema(table_oid)
             .ok_or(format!("Schema not found for OID: {}", table_oid))?;
 
+        let terms = resolve_filter(&schema, filter)?;
         let child_exec: Box<dyn Executor + 'a> =
-            self.build_scan_with_filter(table_oid, &table_name, &schema, filter)?;
+            self.build_scan_with_filter(table_oid, &table_name, terms)?;
 
         // FIX: Wrap in Ok() and use ? on the inner Result
         Ok(Box::new(DeleteExecutor::new(
@@ -190,7 +260,7 @@ impl<'a> Planner<'a> {
         &self,
         table_name: String,
         assignments: Vec<(String, AstValue)>,
-        filter: Option<(String, AstValue)>,
+        filter: Vec<(AstExpr, AstValue)>,
     ) -> Result<Box<dyn Executor + 'a>, String> {
         let table_oid = self
             .catalog
@@ -202,8 +272,9 @@ impl<'a> Planner<'a> {
             .get_table_schema(table_oid)
             .ok_or(format!("Schema not found for OID: {}", table_oid))?;
 
+        let terms = resolve_filter(&schema, filter)?;
         let child_exec: Box<dyn Executor + 'a> =
-            self.build_scan_with_filter(table_oid, &table_name, &schema, filter)?;
+            self.build_scan_with_filter(table_oid, &table_name, terms)?;
 
         // Map column names to schema index and runtime value
         let update_map: Vec<(usize, AttributeValue)> = assignments
@@ -251,48 +322,57 @@ impl<'a> Planner<'a> {
         &self,
         table_oid: u32,
         table_name: &str,
-        schema: &TableType,
-        filter: Option<(String, AstValue)>,
+        terms: Vec<EqualityTerm>,
     ) -> Result<Box<dyn Executor + 'a>, String> {
-        if let Some((filter_col_name, filter_ast_val)) = filter {
-            let index_oid = self
-                .catalog
-                .find_index_for_column(table_name, &filter_col_name);
-
-            if let Some(oid) = index_oid {
-                let key_bytes = convert_value_to_key(filter_ast_val)?;
-                // FIX: Wrap in Ok() and use ? on the inner Result
-                Ok(Box::new(IndexScanExecutor::new(
-                    self.catalog,
-                    oid,
-                    key_bytes,
-                )?))
-            } else {
-                let scan = Box::new(SeqScanExecutor::new(self.catalog, table_oid)?);
-                let (col_idx, _) = schema
-                    .attributes
-                    .iter()
-                    .enumerate()
-                    .find(|(_, a)| a.name == filter_col_name)
-                    .ok_or(format!(
-                        "Column {} not found in WHERE clause",
-                        filter_col_name
-                    ))?;
-
-                let filter_val_runtime = convert_ast_value(filter_ast_val)?;
-
-                // FIX: Wrap in Ok()
-                Ok(Box::new(FilterExecutor::new(scan, move |t: &Tuple| {
-                    t.values[col_idx] == filter_val_runtime
-                })))
-            }
-        } else {
-            // FIX: Wrap in Ok() and use ? on the inner Result
-            Ok(Box::new(SeqScanExecutor::new(self.catalog, table_oid)?))
+        if terms.is_empty() {
+            return Ok(Box::new(SeqScanExecutor::new(self.catalog, table_oid)?));
         }
+
+        let scan: Box<dyn Executor + 'a> =
+            if let Some((oid, key_term)) = self.catalog.find_index(table_name, &terms) {
+                let value = terms[key_term].value.clone();
+                Box::new(IndexScanExecutor::new(self.catalog, oid, value)?)
+            } else {
+                Box::new(SeqScanExecutor::new(self.catalog, table_oid)?)
+            };
+
+        // The index scan only checks its own term, so every term is still checked
+        Ok(Box::new(FilterExecutor::new(scan, move |t: &Tuple| {
+            terms.iter().all(|term| term.matches(t))
+        })))
     }
 }
 
+/// Resolves WHERE equalities against the table schema.
+fn resolve_filter(
+    schema: &TableType,
+    filter: Vec<(AstExpr, AstValue)>,
+) -> Result<Vec<EqualityTerm>, String> {
+    filter
+        .into_iter()
+        .map(|(expr, ast_val)| {
+            let column_idx = schema
+                .attributes
+                .iter()
+                .position(|a| a.name == expr.column())
+                .ok_or(format!(
+                    "Column {} not found in WHERE clause",
+                    expr.column()
+                ))?;
+            let key_expr = match expr {
+                AstExpr::Column(_) => KeyExpr::Column,
+                AstExpr::Lower(_) => KeyExpr::Lower,
+                AstExpr::Upper(_) => KeyExpr::Upper,
+            };
+            Ok(EqualityTerm {
+                column_idx,
+                key_expr,
+                value: convert_ast_value(ast_val)?,
+            })
+        })
+        .collect()
+}
+
 fn convert_ast_value(val: AstValue) -> Result<AttributeValue, String> {
     match val {
         AstValue::U32(v) => Ok(AttributeValue::U32(v)),
@@ -300,11 +380,8 @@ fn convert_ast_value(val: AstValue) -> Result<AttributeValue, String> {
     }
 }
 
-fn convert_value_to_key(val: AstValue) -> Result<Vec<u8>, String> {
-    match val {
-        AstValue::U32(v) => Ok(v.to_be_bytes().to_vec()),
-        _ => Err("Index key must be an integer".to_string()),
-    }
+fn convert_value_to_key(val: &AttributeValue) -> Result<Vec<u8>, String> {
+    index_key(val).ok_or("Unsupported index key type".to_string())
 }
 
 // Added helper method for Tuple to check type kind equality (used in update_fn closure)
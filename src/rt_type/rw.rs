@@ -1,6 +1,4 @@
-use crate::{
-    rt_type::primitives::{self, AttributeKind, AttributeValue, TableAttribute},
-};
+use crate::rt_type::primitives::{self, AttributeKind, AttributeValue, TableAttribute};
 use std::convert::TryInto;
 
 pub struct LayoutReadWriter<'a> {
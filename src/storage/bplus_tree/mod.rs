@@ -1,5 +1,6 @@
 pub mod tree;
 pub use tree::BPlusTree;
+pub use tree::VerifyReport;
 #[derive(Debug)]
 pub struct SplitResult {
     pub split_key: Vec<u8>,
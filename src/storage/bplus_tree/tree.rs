@@ -1,7 +1,15 @@
-use crate::storage::buffer::BufferPool;
-use crate::storage::page::base::{Page, PageId, PageKind};
+use crate::constants;
+use crate::storage::buffer::{AccessStrategy, BufferPool, FrameLatches};
+use crate::storage::disk;
+use crate::storage::page::base::{PageBuf, PageId, PageKind, page_kind_from_buf};
+use crate::storage::page::bplus_key::{common_prefix_len, shortest_separator, significant_len};
+use crate::storage::page::bplus_leaf::LeafEntry;
+use crate::storage::page::header::PageHeader;
+use crate::storage::page::{BPlusInner, BPlusLeaf};
+use std::collections::HashSet;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLockReadGuard, RwLockWriteGuard};
 
 #[derive(Debug)]
 pub enum BTreeError {
@@ -11,800 +19,1269 @@ pub enum BTreeError {
     InvalidPageType,
     InsertError(String),
     DeleteError(String),
+    FreePage(String),
 }
 
-pub struct BPlusTree<'a> {
-    pub bpm: Pin<&'a mut BufferPool>,
-    pub root_page_id: PageId,
+/// Outcome of `BPlusTree::verify`. The tree is sound iff `problems` is empty.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Levels from the root down to the leaves, 0 for an empty tree.
+    pub height: usize,
+    pub inner_pages: usize,
+    pub leaf_pages: usize,
+    pub entries: usize,
+    pub problems: Vec<String>,
 }
 
-impl<'a> BPlusTree<'a> {
-    pub fn new(bpm: Pin<&'a mut BufferPool>, root_page_id: PageId) -> Self {
-        Self { bpm, root_page_id }
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
     }
+}
 
-    /// Traverses the tree from Root -> Leaf for a given key.
-    /// Returns the PageId of the leaf node that *should* contain the key.
-    pub fn find_leaf_page_id(&mut self, key: &[u8]) -> Result<PageId, BTreeError> {
-        if self.root_page_id == 0 {
-            return Err(BTreeError::InvalidPageType);
+/// What `verify` needs from one node, copied out so the page can be unpinned right away.
+struct NodeSummary {
+    is_leaf: bool,
+    keys: Vec<Vec<u8>>,
+    children: Vec<Option<PageId>>,
+    key_size: u32,
+    parent_page_id: PageId,
+    max_keys: usize,
+    prev: Option<PageId>,
+    next: Option<PageId>,
+}
+
+/// How a tree reaches the buffer pool.
+enum TreePool<'a> {
+    /// Borrowed for the tree's lifetime, so nothing else uses the pool meanwhile. The mutex
+    /// is never contended; it only lets `&self` methods reach the pool.
+    Borrowed(Mutex<Pin<&'a mut BufferPool>>),
+    /// Locked only to pin, unpin and allocate pages, so threads can share the tree.
+    Shared(&'a Mutex<BufferPool>),
+}
+
+/// The locked pool, handing out the pinned reference the pool API expects.
+enum PoolGuard<'g, 'a> {
+    Borrowed(MutexGuard<'g, Pin<&'a mut BufferPool>>),
+    Shared(MutexGuard<'g, BufferPool>),
+}
+
+impl PoolGuard<'_, '_> {
+    fn as_mut(&mut self) -> Pin<&mut BufferPool> {
+        match self {
+            PoolGuard::Borrowed(bpm) => bpm.as_mut(),
+            // SAFETY: a shared pool lives behind its mutex and is never moved out of it
+            PoolGuard::Shared(bpm) => unsafe { Pin::new_unchecked(&mut **bpm) },
         }
+    }
+}
 
-        let mut current_page_id = self.root_page_id;
+/// Once created, a tree keeps its root page id for good: root splits and collapses move the
+/// root's contents instead, so an id stored elsewhere (e.g. in the catalog) stays valid.
+/// New pages go to the file the tree lives in, see `BufferPool::alloc_new_page_in`.
+///
+/// Page bytes are only touched under the pool's per-frame latches (see `FrameLatches`),
+/// using latch crabbing:
+///
+/// - Lookups descend with shared latches, releasing each parent once the child is latched.
+/// - Inserts and deletes first try the same descent with an exclusive latch on the leaf
+///   only. If the leaf would split or underflow they release everything and retry
+///   pessimistically, latching exclusively from the root and dropping all ancestors as
+///   soon as a node is known not to split (or merge).
+///
+/// Latches are only taken top-down, or left to right between siblings under an exclusively
+/// latched parent, and the pool is never locked while waiting on a latch, so trees over a
+/// shared pool (`BPlusTree::shared`) can be used from many threads at once. The catalog and
+/// the executors do not do that yet: they build trees over the pool they hold locked, so
+/// their index operations still run one at a time. Iterators and trees over a borrowed
+/// pool read pages without waiting on anyone, so they must not run while shared trees over
+/// the same index are in the middle of an operation.
+pub struct BPlusTree<'a> {
+    pool: TreePool<'a>,
+    latches: Arc<FrameLatches>,
+    root_page_id: AtomicU32,
+    // Looked up from the root on the first allocation unless given
+    segment: OnceLock<u32>,
+}
 
-        loop {
-            let frame = self
-                .bpm
-                .as_mut()
-                .fetch_page(current_page_id)
-                .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-            let frame_id = frame.fid();
-            let page_view = frame.page_view();
+enum Latch<'t> {
+    Shared(#[allow(unused)] RwLockReadGuard<'t, ()>),
+    Exclusive(#[allow(unused)] RwLockWriteGuard<'t, ()>),
+}
 
-            let next_page_id = match page_view {
-                Page::BPlusInner(inner) => {
-                    let idx = inner.find_child_for_key(key);
-                    inner.get_child_at(idx)
-                }
-                Page::BPlusLeaf(_) => None, // Reached leaf
-                _ => {
-                    self.bpm.as_mut().unpin_frame(frame_id).ok();
-                    return Err(BTreeError::InvalidPageType);
-                }
-            };
+/// A pinned and latched page. Dropping it releases the latch, then unpins the page.
+struct PageGuard<'t, 'a> {
+    tree: &'t BPlusTree<'a>,
+    page_id: PageId,
+    fid: u32,
+    buf: *mut PageBuf,
+    latch: Option<Latch<'t>>,
+    dirty: bool,
+}
 
-            self.bpm
-                .as_mut()
-                .unpin_frame(frame_id)
-                .map_err(|e| BTreeError::UnpinPage(format!("{:?}", e)))?;
+impl PageGuard<'_, '_> {
+    fn kind(&self) -> PageKind {
+        // SAFETY: the page is pinned and latched for the guard's lifetime
+        unsafe { page_kind_from_buf(&*self.buf) }
+    }
 
-            match next_page_id {
-                Some(id) => current_page_id = id,
-                None => return Ok(current_page_id),
-            }
-        }
+    fn is_exclusive(&self) -> bool {
+        matches!(self.latch, Some(Latch::Exclusive(_)))
     }
 
-    // ========================= DELETION LOGIC =========================
+    fn raw(&self) -> &PageBuf {
+        unsafe { &*self.buf }
+    }
 
-    pub fn delete(&mut self, key: &[u8]) -> Result<(), BTreeError> {
-        if self.root_page_id == 0 {
-            return Ok(()); // Empty tree
-        }
+    /// Overwrites the page with a copy of `src`, keeping its own page id in the header.
+    fn copy_from(&mut self, src: &PageGuard) {
+        debug_assert!(self.is_exclusive());
+        self.dirty = true;
+        let buf = unsafe { &mut *self.buf };
+        buf.copy_from_slice(src.raw());
+        PageHeader::from_buf_mut(buf).set_page_id(self.page_id);
+    }
 
-        let leaf_page_id = self.find_leaf_page_id(key)?;
-        self.delete_entry(leaf_page_id, key)
+    /// Leaf view of the page. Only mutate it through `leaf_mut`.
+    fn leaf(&self) -> BPlusLeaf<'_> {
+        unsafe { BPlusLeaf::new(&mut *self.buf) }
     }
 
-    fn delete_entry(&mut self, page_id: PageId, key: &[u8]) -> Result<(), BTreeError> {
-        // 1. Fetch Page and Remove Entry
-        let (is_underflow, _parent_id) = {
-            let frame = self
-                .bpm
-                .as_mut()
-                .fetch_page(page_id)
-                .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-            let frame_id = frame.fid();
-            let mut page_view = frame.page_view();
-
-            let (underflow, _parent) = match &mut page_view {
-                Page::BPlusLeaf(leaf) => {
-                    let removed = leaf.remove_key(key);
-                    if !removed {
-                        self.bpm.as_mut().unpin_frame(frame_id).ok();
-                        return Ok(());
-                    }
-                    (leaf.is_underflow(), 0)
-                }
-                Page::BPlusInner(_inner) => {
-                    // Inner node deletion is handled by coalesce/redistribute usually.
-                    // This path is hit if we recursively call delete_entry on root.
-                    (false, 0)
-                }
-                _ => {
-                    self.bpm.as_mut().unpin_frame(frame_id).ok();
-                    return Err(BTreeError::InvalidPageType);
-                }
-            };
+    fn leaf_mut(&mut self) -> BPlusLeaf<'_> {
+        debug_assert!(self.is_exclusive());
+        self.dirty = true;
+        unsafe { BPlusLeaf::new(&mut *self.buf) }
+    }
 
-            self.bpm.as_mut().mark_frame_dirty(frame_id);
-            self.bpm.as_mut().unpin_frame(frame_id).ok();
-            (underflow, _parent)
-        };
+    /// Inner view of the page. Only mutate it through `inner_mut`.
+    fn inner(&self) -> BPlusInner<'_> {
+        unsafe { BPlusInner::new(&mut *self.buf) }
+    }
 
-        // 2. Check Root Adjustment
-        if self.root_page_id == page_id {
-            return self.adjust_root();
-        }
+    fn inner_mut(&mut self) -> BPlusInner<'_> {
+        debug_assert!(self.is_exclusive());
+        self.dirty = true;
+        unsafe { BPlusInner::new(&mut *self.buf) }
+    }
 
-        // 3. Handle Underflow
-        if is_underflow {
-            // Re-traverse to find parent since we don't store parent pointers in pages
-            let parent_id = self.find_parent_of(page_id, key)?;
-            if let Some(pid) = parent_id {
-                self.handle_underflow(page_id, pid, key)?;
-            }
+    /// Trades a shared latch for an exclusive one. The page stays pinned, but other
+    /// writers may slip in between, so callers must still hold the parent's latch.
+    fn relatch_exclusive(&mut self) {
+        if !self.is_exclusive() {
+            self.latch = None;
+            self.latch = Some(Latch::Exclusive(self.tree.latches.write(self.fid)));
         }
+    }
 
-        Ok(())
+    /// Whether inserting `key` cannot split this node. Inner nodes would receive an
+    /// unknown separator from below, so they must have room for any key.
+    fn safe_for_insert(&self, key: &[u8]) -> bool {
+        match self.kind() {
+            PageKind::BPlusLeaf => self.leaf().has_space_for_key(key),
+            PageKind::BPlusInner => self.inner().has_space_for_any_key(),
+            _ => false,
+        }
     }
 
-    fn find_parent_of(
-        &mut self,
-        target_page_id: PageId,
-        key: &[u8],
-    ) -> Result<Option<PageId>, BTreeError> {
-        let mut curr = self.root_page_id;
-        let mut parent = None;
+    /// Whether removing one entry cannot underflow this node, which is not the root.
+    fn safe_for_delete(&self) -> bool {
+        self.can_give()
+    }
 
-        loop {
-            if curr == target_page_id {
-                return Ok(parent);
-            }
+    fn can_give(&self) -> bool {
+        match self.kind() {
+            PageKind::BPlusLeaf => self.leaf().can_give_key(),
+            PageKind::BPlusInner => self.inner().can_give_key(),
+            _ => false,
+        }
+    }
+}
 
-            let frame = self
-                .bpm
-                .as_mut()
-                .fetch_page(curr)
-                .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-            let fid = frame.fid();
-            let view = frame.page_view();
+impl Drop for PageGuard<'_, '_> {
+    fn drop(&mut self) {
+        // Unlatch before unpinning, so no frame the pool may evict or write back is
+        // latched by anyone. Both happen under the pool lock, so whoever latches the page
+        // next and frees it cannot find it still pinned by us.
+        let mut bp = self.tree.pool();
+        self.latch = None;
+        if self.dirty {
+            bp.as_mut().mark_frame_dirty(self.fid);
+        }
+        bp.as_mut().unpin_frame(self.fid).ok();
+    }
+}
 
-            let next_id = match view {
-                Page::BPlusInner(inner) => {
-                    let idx = inner.find_child_for_key(key);
-                    Some(inner.get_child_at(idx).unwrap())
-                }
-                _ => None,
-            };
+impl<'a> BPlusTree<'a> {
+    pub fn new(bpm: Pin<&'a mut BufferPool>, root_page_id: PageId) -> Self {
+        Self {
+            latches: bpm.latches(),
+            pool: TreePool::Borrowed(Mutex::new(bpm)),
+            root_page_id: AtomicU32::new(root_page_id),
+            segment: OnceLock::new(),
+        }
+    }
 
-            self.bpm.as_mut().unpin_frame(fid).ok();
+    /// A tree whose pages go to file `segment`, for building a new tree in a segment.
+    pub fn in_segment(bpm: Pin<&'a mut BufferPool>, root_page_id: PageId, segment: u32) -> Self {
+        let tree = Self::new(bpm, root_page_id);
+        let _ = tree.segment.set(segment);
+        tree
+    }
 
-            if let Some(next) = next_id {
-                parent = Some(curr);
-                curr = next;
-            } else {
-                return Ok(None);
-            }
+    /// A tree that only locks the pool to pin, unpin and allocate pages, so many threads
+    /// can insert, delete and look up keys in it at once. It cannot be iterated.
+    pub fn shared(bpm: &'a Mutex<BufferPool>, root_page_id: PageId) -> Self {
+        let latches = bpm.lock().expect("Lock poisoned").latches();
+        Self {
+            pool: TreePool::Shared(bpm),
+            latches,
+            root_page_id: AtomicU32::new(root_page_id),
+            segment: OnceLock::new(),
         }
     }
 
-    fn adjust_root(&mut self) -> Result<(), BTreeError> {
-        let frame = self
-            .bpm
-            .as_mut()
-            .fetch_page(self.root_page_id)
-            .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-        let fid = frame.fid();
-        let view = frame.page_view();
-
-        let should_update = match view {
-            Page::BPlusLeaf(leaf) => leaf.num_entries() == 0,
-            Page::BPlusInner(inner) => inner.num_entries() == 0,
-            _ => false,
-        };
+    /// The root page id, 0 while the tree is empty.
+    pub fn root_page_id(&self) -> PageId {
+        self.root_page_id.load(Ordering::SeqCst)
+    }
 
-        let new_root = if should_update {
-            match frame.page_view() {
-                Page::BPlusInner(inner) => Some(inner.get_child_at(0).unwrap()),
-                _ => None,
-            }
-        } else {
-            self.bpm.as_mut().unpin_frame(fid).ok();
-            return Ok(());
-        };
+    /// The borrowed pool, for walking the leaves without the tree.
+    pub(crate) fn into_pool(self) -> Option<Pin<&'a mut BufferPool>> {
+        match self.pool {
+            TreePool::Borrowed(bpm) => Some(bpm.into_inner().expect("Lock poisoned")),
+            TreePool::Shared(_) => None,
+        }
+    }
 
-        self.bpm.as_mut().unpin_frame(fid).ok();
+    fn pool(&self) -> PoolGuard<'_, 'a> {
+        match &self.pool {
+            TreePool::Borrowed(bpm) => PoolGuard::Borrowed(bpm.lock().expect("Lock poisoned")),
+            TreePool::Shared(bpm) => PoolGuard::Shared(bpm.lock().expect("Lock poisoned")),
+        }
+    }
 
-        if let Some(new_root_id) = new_root {
-            self.root_page_id = new_root_id;
-            let frame = self
-                .bpm
+    /// Pins and latches a page of the tree.
+    fn fetch(&self, page_id: PageId, exclusive: bool) -> Result<PageGuard<'_, 'a>, BTreeError> {
+        let (fid, buf) = {
+            let mut bp = self.pool();
+            let frame = bp
                 .as_mut()
-                .fetch_page(new_root_id)
+                .fetch_page(page_id, AccessStrategy::Normal)
                 .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-            let fid = frame.fid();
-            if let Page::BPlusInner(inner) = &mut frame.page_view() {
-                inner.set_root(true);
-            }
-            self.bpm.as_mut().mark_frame_dirty(fid);
-            self.bpm.as_mut().unpin_frame(fid).ok();
+            (frame.fid(), frame.buf_ptr())
+        };
+        // The pin keeps the frame from being reused while we wait for the latch
+        let latch = if exclusive {
+            Latch::Exclusive(self.latches.write(fid))
         } else {
-            self.root_page_id = 0;
-        }
+            Latch::Shared(self.latches.read(fid))
+        };
+        Ok(PageGuard {
+            tree: self,
+            page_id,
+            fid,
+            buf,
+            latch: Some(latch),
+            dirty: false,
+        })
+    }
 
-        Ok(())
+    /// The file the tree's pages go to.
+    fn segment(&self) -> Result<u32, BTreeError> {
+        if let Some(&segment) = self.segment.get() {
+            return Ok(segment);
+        }
+        let root_page_id = self.root_page_id();
+        if root_page_id == 0 {
+            return Ok(disk::MAIN_FILE_ID);
+        }
+        let segment = self
+            .pool()
+            .as_mut()
+            .segment_of(root_page_id)
+            .map_err(BTreeError::AllocPage)?;
+        Ok(*self.segment.get_or_init(|| segment))
     }
 
-    fn handle_underflow(
-        &mut self,
-        page_id: PageId,
-        parent_id: PageId,
-        _key: &[u8],
-    ) -> Result<(), BTreeError> {
-        let (left_sib, right_sib, index_in_parent) = {
-            let frame = self
-                .bpm
+    /// Allocates a page in the tree's file and records it in the page directory, so it can
+    /// be located after eviction. It is returned exclusively latched and uninitialized.
+    fn alloc(&self, kind: PageKind, counter: &AtomicU32) -> Result<PageGuard<'_, 'a>, BTreeError> {
+        let segment = self.segment()?;
+        let page_id = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let (fid, buf) = {
+            let mut bp = self.pool();
+            let frame = bp
                 .as_mut()
-                .fetch_page(parent_id)
-                .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-            let fid = frame.fid();
-            let view = frame.page_view();
-
-            if let Page::BPlusInner(inner) = view {
-                let idx = inner
-                    .lookup_child_index(page_id)
-                    .ok_or(BTreeError::DeleteError("Parent child mismatch".into()))?;
+                .alloc_new_page_in(kind, page_id, segment, AccessStrategy::Normal)
+                .map_err(|e| BTreeError::AllocPage(format!("{:?}", e)))?;
+            let (fid, buf, offset) = (frame.fid(), frame.buf_ptr(), frame.file_offset());
+            // Index pages never hold heap tuples, so they advertise no free space
+            if let Err(e) = bp
+                .as_mut()
+                .expand_directory_and_register(page_id, offset, 0, counter)
+            {
+                bp.as_mut().unpin_frame(fid).ok();
+                return Err(BTreeError::AllocPage(e));
+            }
+            (fid, buf)
+        };
+        Ok(PageGuard {
+            tree: self,
+            page_id,
+            fid,
+            buf,
+            latch: Some(Latch::Exclusive(self.latches.write(fid))),
+            dirty: true,
+        })
+    }
 
-                let left = if idx > 0 {
-                    inner.get_child_at(idx - 1)
-                } else {
-                    None
-                };
-                let right = inner.get_child_at(idx + 1);
+    /// Returns a node that was unlinked from the tree to the pool. Nothing can reach it
+    /// any more: its parent entry and sibling links were removed under exclusive latches.
+    fn free_node(&self, page_id: PageId) -> Result<(), BTreeError> {
+        self.pool()
+            .as_mut()
+            .free_page(page_id)
+            .map_err(BTreeError::FreePage)
+    }
 
-                self.bpm.as_mut().unpin_frame(fid).ok();
-                (left, right, idx)
-            } else {
-                self.bpm.as_mut().unpin_frame(fid).ok();
+    /// Descends with shared latches and returns the leaf that should hold `key`, latched
+    /// exclusively if `exclusive` is set. `None` if the tree is empty.
+    fn descend(
+        &self,
+        key: &[u8],
+        exclusive: bool,
+    ) -> Result<Option<PageGuard<'_, 'a>>, BTreeError> {
+        loop {
+            let root_page_id = self.root_page_id();
+            if root_page_id == 0 {
+                return Ok(None);
+            }
+            let mut page = self.fetch(root_page_id, false)?;
+            let mut parent = None;
+
+            while page.kind() == PageKind::BPlusInner {
+                let inner = page.inner();
+                let child_id = inner
+                    .get_child_at(inner.find_child_for_key(key))
+                    .ok_or(BTreeError::InvalidPageType)?;
+                let child = self.fetch(child_id, false)?;
+                // Releases the grandparent only now that the child is latched
+                parent = Some(std::mem::replace(&mut page, child));
+            }
+            if page.kind() != PageKind::BPlusLeaf {
                 return Err(BTreeError::InvalidPageType);
             }
-        };
+            if !exclusive {
+                return Ok(Some(page));
+            }
 
-        if let Some(left_id) = left_sib {
-            if self.redistribute(left_id, page_id, parent_id, index_in_parent - 1, true)? {
-                return Ok(());
+            // The parent's latch keeps the leaf from being split or merged away while the
+            // latches are traded. A root leaf has no parent and may have become an inner
+            // page meanwhile, which only needs another descent.
+            page.relatch_exclusive();
+            if page.kind() == PageKind::BPlusLeaf {
+                return Ok(Some(page));
             }
+            drop(parent);
         }
+    }
 
-        if let Some(right_id) = right_sib {
-            if self.redistribute(right_id, page_id, parent_id, index_in_parent, false)? {
-                return Ok(());
-            }
+    /// Traverses the tree from Root -> Leaf for a given key.
+    /// Returns the PageId of the leaf node that *should* contain the key. Other threads
+    /// may split that leaf by the time the caller looks at it.
+    pub fn find_leaf_page_id(&self, key: &[u8]) -> Result<PageId, BTreeError> {
+        self.descend(key, false)?
+            .map(|leaf| leaf.page_id)
+            .ok_or(BTreeError::InvalidPageType)
+    }
+
+    pub fn get_value(&self, key: &[u8]) -> Result<Option<u64>, BTreeError> {
+        Ok(self
+            .descend(key, false)?
+            .and_then(|leaf| leaf.leaf().get_value(key)))
+    }
+
+    // ========================= INSERTION LOGIC =========================
+
+    pub fn insert(
+        &self,
+        key: &[u8],
+        value: u64,
+        page_id_counter: &AtomicU32,
+    ) -> Result<(), BTreeError> {
+        self.insert_with_payload(key, value, &[], page_id_counter)
+    }
+
+    /// Inserts a key-value pair and stores `payload` next to it in the leaf, overwriting
+    /// an existing key. Every entry of a tree must carry a payload of the same length.
+    pub fn insert_with_payload(
+        &self,
+        key: &[u8],
+        value: u64,
+        payload: &[u8],
+        page_id_counter: &AtomicU32,
+    ) -> Result<(), BTreeError> {
+        if self.root_page_id() == 0 && self.create_root(key, value, payload, page_id_counter)? {
+            return Ok(());
         }
 
-        if let Some(left_id) = left_sib {
-            self.coalesce(left_id, page_id, parent_id, index_in_parent - 1)
-        } else if let Some(right_id) = right_sib {
-            self.coalesce(page_id, right_id, parent_id, index_in_parent)
-        } else {
-            Err(BTreeError::DeleteError("Cannot merge: no siblings".into()))
+        if let Some(mut leaf) = self.descend(key, true)? {
+            if leaf.leaf().payload_size() as usize != payload.len() {
+                return Err(BTreeError::InsertError("Payload size mismatch".into()));
+            }
+            let view = leaf.leaf();
+            if view.get_value(key).is_some() || view.has_space_for_key(key) {
+                leaf.leaf_mut()
+                    .insert_sorted_with_payload(key, value, payload);
+                return Ok(());
+            }
         }
+        self.insert_pessimistic(key, value, payload, page_id_counter)
     }
 
-    fn redistribute(
-        &mut self,
-        sibling_id: PageId,
-        node_id: PageId,
-        parent_id: PageId,
-        parent_key_idx: usize,
-        is_left_sibling: bool,
+    /// Makes a root leaf holding just this entry. False if another thread created the root
+    /// first, in which case the entry still has to be inserted.
+    fn create_root(
+        &self,
+        key: &[u8],
+        value: u64,
+        payload: &[u8],
+        counter: &AtomicU32,
     ) -> Result<bool, BTreeError> {
-        // Check if sibling can give
-        let can_give = {
-            let frame = self
-                .bpm
-                .as_mut()
-                .fetch_page(sibling_id)
-                .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-            let view = frame.page_view();
-            let res = match view {
-                Page::BPlusLeaf(leaf) => leaf.can_give_key(),
-                Page::BPlusInner(inner) => inner.can_give_key(),
-                _ => false,
-            };
-            let fid = frame.fid();
-            self.bpm.as_mut().unpin_frame(fid).ok();
-            res
-        };
+        let mut page = self.alloc(PageKind::BPlusLeaf, counter)?;
+        let page_id = page.page_id;
+        let mut leaf = page.leaf_mut();
+        leaf.init(page_id, key.len() as u32);
+        leaf.set_payload_size(payload.len() as u16);
+        leaf.insert_sorted_with_payload(key, value, payload);
+
+        if self
+            .root_page_id
+            .compare_exchange(0, page_id, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            return Ok(true);
+        }
+        drop(page);
+        self.free_node(page_id)?;
+        Ok(false)
+    }
 
-        if !can_give {
-            return Ok(false);
+    fn insert_pessimistic(
+        &self,
+        key: &[u8],
+        value: u64,
+        payload: &[u8],
+        counter: &AtomicU32,
+    ) -> Result<(), BTreeError> {
+        let root_page_id = self.root_page_id();
+
+        // Exclusively latched path from the highest node that may split down to the leaf
+        let mut path = vec![self.fetch(root_page_id, true)?];
+        while path.last().unwrap().kind() == PageKind::BPlusInner {
+            let inner = path.last().unwrap().inner();
+            let child_id = inner
+                .get_child_at(inner.find_child_for_key(key))
+                .ok_or(BTreeError::InvalidPageType)?;
+            let child = self.fetch(child_id, true)?;
+            if child.safe_for_insert(key) {
+                path.clear();
+            }
+            path.push(child);
         }
 
-        // Fetch pages one by one to avoid borrowing self.bpm multiple times
-        let (p_fid, p_ptr) = {
-            let frame = self.bpm.as_mut().fetch_page(parent_id).unwrap();
-            (
-                frame.fid(),
-                frame.page_view().raw_mut() as *mut crate::storage::page::base::PageBuf,
-            )
-        };
+        let mut leaf = path.pop().unwrap();
+        if leaf.kind() != PageKind::BPlusLeaf {
+            return Err(BTreeError::InvalidPageType);
+        }
+        if leaf.leaf().payload_size() as usize != payload.len() {
+            return Err(BTreeError::InsertError("Payload size mismatch".into()));
+        }
+        let view = leaf.leaf();
+        if view.get_value(key).is_some() || view.has_space_for_key(key) {
+            leaf.leaf_mut()
+                .insert_sorted_with_payload(key, value, payload);
+            return Ok(());
+        }
 
-        let (s_fid, s_ptr) = {
-            let frame = self.bpm.as_mut().fetch_page(sibling_id).unwrap();
-            (
-                frame.fid(),
-                frame.page_view().raw_mut() as *mut crate::storage::page::base::PageBuf,
-            )
-        };
+        let (split_res, new_entries) = leaf
+            .leaf_mut()
+            .split_and_get_new_entries(key, value, payload)
+            .map_err(|e| BTreeError::InsertError(e.to_string()))?;
 
-        let (n_fid, n_ptr) = {
-            let frame = self.bpm.as_mut().fetch_page(node_id).unwrap();
-            (
-                frame.fid(),
-                frame.page_view().raw_mut() as *mut crate::storage::page::base::PageBuf,
-            )
-        };
+        let mut new_leaf = self.alloc(PageKind::BPlusLeaf, counter)?;
+        let new_leaf_id = new_leaf.page_id;
+        let old_next = leaf.leaf().next_sibling();
+        {
+            let mut view = new_leaf.leaf_mut();
+            view.init(new_leaf_id, key.len() as u32);
+            view.set_payload_size(payload.len() as u16);
+            for (k, v, p) in new_entries {
+                view.insert_sorted_with_payload(&k, v, &p);
+            }
+            view.set_prev_sibling(Some(leaf.page_id));
+            view.set_next_sibling(old_next);
+        }
+        leaf.leaf_mut().set_next_sibling(Some(new_leaf_id));
+        if let Some(next_id) = old_next {
+            // Left to right, like every other sideways latch in the tree
+            let mut next = self.fetch(next_id, true)?;
+            next.leaf_mut().set_prev_sibling(Some(new_leaf_id));
+        }
+        drop(new_leaf);
+
+        // Walk back up, handing each split to the parent
+        let mut split_key = split_res.split_key;
+        let mut right_id = new_leaf_id;
+        let mut node = leaf;
+        while let Some(mut parent) = path.pop() {
+            drop(node);
+            if parent.inner().has_space_for_key(&split_key) {
+                let mut inner = parent.inner_mut();
+                let idx = inner.find_child_for_key(&split_key);
+                inner.insert_at(idx, &split_key, right_id);
+                return Ok(());
+            }
 
-        unsafe {
-            let mut parent_view = crate::storage::page::BPlusInner::new(&mut *p_ptr);
-            // We need to interpret sibling and node as either Inner or Leaf
-            // We'll use PageKind from header to decide
-            let s_kind = crate::storage::page::base::page_kind_from_buf(&*s_ptr);
-            let n_kind = crate::storage::page::base::page_kind_from_buf(&*n_ptr);
-
-            match (s_kind, n_kind) {
-                (PageKind::BPlusLeaf, PageKind::BPlusLeaf) => {
-                    let mut sibling = crate::storage::page::BPlusLeaf::new(&mut *s_ptr);
-                    let mut node = crate::storage::page::BPlusLeaf::new(&mut *n_ptr);
-
-                    if is_left_sibling {
-                        let moved_key = sibling.move_last_to_beginning_of(&mut node);
-                        parent_view.set_entry(parent_key_idx, &moved_key, node_id);
-                    } else {
-                        let new_separator = sibling.move_first_to_end_of(&mut node);
-                        parent_view.set_entry(parent_key_idx, &new_separator, sibling_id);
-                    }
-                }
-                (PageKind::BPlusInner, PageKind::BPlusInner) => {
-                    let mut sibling = crate::storage::page::BPlusInner::new(&mut *s_ptr);
-                    let mut node = crate::storage::page::BPlusInner::new(&mut *n_ptr);
-                    let separator_key = parent_view.get_key_at(parent_key_idx).to_vec();
-
-                    if is_left_sibling {
-                        let new_sep = sibling.move_last_to_beginning_of(&mut node, &separator_key);
-                        parent_view.set_entry(parent_key_idx, &new_sep, node_id);
-                    } else {
-                        let new_sep = sibling.move_first_to_end_of(&mut node, &separator_key);
-                        parent_view.set_entry(parent_key_idx, &new_sep, sibling_id);
-                    }
+            let split_data = parent
+                .inner_mut()
+                .split_and_get_new_entries(&split_key, right_id);
+            let level = parent.inner().page_level();
+            let mut new_inner = self.alloc(PageKind::BPlusInner, counter)?;
+            let new_inner_id = new_inner.page_id;
+            {
+                let mut view = new_inner.inner_mut();
+                view.init(new_inner_id, level, split_key.len() as u32);
+                view.set_child_at(0, split_data.new_page_children[0]);
+                for (i, k) in split_data.new_page_keys.iter().enumerate() {
+                    view.insert_at(i, k, split_data.new_page_children[i + 1]);
                 }
-                _ => panic!("Mismatch types in redistribute"),
             }
+            split_key = split_data.key_to_push_up;
+            right_id = new_inner_id;
+            node = parent;
         }
 
-        self.bpm.as_mut().mark_frame_dirty(p_fid);
-        self.bpm.as_mut().mark_frame_dirty(s_fid);
-        self.bpm.as_mut().mark_frame_dirty(n_fid);
-
-        self.bpm.as_mut().unpin_frame(p_fid).ok();
-        self.bpm.as_mut().unpin_frame(s_fid).ok();
-        self.bpm.as_mut().unpin_frame(n_fid).ok();
-
-        Ok(true)
+        // The path only runs out above a node that split if that node is the root
+        if node.page_id != root_page_id {
+            return Err(BTreeError::InsertError(
+                "Split node has no latched parent".into(),
+            ));
+        }
+        self.grow_root(node, split_key, right_id, counter)
     }
 
-    fn coalesce(
-        &mut self,
-        left_id: PageId,
-        right_id: PageId,
-        parent_id: PageId,
-        parent_key_idx: usize,
+    /// Grows the tree by one level after the root split into itself and `right_child_id`.
+    /// The root's contents move to a fresh page that becomes its left child, and the root
+    /// page is rewritten as an inner node above both, so the root page id stays the same.
+    fn grow_root(
+        &self,
+        mut root: PageGuard,
+        key: Vec<u8>,
+        right_child_id: PageId,
+        counter: &AtomicU32,
     ) -> Result<(), BTreeError> {
-        // Fetch pages one by one
-        let (p_fid, p_ptr) = {
-            let frame = self.bpm.as_mut().fetch_page(parent_id).unwrap();
-            (
-                frame.fid(),
-                frame.page_view().raw_mut() as *mut crate::storage::page::base::PageBuf,
-            )
+        let mut left = self.alloc(root.kind(), counter)?;
+        let left_child_id = left.page_id;
+        left.copy_from(&root);
+
+        let level = match left.kind() {
+            PageKind::BPlusLeaf => {
+                // A split root leaf has exactly one sibling, which must point back at the
+                // moved copy
+                let mut right = self.fetch(right_child_id, true)?;
+                right.leaf_mut().set_prev_sibling(Some(left_child_id));
+                0
+            }
+            PageKind::BPlusInner => {
+                left.inner_mut().set_root(false);
+                left.inner().page_level()
+            }
+            _ => return Err(BTreeError::InvalidPageType),
         };
+        drop(left);
+
+        let root_page_id = root.page_id;
+        let mut inner = root.inner_mut();
+        inner.init(root_page_id, level + 1, key.len() as u32);
+        inner.set_root(true);
+        inner.set_child_at(0, left_child_id);
+        inner.insert_at(0, &key, right_child_id);
+        Ok(())
+    }
 
-        let (l_fid, l_ptr) = {
-            let frame = self.bpm.as_mut().fetch_page(left_id).unwrap();
-            (
-                frame.fid(),
-                frame.page_view().raw_mut() as *mut crate::storage::page::base::PageBuf,
-            )
-        };
+    // ========================= DELETION LOGIC =========================
 
-        let (r_fid, r_ptr) = {
-            let frame = self.bpm.as_mut().fetch_page(right_id).unwrap();
-            (
-                frame.fid(),
-                frame.page_view().raw_mut() as *mut crate::storage::page::base::PageBuf,
-            )
+    pub fn delete(&self, key: &[u8]) -> Result<(), BTreeError> {
+        let Some(mut leaf) = self.descend(key, true)? else {
+            return Ok(()); // Empty tree
         };
+        let view = leaf.leaf();
+        if view.get_value(key).is_none() {
+            return Ok(());
+        }
+        // The root leaf may run empty
+        if leaf.page_id == self.root_page_id() || view.can_give_key() {
+            leaf.leaf_mut().remove_key(key);
+            return Ok(());
+        }
+        drop(leaf);
+        self.delete_pessimistic(key)
+    }
 
-        unsafe {
-            let mut parent_view = crate::storage::page::BPlusInner::new(&mut *p_ptr);
-            let l_kind = crate::storage::page::base::page_kind_from_buf(&*l_ptr);
-
-            match l_kind {
-                PageKind::BPlusLeaf => {
-                    let mut left = crate::storage::page::BPlusLeaf::new(&mut *l_ptr);
-                    let mut right = crate::storage::page::BPlusLeaf::new(&mut *r_ptr);
-                    left.merge_from(&mut right);
-                }
-                PageKind::BPlusInner => {
-                    let mut left = crate::storage::page::BPlusInner::new(&mut *l_ptr);
-                    let mut right = crate::storage::page::BPlusInner::new(&mut *r_ptr);
-                    let separator = parent_view.get_key_at(parent_key_idx).to_vec();
-                    left.merge_from(&mut right, &separator);
-                }
-                _ => panic!("Invalid page type in coalesce"),
+    fn delete_pessimistic(&self, key: &[u8]) -> Result<(), BTreeError> {
+        let root_page_id = self.root_page_id();
+
+        // Exclusively latched path from the highest node that may underflow down to the leaf
+        let mut path = vec![self.fetch(root_page_id, true)?];
+        while path.last().unwrap().kind() == PageKind::BPlusInner {
+            let inner = path.last().unwrap().inner();
+            let child_id = inner
+                .get_child_at(inner.find_child_for_key(key))
+                .ok_or(BTreeError::InvalidPageType)?;
+            let child = self.fetch(child_id, true)?;
+            if child.safe_for_delete() {
+                path.clear();
             }
-
-            // Remove from parent
-            parent_view.remove_at(parent_key_idx);
+            path.push(child);
         }
 
-        self.bpm.as_mut().mark_frame_dirty(p_fid);
-        self.bpm.as_mut().mark_frame_dirty(l_fid);
-        self.bpm.as_mut().mark_frame_dirty(r_fid);
+        let mut node = path.pop().unwrap();
+        if node.kind() != PageKind::BPlusLeaf {
+            return Err(BTreeError::InvalidPageType);
+        }
+        if !node.leaf_mut().remove_key(key) || !node.leaf().is_underflow() {
+            return Ok(());
+        }
 
-        self.bpm.as_mut().unpin_frame(p_fid).ok();
-        self.bpm.as_mut().unpin_frame(l_fid).ok();
-        self.bpm.as_mut().unpin_frame(r_fid).ok();
+        // Walk back up, fixing each underflowing node with help from a sibling
+        while let Some(mut parent) = path.pop() {
+            let idx = parent
+                .inner()
+                .lookup_child_index(node.page_id)
+                .ok_or(BTreeError::DeleteError("Parent child mismatch".into()))?;
 
-        // Recursively check if parent underflowed
-        let (p_underflow, search_key) = {
-            let frame = self.bpm.as_mut().fetch_page(parent_id).unwrap();
-            let is_uf = match frame.page_view() {
-                Page::BPlusInner(inner) => inner.is_underflow(),
-                _ => false,
-            };
-            let fid = frame.fid();
-            self.bpm.as_mut().unpin_frame(fid).ok();
-
-            // Get a key to find the parent's parent
-            let l_frame = self.bpm.as_mut().fetch_page(left_id).unwrap();
-            let key = match l_frame.page_view() {
-                Page::BPlusLeaf(l) => l.get_first_key(),
-                Page::BPlusInner(i) => i.get_first_key(),
-                _ => None,
+            let left_id = if idx > 0 {
+                parent.inner().get_child_at(idx - 1)
+            } else {
+                None
             };
-            let l_fid = l_frame.fid();
-            self.bpm.as_mut().unpin_frame(l_fid).ok();
+            let right_id = parent.inner().get_child_at(idx + 1);
 
-            (is_uf, key)
-        };
+            let mut left = left_id.map(|id| self.fetch(id, true)).transpose()?;
+            if let Some(sibling) = left.as_mut()
+                && sibling.can_give()
+                && Self::borrow_from_left(&mut parent, sibling, &mut node, idx - 1)
+            {
+                return Ok(());
+            }
+            let mut right = right_id.map(|id| self.fetch(id, true)).transpose()?;
+            if let Some(sibling) = right.as_mut()
+                && sibling.can_give()
+                && Self::borrow_from_right(&mut parent, &mut node, sibling, idx)
+            {
+                return Ok(());
+            }
+            if left.is_none() && right.is_none() {
+                return Err(BTreeError::DeleteError("Cannot merge: no siblings".into()));
+            }
 
-        if p_underflow && parent_id != self.root_page_id {
-            if let Some(key) = search_key {
-                if let Some(grandparent_id) = self.find_parent_of(parent_id, &key)? {
-                    self.handle_underflow(parent_id, grandparent_id, &key)?;
-                } else if parent_id == self.root_page_id {
-                    self.adjust_root()?;
+            let freed = if let Some(mut left) = left
+                && Self::can_merge(&parent, &left, &node, idx - 1)
+            {
+                // The right sibling may be the leaf whose back link the merge rewrites
+                drop(right);
+                self.coalesce(&mut parent, &mut left, &mut node, idx - 1)?;
+                node.page_id
+            } else if let Some(mut right) = right
+                && Self::can_merge(&parent, &node, &right, idx)
+            {
+                self.coalesce(&mut parent, &mut node, &mut right, idx)?;
+                right.page_id
+            } else {
+                // Compressed neighbours with too little in common to share a page: leave
+                // the node underfull rather than overflow
+                return Ok(());
+            };
+            drop(node);
+            self.free_node(freed)?;
+
+            if parent.page_id == root_page_id {
+                if parent.inner().num_entries() == 0 {
+                    self.collapse_root(parent)?;
                 }
+                return Ok(());
+            }
+            if !parent.inner().is_underflow() {
+                return Ok(());
             }
-        } else if parent_id == self.root_page_id {
-            self.adjust_root()?;
+            node = parent;
         }
-
         Ok(())
     }
 
-    pub fn get_value(&mut self, key: &[u8]) -> Result<Option<u64>, BTreeError> {
-        if self.root_page_id == 0 {
-            return Ok(None);
+    /// Pulls the only child of a root inner page up into the root page, so the root page
+    /// id never changes. The child has no siblings, so its contents can move as they are.
+    fn collapse_root(&self, mut root: PageGuard) -> Result<(), BTreeError> {
+        let child_id = root
+            .inner()
+            .get_child_at(0)
+            .ok_or(BTreeError::InvalidPageType)?;
+        let child = self.fetch(child_id, true)?;
+        root.copy_from(&child);
+        if root.kind() == PageKind::BPlusInner {
+            root.inner_mut().set_root(true);
         }
+        drop(child);
+        self.free_node(child_id)
+    }
 
-        let leaf_page_id = self.find_leaf_page_id(key)?;
-        let frame = self
-            .bpm
-            .as_mut()
-            .fetch_page(leaf_page_id)
-            .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-        let frame_id = frame.fid();
-        let page_view = frame.page_view();
-
-        let result = if let Page::BPlusLeaf(leaf) = page_view {
-            leaf.get_value(key)
+    /// Moves the last entry of the left `sibling` into `node`. Returns false, changing
+    /// nothing, when the entry or the new separator would not fit.
+    fn borrow_from_left(
+        parent: &mut PageGuard,
+        sibling: &mut PageGuard,
+        node: &mut PageGuard,
+        key_idx: usize,
+    ) -> bool {
+        let node_id = node.page_id;
+        if node.kind() == PageKind::BPlusLeaf {
+            let new_sep = sibling.leaf().separator_if_last_moved();
+            if !sibling.leaf().can_move_last_to(&node.leaf())
+                || !parent.inner().can_set_key_at(key_idx, &new_sep)
+            {
+                return false;
+            }
+            sibling
+                .leaf_mut()
+                .move_last_to_beginning_of(&mut node.leaf_mut());
+            parent.inner_mut().set_entry(key_idx, &new_sep, node_id);
         } else {
-            self.bpm.as_mut().unpin_frame(frame_id).ok();
-            return Err(BTreeError::InvalidPageType);
-        };
-
-        self.bpm
-            .as_mut()
-            .unpin_frame(frame_id)
-            .map_err(|e| BTreeError::UnpinPage(format!("{:?}", e)))?;
-
-        Ok(result)
+            let separator = parent.inner().get_key_at(key_idx);
+            let last = sibling.inner().num_entries() as usize - 1;
+            if !sibling.inner().can_move_last_to(&node.inner(), &separator)
+                || !parent
+                    .inner()
+                    .can_set_key_at(key_idx, &sibling.inner().get_key_at(last))
+            {
+                return false;
+            }
+            let new_sep = sibling
+                .inner_mut()
+                .move_last_to_beginning_of(&mut node.inner_mut(), &separator);
+            parent.inner_mut().set_entry(key_idx, &new_sep, node_id);
+        }
+        true
     }
 
-    pub fn insert(
-        &mut self,
-        key: &[u8],
-        value: u64,
-        page_id_counter: &AtomicU32,
-    ) -> Result<(), BTreeError> {
-        if self.root_page_id == 0 {
-            let new_root_id = page_id_counter.fetch_add(1, Ordering::SeqCst) + 1;
-            let frame = self
-                .bpm
-                .as_mut()
-                .alloc_new_page(PageKind::BPlusLeaf, new_root_id)
-                .map_err(|e| BTreeError::AllocPage(format!("{:?}", e)))?;
-
+    /// Moves the first entry of the right `sibling` into `node`. Returns false, changing
+    /// nothing, when the entry or the new separator would not fit.
+    fn borrow_from_right(
+        parent: &mut PageGuard,
+        node: &mut PageGuard,
+        sibling: &mut PageGuard,
+        key_idx: usize,
+    ) -> bool {
+        let sibling_id = sibling.page_id;
+        if node.kind() == PageKind::BPlusLeaf {
+            let new_sep = sibling.leaf().separator_if_first_moved();
+            if !sibling.leaf().can_move_first_to(&node.leaf())
+                || !parent.inner().can_set_key_at(key_idx, &new_sep)
             {
-                let mut view = frame.page_view();
-                if let Page::BPlusLeaf(leaf) = &mut view {
-                    leaf.init(new_root_id, key.len() as u32);
-                    leaf.insert_sorted(key, value);
-                }
+                return false;
             }
-            self.root_page_id = new_root_id;
-            let fid = frame.fid();
-            self.bpm.as_mut().unpin_frame(fid).ok();
-            return Ok(());
+            sibling
+                .leaf_mut()
+                .move_first_to_end_of(&mut node.leaf_mut());
+            parent.inner_mut().set_entry(key_idx, &new_sep, sibling_id);
+        } else {
+            let separator = parent.inner().get_key_at(key_idx);
+            if !sibling.inner().can_move_first_to(&node.inner(), &separator)
+                || !parent
+                    .inner()
+                    .can_set_key_at(key_idx, &sibling.inner().get_key_at(0))
+            {
+                return false;
+            }
+            let new_sep = sibling
+                .inner_mut()
+                .move_first_to_end_of(&mut node.inner_mut(), &separator);
+            parent.inner_mut().set_entry(key_idx, &new_sep, sibling_id);
         }
+        true
+    }
 
-        let path = self.find_path_to_leaf(key)?;
-        let leaf_id = *path.last().unwrap();
-
-        let split_result = self.insert_into_leaf(leaf_id, key, value, page_id_counter)?;
-
-        if let Some((split_key, new_node_id)) = split_result {
-            self.insert_into_parent(path, split_key, new_node_id, page_id_counter)?;
+    /// Whether `right` fits into `left` in one page.
+    fn can_merge(parent: &PageGuard, left: &PageGuard, right: &PageGuard, key_idx: usize) -> bool {
+        if left.kind() == PageKind::BPlusLeaf {
+            left.leaf().can_merge_from(&right.leaf())
+        } else {
+            let separator = parent.inner().get_key_at(key_idx);
+            left.inner().can_merge_from(&right.inner(), &separator)
         }
+    }
 
+    /// Merges `right` into `left` and drops their separator from `parent`.
+    fn coalesce(
+        &self,
+        parent: &mut PageGuard,
+        left: &mut PageGuard,
+        right: &mut PageGuard,
+        key_idx: usize,
+    ) -> Result<(), BTreeError> {
+        if left.kind() == PageKind::BPlusLeaf {
+            left.leaf_mut().merge_from(&mut right.leaf_mut());
+            // The leaf after the merged one must point back at the survivor
+            if let Some(next_id) = left.leaf().next_sibling() {
+                let mut next = self.fetch(next_id, true)?;
+                next.leaf_mut().set_prev_sibling(Some(left.page_id));
+            }
+        } else {
+            let separator = parent.inner().get_key_at(key_idx);
+            left.inner_mut()
+                .merge_from(&mut right.inner_mut(), &separator);
+        }
+        parent.inner_mut().remove_at(key_idx);
         Ok(())
     }
 
-    fn find_path_to_leaf(&mut self, key: &[u8]) -> Result<Vec<PageId>, BTreeError> {
-        let mut path = Vec::new();
-        let mut curr = self.root_page_id;
+    // ========================= BULK LOAD =========================
 
-        loop {
-            path.push(curr);
-            let frame = self
-                .bpm
-                .as_mut()
-                .fetch_page(curr)
-                .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-            let frame_id = frame.fid();
-            let page_view = frame.page_view();
+    /// Builds the tree bottom-up from `entries`, which must be sorted by key.
+    /// Leaves are packed left to right and every inner level is built from the low keys
+    /// of the level below, so each page is written once instead of split repeatedly.
+    /// Only valid on an empty tree. Duplicate keys keep the last value, like `insert`.
+    pub fn bulk_load(
+        &mut self,
+        key_size: u32,
+        entries: &[(Vec<u8>, u64)],
+        page_id_counter: &AtomicU32,
+    ) -> Result<(), BTreeError> {
+        let entries: Vec<LeafEntry> = entries
+            .iter()
+            .map(|(k, v)| (k.clone(), *v, Vec::new()))
+            .collect();
+        self.bulk_load_with_payload(key_size, 0, &entries, 100, page_id_counter)
+    }
 
-            let next = match page_view {
-                Page::BPlusInner(inner) => {
-                    let idx = inner.find_child_for_key(key);
-                    inner.get_child_at(idx)
+    /// Same as `bulk_load`, with `payload_size` bytes of covering data stored per entry.
+    /// Leaves are filled to `fill_factor` percent of the page (10 to 100), leaving room
+    /// for later inserts before they split; inner pages are always packed full.
+    pub fn bulk_load_with_payload(
+        &mut self,
+        key_size: u32,
+        payload_size: u16,
+        entries: &[LeafEntry],
+        fill_factor: u8,
+        page_id_counter: &AtomicU32,
+    ) -> Result<(), BTreeError> {
+        if self.root_page_id() != 0 {
+            return Err(BTreeError::InsertError(
+                "Bulk load requires an empty tree".into(),
+            ));
+        }
+        if !(10..=100).contains(&fill_factor) {
+            return Err(BTreeError::InsertError(format!(
+                "Fill factor {} is outside 10..=100",
+                fill_factor
+            )));
+        }
+
+        let mut sorted: Vec<(&[u8], u64, &[u8])> = Vec::with_capacity(entries.len());
+        for (key, value, payload) in entries {
+            if key.len() != key_size as usize {
+                return Err(BTreeError::InsertError(
+                    "Bulk load key length mismatch".into(),
+                ));
+            }
+            if payload.len() != payload_size as usize {
+                return Err(BTreeError::InsertError("Payload size mismatch".into()));
+            }
+            match sorted.last_mut() {
+                Some(last) if last.0 == key.as_slice() => {
+                    last.1 = *value;
+                    last.2 = payload;
                 }
-                Page::BPlusLeaf(_) => None,
-                _ => {
-                    self.bpm.as_mut().unpin_frame(frame_id).ok();
-                    return Err(BTreeError::InvalidPageType);
+                Some(last) if last.0 > key.as_slice() => {
+                    return Err(BTreeError::InsertError(
+                        "Bulk load input is not sorted".into(),
+                    ));
                 }
-            };
-
-            self.bpm.as_mut().unpin_frame(frame_id).ok();
-
-            match next {
-                Some(id) => curr = id,
-                None => return Ok(path),
+                _ => sorted.push((key, *value, payload)),
             }
         }
-    }
-
-    fn insert_into_leaf(
-        &mut self,
-        page_id: PageId,
-        key: &[u8],
-        value: u64,
-        counter: &AtomicU32,
-    ) -> Result<Option<(Vec<u8>, PageId)>, BTreeError> {
-        let (split_info, frame_id) = {
-            let frame = self
-                .bpm
-                .as_mut()
-                .fetch_page(page_id)
-                .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-            let frame_id = frame.fid();
-            let mut page_view = frame.page_view();
 
-            if let Page::BPlusLeaf(leaf) = &mut page_view {
-                if leaf.has_space_for_key() {
-                    leaf.insert_sorted(key, value);
-                    (None, frame_id)
-                } else {
-                    let (split_res, new_entries) = leaf
-                        .split_and_get_new_entries(key, value)
-                        .map_err(|e| BTreeError::InsertError(e.to_string()))?;
-                    (Some((split_res.split_key, new_entries)), frame_id)
-                }
-            } else {
-                self.bpm.as_mut().unpin_frame(frame_id).ok();
-                return Err(BTreeError::InvalidPageType);
+        // 1. Leaf level: (low key, page id) of every leaf, left to right. The low key is the
+        // shortest separator from the previous leaf, so inner pages hold truncated keys.
+        let mut level: Vec<(Vec<u8>, PageId)> = Vec::new();
+        let leaf_budget = constants::storage::PAGE_SIZE * fill_factor as usize / 100;
+        let leaf_runs = pack_runs(sorted.len(), |a, b| {
+            let prefix_len = common_prefix_len(sorted[a].0, sorted[b - 1].0);
+            BPlusLeaf::bytes_needed(key_size as usize, payload_size as usize, prefix_len, b - a)
+                <= leaf_budget
+        });
+        let mut start = 0;
+
+        for size in leaf_runs {
+            let chunk = &sorted[start..start + size];
+            start += size;
+
+            let prev_leaf = level.last().map(|(_, id)| *id);
+
+            let mut page = self.alloc(PageKind::BPlusLeaf, page_id_counter)?;
+            let page_id = page.page_id;
+            let mut leaf = page.leaf_mut();
+            leaf.init(page_id, key_size);
+            leaf.set_payload_size(payload_size);
+            for (key, value, payload) in chunk {
+                leaf.insert_sorted_with_payload(key, *value, payload);
             }
-        };
+            leaf.set_prev_sibling(prev_leaf);
+            drop(page);
 
-        self.bpm.as_mut().mark_frame_dirty(frame_id);
-        self.bpm
-            .as_mut()
-            .unpin_frame(frame_id)
-            .map_err(|e| BTreeError::UnpinPage(format!("{:?}", e)))?;
+            if let Some(prev_id) = prev_leaf {
+                let mut prev = self.fetch(prev_id, true)?;
+                prev.leaf_mut().set_next_sibling(Some(page_id));
+            }
 
-        if split_info.is_none() {
-            return Ok(None);
+            let low_key = match chunk.first() {
+                Some((first, _, _)) if start > size => {
+                    shortest_separator(sorted[start - size - 1].0, first)
+                }
+                Some((first, _, _)) => first.to_vec(),
+                None => Vec::new(),
+            };
+            level.push((low_key, page_id));
         }
 
-        let (split_key, new_entries) = split_info.unwrap();
+        // 2. Inner levels: each node separates its children by their low keys
+        let mut height = 0;
 
-        let new_page_id = counter.fetch_add(1, Ordering::SeqCst) + 1;
-        let new_frame = self
-            .bpm
-            .as_mut()
-            .alloc_new_page(PageKind::BPlusLeaf, new_page_id)
-            .map_err(|e| BTreeError::AllocPage(format!("{:?}", e)))?;
-        let new_frame_id = new_frame.fid();
+        while level.len() > 1 {
+            height += 1;
+            let mut parents = Vec::new();
+            let mut start = 0;
 
-        {
-            let mut new_view = new_frame.page_view();
-            if let Page::BPlusLeaf(new_leaf) = &mut new_view {
-                new_leaf.init(new_page_id, key.len() as u32);
-                for (k, v) in new_entries {
-                    new_leaf.insert_sorted(&k, v);
+            // The first child's key is not stored, so a node over children a..b holds the
+            // keys of a+1..b. Every node needs at least one key.
+            let sig_lens: Vec<usize> = level.iter().map(|(k, _)| significant_len(k)).collect();
+            let runs = pack_runs(level.len(), |a, b| {
+                if b - a < 2 {
+                    return false;
+                }
+                let key_len = sig_lens[a + 1..b].iter().copied().max().unwrap_or(0);
+                let prefix_len = common_prefix_len(&level[a + 1].0, &level[b - 1].0).min(key_len);
+                BPlusInner::bytes_needed(prefix_len, key_len, b - a - 1)
+                    <= constants::storage::PAGE_SIZE
+            });
+
+            for size in runs {
+                let children = &level[start..start + size];
+                start += size;
+
+                let mut page = self.alloc(PageKind::BPlusInner, page_id_counter)?;
+                let page_id = page.page_id;
+                let mut inner = page.inner_mut();
+                inner.init(page_id, height, key_size);
+                inner.set_child_at(0, children[0].1);
+                for (i, (key, child_id)) in children[1..].iter().enumerate() {
+                    inner.insert_at(i, key, *child_id);
                 }
-                new_leaf.set_prev_sibling(Some(page_id));
+                drop(page);
+
+                parents.push((children[0].0.clone(), page_id));
             }
-        }
 
-        self.bpm.as_mut().mark_frame_dirty(new_frame_id);
-        self.bpm.as_mut().unpin_frame(new_frame_id).ok();
+            level = parents;
+        }
 
-        let old_frame = self
-            .bpm
-            .as_mut()
-            .fetch_page(page_id)
-            .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-        let old_frame_id = old_frame.fid();
-        let mut old_next_sibling_id = None;
+        let root_page_id = level[0].1;
+        if height > 0 {
+            self.fetch(root_page_id, true)?.inner_mut().set_root(true);
+        }
+        self.root_page_id.store(root_page_id, Ordering::SeqCst);
+        Ok(())
+    }
 
-        {
-            let mut old_view = old_frame.page_view();
-            if let Page::BPlusLeaf(old_leaf) = &mut old_view {
-                old_next_sibling_id = old_leaf.next_sibling();
-                old_leaf.set_next_sibling(Some(new_page_id));
-            }
+    /// Frees every page reachable from the root and leaves the tree empty.
+    pub fn destroy(&mut self) -> Result<(), BTreeError> {
+        let mut pending = Vec::new();
+        let mut pages = Vec::new();
+        if self.root_page_id() != 0 {
+            pending.push(self.root_page_id());
         }
-        self.bpm.as_mut().mark_frame_dirty(old_frame_id);
-        self.bpm.as_mut().unpin_frame(old_frame_id).ok();
 
-        if let Some(next_sib_id) = old_next_sibling_id {
-            let new_frame = self
-                .bpm
-                .as_mut()
-                .fetch_page(new_page_id)
-                .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-            let new_fid = new_frame.fid();
-            {
-                let mut new_view = new_frame.page_view();
-                if let Page::BPlusLeaf(new_leaf) = &mut new_view {
-                    new_leaf.set_next_sibling(Some(next_sib_id));
+        while let Some(page_id) = pending.pop() {
+            let page = self.fetch(page_id, false)?;
+            if page.kind() == PageKind::BPlusInner {
+                let inner = page.inner();
+                for i in 0..=inner.num_entries() as usize {
+                    if let Some(child_id) = inner.get_child_at(i) {
+                        pending.push(child_id);
+                    }
                 }
             }
-            self.bpm.as_mut().mark_frame_dirty(new_fid);
-            self.bpm.as_mut().unpin_frame(new_fid).ok();
+            pages.push(page_id);
+        }
 
-            let sib_frame = self
-                .bpm
-                .as_mut()
-                .fetch_page(next_sib_id)
-                .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-            let sib_fid = sib_frame.fid();
-            {
-                let mut sib_view = sib_frame.page_view();
-                if let Page::BPlusLeaf(sib_leaf) = &mut sib_view {
-                    sib_leaf.set_prev_sibling(Some(new_page_id));
-                }
-            }
-            self.bpm.as_mut().mark_frame_dirty(sib_fid);
-            self.bpm.as_mut().unpin_frame(sib_fid).ok();
+        for page_id in pages {
+            self.free_node(page_id)?;
         }
 
-        Ok(Some((split_key, new_page_id)))
+        self.root_page_id.store(0, Ordering::SeqCst);
+        Ok(())
     }
 
-    fn insert_into_parent(
-        &mut self,
-        mut path: Vec<PageId>,
-        split_key: Vec<u8>,
-        new_child_id: PageId,
-        counter: &AtomicU32,
-    ) -> Result<(), BTreeError> {
-        let _child_id = path.pop();
-
-        if let Some(parent_id) = path.last().copied() {
-            let (split_data_opt, frame_id) = {
-                let frame = self
-                    .bpm
-                    .as_mut()
-                    .fetch_page(parent_id)
-                    .map_err(|e| BTreeError::FetchPage(format!("{:?}", e)))?;
-                let frame_id = frame.fid();
-                let mut page_view = frame.page_view();
-
-                if let Page::BPlusInner(inner) = &mut page_view {
-                    if inner.has_space_for_key() {
-                        let idx = inner.find_child_for_key(&split_key);
-                        inner.insert_at(idx, &split_key, new_child_id);
-                        (None, frame_id)
-                    } else {
-                        let split_res = inner.split_and_get_new_entries(&split_key, new_child_id);
-                        (Some(split_res), frame_id)
-                    }
-                } else {
-                    self.bpm.as_mut().unpin_frame(frame_id).ok();
-                    return Err(BTreeError::InvalidPageType);
-                }
-            };
-
-            self.bpm.as_mut().mark_frame_dirty(frame_id);
-            self.bpm.as_mut().unpin_frame(frame_id).ok();
+    // ========================= VERIFICATION =========================
+
+    /// Walks the whole tree and collects every structural problem instead of stopping at the
+    /// first: key order within and across leaves, key ranges implied by the separators above
+    /// each node, `parent_page_id` where one is recorded, sibling links, fill bounds and leaf
+    /// depth. Only failing to read the root is an error; an unreadable child is a problem.
+    pub fn verify(&self) -> Result<VerifyReport, BTreeError> {
+        let mut report = VerifyReport::default();
+        let root_page_id = self.root_page_id();
+        if root_page_id == 0 {
+            return Ok(report);
+        }
 
-            if split_data_opt.is_none() {
-                return Ok(());
+        // (page, parent it was reached from, depth, inclusive low bound, exclusive high bound)
+        type Visit = (PageId, PageId, usize, Option<Vec<u8>>, Option<Vec<u8>>);
+        let mut pending: Vec<Visit> = vec![(root_page_id, 0, 0, None, None)];
+        let mut seen = HashSet::new();
+        let mut leaves: Vec<(PageId, NodeSummary)> = Vec::new();
+        let mut key_size = None;
+        let mut leaf_depth = None;
+
+        while let Some((page_id, parent_id, depth, low, high)) = pending.pop() {
+            let is_root = page_id == root_page_id;
+            if !seen.insert(page_id) {
+                report
+                    .problems
+                    .push(format!("page {}: reachable more than once", page_id));
+                continue;
             }
 
-            let split_data = split_data_opt.unwrap();
-            let new_inner_id = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            let node = match self.summarize_node(page_id) {
+                Ok(Some(node)) => node,
+                Ok(None) => {
+                    report
+                        .problems
+                        .push(format!("page {}: not a B+ tree page", page_id));
+                    continue;
+                }
+                Err(e) if is_root => return Err(e),
+                Err(e) => {
+                    report.problems.push(format!(
+                        "page {}: child of page {} cannot be read: {:?}",
+                        page_id, parent_id, e
+                    ));
+                    continue;
+                }
+            };
+            let problems = &mut report.problems;
 
-            let new_frame = self
-                .bpm
-                .as_mut()
-                .alloc_new_page(PageKind::BPlusInner, new_inner_id)
-                .map_err(|e| BTreeError::AllocPage(format!("{:?}", e)))?;
-            let new_fid = new_frame.fid();
+            if *key_size.get_or_insert(node.key_size) != node.key_size {
+                problems.push(format!(
+                    "page {}: key size {} differs from the root's",
+                    page_id, node.key_size
+                ));
+            }
+            if node.parent_page_id != 0 && node.parent_page_id != parent_id {
+                problems.push(format!(
+                    "page {}: records parent {} but is a child of page {}",
+                    page_id, node.parent_page_id, parent_id
+                ));
+            }
 
-            {
-                let mut new_view = new_frame.page_view();
-                if let Page::BPlusInner(new_inner) = &mut new_view {
-                    new_inner.init(new_inner_id, 0, split_key.len() as u32);
+            for (i, pair) in node.keys.windows(2).enumerate() {
+                if pair[0] >= pair[1] {
+                    problems.push(format!(
+                        "page {}: keys {} and {} are out of order",
+                        page_id,
+                        i,
+                        i + 1
+                    ));
+                }
+            }
+            for (i, key) in node.keys.iter().enumerate() {
+                if low.as_ref().is_some_and(|low| key < low) {
+                    problems.push(format!(
+                        "page {}: key {} sorts before its parent's separator",
+                        page_id, i
+                    ));
+                }
+                if high.as_ref().is_some_and(|high| key >= high) {
+                    problems.push(format!(
+                        "page {}: key {} sorts at or after its parent's next separator",
+                        page_id, i
+                    ));
+                }
+            }
 
-                    let first_child = split_data.new_page_children[0];
-                    new_inner.set_child_at(0, first_child);
+            let n = node.keys.len();
+            if n > node.max_keys {
+                problems.push(format!(
+                    "page {}: holds {} keys, more than the maximum of {}",
+                    page_id, n, node.max_keys
+                ));
+            }
+            // Compressed neighbours may be unable to merge, so underfull pages are legal
+            if !is_root && n == 0 {
+                problems.push(format!("page {}: holds no keys", page_id));
+            }
 
-                    for (i, key) in split_data.new_page_keys.iter().enumerate() {
-                        let child = split_data.new_page_children[i + 1];
-                        new_inner.insert_at(i, key, child);
-                    }
+            if node.is_leaf {
+                if *leaf_depth.get_or_insert(depth) != depth {
+                    problems.push(format!(
+                        "page {}: leaf at depth {}, others are at depth {}",
+                        page_id,
+                        depth,
+                        leaf_depth.unwrap()
+                    ));
                 }
+                report.leaf_pages += 1;
+                report.entries += n;
+                leaves.push((page_id, node));
+                continue;
             }
-            self.bpm.as_mut().mark_frame_dirty(new_fid);
-            self.bpm.as_mut().unpin_frame(new_fid).ok();
 
-            self.insert_into_parent(path, split_data.key_to_push_up, new_inner_id, counter)
-        } else {
-            self.create_new_root(self.root_page_id, split_key, new_child_id, counter)
+            report.inner_pages += 1;
+            if n == 0 {
+                problems.push(format!("page {}: inner page without keys", page_id));
+            }
+            // Pushed right to left so leaves are visited, and collected, in key order
+            for (i, child) in node.children.iter().enumerate().rev() {
+                let Some(child_id) = child else {
+                    problems.push(format!("page {}: child {} is missing", page_id, i));
+                    continue;
+                };
+                let child_low = if i == 0 {
+                    low.clone()
+                } else {
+                    Some(node.keys[i - 1].clone())
+                };
+                let child_high = node.keys.get(i).cloned().or(high.clone());
+                pending.push((*child_id, page_id, depth + 1, child_low, child_high));
+            }
         }
-    }
 
-    fn create_new_root(
-        &mut self,
-        left_child_id: PageId,
-        key: Vec<u8>,
-        right_child_id: PageId,
-        counter: &AtomicU32,
-    ) -> Result<(), BTreeError> {
-        let new_root_id = counter.fetch_add(1, Ordering::SeqCst) + 1;
-
-        let frame = self
-            .bpm
-            .as_mut()
-            .alloc_new_page(PageKind::BPlusInner, new_root_id)
-            .map_err(|e| BTreeError::AllocPage(format!("{:?}", e)))?;
-        let frame_id = frame.fid();
+        report.height = leaf_depth.map_or(0, |d| d + 1);
 
+        let problems = &mut report.problems;
+        if let Some((first_id, first)) = leaves.first()
+            && let Some(prev) = first.prev
         {
-            let mut view = frame.page_view();
-            if let Page::BPlusInner(inner) = &mut view {
-                inner.init(new_root_id, 1, key.len() as u32);
-                inner.set_root(true);
-                inner.set_child_at(0, left_child_id);
-                inner.insert_at(0, &key, right_child_id);
+            problems.push(format!(
+                "page {}: leftmost leaf has prev_sibling {}",
+                first_id, prev
+            ));
+        }
+        if let Some((last_id, last)) = leaves.last()
+            && let Some(next) = last.next
+        {
+            problems.push(format!(
+                "page {}: rightmost leaf has next_sibling {}",
+                last_id, next
+            ));
+        }
+        for pair in leaves.windows(2) {
+            let ((left_id, left), (right_id, right)) = (&pair[0], &pair[1]);
+            if left.next != Some(*right_id) {
+                problems.push(format!(
+                    "page {}: next_sibling is {:?}, expected {}",
+                    left_id, left.next, right_id
+                ));
+            }
+            if right.prev != Some(*left_id) {
+                problems.push(format!(
+                    "page {}: prev_sibling is {:?}, expected {}",
+                    right_id, right.prev, left_id
+                ));
+            }
+            if let (Some(last), Some(first)) = (left.keys.last(), right.keys.first())
+                && last >= first
+            {
+                problems.push(format!(
+                    "page {}: last key does not sort before the first key of leaf {}",
+                    left_id, right_id
+                ));
             }
         }
 
-        self.bpm.as_mut().mark_frame_dirty(frame_id);
-        self.bpm.as_mut().unpin_frame(frame_id).ok();
+        Ok(report)
+    }
 
-        self.root_page_id = new_root_id;
+    /// Copies out what `verify` checks about a node. `None` if it is not a B+ tree page.
+    fn summarize_node(&self, page_id: PageId) -> Result<Option<NodeSummary>, BTreeError> {
+        use crate::storage::page::base::DiskPage;
+
+        let page = self.fetch(page_id, false)?;
+        let summary = match page.kind() {
+            PageKind::BPlusLeaf => {
+                let leaf = page.leaf();
+                let n = leaf.num_entries() as usize;
+                Some(NodeSummary {
+                    is_leaf: true,
+                    keys: (0..n).map(|i| leaf.get_key_at(i)).collect(),
+                    children: Vec::new(),
+                    key_size: leaf.get_key_size(),
+                    parent_page_id: leaf.header().parent_page_id(),
+                    max_keys: leaf.calculate_max_keys() as usize,
+                    prev: leaf.prev_sibling(),
+                    next: leaf.next_sibling(),
+                })
+            }
+            PageKind::BPlusInner => {
+                let inner = page.inner();
+                let n = inner.num_entries() as usize;
+                Some(NodeSummary {
+                    is_leaf: false,
+                    keys: (0..n).map(|i| inner.get_key_at(i)).collect(),
+                    children: (0..=n).map(|i| inner.get_child_at(i)).collect(),
+                    key_size: inner.get_key_size(),
+                    parent_page_id: inner.parent_page_id(),
+                    max_keys: inner.calculate_max_keys() as usize,
+                    prev: None,
+                    next: None,
+                })
+            }
+            _ => None,
+        };
+        Ok(summary)
+    }
+}
 
-        Ok(())
+/// Splits `total` sorted items into consecutive page-sized runs: each page takes items
+/// while `fits(start, end)` holds, then the last two runs are evened out so the final page
+/// is not left nearly empty.
+fn pack_runs(total: usize, fits: impl Fn(usize, usize) -> bool) -> Vec<usize> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    while start < total {
+        let mut end = start + 1;
+        while end < total && fits(start, end + 1) {
+            end += 1;
+        }
+        runs.push(end - start);
+        start = end;
+    }
+    if runs.len() >= 2 {
+        let last_two = runs[runs.len() - 2] + runs[runs.len() - 1];
+        let base = total - last_two;
+        let best = (1..last_two)
+            .filter(|&k| fits(base, base + k) && fits(base + k, total))
+            .min_by_key(|&k| k.abs_diff(last_two / 2));
+        if let Some(k) = best {
+            let n = runs.len();
+            runs[n - 2] = k;
+            runs[n - 1] = last_two - k;
+        }
+    }
+    if runs.is_empty() {
+        runs.push(0);
     }
+    runs
 }
 
 #[cfg(test)]
@@ -822,14 +1299,23 @@ mod tests {
         let file_manager = FileManager::new(file_name.clone()).unwrap();
         let evictor = Box::new(FifoEvictor::new());
         let locator = Box::new(page_locator::locator::DirectoryPageLocator::new());
-        let bp = Box::pin(BufferPool::new(file_manager, evictor, locator));
-        (PathBuf::from(file_name), bp, AtomicU32::new(0))
+        let mut bp = Box::pin(BufferPool::new(Box::new(file_manager), evictor, locator));
+
+        // New nodes register themselves in the directory, so it has to exist first
+        let frame = bp
+            .as_mut()
+            .alloc_new_page(PageKind::Directory, 1)
+            .expect("Failed to allocate root directory page");
+        let fid = frame.fid();
+        bp.as_mut().unpin_frame(fid).unwrap();
+
+        (PathBuf::from(file_name), bp, AtomicU32::new(1))
     }
 
     #[test]
     fn test_btree_insert_and_split() {
         let (path, mut bp, counter) = setup_bp("split");
-        let mut tree = BPlusTree::new(bp.as_mut(), 0);
+        let tree = BPlusTree::new(bp.as_mut(), 0);
 
         let n = 500;
         for i in 0..n {
@@ -847,7 +1333,331 @@ mod tests {
             assert_eq!(val, i as u64);
         }
 
-        assert!(tree.root_page_id > 1);
+        assert!(tree.root_page_id() > 1);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_btree_payload_survives_splits() {
+        use crate::storage::heap::iterator::BTreeIterator;
+
+        let (path, mut bp, counter) = setup_bp("payload");
+        let tree = BPlusTree::new(bp.as_mut(), 0);
+
+        let n = 1000u32;
+        for i in (0..n).rev() {
+            let payload = (i * 7).to_le_bytes();
+            tree.insert_with_payload(&i.to_be_bytes(), i as u64, &payload, &counter)
+                .expect("Insert failed");
+        }
+        assert!(tree.insert(&n.to_be_bytes(), 0, &counter).is_err());
+
+        let mut iter = BTreeIterator::new(tree, None);
+        let mut seen = 0;
+        while let Some((key, val, payload)) = iter.next_with_payload() {
+            let i = u32::from_be_bytes(key.try_into().unwrap());
+            assert_eq!(i, seen);
+            assert_eq!(val, i as u64);
+            assert_eq!(payload, (i * 7).to_le_bytes());
+            seen += 1;
+        }
+        assert_eq!(seen, n);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_btree_reverse_and_bounded_iteration() {
+        use crate::storage::heap::iterator::BTreeIterator;
+        use std::ops::Bound;
+
+        let (path, mut bp, counter) = setup_bp("reverse_iter");
+        let tree = BPlusTree::new(bp.as_mut(), 0);
+
+        // Even keys only, spread over many leaves
+        let n = 2000u32;
+        for i in 0..n {
+            tree.insert(&(i * 2).to_be_bytes(), i as u64, &counter)
+                .expect("Insert failed");
+        }
+        let root = tree.root_page_id();
+        let key = |k: &[u8]| u32::from_be_bytes(k.try_into().unwrap());
+
+        // Seek to last and walk the whole chain backwards
+        let mut iter = BTreeIterator::last(BPlusTree::new(bp.as_mut(), root));
+        let mut expected = n;
+        while let Some((k, v)) = iter.prev() {
+            expected -= 1;
+            assert_eq!(key(&k), expected * 2);
+            assert_eq!(v, expected as u64);
+        }
+        assert_eq!(expected, 0);
+        // Turning around at the start yields the first entry again
+        assert_eq!(iter.next().map(|(k, _)| key(&k)), Some(0));
+
+        // Keys before 1001 (absent), newest first, down to 900 inclusive
+        let tree = BPlusTree::new(bp.as_mut(), root);
+        let mut iter = BTreeIterator::new(tree, Some(&1001u32.to_be_bytes()))
+            .with_lower_bound(Bound::Included(900u32.to_be_bytes().to_vec()));
+        let mut keys = Vec::new();
+        while let Some((k, _)) = iter.prev() {
+            keys.push(key(&k));
+        }
+        assert_eq!(keys, (450..=500).rev().map(|i| i * 2).collect::<Vec<_>>());
+
+        // Exclusive upper bound stops short of the key and leaves the cursor before it
+        let tree = BPlusTree::new(bp.as_mut(), root);
+        let mut iter = BTreeIterator::new(tree, Some(&100u32.to_be_bytes()))
+            .with_upper_bound(Bound::Excluded(200u32.to_be_bytes().to_vec()));
+        let mut count = 0;
+        while iter.next().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 50);
+        assert_eq!(iter.prev().map(|(k, _)| key(&k)), Some(198));
+
+        // Inclusive upper bound keeps the key
+        let mut iter = BTreeIterator::new(BPlusTree::new(bp.as_mut(), root), None)
+            .with_upper_bound(Bound::Included(4u32.to_be_bytes().to_vec()));
+        let keys: Vec<u32> = std::iter::from_fn(|| iter.next().map(|(k, _)| key(&k))).collect();
+        assert_eq!(keys, vec![0, 2, 4]);
+
+        // An empty tree yields nothing in either direction
+        let empty = BPlusTree::new(bp.as_mut(), 0);
+        let mut iter = BTreeIterator::last(empty);
+        assert!(iter.prev().is_none());
+        assert!(iter.next().is_none());
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_btree_bulk_load_and_destroy() {
+        let (path, mut bp, counter) = setup_bp("bulk");
+        let mut tree = BPlusTree::new(bp.as_mut(), 0);
+
+        // Enough keys for a few hundred leaves and two inner levels
+        let n = 100_000u32;
+        let entries: Vec<(Vec<u8>, u64)> = (0..n)
+            .map(|i| (i.to_be_bytes().to_vec(), i as u64 * 2))
+            .collect();
+        tree.bulk_load(4, &entries, &counter)
+            .expect("Bulk load failed");
+
+        for i in (0..n).step_by(997) {
+            let val = tree.get_value(&i.to_be_bytes()).expect("Get failed");
+            assert_eq!(val, Some(i as u64 * 2));
+        }
+        assert_eq!(tree.get_value(&n.to_be_bytes()).unwrap(), None);
+
+        // The loaded tree keeps accepting regular inserts
+        tree.insert(&n.to_be_bytes(), 7, &counter)
+            .expect("Insert failed");
+        assert_eq!(tree.get_value(&n.to_be_bytes()).unwrap(), Some(7));
+
+        let unsorted = vec![(vec![0, 0, 0, 2], 0), (vec![0, 0, 0, 1], 0)];
+        assert!(tree.bulk_load(4, &unsorted, &counter).is_err());
+
+        let old_root = tree.root_page_id();
+        tree.destroy().expect("Destroy failed");
+        assert_eq!(tree.root_page_id(), 0);
+        assert!(
+            tree.pool()
+                .as_mut()
+                .fetch_page(old_root, AccessStrategy::Normal)
+                .is_err()
+        );
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_btree_prefix_compression() {
+        let (path, mut bp, counter) = setup_bp("prefix");
+        let mut tree = BPlusTree::new(bp.as_mut(), 0);
+
+        // Long clustered keys, like a composite key whose leading columns rarely change
+        let key = |i: u32| {
+            let mut k = b"tenant-0042/orders/".to_vec();
+            k.resize(56, b'.');
+            k.extend_from_slice(&(i as u64).to_be_bytes());
+            k
+        };
+        let n = 5000u32;
+        let uncompressed = BPlusLeaf::max_keys_for(64, 0) as usize;
+
+        for i in 0..n {
+            let k = (i * 7919) % n;
+            tree.insert(&key(k), k as u64, &counter)
+                .expect("Insert failed");
+        }
+        for i in (0..n).step_by(3) {
+            tree.delete(&key(i)).expect("Delete failed");
+        }
+        let report = tree.verify().expect("Verify failed");
+        assert!(report.is_ok(), "{:?}", report.problems);
+        let remaining = (n - n.div_ceil(3)) as usize;
+        assert_eq!(report.entries, remaining);
+        assert!(
+            report.leaf_pages * 2 < remaining / uncompressed,
+            "{} leaves",
+            report.leaf_pages
+        );
+        for i in (1..n).step_by(3) {
+            assert_eq!(tree.get_value(&key(i)).unwrap(), Some(i as u64));
+        }
+        tree.destroy().expect("Destroy failed");
+
+        // Bulk loading at a lower fill factor leaves room in every leaf
+        let entries: Vec<LeafEntry> = (0..n).map(|i| (key(i), i as u64, Vec::new())).collect();
+        let mut leaves = Vec::new();
+        for fill_factor in [100, 50] {
+            tree.bulk_load_with_payload(64, 0, &entries, fill_factor, &counter)
+                .expect("Bulk load failed");
+            let report = tree.verify().expect("Verify failed");
+            assert!(report.is_ok(), "{:?}", report.problems);
+            assert_eq!(report.entries, n as usize);
+            leaves.push(report.leaf_pages);
+            tree.destroy().expect("Destroy failed");
+        }
+        assert!(leaves[1] > leaves[0] * 3 / 2, "{:?}", leaves);
+        assert!(
+            tree.bulk_load_with_payload(64, 0, &entries, 5, &counter)
+                .is_err()
+        );
+
         let _ = fs::remove_file(&path);
     }
+
+    #[test]
+    fn test_btree_verify() {
+        let (path, mut bp, counter) = setup_bp("verify");
+        let tree = BPlusTree::new(bp.as_mut(), 0);
+
+        // Wide keys keep pages small, so a few thousand entries build several inner levels.
+        // Runs of four keys differ only in the last byte, so neither the leaf prefixes nor
+        // the separators between them get much shorter.
+        let key = |i: u32| {
+            let mut k = vec![((i / 4) * 131 % 251 + 1) as u8; 199];
+            k[..4].copy_from_slice(&(i / 4).to_be_bytes());
+            k[198] = (i % 4) as u8 + 1;
+            k
+        };
+        let n = 3000u32;
+        tree.insert(&key(0), 0, &counter).expect("Insert failed");
+        let root = tree.root_page_id();
+        for i in 0..n {
+            let k = (i * 7919) % n;
+            tree.insert(&key(k), k as u64, &counter)
+                .expect("Insert failed");
+        }
+        for i in (0..n).step_by(3) {
+            tree.delete(&key(i)).expect("Delete failed");
+        }
+
+        let report = tree.verify().expect("Verify failed");
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert!(report.height > 2);
+        assert_eq!(report.entries, (n - n.div_ceil(3)) as usize);
+        assert_eq!(tree.root_page_id(), root);
+
+        // Break the leaf chain and the order inside one leaf
+        let leaf_id = tree.find_leaf_page_id(&key(1)).unwrap();
+        {
+            use crate::storage::page::base::DiskPage;
+            let mut page = tree.fetch(leaf_id, true).unwrap();
+            let mut leaf = page.leaf_mut();
+            leaf.set_next_sibling(None);
+            let first = leaf.get_key_at(0);
+            let prefix_len = leaf.prefix_len();
+            let raw = leaf.raw_mut();
+            // The page prefix and the first suffix sit back to back; corrupt the suffix
+            let at = raw.windows(first.len()).position(|w| w == first).unwrap() + prefix_len;
+            raw[at..at + 4].fill(0xFF);
+        }
+
+        let report = tree.verify().expect("Verify failed");
+        let problems = report.problems.join("\n");
+        assert!(problems.contains("next_sibling is None"), "{}", problems);
+        assert!(problems.contains("out of order"), "{}", problems);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_btree_shared_insert_get_delete() {
+        let file_name = "test_btree_full_shared.db";
+        let _ = fs::remove_file(file_name);
+        let file_manager = FileManager::new(file_name.to_string()).unwrap();
+        let evictor = Box::new(FifoEvictor::new());
+        let locator = Box::new(page_locator::locator::DirectoryPageLocator::new());
+        let bp = Mutex::new(BufferPool::new(Box::new(file_manager), evictor, locator));
+        {
+            let mut guard = bp.lock().unwrap();
+            let mut pinned = unsafe { Pin::new_unchecked(&mut *guard) };
+            let fid = pinned
+                .as_mut()
+                .alloc_new_page(PageKind::Directory, 1)
+                .expect("Failed to allocate root directory page")
+                .fid();
+            pinned.as_mut().unpin_frame(fid).unwrap();
+        }
+        let counter = AtomicU32::new(1);
+        let tree = BPlusTree::shared(&bp, 0);
+
+        let threads = 8u32;
+        let per_thread = 1500u32;
+
+        // Every thread inserts an interleaved slice of the key space, reading back as it goes
+        std::thread::scope(|s| {
+            for t in 0..threads {
+                let (tree, counter) = (&tree, &counter);
+                s.spawn(move || {
+                    for i in 0..per_thread {
+                        let k = i * threads + t;
+                        tree.insert(&k.to_be_bytes(), k as u64, counter).unwrap();
+                        let seen = tree.get_value(&k.to_be_bytes()).unwrap();
+                        assert_eq!(seen, Some(k as u64));
+                    }
+                });
+            }
+        });
+        let root = tree.root_page_id();
+
+        // Then half of them delete their odd keys while the other half keep reading
+        std::thread::scope(|s| {
+            for t in 0..threads {
+                let tree = &tree;
+                s.spawn(move || {
+                    for i in 0..per_thread {
+                        let k = i * threads + t;
+                        if t % 2 == 1 {
+                            tree.delete(&k.to_be_bytes()).unwrap();
+                        } else {
+                            let seen = tree.get_value(&k.to_be_bytes()).unwrap();
+                            assert_eq!(seen, Some(k as u64));
+                        }
+                    }
+                });
+            }
+        });
+
+        for k in 0..threads * per_thread {
+            let expected = if k % 2 == 0 { Some(k as u64) } else { None };
+            assert_eq!(
+                tree.get_value(&k.to_be_bytes()).unwrap(),
+                expected,
+                "key {}",
+                k
+            );
+        }
+        // Splits and merges never move the root
+        assert_eq!(tree.root_page_id(), root);
+        let report = tree.verify().expect("Verify failed");
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!(report.entries, (threads * per_thread / 2) as usize);
+
+        drop(tree);
+        let _ = fs::remove_file(file_name);
+    }
 }
@@ -0,0 +1,164 @@
+use crate::storage::buffer::BufferPool;
+use crate::storage::buffer::buffer_pool::errors::CheckpointError;
+use crate::storage::buffer::checkpoint::CheckpointStats;
+use std::pin::Pin;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How hard the background writer works.
+#[derive(Clone, Copy, Debug)]
+pub struct WriterConfig {
+    /// Pause between rounds
+    pub interval: Duration,
+    /// Dirty pages written per round. Keeps each round's hold on the pool short.
+    pub pages_per_round: usize,
+    /// Time between checkpoints, or None to only checkpoint on request
+    pub checkpoint_interval: Option<Duration>,
+}
+
+impl Default for WriterConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(200),
+            pages_per_round: 32,
+            checkpoint_interval: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// Trickles dirty pages to disk from a background thread, so evictions and shutdown find
+/// most pages already clean, and takes a checkpoint every so often.
+/// The thread stops when this is dropped.
+pub struct BackgroundWriter {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl BackgroundWriter {
+    pub fn start(bp: Arc<Mutex<BufferPool>>, config: WriterConfig) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            let mut last_checkpoint = Instant::now();
+            // Both a stop message and a dropped sender end the loop
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(config.interval) {
+                {
+                    let Ok(mut bp_guard) = bp.lock() else {
+                        return;
+                    };
+                    let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+                    if let Err(e) = pinned_bp.flush_some(config.pages_per_round) {
+                        eprintln!("Background writer failed to flush: {:?}", e);
+                    }
+                }
+                if let Some(every) = config.checkpoint_interval
+                    && last_checkpoint.elapsed() >= every
+                {
+                    match checkpoint_in_batches(&bp, config.pages_per_round) {
+                        Err(CheckpointError::LockPoisoned) => return,
+                        Err(e) => eprintln!("Background checkpoint failed: {:?}", e),
+                        Ok(_) => {}
+                    }
+                    last_checkpoint = Instant::now();
+                }
+            }
+        });
+
+        Self {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+/// Takes a checkpoint holding `bp` for no more than `pages_per_batch` page writes at a
+/// time, so queries get to use the pool in between.
+pub fn checkpoint_in_batches(
+    bp: &Mutex<BufferPool>,
+    pages_per_batch: usize,
+) -> Result<CheckpointStats, CheckpointError> {
+    let lock = || bp.lock().map_err(|_| CheckpointError::LockPoisoned);
+    let mut checkpoint = {
+        let mut bp_guard = lock()?;
+        unsafe { Pin::new_unchecked(&mut *bp_guard) }.begin_checkpoint()?
+    };
+    loop {
+        let mut bp_guard = lock()?;
+        let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        if let Some(stats) = pinned_bp.checkpoint_step(&mut checkpoint, pages_per_batch)? {
+            return Ok(stats);
+        }
+    }
+}
+
+impl Drop for BackgroundWriter {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::buffer::checkpoint::CheckpointRecord;
+    use crate::storage::buffer::fifo_evictor::FifoEvictor;
+    use crate::storage::disk::FileManager;
+    use crate::storage::page::base::PageKind;
+    use crate::storage::page_locator::locator::DirectoryPageLocator;
+    use std::fs;
+
+    #[test]
+    fn test_writer_cleans_pool_and_checkpoints() {
+        let mut path = std::env::temp_dir();
+        path.push("nimbus_test_bg_writer.db");
+        let path = path.to_str().unwrap().to_string();
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(CheckpointRecord::path_for(&path));
+
+        let bp = Arc::new(Mutex::new(BufferPool::new(
+            Box::new(FileManager::new(path.clone()).unwrap()),
+            Box::new(FifoEvictor::new()),
+            Box::new(DirectoryPageLocator::new()),
+        )));
+        {
+            let mut bp_guard = bp.lock().unwrap();
+            let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            for page_id in 1..=50 {
+                let fid = pinned_bp
+                    .as_mut()
+                    .alloc_new_page(PageKind::SlottedData, page_id)
+                    .unwrap()
+                    .fid();
+                pinned_bp.as_mut().unpin_frame(fid).unwrap();
+            }
+            assert_eq!(pinned_bp.dirty_page_table().len(), 50);
+        }
+
+        let config = WriterConfig {
+            interval: Duration::from_millis(5),
+            pages_per_round: 8,
+            checkpoint_interval: Some(Duration::ZERO),
+        };
+        let writer = BackgroundWriter::start(bp.clone(), config);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !bp.lock().unwrap().dirty_page_table().is_empty() {
+            assert!(Instant::now() < deadline, "Writer did not clean the pool");
+            thread::sleep(Duration::from_millis(5));
+        }
+        drop(writer);
+
+        let record = bp.lock().unwrap().last_checkpoint().unwrap().unwrap();
+        assert!(record.id >= 1);
+        assert!(record.complete);
+
+        drop(bp);
+        let _ = fs::remove_file(CheckpointRecord::path_for(&path));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1,13 +1,80 @@
 use crate::constants;
-use crate::storage::buffer::Evictor;
+use crate::storage::buffer::checkpoint::{
+    Checkpoint, CheckpointRecord, CheckpointStats, DirtyPage,
+};
+use crate::storage::buffer::{AccessStrategy, Evictor};
 use crate::storage::disk;
 use crate::storage::page_locator::{PageLocator, locator};
 use crate::storage::{page, page::base};
 use std::alloc::{Layout, alloc, dealloc};
 use std::collections::HashMap;
 use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
 
-pub const FRAME_COUNT: usize = 128;
+/// Frames a pool gets when the caller does not ask for a size.
+pub const DEFAULT_FRAME_COUNT: usize = 128;
+/// A B+ tree split pins a few pages per level, so a pool needs some headroom to work at all.
+pub const MIN_FRAME_COUNT: usize = 16;
+pub const MAX_FRAME_COUNT: usize = LATCH_CHUNK * MAX_LATCH_CHUNKS;
+
+/// Page ids the superblock is moved past at a time, see `BufferPoolCore::reserve_page_id`.
+pub const PAGE_ID_RESERVE: u32 = 1024;
+
+const LATCH_CHUNK: usize = 1024;
+const MAX_LATCH_CHUNKS: usize = 4096;
+
+type LatchChunk = OnceLock<Box<[RwLock<()>]>>;
+
+/// One reader/writer latch per frame, guarding the bytes of whichever page occupies it.
+/// A latch only means something while the caller holds a pin on the page, since an
+/// unpinned frame can be handed to a different page at any time.
+pub struct FrameLatches {
+    // Allocated a chunk at a time as the pool grows, and never moved or freed, so other
+    // threads can keep latching while the pool is resized
+    chunks: Box<[LatchChunk]>,
+}
+
+impl FrameLatches {
+    fn new(frame_count: usize) -> Self {
+        let latches = Self {
+            chunks: (0..MAX_LATCH_CHUNKS).map(|_| OnceLock::new()).collect(),
+        };
+        latches.ensure(frame_count);
+        latches
+    }
+
+    /// Makes sure frames `0..frame_count` have a latch.
+    fn ensure(&self, frame_count: usize) {
+        for chunk in &self.chunks[..frame_count.div_ceil(LATCH_CHUNK)] {
+            chunk.get_or_init(|| (0..LATCH_CHUNK).map(|_| RwLock::new(())).collect());
+        }
+    }
+
+    fn latch(&self, frame_id: u32) -> &RwLock<()> {
+        let idx = frame_id as usize;
+        let chunk = self.chunks[idx / LATCH_CHUNK]
+            .get()
+            .expect("Latch for a frame the pool never had");
+        &chunk[idx % LATCH_CHUNK]
+    }
+
+    pub fn read(&self, frame_id: u32) -> RwLockReadGuard<'_, ()> {
+        self.latch(frame_id).read().expect("Latch poisoned")
+    }
+
+    pub fn write(&self, frame_id: u32) -> RwLockWriteGuard<'_, ()> {
+        self.latch(frame_id).write().expect("Latch poisoned")
+    }
+
+    /// `read`, unless someone holds the latch exclusively or is waiting to.
+    pub fn try_read(&self, frame_id: u32) -> Option<RwLockReadGuard<'_, ()>> {
+        match self.latch(frame_id).try_read() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Poisoned(_)) => panic!("Latch poisoned"),
+        }
+    }
+}
 
 #[derive(Copy, Clone)]
 pub struct Frame {
@@ -17,7 +84,7 @@ pub struct Frame {
     dirty: bool,
     file_offset: u64, // can be garbage as long as is_ready is false
     page_id: page::base::PageId,
-    buf_ptr: *mut page::base::PageBuf, // raw pointer into frames_backing_buf
+    buf_ptr: *mut page::base::PageBuf, // raw pointer to the frame's entry in frame_bufs
 }
 
 impl Frame {
@@ -26,6 +93,13 @@ impl Frame {
         self.page_id
     }
 
+    /// Raw pointer to the frame's page bytes. Callers must hold the frame's latch
+    /// (see `FrameLatches`) and a pin for as long as they use it.
+    #[inline]
+    pub fn buf_ptr(&self) -> *mut page::base::PageBuf {
+        self.buf_ptr
+    }
+
     pub fn page_view(&mut self) -> page::base::Page<'_> {
         unsafe {
             let buf = &mut (*self.buf_ptr);
@@ -45,7 +119,16 @@ impl Frame {
                 page::base::PageKind::BPlusLeaf => {
                     page::base::Page::BPlusLeaf(page::BPlusLeaf::new(buf))
                 }
-                page::base::PageKind::Invalid => page::base::Page::Invalid(),
+                page::base::PageKind::HashDirectory => {
+                    page::base::Page::HashDirectory(page::HashDirectory::new(buf))
+                }
+                page::base::PageKind::HashBucket => {
+                    page::base::Page::HashBucket(page::HashBucket::new(buf))
+                }
+                // Free pages and the superblock are never loaded into the pool
+                page::base::PageKind::Invalid
+                | page::base::PageKind::Free
+                | page::base::PageKind::Superblock => page::base::Page::Invalid(),
             }
         }
     }
@@ -75,6 +158,25 @@ impl Frame {
     }
 }
 
+/// Page request counters since the pool was opened.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl PoolStats {
+    /// Fraction of page requests served without reading from disk.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 struct FrameMeta {
     file_offset: u64,
@@ -84,61 +186,214 @@ struct FrameMeta {
 
 pub struct BufferPoolCore {
     // All original fields except page_locator
-    frames_backing_buf: *mut u8,
-    layout: Layout,
-    frames: [Option<Frame>; FRAME_COUNT],
+    // One page-aligned buffer per frame, so the pool can grow without moving pages
+    frame_bufs: Vec<*mut u8>,
+    // Pages are checksummed here on their way to disk, see flush_frame
+    write_buf: *mut u8,
+    // Pages of compressed segments as stored, on their way to or from disk
+    packed_buf: Vec<u8>,
+    // Pages of compressed segments that moved to a new slot the directory does not know
+    // about yet, see `BufferPool::record_relocations`
+    relocated: Vec<(base::PageId, u64)>,
+    // The page id the superblock says nothing has reached yet, once the database file has
+    // one, see `reserve_page_id`
+    page_id_limit: Option<base::PageId>,
+    // Whether pages without a checksum are refused, see `Superblock::checksums_required`
+    checksums_required: bool,
+    frames: Vec<Option<Frame>>,
     free_frames: u32,
 
     frames_meta_pid: HashMap<base::PageId, FrameMeta>, // (key is u32)
     frames_meta_offset: HashMap<u64, FrameMeta>,
 
-    file_manager: disk::FileManager,
+    disk: disk::DiskManager,
     evictor: Box<dyn Evictor>,
+    latches: Arc<FrameLatches>,
+    stats: PoolStats,
+    writer_hand: usize, // where flush_some picks up next time
 
     _pin: std::marker::PhantomPinned,
 }
 
+// SAFETY: the raw pointers only ever point into `frame_bufs`, which the core owns and
+// frees on drop or when shrinking. Moving the core to another thread moves that ownership
+// with it.
+unsafe impl Send for BufferPoolCore {}
+
 impl Drop for BufferPoolCore {
     fn drop(&mut self) {
         // Ensure all dirty pages are flushed before dropping
         let mut pinned_self = unsafe { Pin::new_unchecked(&mut *self) };
         let _ = pinned_self.as_mut().flush_all();
 
-        unsafe {
-            dealloc(self.frames_backing_buf, self.layout);
+        for &buf in self.frame_bufs.iter().chain([&self.write_buf]) {
+            unsafe { dealloc(buf, frame_layout()) };
         }
     }
 }
 
+fn frame_layout() -> Layout {
+    Layout::from_size_align(constants::storage::PAGE_SIZE, constants::storage::PAGE_SIZE)
+        .expect("Failed to create memory layout for buffer pool")
+}
+
+// An encrypted device refuses a page that fails authentication as invalid data, which is
+// as bad as a page failing its checksum
+fn read_error(e: std::io::Error) -> errors::FetchPageError {
+    match e.kind() {
+        std::io::ErrorKind::InvalidData => errors::FetchPageError::ChecksumMismatch,
+        _ => errors::FetchPageError::IOError,
+    }
+}
+
+fn alloc_frame_buf() -> *mut u8 {
+    unsafe {
+        let ptr = alloc(frame_layout());
+        if ptr.is_null() {
+            panic!("Failed to allocate aligned memory for buffer pool");
+        }
+        std::ptr::write_bytes(ptr, 0, constants::storage::PAGE_SIZE);
+        ptr
+    }
+}
+
 impl BufferPoolCore {
-    pub fn new(file_manager: disk::FileManager, evictor: Box<dyn Evictor>) -> Self {
-        let size = FRAME_COUNT * constants::storage::PAGE_SIZE;
-        let align = constants::storage::PAGE_SIZE;
-        let layout = Layout::from_size_align(size, align)
-            .expect("Failed to create memory layout for buffer pool");
-
-        let backing_buf_ptr = unsafe {
-            let ptr = alloc(layout);
-            if ptr.is_null() {
-                panic!("Failed to allocate aligned memory for buffer pool");
-            }
-            std::ptr::write_bytes(ptr, 0, size);
-            ptr
-        };
+    pub fn new(device: Box<dyn disk::BlockDevice>, evictor: Box<dyn Evictor>) -> Self {
+        Self::with_frame_count(device, evictor, DEFAULT_FRAME_COUNT)
+    }
+
+    /// NOTE: frame_count must be within MIN_FRAME_COUNT..=MAX_FRAME_COUNT
+    pub fn with_frame_count(
+        device: Box<dyn disk::BlockDevice>,
+        evictor: Box<dyn Evictor>,
+        frame_count: usize,
+    ) -> Self {
+        assert!(
+            (MIN_FRAME_COUNT..=MAX_FRAME_COUNT).contains(&frame_count),
+            "Buffer pool size must be between {} and {} frames",
+            MIN_FRAME_COUNT,
+            MAX_FRAME_COUNT
+        );
 
         Self {
-            frames_backing_buf: backing_buf_ptr,
-            layout: layout,
-            frames: std::array::from_fn(|_| None),
-            free_frames: FRAME_COUNT as u32,
+            frame_bufs: (0..frame_count).map(|_| alloc_frame_buf()).collect(),
+            write_buf: alloc_frame_buf(),
+            packed_buf: Vec::new(),
+            relocated: Vec::new(),
+            page_id_limit: None,
+            checksums_required: true,
+            frames: vec![None; frame_count],
+            free_frames: frame_count as u32,
             frames_meta_pid: HashMap::new(),
             frames_meta_offset: HashMap::new(),
-            file_manager,
+            disk: disk::DiskManager::new(device),
             evictor,
+            latches: Arc::new(FrameLatches::new(frame_count)),
+            stats: PoolStats::default(),
+            writer_hand: 0,
             _pin: std::marker::PhantomPinned::default(),
         }
     }
 
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Grows or shrinks the pool to `frame_count` frames. Pages in the frames that go
+    /// away are flushed and dropped, so shrinking fails if any of them is pinned.
+    pub fn resize(mut self: Pin<&mut Self>, frame_count: usize) -> Result<(), errors::ResizeError> {
+        if !(MIN_FRAME_COUNT..=MAX_FRAME_COUNT).contains(&frame_count) {
+            return Err(errors::ResizeError::OutOfRange);
+        }
+
+        let current = self.frames.len();
+        if frame_count >= current {
+            let self_mut = unsafe { self.get_unchecked_mut() };
+            self_mut.latches.ensure(frame_count);
+            self_mut
+                .frame_bufs
+                .extend((current..frame_count).map(|_| alloc_frame_buf()));
+            self_mut.frames.resize(frame_count, None);
+            self_mut.free_frames += (frame_count - current) as u32;
+            return Ok(());
+        }
+
+        if self.frames[frame_count..]
+            .iter()
+            .flatten()
+            .any(|frame| frame.pinned())
+        {
+            return Err(errors::ResizeError::FramesPinned);
+        }
+        for idx in frame_count..current {
+            if self.frames[idx].is_some() {
+                self.as_mut()
+                    .flush_frame(idx as u32)
+                    .map_err(|_| errors::ResizeError::IOError)?;
+                self.as_mut()
+                    .dealloc_frame_at(idx)
+                    .map_err(|_| errors::ResizeError::IOError)?;
+            }
+        }
+
+        let self_mut = unsafe { self.get_unchecked_mut() };
+        for buf in self_mut.frame_bufs.drain(frame_count..) {
+            unsafe { dealloc(buf, frame_layout()) };
+        }
+        self_mut.frames.truncate(frame_count);
+        self_mut.free_frames -= (current - frame_count) as u32;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    /// Counts a request served from memory and tells the evictor the frame was used.
+    /// A ring's reads are one-offs and do not make a page look hot.
+    fn record_hit(self: Pin<&mut Self>, frame_id: u32, strategy: &AccessStrategy) {
+        let self_mut = unsafe { self.get_unchecked_mut() };
+        self_mut.stats.hits += 1;
+        if let AccessStrategy::Normal = strategy {
+            self_mut.evictor.notify_frame_access(frame_id);
+        }
+    }
+
+    /// Finds a frame to load a page into. A full ring hands back its oldest frame as long
+    /// as that still holds the ring's page and nobody has it pinned; otherwise the evictor
+    /// picks one. None if every frame is taken; an error if the ring's frame could not be
+    /// written back.
+    fn find_frame_for(
+        mut self: Pin<&mut Self>,
+        strategy: &AccessStrategy,
+    ) -> Result<Option<usize>, errors::DeallocFrameError> {
+        if let AccessStrategy::Ring(ring) = strategy
+            && let Some((frame_id, offset)) = ring.victim()
+        {
+            // The ring may remember frames the pool has shrunk away since
+            let reusable = self
+                .frames
+                .get(frame_id as usize)
+                .and_then(Option::as_ref)
+                .is_some_and(|frame| frame.file_offset == offset && !frame.pinned());
+            if reusable {
+                self.as_mut().dealloc_frame_at(frame_id as usize)?;
+                return Ok(Some(frame_id as usize));
+            }
+        }
+        Ok(self.find_free_frame_with_evict())
+    }
+
+    /// Hands a freshly loaded frame to the ring it was loaded through, if any.
+    fn adopt_frame(self: Pin<&mut Self>, frame_id: u32, offset: u64, strategy: AccessStrategy) {
+        if let AccessStrategy::Ring(ring) = strategy {
+            ring.record(frame_id, offset);
+            unsafe { self.get_unchecked_mut() }
+                .evictor
+                .mark_frame_cold(frame_id);
+        }
+    }
+
     pub fn mark_frame_dirty(self: Pin<&mut Self>, frame_id: u32) {
         unsafe {
             if let Some(f) = &mut self.get_unchecked_mut().frames[frame_id as usize] {
@@ -148,7 +403,7 @@ impl BufferPoolCore {
     }
 
     pub fn pin_frame(self: Pin<&mut Self>, frame_id: u32) -> Result<(), errors::PinFrameError> {
-        if frame_id >= FRAME_COUNT as u32 {
+        if frame_id as usize >= self.frames.len() {
             return Err(errors::PinFrameError::FrameNotFound);
         }
 
@@ -167,7 +422,7 @@ impl BufferPoolCore {
     }
 
     pub fn unpin_frame(self: Pin<&mut Self>, frame_id: u32) -> Result<(), errors::UnpinFrameError> {
-        if frame_id >= FRAME_COUNT as u32 {
+        if frame_id as usize >= self.frames.len() {
             return Err(errors::UnpinFrameError::FrameNotFound);
         }
 
@@ -190,15 +445,19 @@ impl BufferPoolCore {
         }
     }
 
+    /// Writes a dirty frame back, reading its bytes under the frame's shared latch. A
+    /// pinned frame whose latch is taken stays dirty rather than waiting: its holder may
+    /// be waiting for the pool.
     pub fn flush_frame(
         mut self: Pin<&mut Self>,
         frame_id: u32,
     ) -> Result<(), errors::FlushFrameError> {
-        if frame_id >= FRAME_COUNT as u32 {
+        if frame_id as usize >= self.frames.len() {
             return Err(errors::FlushFrameError::FrameNotFound);
         }
+        let latches = Arc::clone(&self.latches);
         unsafe {
-            let (buf_ptr, offset, is_dirty) = {
+            let (buf_ptr, offset, page_id, is_dirty, pinned) = {
                 let self_mut = self.as_mut().get_unchecked_mut();
                 let frame = self_mut.frames[frame_id as usize]
                     .as_ref()
@@ -207,18 +466,56 @@ impl BufferPoolCore {
                 if !frame.dirty() {
                     return Ok(());
                 }
-                (frame.buf_ptr, frame.file_offset, frame.dirty)
+                (
+                    frame.buf_ptr,
+                    frame.file_offset,
+                    frame.page_id,
+                    frame.dirty,
+                    frame.pinned(),
+                )
             }; // Immutable borrow of self_mut.frames ends here
 
             if !is_dirty {
                 return Ok(());
             }
 
+            // Stamp a copy rather than the frame, so the page never changes under someone
+            // reading it and a change made mid-flush cannot end up under the old checksum
             let self_mut = self.as_mut().get_unchecked_mut();
-            self_mut
-                .file_manager
-                .write_block_from(offset, &(*buf_ptr))
-                .map_err(|_| errors::FlushFrameError::IOError)?;
+            let write_buf = &mut *self_mut.write_buf.cast::<page::base::PageBuf>();
+            {
+                let _latch = if pinned {
+                    let Some(latch) = latches.try_read(frame_id) else {
+                        return Ok(());
+                    };
+                    latch
+                } else {
+                    latches.read(frame_id)
+                };
+                write_buf.copy_from_slice(&*buf_ptr);
+            }
+            page::checksum::stamp(write_buf);
+            let moved = if self_mut.disk.is_packed(offset) {
+                // Pages that do not shrink are stored as they are
+                disk::compression::compress(write_buf, &mut self_mut.packed_buf);
+                let data: &[u8] = if self_mut.packed_buf.len() < constants::storage::PAGE_SIZE {
+                    &self_mut.packed_buf
+                } else {
+                    write_buf
+                };
+                self_mut.disk.write_packed(offset, data)
+            } else {
+                self_mut
+                    .disk
+                    .write_block_from(offset, write_buf)
+                    .map(|()| None)
+            };
+            if moved
+                .map_err(|_| errors::FlushFrameError::IOError)?
+                .is_some()
+            {
+                self_mut.relocated.push((page_id, offset));
+            }
 
             // Re-borrow mutably to update dirty flag
             if let Some(frame) = &mut self_mut.frames[frame_id as usize] {
@@ -229,8 +526,269 @@ impl BufferPoolCore {
         }
     }
 
+    /// Reads a page of a compressed segment. One that was allocated but never written
+    /// reads as zeroes, like a new page of any other file.
+    fn read_packed(
+        &mut self,
+        offset: u64,
+        buf: &mut page::base::PageBuf,
+    ) -> Result<(), errors::FetchPageError> {
+        if self.disk.packed_slot(offset).is_none() {
+            buf.fill(0);
+            return Ok(());
+        }
+        self.disk
+            .read_packed(offset, &mut self.packed_buf)
+            .map_err(read_error)?;
+        if self.packed_buf.len() == constants::storage::PAGE_SIZE {
+            buf.copy_from_slice(&self.packed_buf);
+            return Ok(());
+        }
+        match disk::compression::decompress(&self.packed_buf, buf) {
+            Ok(len) if len == constants::storage::PAGE_SIZE => Ok(()),
+            _ => Err(errors::FetchPageError::ChecksumMismatch),
+        }
+    }
+
+    /// Where the page at `offset` is stored if it is in a compressed segment, see
+    /// `DiskManager::packed_slot`.
+    pub fn packed_slot(&self, offset: u64) -> Option<disk::Slot> {
+        self.disk.packed_slot(offset)
+    }
+
+    /// Number of pages in file `file_id`, whether or not they are in the pool.
+    pub fn page_count(self: Pin<&mut Self>, file_id: u32) -> std::io::Result<u64> {
+        unsafe { self.get_unchecked_mut() }.disk.page_count(file_id)
+    }
+
+    /// Makes the pages written back so far durable.
+    pub fn sync(&self) -> std::io::Result<()> {
+        self.disk.sync()
+    }
+
+    /// Cuts file `file_id` down to `page_count` pages. Only pages on the free list may
+    /// be cut off: none of them are in the pool.
+    pub fn truncate(self: Pin<&mut Self>, file_id: u32, page_count: u64) -> std::io::Result<()> {
+        let self_mut = unsafe { self.get_unchecked_mut() };
+        debug_assert!(self_mut.frames_meta_offset.keys().all(|&offset| {
+            disk::address_file(offset) != file_id || disk::address_index(offset) < page_count
+        }));
+        self_mut.disk.truncate(file_id, page_count)
+    }
+
+    /// Creates segment `file_id` with its header page, see `DirectoryPageLocator`.
+    /// It goes to directory `location`, or next to the database file.
+    pub fn create_segment(
+        self: Pin<&mut Self>,
+        file_id: u32,
+        location: Option<&str>,
+    ) -> std::io::Result<()> {
+        let self_mut = unsafe { self.get_unchecked_mut() };
+        self_mut.disk.create_segment(file_id, location)?;
+        let address = self_mut.disk.allocate_page(file_id)?;
+        // Like free pages, the header is written straight to disk instead of going
+        // through the pool
+        let buf = unsafe { &mut *self_mut.write_buf.cast::<page::base::PageBuf>() };
+        buf.fill(0);
+        page::header::PageHeader::from_buf_mut(buf).init(0, page::base::PageKind::Directory);
+        page::checksum::stamp(buf);
+        self_mut.disk.write_block_from(address, buf)
+    }
+
+    /// Drops the pages of segment `file_id` from the pool without writing them back and
+    /// deletes the segment. Fails if any of its pages is pinned.
+    pub fn remove_segment(
+        mut self: Pin<&mut Self>,
+        file_id: u32,
+    ) -> Result<(), errors::FreePageError> {
+        let frames: Vec<usize> = self
+            .frames
+            .iter()
+            .flatten()
+            .filter(|frame| disk::address_file(frame.file_offset) == file_id)
+            .map(|frame| frame.fid as usize)
+            .collect();
+        if frames
+            .iter()
+            .any(|&fid| self.frames[fid].is_some_and(|f| f.pinned()))
+        {
+            return Err(errors::FreePageError::PagePinned);
+        }
+        for fid in frames {
+            let self_mut = unsafe { self.as_mut().get_unchecked_mut() };
+            if let Some(frame) = &mut self_mut.frames[fid] {
+                frame.dirty = false;
+            }
+            self.as_mut()
+                .dealloc_frame_at(fid)
+                .map_err(|_| errors::FreePageError::PagePinned)?;
+        }
+        unsafe { self.get_unchecked_mut() }
+            .disk
+            .remove_segment(file_id)
+            .map_err(|_| errors::FreePageError::IOError)
+    }
+
+    /// The segments the pool has opened so far.
+    pub fn open_segments(&self) -> Vec<u32> {
+        self.disk.open_segments()
+    }
+
+    /// Records that segment `file_id` lives in directory `location`, see `DiskManager`.
+    pub fn place_segment(self: Pin<&mut Self>, file_id: u32, location: &str) {
+        unsafe { self.get_unchecked_mut() }
+            .disk
+            .place_segment(file_id, location)
+    }
+
+    /// Writes the page at `file_offset` out as a free page whose successor on the free
+    /// list is `next`, a page of the same file. Free pages never live in the pool, so this
+    /// goes straight to disk and drops whatever frame still holds the old contents.
+    pub fn write_free_page(
+        mut self: Pin<&mut Self>,
+        file_offset: u64,
+        next: Option<u64>,
+    ) -> Result<(), errors::FreePageError> {
+        if !self.as_mut().discard_frame_at(file_offset) {
+            return Err(errors::FreePageError::PagePinned);
+        }
+
+        unsafe {
+            let self_mut = self.get_unchecked_mut();
+            let buf = &mut *self_mut.write_buf.cast::<page::base::PageBuf>();
+            buf.fill(0);
+            let header = page::header::PageHeader::from_buf_mut(buf);
+            header.init(0, page::base::PageKind::Free);
+            // Free lists never leave their file, so the u32 header field holds the page
+            // index within it, see `Directory::free_list_head`
+            let file_id = disk::address_file(file_offset);
+            debug_assert!(next.is_none_or(|n| disk::address_file(n) == file_id));
+            header.set_next_page_id(next.map_or(0, disk::address_index) as page::base::PageId);
+            page::checksum::stamp(buf);
+            self_mut
+                .disk
+                .write_block_from(file_offset, buf)
+                .map_err(|_| errors::FreePageError::IOError)
+        }
+    }
+
+    /// Drops the frame that holds the page at `file_offset`, if any, without writing it
+    /// back. Returns false if the page is pinned.
+    fn discard_frame_at(mut self: Pin<&mut Self>, file_offset: u64) -> bool {
+        if let Some(frame_meta) = self.frames_meta_offset.get(&file_offset) {
+            let fid = frame_meta.frame_id as usize;
+            let self_mut = unsafe { self.as_mut().get_unchecked_mut() };
+            if let Some(frame) = &mut self_mut.frames[fid] {
+                if frame.pinned() {
+                    return false;
+                }
+                frame.dirty = false;
+            }
+            return self.as_mut().dealloc_frame_at(fid).is_ok();
+        }
+        true
+    }
+
+    /// Reads the superblock, the first page of the database file. Like free pages it
+    /// never lives in the pool.
+    pub fn read_superblock(
+        self: Pin<&mut Self>,
+    ) -> Result<page::Superblock, errors::SuperblockError> {
+        unsafe {
+            let self_mut = self.get_unchecked_mut();
+            let buf = &mut *self_mut.write_buf.cast::<page::base::PageBuf>();
+            self_mut
+                .disk
+                .read_block_into(page::superblock::SUPERBLOCK_OFFSET, buf)
+                .map_err(|_| errors::SuperblockError::IOError)?;
+            page::Superblock::read_from(buf).map_err(errors::SuperblockError::Format)
+        }
+    }
+
+    /// Writes `superblock` over the first page of the database file and makes it durable,
+    /// along with everything written before it.
+    pub fn write_superblock(
+        mut self: Pin<&mut Self>,
+        superblock: &page::Superblock,
+    ) -> Result<(), errors::SuperblockError> {
+        // Only a file being upgraded has something else there
+        let offset = page::superblock::SUPERBLOCK_OFFSET;
+        if !self.as_mut().discard_frame_at(offset) {
+            return Err(errors::SuperblockError::PagePinned);
+        }
+        unsafe {
+            let self_mut = self.get_unchecked_mut();
+            let buf = &mut *self_mut.write_buf.cast::<page::base::PageBuf>();
+            superblock.write_to(buf);
+            self_mut
+                .disk
+                .write_block_from(offset, buf)
+                .and_then(|()| self_mut.disk.sync())
+                .map_err(|_| errors::SuperblockError::IOError)
+        }
+    }
+
+    /// Moves the superblock past `page_id` before a page with it exists, so it is not
+    /// handed out again after a restart however much of the pool made it to disk. Moves it
+    /// `PAGE_ID_RESERVE` ids further, so most allocations need not write it.
+    pub fn reserve_page_id(
+        mut self: Pin<&mut Self>,
+        page_id: base::PageId,
+    ) -> Result<(), errors::SuperblockError> {
+        match self.page_id_limit {
+            Some(limit) if page_id >= limit => {}
+            _ => return Ok(()),
+        }
+        let mut superblock = self.as_mut().read_superblock()?;
+        superblock.next_page_id = page_id.saturating_add(PAGE_ID_RESERVE + 1);
+        self.as_mut().write_superblock(&superblock)?;
+        unsafe { self.get_unchecked_mut() }.page_id_limit = Some(superblock.next_page_id);
+        Ok(())
+    }
+
+    /// Copies the page at `file_offset` of the database file to a new page at its end,
+    /// straight on disk, and returns where the copy went.
+    fn copy_page_to_end(mut self: Pin<&mut Self>, file_offset: u64) -> std::io::Result<u64> {
+        unsafe {
+            let self_mut = self.as_mut().get_unchecked_mut();
+            let buf = &mut *self_mut.write_buf.cast::<page::base::PageBuf>();
+            self_mut.disk.read_block_into(file_offset, buf)?;
+            let copy = self_mut.disk.allocate_page(disk::MAIN_FILE_ID)?;
+            self_mut.disk.write_block_from(copy, buf)?;
+            Ok(copy)
+        }
+    }
+
+    /// Reads the free page at `file_offset` and returns the next page on the free list.
+    pub fn read_free_page(
+        self: Pin<&mut Self>,
+        file_offset: u64,
+    ) -> Result<Option<u64>, errors::FreePageError> {
+        unsafe {
+            let self_mut = self.get_unchecked_mut();
+            let buf = &mut *self_mut.write_buf.cast::<page::base::PageBuf>();
+            self_mut
+                .disk
+                .read_block_into(file_offset, buf)
+                .map_err(|_| errors::FreePageError::IOError)?;
+            if !page::checksum::verify(buf, self_mut.checksums_required) {
+                return Err(errors::FreePageError::ChecksumMismatch);
+            }
+
+            let header = page::header::PageHeader::from_buf(buf);
+            if header.page_kind() != page::base::PageKind::Free {
+                return Err(errors::FreePageError::NotFree);
+            }
+            let next = header.next_page_id();
+            let file_id = disk::address_file(file_offset);
+            Ok((next != 0).then(|| disk::page_address(file_id, next as u64)))
+        }
+    }
+
+    /// Writes back every dirty frame, except pinned ones that are latched at the moment
+    /// (see `flush_frame`).
     pub fn flush_all(mut self: Pin<&mut Self>) -> Result<(), errors::FlushAllError> {
-        for i in 0..FRAME_COUNT {
+        for i in 0..self.frames.len() {
             if let Some(frame) = self.as_ref().get_ref().frames[i] {
                 if frame.dirty {
                     self.as_mut()
@@ -242,9 +800,136 @@ impl BufferPoolCore {
         Ok(())
     }
 
+    /// Writes back up to `max_pages` dirty frames, continuing round-robin from where the
+    /// last call stopped so every dirty page gets its turn. Pinned frames are skipped since
+    /// their page may be halfway through a change. Returns how many pages were written.
+    pub fn flush_some(
+        mut self: Pin<&mut Self>,
+        max_pages: usize,
+    ) -> Result<usize, errors::FlushAllError> {
+        let frame_count = self.frames.len();
+        let start = self.writer_hand % frame_count;
+        let mut written = 0;
+        let mut scanned = 0;
+        while scanned < frame_count && written < max_pages {
+            let idx = (start + scanned) % frame_count;
+            scanned += 1;
+            if let Some(frame) = self.frames[idx]
+                && frame.dirty
+                && !frame.pinned()
+            {
+                self.as_mut()
+                    .flush_frame(idx as u32)
+                    .map_err(|_| errors::FlushAllError::IOError)?;
+                written += 1;
+            }
+        }
+        unsafe { self.get_unchecked_mut() }.writer_hand = (start + scanned) % frame_count;
+        Ok(written)
+    }
+
+    /// Pages whose copy in the pool has not been written back yet.
+    pub fn dirty_page_table(&self) -> Vec<DirtyPage> {
+        self.frames
+            .iter()
+            .flatten()
+            .filter(|frame| frame.dirty)
+            .map(|frame| DirtyPage {
+                page_id: frame.page_id,
+                file_offset: frame.file_offset,
+            })
+            .collect()
+    }
+
+    /// Starts a fuzzy checkpoint from a snapshot of the dirty page table, whose pages
+    /// `checkpoint_step` then writes back a batch at a time.
+    pub fn begin_checkpoint(&self) -> Result<Checkpoint, errors::CheckpointError> {
+        let last_id = match self.disk.path() {
+            Some(path) => CheckpointRecord::read(path)
+                .map_err(|_| errors::CheckpointError::IOError)?
+                .map_or(0, |record| record.id),
+            None => 0,
+        };
+        let mut pending = self.dirty_page_table();
+        pending.reverse();
+        Ok(Checkpoint {
+            id: last_id + 1,
+            pending,
+            still_dirty: Vec::new(),
+            stats: CheckpointStats {
+                id: last_id + 1,
+                ..Default::default()
+            },
+        })
+    }
+
+    /// Writes back up to `max_pages` more pages of the snapshot `checkpoint` started
+    /// from. Pages written back or evicted since need nothing more, and pinned pages are
+    /// left alone instead of waiting for them. Returns whether the snapshot is done.
+    pub fn checkpoint_step(
+        mut self: Pin<&mut Self>,
+        checkpoint: &mut Checkpoint,
+        max_pages: usize,
+    ) -> Result<bool, errors::CheckpointError> {
+        let mut written = 0;
+        while written < max_pages
+            && let Some(page) = checkpoint.pending.pop()
+        {
+            let Some(meta) = self.frames_meta_offset.get(&page.file_offset).copied() else {
+                continue;
+            };
+            let frame = self.frames[meta.frame_id as usize].expect("mapped frame to exist");
+            if !frame.dirty {
+                continue;
+            }
+            if frame.pinned() {
+                checkpoint.stats.pages_skipped += 1;
+                checkpoint.still_dirty.push(page);
+                continue;
+            }
+            self.as_mut()
+                .flush_frame(meta.frame_id)
+                .map_err(|_| errors::CheckpointError::IOError)?;
+            checkpoint.stats.pages_written += 1;
+            written += 1;
+        }
+        Ok(checkpoint.pending.is_empty())
+    }
+
+    /// Makes the pages `checkpoint` wrote durable and only then records it, along with
+    /// the pages it had to leave dirty (see `CheckpointRecord`).
+    pub fn finish_checkpoint(
+        &self,
+        checkpoint: &Checkpoint,
+    ) -> Result<CheckpointStats, errors::CheckpointError> {
+        self.disk
+            .sync()
+            .map_err(|_| errors::CheckpointError::IOError)?;
+        // Storage that is not a file has nowhere to keep the record
+        if let Some(path) = self.disk.path() {
+            let record = CheckpointRecord {
+                id: checkpoint.id,
+                complete: true,
+                dirty_pages: checkpoint.still_dirty.clone(),
+            };
+            record
+                .write(path)
+                .map_err(|_| errors::CheckpointError::IOError)?;
+        }
+        Ok(checkpoint.stats)
+    }
+
     pub fn fetch_page_at_offset(
+        self: Pin<&mut Self>,
+        offset: u64,
+    ) -> Result<&mut Frame, errors::FetchPageError> {
+        self.fetch_page_at_offset_with(offset, AccessStrategy::Normal)
+    }
+
+    pub fn fetch_page_at_offset_with(
         mut self: Pin<&mut Self>,
         offset: u64,
+        strategy: AccessStrategy,
     ) -> Result<&mut Frame, errors::FetchPageError> {
         // is page is this offset already loaded?
         let is_loaded = self.as_mut().frames_meta_offset.contains_key(&offset);
@@ -260,6 +945,7 @@ impl BufferPoolCore {
                 .frame_id;
 
             let _ = self.as_mut().pin_frame(frame_id);
+            self.as_mut().record_hit(frame_id, &strategy);
             let frame = unsafe {
                 self.get_unchecked_mut().frames[frame_id as usize]
                     .as_mut()
@@ -271,9 +957,11 @@ impl BufferPoolCore {
 
         // if not then create a frame and load it
         // cache miss load it
+        unsafe { self.as_mut().get_unchecked_mut().stats.misses += 1 };
         let frame_idx = self
             .as_mut()
-            .find_free_frame_with_evict()
+            .find_frame_for(&strategy)
+            .map_err(|_| errors::FetchPageError::IOError)?
             .ok_or(errors::FetchPageError::BufferFull)?;
 
         let frame = self
@@ -284,11 +972,29 @@ impl BufferPoolCore {
         let buf_ptr = frame.buf_ptr;
 
         unsafe {
-            self.as_mut()
-                .get_unchecked_mut()
-                .file_manager
-                .read_block_into(offset, &mut (*buf_ptr))
-                .map_err(|_| errors::FetchPageError::IOError)?;
+            let self_mut = self.as_mut().get_unchecked_mut();
+            let read = if self_mut.disk.is_packed(offset) {
+                self_mut.read_packed(offset, &mut *buf_ptr)
+            } else {
+                self_mut
+                    .disk
+                    .read_block_into(offset, &mut (*buf_ptr))
+                    .map_err(read_error)
+            };
+            let error = match read {
+                Err(e) => Some(e),
+                Ok(()) if !page::checksum::verify(&*buf_ptr, self_mut.checksums_required) => {
+                    Some(errors::FetchPageError::ChecksumMismatch)
+                }
+                Ok(()) => None,
+            };
+            if let Some(error) = error {
+                // Nothing knows about the frame yet, so just hand it back
+                let self_mut = self.as_mut().get_unchecked_mut();
+                self_mut.frames[frame_idx] = None;
+                self_mut.free_frames += 1;
+                return Err(error);
+            }
         }
 
         let frame = unsafe {
@@ -327,19 +1033,47 @@ impl BufferPoolCore {
             frame.pin_count = 1;
             self_mut_ref.evictor.set_frame_evictable(frame, false);
         }
+        self.as_mut()
+            .adopt_frame(frame_idx as u32, frame_meta.file_offset, strategy);
 
         let frame = unsafe { self.get_unchecked_mut().frames[frame_idx].as_mut().unwrap() };
         Ok(frame)
     }
 
     pub fn alloc_new_page(
+        self: Pin<&mut Self>,
+        page_kind: page::base::PageKind,
+        page_id: page::base::PageId,
+    ) -> Result<&mut Frame, errors::AllocNewPageError> {
+        self.alloc_new_page_with(page_kind, page_id, AccessStrategy::Normal)
+    }
+
+    pub fn alloc_new_page_with(
+        self: Pin<&mut Self>,
+        page_kind: page::base::PageKind,
+        page_id: page::base::PageId,
+        strategy: AccessStrategy,
+    ) -> Result<&mut Frame, errors::AllocNewPageError> {
+        self.alloc_new_page_at(page_kind, page_id, disk::MAIN_FILE_ID, None, strategy)
+    }
+
+    /// Allocates a page at `file_offset`, a page taken off the free list, or at the end
+    /// of file `file_id` when it is None.
+    pub fn alloc_new_page_at(
         mut self: Pin<&mut Self>,
         page_kind: page::base::PageKind,
         page_id: page::base::PageId,
+        file_id: u32,
+        file_offset: Option<u64>,
+        strategy: AccessStrategy,
     ) -> Result<&mut Frame, errors::AllocNewPageError> {
+        self.as_mut()
+            .reserve_page_id(page_id)
+            .map_err(|_| errors::AllocNewPageError::IOError)?;
         let frame_idx = self
             .as_mut()
-            .find_free_frame_with_evict()
+            .find_frame_for(&strategy)
+            .map_err(|_| errors::AllocNewPageError::IOError)?
             .ok_or(errors::AllocNewPageError::BufferFull)?;
 
         let frame = self
@@ -349,12 +1083,15 @@ impl BufferPoolCore {
 
         let buf_ptr = frame.buf_ptr;
 
-        let offset = unsafe {
-            self.as_mut()
-                .get_unchecked_mut()
-                .file_manager
-                .allocate_new_page_offset()
-                .map_err(|_| errors::AllocNewPageError::IOError)?
+        let offset = match file_offset {
+            Some(offset) => offset,
+            None => unsafe {
+                self.as_mut()
+                    .get_unchecked_mut()
+                    .disk
+                    .allocate_page(file_id)
+                    .map_err(|_| errors::AllocNewPageError::IOError)?
+            },
         };
 
         let frame = unsafe {
@@ -396,6 +1133,8 @@ impl BufferPoolCore {
             frame.pin_count = 1;
             self_mut_ref.evictor.set_frame_evictable(frame, false);
         }
+        self.as_mut()
+            .adopt_frame(frame_idx as u32, frame_meta.file_offset, strategy);
 
         let frame = unsafe { self.get_unchecked_mut().frames[frame_idx].as_mut().unwrap() };
         Ok(frame)
@@ -428,10 +1167,16 @@ impl BufferPoolCore {
         }
     }
 
-    pub fn dealloc_frame_at(mut self: Pin<&mut Self>, frame_idx: usize) {
+    /// Empties frame `frame_idx`, writing its page back first if it is dirty.
+    pub fn dealloc_frame_at(
+        mut self: Pin<&mut Self>,
+        frame_idx: usize,
+    ) -> Result<(), errors::DeallocFrameError> {
         let (is_pinned, is_dirty, page_id, file_offset) = {
             let self_mut = unsafe { self.as_mut().get_unchecked_mut() };
-            let frame = self_mut.frames[frame_idx].as_ref().unwrap();
+            let frame = self_mut.frames[frame_idx]
+                .as_ref()
+                .ok_or(errors::DeallocFrameError::FrameNotFound)?;
             (
                 frame.pinned(),
                 frame.dirty(),
@@ -441,13 +1186,11 @@ impl BufferPoolCore {
         };
 
         if is_pinned {
-            panic!("Frame Pinned cannot dealloc");
+            return Err(errors::DeallocFrameError::FramePinned);
         }
 
-        if is_dirty {
-            if self.as_mut().flush_frame(frame_idx as u32).is_err() {
-                panic!("Failed to flush dirty frame {} on dealloc", frame_idx);
-            }
+        if is_dirty && self.as_mut().flush_frame(frame_idx as u32).is_err() {
+            return Err(errors::DeallocFrameError::FlushError);
         }
         let self_mut = unsafe { self.as_mut().get_unchecked_mut() };
         self_mut.frames[frame_idx] = None;
@@ -463,6 +1206,7 @@ impl BufferPoolCore {
 
         // evictor bookkeeping
         self_mut.evictor.notify_frame_destroy(frame_idx as u32);
+        Ok(())
     }
 
     pub fn find_free_frame_with_evict(mut self: Pin<&mut Self>) -> Option<usize> {
@@ -470,7 +1214,11 @@ impl BufferPoolCore {
         let free_frames = self_mut.free_frames;
         if free_frames == 0 {
             let victim_frame_idx = self_mut.evictor.pick_victim()?;
-            self.as_mut().dealloc_frame_at(victim_frame_idx as usize);
+            // A victim that cannot be written back stays, and the pool counts as full
+            self.as_mut().flush_frame(victim_frame_idx).ok()?;
+            self.as_mut()
+                .dealloc_frame_at(victim_frame_idx as usize)
+                .ok()?;
         }
 
         self.frames.iter().position(|frame| frame.is_none())
@@ -483,15 +1231,9 @@ impl BufferPoolCore {
         self.frames.iter().position(|frame| frame.is_none())
     }
 
-    /// NOTE: idx must be within 0..FRAME_COUNT
+    /// NOTE: idx must be within 0..frame_count()
     pub unsafe fn get_frame_buf_at(self: Pin<&mut Self>, idx: usize) -> *mut page::base::PageBuf {
-        let offset = idx * constants::storage::PAGE_SIZE;
-        unsafe {
-            self.get_unchecked_mut()
-                .frames_backing_buf
-                .add(offset)
-                .cast::<page::base::PageBuf>()
-        }
+        self.frame_bufs[idx].cast::<page::base::PageBuf>()
     }
 }
 
@@ -500,18 +1242,48 @@ pub struct BufferPool {
     pub page_locator: Box<dyn PageLocator>,
 }
 
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        // Before the core flushes on its own, so moved pages still reach the directory
+        let _ = unsafe { Pin::new_unchecked(self) }.flush_all();
+    }
+}
+
 impl BufferPool {
     pub fn new(
-        file_manager: disk::FileManager,
+        device: Box<dyn disk::BlockDevice>,
+        evictor: Box<dyn Evictor>,
+        page_locator: Box<dyn PageLocator>,
+    ) -> Self {
+        Self::with_frame_count(device, evictor, page_locator, DEFAULT_FRAME_COUNT)
+    }
+
+    pub fn with_frame_count(
+        device: Box<dyn disk::BlockDevice>,
         evictor: Box<dyn Evictor>,
         page_locator: Box<dyn PageLocator>,
+        frame_count: usize,
     ) -> Self {
         Self {
-            core: BufferPoolCore::new(file_manager, evictor),
+            core: BufferPoolCore::with_frame_count(device, evictor, frame_count),
             page_locator,
         }
     }
 
+    pub fn frame_count(&self) -> usize {
+        self.core.frame_count()
+    }
+
+    pub fn resize(self: Pin<&mut Self>, frame_count: usize) -> Result<(), errors::ResizeError> {
+        self.core().resize(frame_count)
+    }
+
+    /// Shared handle to the per-frame latches, for callers that touch page bytes
+    /// without holding the pool itself (see `BPlusTree::shared`).
+    pub fn latches(&self) -> Arc<FrameLatches> {
+        Arc::clone(&self.core.latches)
+    }
+
     pub fn core(self: Pin<&mut Self>) -> Pin<&mut BufferPoolCore> {
         unsafe { self.map_unchecked_mut(|s| &mut s.core) }
     }
@@ -528,6 +1300,7 @@ impl BufferPool {
     pub fn fetch_page(
         mut self: Pin<&mut Self>,
         page_id: page::base::PageId,
+        strategy: AccessStrategy,
     ) -> Result<&mut Frame, errors::FetchPageError> {
         // 1. Check if page is already in buffer
         if let Some(frame_meta) = self.as_ref().get_ref().core.frames_meta_pid.get(&page_id) {
@@ -536,6 +1309,7 @@ impl BufferPool {
             self.as_mut()
                 .pin_frame(fid)
                 .map_err(|_| errors::FetchPageError::InvalidPage)?; // Should not fail
+            self.as_mut().core().record_hit(fid, &strategy);
             return Ok(unsafe {
                 self.get_unchecked_mut().core.frames[fid as usize]
                     .as_mut()
@@ -549,66 +1323,476 @@ impl BufferPool {
             (Pin::new_unchecked(&mut this.core), &mut this.page_locator)
         };
 
-        let offset_result = locator.find_file_offset(page_id, core);
+        let offset_result = locator.find_file_offset(page_id, core);
+
+        let offset = offset_result.map_err(|err| match err {
+            locator::errors::FindOffsetError::PageNotFoundError => errors::FetchPageError::NotFound,
+            // A corrupt directory page is as bad as a corrupt target page
+            locator::errors::FindOffsetError::PageFetchError(
+                errors::FetchPageError::ChecksumMismatch,
+            ) => errors::FetchPageError::ChecksumMismatch,
+            _ => errors::FetchPageError::IOError,
+        })?;
+
+        // 3. Fetch from disk at offset
+        self.core().fetch_page_at_offset_with(offset, strategy)
+    }
+
+    pub fn fetch_page_at_offset(
+        self: Pin<&mut Self>,
+        offset: u64,
+    ) -> Result<&mut Frame, errors::FetchPageError> {
+        self.core().fetch_page_at_offset(offset)
+    }
+
+    pub fn alloc_new_page(
+        self: Pin<&mut Self>,
+        page_kind: page::base::PageKind,
+        page_id: page::base::PageId,
+    ) -> Result<&mut Frame, errors::AllocNewPageError> {
+        self.alloc_new_page_with(page_kind, page_id, AccessStrategy::Normal)
+    }
+
+    /// Allocates a page in the database file, see `alloc_new_page_in`.
+    pub fn alloc_new_page_with(
+        self: Pin<&mut Self>,
+        page_kind: page::base::PageKind,
+        page_id: page::base::PageId,
+        strategy: AccessStrategy,
+    ) -> Result<&mut Frame, errors::AllocNewPageError> {
+        self.alloc_new_page_in(page_kind, page_id, disk::MAIN_FILE_ID, strategy)
+    }
+
+    /// Allocates a page in the file that holds page `near`, so a table or index grows
+    /// inside its own segment. Pages near nothing (0) go to the database file.
+    pub fn alloc_new_page_near(
+        mut self: Pin<&mut Self>,
+        page_kind: page::base::PageKind,
+        page_id: page::base::PageId,
+        near: page::base::PageId,
+        strategy: AccessStrategy,
+    ) -> Result<&mut Frame, errors::AllocNewPageError> {
+        let file_id = match near {
+            0 => disk::MAIN_FILE_ID,
+            near => self
+                .as_mut()
+                .segment_of(near)
+                .map_err(|_| errors::AllocNewPageError::InvalidPage)?,
+        };
+        self.alloc_new_page_in(page_kind, page_id, file_id, strategy)
+    }
+
+    /// Allocates a page in file `file_id`, reusing one from its free list before growing it.
+    pub fn alloc_new_page_in(
+        mut self: Pin<&mut Self>,
+        page_kind: page::base::PageKind,
+        page_id: page::base::PageId,
+        file_id: u32,
+        strategy: AccessStrategy,
+    ) -> Result<&mut Frame, errors::AllocNewPageError> {
+        let (core, locator) = self.as_mut().get_core_and_locator();
+        let reused = locator
+            .reuse_page(file_id, core)
+            .map_err(|_| errors::AllocNewPageError::IOError)?;
+
+        let fid = match self
+            .as_mut()
+            .core()
+            .alloc_new_page_at(page_kind, page_id, file_id, reused, strategy)
+        {
+            Ok(frame) => frame.fid(),
+            Err(e) => {
+                // Put the page back rather than leak it
+                if let Some(offset) = reused {
+                    let (core, locator) = self.as_mut().get_core_and_locator();
+                    locator.release_page(offset, core).ok();
+                }
+                return Err(e);
+            }
+        };
+
+        Ok(unsafe {
+            self.get_unchecked_mut().core.frames[fid as usize]
+                .as_mut()
+                .unwrap()
+        })
+    }
+
+    pub fn pin_frame(self: Pin<&mut Self>, frame_id: u32) -> Result<(), errors::PinFrameError> {
+        self.core().pin_frame(frame_id)
+    }
+
+    pub fn unpin_frame(self: Pin<&mut Self>, frame_id: u32) -> Result<(), errors::UnpinFrameError> {
+        self.core().unpin_frame(frame_id)
+    }
+
+    pub fn flush_frame(self: Pin<&mut Self>, frame_id: u32) -> Result<(), errors::FlushFrameError> {
+        self.core().flush_frame(frame_id)
+    }
+
+    pub fn flush_all(mut self: Pin<&mut Self>) -> Result<(), errors::FlushAllError> {
+        loop {
+            self.as_mut().core().flush_all()?;
+            // Recording moves dirties the directory, which then has to be written too
+            let moved = self
+                .as_mut()
+                .record_relocations()
+                .map_err(|_| errors::FlushAllError::IOError)?;
+            if !moved {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn flush_some(
+        mut self: Pin<&mut Self>,
+        max_pages: usize,
+    ) -> Result<usize, errors::FlushAllError> {
+        let written = self.as_mut().core().flush_some(max_pages)?;
+        self.record_relocations()
+            .map_err(|_| errors::FlushAllError::IOError)?;
+        Ok(written)
+    }
+
+    pub fn sync(&self) -> std::io::Result<()> {
+        self.core.sync()
+    }
+
+    /// Takes a whole checkpoint in one go. `bg_writer::checkpoint_in_batches` lets go of
+    /// the pool between batches instead.
+    pub fn checkpoint(
+        mut self: Pin<&mut Self>,
+    ) -> Result<CheckpointStats, errors::CheckpointError> {
+        let mut checkpoint = self.as_mut().begin_checkpoint()?;
+        loop {
+            if let Some(stats) = self.as_mut().checkpoint_step(&mut checkpoint, usize::MAX)? {
+                return Ok(stats);
+            }
+        }
+    }
+
+    /// Starts a fuzzy checkpoint, see `BufferPoolCore::begin_checkpoint`.
+    pub fn begin_checkpoint(
+        mut self: Pin<&mut Self>,
+    ) -> Result<Checkpoint, errors::CheckpointError> {
+        self.as_mut()
+            .record_relocations()
+            .map_err(|_| errors::CheckpointError::IOError)?;
+        self.core.begin_checkpoint()
+    }
+
+    /// Writes up to `max_pages` more pages of `checkpoint`, see
+    /// `BufferPoolCore::checkpoint_step`. Once they are all written, pages of compressed
+    /// segments that moved are recorded in the directory, whose pages the checkpoint
+    /// writes too, and the checkpoint is finished: its stats are returned then. After
+    /// that the sectors the pages left behind can be reused.
+    pub fn checkpoint_step(
+        mut self: Pin<&mut Self>,
+        checkpoint: &mut Checkpoint,
+        max_pages: usize,
+    ) -> Result<Option<CheckpointStats>, errors::CheckpointError> {
+        if !self
+            .as_mut()
+            .core()
+            .checkpoint_step(checkpoint, max_pages)?
+        {
+            return Ok(None);
+        }
+        if self
+            .as_mut()
+            .record_relocations()
+            .map_err(|_| errors::CheckpointError::IOError)?
+        {
+            checkpoint.pending = self.core.dirty_page_table();
+            checkpoint.pending.reverse();
+            return Ok(None);
+        }
+        let stats = self.core.finish_checkpoint(checkpoint)?;
+        // A skipped directory page may still hold a move the disk does not know about
+        if stats.pages_skipped == 0 {
+            unsafe { self.get_unchecked_mut() }
+                .core
+                .disk
+                .recycle_packed();
+        }
+        Ok(Some(stats))
+    }
+
+    /// Writes the slots of the pages of compressed segments that moved on their last
+    /// flush to the directory. Returns whether there were any.
+    fn record_relocations(mut self: Pin<&mut Self>) -> Result<bool, String> {
+        let core = unsafe { &mut self.as_mut().get_unchecked_mut().core };
+        let relocated = std::mem::take(&mut core.relocated);
+        let moved = !relocated.is_empty();
+        for (page_id, offset) in relocated {
+            // Freed since, or moved again and still recorded now
+            let Some(slot) = self.core.packed_slot(offset) else {
+                continue;
+            };
+            let (core, locator) = self.as_mut().get_core_and_locator();
+            match locator.update_page_slot(page_id, slot, core) {
+                Ok(()) | Err(locator::errors::SlotError::PageNotFoundError) => {}
+                Err(e) => return Err(format!("Failed to record page slot: {:?}", e)),
+            }
+        }
+        Ok(moved)
+    }
+
+    pub fn dirty_page_table(&self) -> Vec<DirtyPage> {
+        self.core.dirty_page_table()
+    }
+
+    /// The record left by the last checkpoint of this database file, if any.
+    pub fn last_checkpoint(&self) -> std::io::Result<Option<CheckpointRecord>> {
+        match self.core.disk.path() {
+            Some(path) => CheckpointRecord::read(path),
+            None => Ok(None),
+        }
+    }
+
+    pub fn mark_frame_dirty(self: Pin<&mut Self>, frame_id: u32) {
+        self.core().mark_frame_dirty(frame_id)
+    }
+
+    /// Buffer hits and misses since the pool was opened.
+    pub fn stats(&self) -> PoolStats {
+        self.core.stats()
+    }
+
+    pub fn register_page_in_directory(
+        mut self: Pin<&mut Self>,
+        page_id: page::base::PageId,
+        file_offset: u64,
+        free_space: u32,
+    ) -> Result<(), String> {
+        let (core, locator) = unsafe {
+            let this = self.as_mut().get_unchecked_mut();
+            (Pin::new_unchecked(&mut this.core), &mut this.page_locator)
+        };
+
+        locator
+            .register_page(page_id, file_offset, free_space, core)
+            .map_err(|e| format!("Failed to register page: {:?}", e))
+    }
 
-        let offset = offset_result.map_err(|err| match err {
-            locator::errors::FindOffsetError::PageNotFoundError => errors::FetchPageError::NotFound,
-            _ => errors::FetchPageError::IOError,
-        })?;
+    /// Reads the superblock of the database file and starts the directory where it says.
+    /// An empty file gets the superblock of a new database, whose first directory page is
+    /// to follow right after it. A file from before the superblock is upgraded to the
+    /// current format: its first directory page is moved to the end of the file, durably,
+    /// so the superblock can take its place. A crash in between leaves the old layout,
+    /// which is upgraded again. The indexes of such a file are rebuilt by the catalog.
+    pub fn open_superblock(mut self: Pin<&mut Self>) -> Result<page::Superblock, String> {
+        use errors::SuperblockError;
+        use page::superblock::errors::FormatError;
+
+        let page_count = self
+            .as_mut()
+            .core()
+            .page_count(disk::MAIN_FILE_ID)
+            .map_err(|e| format!("Failed to read the database file: {}", e))?;
+        let superblock = if page_count == 0 {
+            let superblock = page::Superblock::new(page::superblock::SUPERBLOCK_OFFSET + 1, 0);
+            self.as_mut().write_superblock(&superblock)?;
+            superblock
+        } else {
+            match self.as_mut().core().read_superblock() {
+                Ok(superblock) => superblock,
+                Err(SuperblockError::Format(FormatError::Unversioned)) => {
+                    self.as_mut().upgrade_unversioned()?
+                }
+                Err(SuperblockError::Format(e)) => return Err(e.to_string()),
+                Err(e) => return Err(format!("Failed to read the superblock: {:?}", e)),
+            }
+        };
 
-        // 3. Fetch from disk at offset
-        self.fetch_page_at_offset(offset)
+        let (core, locator) = self.get_core_and_locator();
+        locator.set_root_directory(superblock.root_directory);
+        let core = unsafe { core.get_unchecked_mut() };
+        core.page_id_limit = Some(superblock.next_page_id);
+        core.checksums_required = superblock.checksums_required();
+        Ok(superblock)
     }
 
-    pub fn fetch_page_at_offset(
-        self: Pin<&mut Self>,
-        offset: u64,
-    ) -> Result<&mut Frame, errors::FetchPageError> {
-        self.core().fetch_page_at_offset(offset)
+    fn upgrade_unversioned(mut self: Pin<&mut Self>) -> Result<page::Superblock, String> {
+        self.as_mut()
+            .flush_all()
+            .map_err(|e| format!("Failed to flush before upgrading: {:?}", e))?;
+        let root = self
+            .as_mut()
+            .core()
+            .copy_page_to_end(page::superblock::SUPERBLOCK_OFFSET)
+            .and_then(|root| self.core.sync().map(|()| root))
+            .map_err(|e| format!("Failed to move the directory: {}", e))?;
+        // The catalog moves the oid counter past every oid it finds. Pages from before
+        // checksums may still carry none
+        let superblock = page::Superblock {
+            format_version: page::superblock::UNCHECKSUMMED_VERSION,
+            ..page::Superblock::new(root, 0)
+        };
+        self.write_superblock(&superblock)?;
+        Ok(superblock)
     }
 
-    pub fn alloc_new_page(
-        self: Pin<&mut Self>,
-        page_kind: page::base::PageKind,
-        page_id: page::base::PageId,
-    ) -> Result<&mut Frame, errors::AllocNewPageError> {
-        self.core().alloc_new_page(page_kind, page_id)
+    /// The highest page id in the directory, or 0 before there is one. Recovers the page id
+    /// counter of a file whose superblock does not record it.
+    pub fn max_page_id(mut self: Pin<&mut Self>) -> Result<page::base::PageId, String> {
+        let root = self.page_locator.root_directory();
+        let page_count = self
+            .as_mut()
+            .core()
+            .page_count(disk::MAIN_FILE_ID)
+            .map_err(|e| format!("Failed to read the database file: {}", e))?;
+        if disk::address_index(root) >= page_count {
+            return Ok(0);
+        }
+        let (core, locator) = self.get_core_and_locator();
+        locator
+            .max_page_id(core)
+            .map_err(|e| format!("Failed to scan the directory: {:?}", e))
     }
 
-    pub fn pin_frame(self: Pin<&mut Self>, frame_id: u32) -> Result<(), errors::PinFrameError> {
-        self.core().pin_frame(frame_id)
+    /// The ids of the pages of file `file_id`, in directory order.
+    pub fn file_page_ids(
+        mut self: Pin<&mut Self>,
+        file_id: u32,
+    ) -> Result<Vec<page::base::PageId>, String> {
+        let (core, locator) = self.as_mut().get_core_and_locator();
+        locator
+            .file_page_ids(file_id, core)
+            .map_err(|e| format!("Failed to scan the directory: {:?}", e))
     }
 
-    pub fn unpin_frame(self: Pin<&mut Self>, frame_id: u32) -> Result<(), errors::UnpinFrameError> {
-        self.core().unpin_frame(frame_id)
+    /// Writes the superblock of the database file, see `BufferPoolCore::write_superblock`.
+    pub fn write_superblock(
+        self: Pin<&mut Self>,
+        superblock: &page::Superblock,
+    ) -> Result<(), String> {
+        self.core()
+            .write_superblock(superblock)
+            .map_err(|e| format!("Failed to write the superblock: {:?}", e))
     }
 
-    pub fn flush_frame(self: Pin<&mut Self>, frame_id: u32) -> Result<(), errors::FlushFrameError> {
-        self.core().flush_frame(frame_id)
-    }
+    /// Drops a page from the pool without writing it back, removes it from the directory
+    /// and puts it on the free list for a later allocation to reuse.
+    /// The page must not be pinned by anyone.
+    pub fn free_page(mut self: Pin<&mut Self>, page_id: page::base::PageId) -> Result<(), String> {
+        let file_offset = if let Some(frame_meta) = self.core.frames_meta_pid.get(&page_id) {
+            frame_meta.file_offset
+        } else {
+            let (core, locator) = self.as_mut().get_core_and_locator();
+            locator
+                .find_file_offset(page_id, core)
+                .map_err(|e| format!("Failed to locate page {}: {:?}", page_id, e))?
+        };
+
+        if let Some(frame_meta) = self.as_ref().get_ref().core.frames_meta_pid.get(&page_id) {
+            let fid = frame_meta.frame_id as usize;
+            let core = unsafe { &mut self.as_mut().get_unchecked_mut().core };
+            if let Some(frame) = &mut core.frames[fid] {
+                if frame.pinned() {
+                    return Err(format!("Cannot free pinned page {}", page_id));
+                }
+                // Contents are garbage from here on, no point flushing them
+                frame.dirty = false;
+            }
+            self.as_mut()
+                .core()
+                .dealloc_frame_at(fid)
+                .map_err(|e| format!("Failed to drop the frame of page {}: {:?}", page_id, e))?;
+        }
 
-    pub fn flush_all(self: Pin<&mut Self>) -> Result<(), errors::FlushAllError> {
-        self.core().flush_all()
+        let (core, locator) = self.as_mut().get_core_and_locator();
+        locator
+            .unregister_page(page_id, core)
+            .map_err(|e| format!("Failed to unregister page: {:?}", e))?;
+
+        // A page of a compressed segment gives up its sectors instead
+        if self.core.disk.is_packed(file_offset) {
+            unsafe { self.get_unchecked_mut() }
+                .core
+                .disk
+                .forget_packed(file_offset);
+            return Ok(());
+        }
+        let (core, locator) = self.get_core_and_locator();
+        locator
+            .release_page(file_offset, core)
+            .map_err(|e| format!("Failed to release page: {:?}", e))
     }
 
-    pub fn mark_frame_dirty(self: Pin<&mut Self>, frame_id: u32) {
-        self.core().mark_frame_dirty(frame_id)
+    /// Returns the free pages at the end of the database file and of every open segment
+    /// to the file system. Returns how many pages the files shrank by in total.
+    pub fn trim_free_pages(mut self: Pin<&mut Self>) -> Result<u64, String> {
+        let files = std::iter::once(disk::MAIN_FILE_ID).chain(self.core.open_segments());
+        let mut trimmed = 0;
+        for file_id in files {
+            let (core, locator) = self.as_mut().get_core_and_locator();
+            trimmed += locator
+                .trim_free_pages(file_id, core)
+                .map_err(|e| format!("Failed to trim free pages: {:?}", e))?;
+        }
+        Ok(trimmed)
     }
 
-    pub fn register_page_in_directory(
+    /// The file that holds page `page_id`: the database file or a segment.
+    pub fn segment_of(
         mut self: Pin<&mut Self>,
         page_id: page::base::PageId,
-        file_offset: u64,
-        free_space: u32,
-    ) -> Result<(), String> {
-        let (core, locator) = unsafe {
-            let this = self.as_mut().get_unchecked_mut();
-            (Pin::new_unchecked(&mut this.core), &mut this.page_locator)
+    ) -> Result<u32, String> {
+        let file_offset = if let Some(frame_meta) = self.core.frames_meta_pid.get(&page_id) {
+            frame_meta.file_offset
+        } else {
+            let (core, locator) = self.as_mut().get_core_and_locator();
+            locator
+                .find_file_offset(page_id, core)
+                .map_err(|e| format!("Failed to locate page {}: {:?}", page_id, e))?
         };
+        Ok(disk::address_file(file_offset))
+    }
+
+    /// Creates segment `file_id`, an empty file for a table or index to grow in. It goes
+    /// to directory `location`, or next to the database file.
+    pub fn create_segment(
+        self: Pin<&mut Self>,
+        file_id: u32,
+        location: Option<&str>,
+    ) -> Result<(), String> {
+        self.core()
+            .create_segment(file_id, location)
+            .map_err(|e| format!("Failed to create segment {}: {:?}", file_id, e))
+    }
+
+    /// Stores the pages of segment `file_id` compressed from now on, see `PackedSegment`.
+    /// Must be called right after the segment is created, and every time the database is
+    /// opened before any of its pages are read.
+    pub fn compress_segment(mut self: Pin<&mut Self>, file_id: u32) -> Result<(), String> {
+        let (core, locator) = self.as_mut().get_core_and_locator();
+        let slots = locator
+            .file_slots(file_id, core)
+            .map_err(|e| format!("Failed to read slots of segment {}: {:?}", file_id, e))?;
+        unsafe { self.get_unchecked_mut() }
+            .core
+            .disk
+            .pack_segment(file_id, slots);
+        Ok(())
+    }
+
+    /// Tells the pool where segment `file_id` lives before any of its pages are read.
+    pub fn place_segment(self: Pin<&mut Self>, file_id: u32, location: &str) {
+        self.core().place_segment(file_id, location)
+    }
 
+    /// Deletes segment `file_id` along with its pages, in the pool and in the directory.
+    /// None of them may be pinned.
+    pub fn drop_segment(mut self: Pin<&mut Self>, file_id: u32) -> Result<(), String> {
+        self.as_mut()
+            .core()
+            .remove_segment(file_id)
+            .map_err(|e| format!("Failed to remove segment {}: {:?}", file_id, e))?;
+        let (core, locator) = self.get_core_and_locator();
         locator
-            .register_page(page_id, file_offset, free_space, core)
-            .map_err(|e| format!("Failed to register page: {:?}", e))
+            .unregister_file(file_id, core)
+            .map_err(|e| format!("Failed to unregister segment {}: {:?}", file_id, e))
     }
 
     pub fn expand_directory_and_register(
@@ -671,7 +1855,7 @@ impl BufferPool {
         new_dir_offset: u64,
     ) -> Result<(), String> {
         // Find the last directory page and update its next pointer
-        let mut curr_offset = 0u64;
+        let mut curr_offset = self.page_locator.root_directory();
 
         loop {
             let frame = self
@@ -706,6 +1890,7 @@ impl BufferPool {
                         page_id: new_dir_id,
                         file_offset: new_dir_offset,
                         free_space: 0, // Will be updated as it fills
+                        ..Default::default()
                     };
 
                     dir_page
@@ -743,6 +1928,8 @@ pub mod errors {
         NotFound,
         AllocError,
         InvalidPage,
+        /// The page read back is not what was written: a torn write or a bad disk
+        ChecksumMismatch,
     }
 
     #[derive(Debug, Default)]
@@ -778,7 +1965,35 @@ pub mod errors {
         IOError,
     }
 
-    // This is from your original file, but `dealloc_frame_at` isn't fallible
+    #[derive(Debug)]
+    pub enum CheckpointError {
+        IOError,
+        LockPoisoned,
+    }
+
+    #[derive(Debug)]
+    pub enum FreePageError {
+        PagePinned,
+        IOError,
+        ChecksumMismatch,
+        /// The free list points at a page that is in use
+        NotFree,
+    }
+
+    #[derive(Debug)]
+    pub enum SuperblockError {
+        IOError,
+        PagePinned,
+        Format(crate::storage::page::superblock::errors::FormatError),
+    }
+
+    #[derive(Debug)]
+    pub enum ResizeError {
+        OutOfRange,
+        FramesPinned,
+        IOError,
+    }
+
     #[derive(Debug)]
     pub enum DeallocFrameError {
         FrameNotFound,
@@ -792,7 +2007,7 @@ mod buffer_tests {
     use super::*;
     use crate::constants;
     use crate::storage::buffer::fifo_evictor::FifoEvictor;
-    use crate::storage::disk::FileManager;
+    use crate::storage::disk::{BlockDevice, FileManager};
     use crate::storage::page::base::{PageId, PageKind};
     use crate::storage::page_locator::locator;
     use std::fs;
@@ -801,6 +2016,13 @@ mod buffer_tests {
     use std::sync::atomic::{AtomicU32, Ordering};
 
     fn setup_buffer_pool_test(test_name: &str) -> (PathBuf, Pin<Box<BufferPool>>, AtomicU32) {
+        setup_buffer_pool_with(test_name, Box::new(FifoEvictor::new()))
+    }
+
+    fn setup_buffer_pool_with(
+        test_name: &str,
+        evictor: Box<dyn Evictor>,
+    ) -> (PathBuf, Pin<Box<BufferPool>>, AtomicU32) {
         let mut temp_dir = std::env::temp_dir();
         temp_dir.push(format!("nimbus_test_{}.db", test_name));
         let temp_file_path = temp_dir.clone();
@@ -811,10 +2033,12 @@ mod buffer_tests {
         let file_manager =
             FileManager::new(temp_file_str.to_string()).expect("Failed to create FileManager");
 
-        let evictor = Box::new(FifoEvictor::new());
-
         let page_locator = Box::new(locator::DirectoryPageLocator::new());
-        let buffer_pool = Box::pin(BufferPool::new(file_manager, evictor, page_locator));
+        let buffer_pool = Box::pin(BufferPool::new(
+            Box::new(file_manager),
+            evictor,
+            page_locator,
+        ));
 
         let page_id_cnt = AtomicU32::new(0);
         (temp_file_path, buffer_pool, page_id_cnt)
@@ -833,8 +2057,8 @@ mod buffer_tests {
     #[test]
     fn test_new_buffer_pool() {
         let (temp_path, buffer_pool, _) = setup_buffer_pool_test("new_buffer_pool");
-        assert_eq!(buffer_pool.core.frames.len(), FRAME_COUNT);
-        assert_eq!(buffer_pool.core.free_frames, FRAME_COUNT as u32);
+        assert_eq!(buffer_pool.core.frames.len(), DEFAULT_FRAME_COUNT);
+        assert_eq!(buffer_pool.core.free_frames, DEFAULT_FRAME_COUNT as u32);
         cleanup_temp_file(&temp_path);
     }
 
@@ -929,6 +2153,7 @@ mod buffer_tests {
             page::base::init_page_buf(page_buf_disk, PageKind::SlottedData);
             // Need to set PageId manually in the buffer
             page::header::PageHeader::from_buf_mut(page_buf_disk).set_page_id(page_id_on_disk);
+            page::checksum::stamp(page_buf_disk);
 
             // Write this buffer directly to the file
             let mut fm_direct = FileManager::new(temp_path.to_str().unwrap().to_string()).unwrap();
@@ -961,7 +2186,7 @@ mod buffer_tests {
         let mut allocated_offsets = Vec::new();
 
         // Fill the buffer pool completely
-        for i in 0..FRAME_COUNT {
+        for i in 0..DEFAULT_FRAME_COUNT {
             let page_id = generate_test_page_id(&page_id_cnt);
             let frame = buffer_pool
                 .as_mut()
@@ -1061,7 +2286,7 @@ mod buffer_tests {
         allocated_offsets.push(offset0);
 
         // Fill the rest of the buffer pool (these frames remain unpinned)
-        for i in 1..FRAME_COUNT {
+        for i in 1..DEFAULT_FRAME_COUNT {
             let page_id1 = generate_test_page_id(&page_id_cnt);
             let frame = buffer_pool
                 .as_mut()
@@ -1256,10 +2481,9 @@ mod buffer_tests {
     }
 
     #[test]
-    #[should_panic]
-    fn test_dealloc_pinned_frame_panics() {
+    fn test_dealloc_pinned_frame_fails() {
         let (temp_path, mut buffer_pool, page_id_cnt) =
-            setup_buffer_pool_test("dealloc_pinned_panics");
+            setup_buffer_pool_test("dealloc_pinned_fails");
 
         let page_id = generate_test_page_id(&page_id_cnt);
         let frame = buffer_pool
@@ -1269,9 +2493,422 @@ mod buffer_tests {
         let fid = frame.fid();
         buffer_pool.as_mut().pin_frame(fid).expect("Pinning failed");
 
-        // Directly call dealloc_frame_at (via BufferPoolCore) - This should panic
         let core = buffer_pool.as_mut().core();
-        core.dealloc_frame_at(fid as usize); // This line should trigger the panic
+        assert!(matches!(
+            core.dealloc_frame_at(fid as usize),
+            Err(errors::DeallocFrameError::FramePinned)
+        ));
+
+        cleanup_temp_file(&temp_path);
+    }
+
+    #[test]
+    fn test_hot_pages_survive_scan() {
+        use crate::storage::buffer::EvictionPolicy;
+
+        let hot = 8;
+        for policy in [EvictionPolicy::Fifo, EvictionPolicy::LruK(2)] {
+            let name = format!("hot_pages_{}", policy);
+            let (temp_path, mut buffer_pool, page_id_cnt) =
+                setup_buffer_pool_with(&name, policy.evictor());
+
+            let mut offsets = Vec::new();
+            for _ in 0..DEFAULT_FRAME_COUNT * 2 {
+                let page_id = generate_test_page_id(&page_id_cnt);
+                let frame = buffer_pool
+                    .as_mut()
+                    .alloc_new_page(PageKind::SlottedData, page_id)
+                    .expect("Alloc failed");
+                offsets.push(frame.file_offset());
+                let fid = frame.fid();
+                buffer_pool.as_mut().unpin_frame(fid).unwrap();
+            }
+
+            let touch = |buffer_pool: &mut Pin<Box<BufferPool>>, offset: u64| {
+                let fid = buffer_pool
+                    .as_mut()
+                    .fetch_page_at_offset(offset)
+                    .unwrap()
+                    .fid();
+                buffer_pool.as_mut().unpin_frame(fid).unwrap();
+            };
+            // Warm the working set, then scan everything else once
+            for _ in 0..2 {
+                for &offset in &offsets[..hot] {
+                    touch(&mut buffer_pool, offset);
+                }
+            }
+            for &offset in &offsets[hot..] {
+                touch(&mut buffer_pool, offset);
+            }
+
+            let before = buffer_pool.stats();
+            for &offset in &offsets[..hot] {
+                touch(&mut buffer_pool, offset);
+            }
+            let hits = buffer_pool.stats().hits - before.hits;
+            match policy {
+                EvictionPolicy::Fifo => assert_eq!(hits, 0),
+                _ => assert_eq!(hits, hot as u64, "{} lost the working set", policy),
+            }
+
+            cleanup_temp_file(&temp_path);
+        }
+
+        assert_eq!(
+            "lru-3".parse::<EvictionPolicy>(),
+            Ok(EvictionPolicy::LruK(3))
+        );
+        assert_eq!("CLOCK".parse::<EvictionPolicy>(), Ok(EvictionPolicy::Clock));
+        assert!("lru-0".parse::<EvictionPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_ring_scan_keeps_working_set() {
+        use crate::storage::buffer::{AccessStrategy, BufferRing};
+        use std::collections::HashSet;
+
+        // FIFO on its own would lose the hot pages to a scan this long
+        let (temp_path, mut buffer_pool, page_id_cnt) = setup_buffer_pool_test("ring_scan");
+        let hot = 8;
+
+        let mut offsets = Vec::new();
+        for _ in 0..DEFAULT_FRAME_COUNT * 3 {
+            let page_id = generate_test_page_id(&page_id_cnt);
+            let frame = buffer_pool
+                .as_mut()
+                .alloc_new_page(PageKind::SlottedData, page_id)
+                .expect("Alloc failed");
+            offsets.push(frame.file_offset());
+            let fid = frame.fid();
+            buffer_pool.as_mut().unpin_frame(fid).unwrap();
+        }
+        for &offset in &offsets[..hot] {
+            let fid = buffer_pool
+                .as_mut()
+                .fetch_page_at_offset(offset)
+                .unwrap()
+                .fid();
+            buffer_pool.as_mut().unpin_frame(fid).unwrap();
+        }
+
+        let mut ring = BufferRing::default();
+        let mut ring_frames = HashSet::new();
+        for &offset in &offsets[hot..DEFAULT_FRAME_COUNT * 2] {
+            let fid = buffer_pool
+                .as_mut()
+                .core()
+                .fetch_page_at_offset_with(offset, AccessStrategy::Ring(&mut ring))
+                .unwrap()
+                .fid();
+            ring_frames.insert(fid);
+            buffer_pool.as_mut().unpin_frame(fid).unwrap();
+        }
+        assert!(ring_frames.len() <= BufferRing::DEFAULT_SIZE);
+
+        let before = buffer_pool.stats();
+        for &offset in &offsets[..hot] {
+            let fid = buffer_pool
+                .as_mut()
+                .fetch_page_at_offset(offset)
+                .unwrap()
+                .fid();
+            buffer_pool.as_mut().unpin_frame(fid).unwrap();
+        }
+        assert_eq!(buffer_pool.stats().hits - before.hits, hot as u64);
+
+        cleanup_temp_file(&temp_path);
+    }
+
+    #[test]
+    fn test_ring_write_back_failure_is_an_error() {
+        use crate::storage::buffer::{AccessStrategy, BufferRing};
+        use crate::storage::disk::SimulatedDisk;
+
+        let disk = SimulatedDisk::new();
+        let mut buffer_pool = Box::pin(BufferPool::new(
+            Box::new(disk.device()),
+            Box::new(FifoEvictor::new()),
+            Box::new(locator::DirectoryPageLocator::new()),
+        ));
+        let page_id_cnt = AtomicU32::new(0);
+        let mut offsets = Vec::new();
+        for _ in 0..BufferRing::DEFAULT_SIZE + 1 {
+            let page_id = generate_test_page_id(&page_id_cnt);
+            let frame = buffer_pool
+                .as_mut()
+                .alloc_new_page(PageKind::SlottedData, page_id)
+                .expect("Alloc failed");
+            offsets.push(frame.file_offset());
+            let fid = frame.fid();
+            buffer_pool.as_mut().unpin_frame(fid).unwrap();
+        }
+        buffer_pool.as_mut().flush_all().unwrap();
+        for &offset in &offsets {
+            buffer_pool.as_mut().core().discard_frame_at(offset);
+        }
+
+        // The ring dirties every page it reads, then the disk stops taking writes
+        let mut ring = BufferRing::default();
+        for &offset in &offsets[..BufferRing::DEFAULT_SIZE] {
+            let fid = buffer_pool
+                .as_mut()
+                .core()
+                .fetch_page_at_offset_with(offset, AccessStrategy::Ring(&mut ring))
+                .unwrap()
+                .fid();
+            buffer_pool.as_mut().mark_frame_dirty(fid);
+            buffer_pool.as_mut().unpin_frame(fid).unwrap();
+        }
+        disk.crash();
+
+        // Reusing the ring's oldest frame has to write it back first
+        let last = *offsets.last().unwrap();
+        let result = buffer_pool
+            .as_mut()
+            .core()
+            .fetch_page_at_offset_with(last, AccessStrategy::Ring(&mut ring));
+        assert!(matches!(result, Err(errors::FetchPageError::IOError)));
+    }
+
+    #[test]
+    fn test_resize_pool() {
+        let (temp_path, mut buffer_pool, page_id_cnt) = setup_buffer_pool_test("resize");
+
+        buffer_pool
+            .as_mut()
+            .resize(DEFAULT_FRAME_COUNT * 2)
+            .unwrap();
+        assert_eq!(buffer_pool.frame_count(), DEFAULT_FRAME_COUNT * 2);
+
+        // The grown pool holds twice as many pages without evicting any
+        let mut pages = Vec::new();
+        for _ in 0..DEFAULT_FRAME_COUNT * 2 {
+            let page_id = generate_test_page_id(&page_id_cnt);
+            let frame = buffer_pool
+                .as_mut()
+                .alloc_new_page(PageKind::SlottedData, page_id)
+                .expect("Alloc failed");
+            pages.push((page_id, frame.file_offset()));
+            let fid = frame.fid();
+            buffer_pool.as_mut().unpin_frame(fid).unwrap();
+        }
+        assert_eq!(buffer_pool.core.free_frames, 0);
+        assert!(buffer_pool.core.frames.iter().all(Option::is_some));
+
+        // A pinned frame past the new size blocks shrinking
+        let pinned = buffer_pool
+            .as_mut()
+            .fetch_page_at_offset(pages.last().unwrap().1)
+            .unwrap()
+            .fid();
+        assert!(pinned as usize >= MIN_FRAME_COUNT);
+        assert!(matches!(
+            buffer_pool.as_mut().resize(MIN_FRAME_COUNT),
+            Err(errors::ResizeError::FramesPinned)
+        ));
+        buffer_pool.as_mut().unpin_frame(pinned).unwrap();
+
+        buffer_pool.as_mut().resize(MIN_FRAME_COUNT).unwrap();
+        assert_eq!(buffer_pool.frame_count(), MIN_FRAME_COUNT);
+        assert!(matches!(
+            buffer_pool.as_mut().resize(MIN_FRAME_COUNT - 1),
+            Err(errors::ResizeError::OutOfRange)
+        ));
+
+        // Pages dropped by the shrink were written back first
+        for &(page_id, offset) in &pages {
+            let frame = buffer_pool.as_mut().fetch_page_at_offset(offset).unwrap();
+            assert!((frame.fid() as usize) < MIN_FRAME_COUNT);
+            assert_eq!(frame.page_id(), page_id);
+            let fid = frame.fid();
+            buffer_pool.as_mut().unpin_frame(fid).unwrap();
+        }
+
+        cleanup_temp_file(&temp_path);
+    }
+
+    #[test]
+    fn test_flush_leaves_latched_pages_dirty() {
+        let (temp_path, mut buffer_pool, page_id_cnt) = setup_buffer_pool_test("flush_latched");
+
+        let page_id = generate_test_page_id(&page_id_cnt);
+        let fid = buffer_pool
+            .as_mut()
+            .alloc_new_page(PageKind::SlottedData, page_id)
+            .expect("Alloc failed")
+            .fid();
+        let latches = buffer_pool.latches();
+
+        // A writer may be halfway through changing the page
+        let latch = latches.write(fid);
+        buffer_pool.as_mut().flush_all().unwrap();
+        assert_eq!(buffer_pool.dirty_page_table().len(), 1);
+
+        drop(latch);
+        buffer_pool.as_mut().flush_all().unwrap();
+        assert!(buffer_pool.dirty_page_table().is_empty());
+
+        buffer_pool.as_mut().unpin_frame(fid).unwrap();
+        cleanup_temp_file(&temp_path);
+    }
+
+    #[test]
+    fn test_flush_some_and_checkpoint() {
+        let (temp_path, mut buffer_pool, page_id_cnt) = setup_buffer_pool_test("checkpoint");
+        let db_path = temp_path.to_str().unwrap().to_string();
+        let _ = fs::remove_file(CheckpointRecord::path_for(&db_path));
+
+        let mut pinned = None;
+        for i in 0..10 {
+            let page_id = generate_test_page_id(&page_id_cnt);
+            let frame = buffer_pool
+                .as_mut()
+                .alloc_new_page(PageKind::SlottedData, page_id)
+                .expect("Alloc failed");
+            let fid = frame.fid();
+            if i == 0 {
+                pinned = Some((fid, page_id, frame.file_offset()));
+            } else {
+                buffer_pool.as_mut().unpin_frame(fid).unwrap();
+            }
+        }
+        let (pinned_fid, pinned_pid, pinned_offset) = pinned.unwrap();
+
+        assert_eq!(buffer_pool.as_mut().flush_some(4).unwrap(), 4);
+        assert_eq!(buffer_pool.dirty_page_table().len(), 6);
+
+        let stats = buffer_pool.as_mut().checkpoint().unwrap();
+        assert_eq!(stats.id, 1);
+        assert_eq!(stats.pages_written, 5);
+        assert_eq!(stats.pages_skipped, 1);
+
+        // Only the pinned page is left for recovery to worry about
+        let record = buffer_pool.last_checkpoint().unwrap().unwrap();
+        assert!(record.complete);
+        assert_eq!(
+            record.dirty_pages,
+            vec![DirtyPage {
+                page_id: pinned_pid,
+                file_offset: pinned_offset
+            }]
+        );
+
+        buffer_pool.as_mut().unpin_frame(pinned_fid).unwrap();
+        assert_eq!(buffer_pool.as_mut().checkpoint().unwrap().id, 2);
+        assert!(buffer_pool.dirty_page_table().is_empty());
+
+        let _ = fs::remove_file(CheckpointRecord::path_for(&db_path));
+        cleanup_temp_file(&temp_path);
+    }
+
+    #[test]
+    fn test_checkpoint_in_steps() {
+        let (temp_path, mut buffer_pool, page_id_cnt) = setup_buffer_pool_test("checkpoint_steps");
+        let db_path = temp_path.to_str().unwrap().to_string();
+        let _ = fs::remove_file(CheckpointRecord::path_for(&db_path));
+
+        let alloc = |buffer_pool: &mut Pin<Box<BufferPool>>| {
+            let page_id = generate_test_page_id(&page_id_cnt);
+            let fid = buffer_pool
+                .as_mut()
+                .alloc_new_page(PageKind::SlottedData, page_id)
+                .expect("Alloc failed")
+                .fid();
+            buffer_pool.as_mut().unpin_frame(fid).unwrap();
+        };
+        for _ in 0..5 {
+            alloc(&mut buffer_pool);
+        }
+
+        let mut checkpoint = buffer_pool.as_mut().begin_checkpoint().unwrap();
+        // Pages dirtied after the snapshot are left for the next checkpoint
+        alloc(&mut buffer_pool);
+        for _ in 0..2 {
+            let step = buffer_pool.as_mut().checkpoint_step(&mut checkpoint, 2);
+            assert_eq!(step.unwrap(), None);
+            // Nothing is recorded before the snapshot is durable
+            assert!(buffer_pool.last_checkpoint().unwrap().is_none());
+        }
+        assert_eq!(buffer_pool.dirty_page_table().len(), 2);
+
+        let stats = buffer_pool.as_mut().checkpoint_step(&mut checkpoint, 2);
+        let stats = stats.unwrap().expect("Checkpoint to be finished");
+        assert_eq!(stats.id, 1);
+        assert_eq!(stats.pages_written, 5);
+        assert_eq!(buffer_pool.dirty_page_table().len(), 1);
+
+        let record = buffer_pool.last_checkpoint().unwrap().unwrap();
+        assert!(record.complete);
+        assert!(record.dirty_pages.is_empty());
+
+        let _ = fs::remove_file(CheckpointRecord::path_for(&db_path));
+        cleanup_temp_file(&temp_path);
+    }
+
+    #[test]
+    fn test_checksum_mismatch_detected() {
+        use std::io::{Seek, SeekFrom, Write};
+
+        let (temp_path, mut buffer_pool, page_id_cnt) = setup_buffer_pool_test("checksum");
+
+        let mut offsets = Vec::new();
+        for _ in 0..3 {
+            let page_id = generate_test_page_id(&page_id_cnt);
+            let frame = buffer_pool
+                .as_mut()
+                .alloc_new_page(PageKind::SlottedData, page_id)
+                .expect("Alloc failed");
+            let (fid, offset) = (frame.fid(), frame.file_offset());
+            buffer_pool.as_mut().unpin_frame(fid).unwrap();
+            // Write it out and drop it, so the next fetch reads the disk
+            buffer_pool
+                .as_mut()
+                .core()
+                .dealloc_frame_at(fid as usize)
+                .unwrap();
+            offsets.push(offset);
+        }
+
+        // Flip one bit in the body of the second page behind the pool's back
+        let mut file = fs::OpenOptions::new().write(true).open(&temp_path).unwrap();
+        let byte = offsets[1] * constants::storage::PAGE_SIZE as u64 + 1000;
+        file.seek(SeekFrom::Start(byte)).unwrap();
+        file.write_all(&[1]).unwrap();
+        file.sync_all().unwrap();
+
+        let frame = buffer_pool
+            .as_mut()
+            .fetch_page_at_offset(offsets[0])
+            .unwrap();
+        let fid = frame.fid();
+        buffer_pool.as_mut().unpin_frame(fid).unwrap();
+
+        let free_before = buffer_pool.core.free_frames;
+        assert!(matches!(
+            buffer_pool.as_mut().fetch_page_at_offset(offsets[1]),
+            Err(errors::FetchPageError::ChecksumMismatch)
+        ));
+        // The frame it was read into is free again
+        assert_eq!(buffer_pool.core.free_frames, free_before);
+
+        // A page without a checksum, such as a write that never happened leaves, only
+        // passes in files from before checksums were required
+        let byte = offsets[2] * constants::storage::PAGE_SIZE as u64;
+        file.seek(SeekFrom::Start(byte)).unwrap();
+        file.write_all(&[0; constants::storage::PAGE_SIZE]).unwrap();
+        file.sync_all().unwrap();
+        assert!(matches!(
+            buffer_pool.as_mut().fetch_page_at_offset(offsets[2]),
+            Err(errors::FetchPageError::ChecksumMismatch)
+        ));
+        unsafe { buffer_pool.as_mut().core().get_unchecked_mut() }.checksums_required = false;
+        let frame = buffer_pool
+            .as_mut()
+            .fetch_page_at_offset(offsets[2])
+            .unwrap();
+        let fid = frame.fid();
+        buffer_pool.as_mut().unpin_frame(fid).unwrap();
 
         cleanup_temp_file(&temp_path);
     }
@@ -0,0 +1,122 @@
+use crate::storage::page::base::PageId;
+use std::fs;
+use std::io::{self, Write};
+
+const MAGIC: &[u8; 4] = b"NCKP";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 4 + 4 + 8 + 1 + 4;
+const ENTRY_SIZE: usize = 4 + 8;
+
+/// A page whose copy in the pool may be newer than the one on disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirtyPage {
+    pub page_id: PageId,
+    pub file_offset: u64,
+}
+
+/// What the last checkpoint recorded, kept in `<db>.checkpoint` next to the database.
+///
+/// A checkpoint writes this once the pages of the dirty page table it started from are on
+/// disk, listing those it had to leave dirty. Every page not listed was current on disk as
+/// of the start of the checkpoint, so after a crash only the listed pages and those
+/// modified since can be stale.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CheckpointRecord {
+    pub id: u64,
+    /// Always set by this build. Earlier ones also wrote the record as a checkpoint
+    /// started, listing its whole dirty page table.
+    pub complete: bool,
+    pub dirty_pages: Vec<DirtyPage>,
+}
+
+/// A checkpoint underway, see `BufferPool::begin_checkpoint`.
+#[derive(Debug)]
+pub struct Checkpoint {
+    pub(crate) id: u64,
+    /// Pages of the dirty page table it started from that are still to be written, the
+    /// next one last
+    pub(crate) pending: Vec<DirtyPage>,
+    /// Pages that were pinned when their turn came
+    pub(crate) still_dirty: Vec<DirtyPage>,
+    pub(crate) stats: CheckpointStats,
+}
+
+/// What a checkpoint did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CheckpointStats {
+    pub id: u64,
+    pub pages_written: usize,
+    /// Pinned pages are being worked on and are left for the next checkpoint
+    pub pages_skipped: usize,
+}
+
+impl CheckpointRecord {
+    pub fn path_for(db_path: &str) -> String {
+        format!("{}.checkpoint", db_path)
+    }
+
+    /// Reads the record for `db_path`, or None if it has never been checkpointed.
+    pub fn read(db_path: &str) -> io::Result<Option<Self>> {
+        let bytes = match fs::read(Self::path_for(db_path)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Self::from_bytes(&bytes)
+            .map(Some)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Corrupt checkpoint file"))
+    }
+
+    /// Replaces the record for `db_path`. Goes through a temporary file so a crash leaves
+    /// either the old record or the new one.
+    pub fn write(&self, db_path: &str) -> io::Result<()> {
+        let path = Self::path_for(db_path);
+        let tmp_path = format!("{}.tmp", path);
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(&self.to_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &path)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE + self.dirty_pages.len() * ENTRY_SIZE);
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+        buf.extend_from_slice(&self.id.to_le_bytes());
+        buf.push(self.complete as u8);
+        buf.extend_from_slice(&(self.dirty_pages.len() as u32).to_le_bytes());
+        for page in &self.dirty_pages {
+            buf.extend_from_slice(&page.page_id.to_le_bytes());
+            buf.extend_from_slice(&page.file_offset.to_le_bytes());
+        }
+        buf
+    }
+
+    fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() < HEADER_SIZE || &buf[0..4] != MAGIC {
+            return None;
+        }
+        if u32::from_le_bytes(buf[4..8].try_into().ok()?) != VERSION {
+            return None;
+        }
+        let id = u64::from_le_bytes(buf[8..16].try_into().ok()?);
+        let complete = buf[16] != 0;
+        let count = u32::from_le_bytes(buf[17..21].try_into().ok()?) as usize;
+        if buf.len() != HEADER_SIZE + count * ENTRY_SIZE {
+            return None;
+        }
+
+        let dirty_pages = buf[HEADER_SIZE..]
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| DirtyPage {
+                page_id: PageId::from_le_bytes(entry[0..4].try_into().unwrap()),
+                file_offset: u64::from_le_bytes(entry[4..12].try_into().unwrap()),
+            })
+            .collect();
+        Some(Self {
+            id,
+            complete,
+            dirty_pages,
+        })
+    }
+}
@@ -0,0 +1,87 @@
+use crate::storage::buffer::{Evictor, Frame};
+
+#[derive(Clone, Copy, Default)]
+struct ClockSlot {
+    in_use: bool,
+    evictable: bool,
+    referenced: bool,
+}
+
+/// CLOCK (second chance): a hand sweeps the frames, clearing reference bits, and evicts
+/// the first evictable frame whose bit is already clear. A frame only gets its bit set by
+/// being accessed again after it was loaded, so a page read once is evicted on the first
+/// pass of the hand.
+#[derive(Default)]
+pub struct ClockEvictor {
+    slots: Vec<ClockSlot>, // indexed by frame_id
+    hand: usize,
+}
+
+impl ClockEvictor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn slot_mut(&mut self, frame_id: u32) -> Option<&mut ClockSlot> {
+        self.slots
+            .get_mut(frame_id as usize)
+            .filter(|slot| slot.in_use)
+    }
+}
+
+impl Evictor for ClockEvictor {
+    fn pick_victim(&mut self) -> Option<u32> {
+        let n = self.slots.len();
+        // Two sweeps: the first may only clear reference bits
+        for _ in 0..2 * n {
+            let idx = self.hand;
+            self.hand = (self.hand + 1) % n;
+            let slot = &mut self.slots[idx];
+            if !slot.in_use || !slot.evictable {
+                continue;
+            }
+            if slot.referenced {
+                slot.referenced = false;
+            } else {
+                return Some(idx as u32);
+            }
+        }
+        None
+    }
+
+    fn notify_frame_alloc(&mut self, frame: &Frame) {
+        let idx = frame.fid() as usize;
+        if self.slots.len() <= idx {
+            self.slots.resize(idx + 1, ClockSlot::default());
+        }
+        self.slots[idx] = ClockSlot {
+            in_use: true,
+            evictable: false,
+            referenced: false,
+        };
+    }
+
+    fn notify_frame_access(&mut self, frame_id: u32) {
+        if let Some(slot) = self.slot_mut(frame_id) {
+            slot.referenced = true;
+        }
+    }
+
+    fn mark_frame_cold(&mut self, frame_id: u32) {
+        if let Some(slot) = self.slot_mut(frame_id) {
+            slot.referenced = false;
+        }
+    }
+
+    fn set_frame_evictable(&mut self, frame: &Frame, evictable: bool) {
+        if let Some(slot) = self.slot_mut(frame.fid()) {
+            slot.evictable = evictable;
+        }
+    }
+
+    fn notify_frame_destroy(&mut self, frame_id: u32) {
+        if let Some(slot) = self.slot_mut(frame_id) {
+            *slot = ClockSlot::default();
+        }
+    }
+}
@@ -1,9 +1,73 @@
 use crate::storage::buffer::buffer_pool::Frame;
+use crate::storage::buffer::clock_evictor::ClockEvictor;
+use crate::storage::buffer::fifo_evictor::FifoEvictor;
+use crate::storage::buffer::lru_k_evictor::LruKEvictor;
+use std::fmt;
+use std::str::FromStr;
 
-pub trait Evictor {
+pub trait Evictor: Send {
     fn pick_victim(&mut self) -> Option<u32>;
 
     fn notify_frame_alloc(&mut self, frame: &Frame);
+    /// Called on every buffer hit, so policies can tell hot pages from pages read once.
+    fn notify_frame_access(&mut self, frame_id: u32);
+    /// The frame was loaded through a `BufferRing` and is not expected to be reused,
+    /// so it should be offered up before the frames the policy is tracking.
+    fn mark_frame_cold(&mut self, frame_id: u32);
     fn set_frame_evictable(&mut self, frame: &Frame, evictable: bool);
     fn notify_frame_destroy(&mut self, frame_id: u32);
 }
+
+/// Which `Evictor` a buffer pool is opened with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    Fifo,
+    LruK(usize),
+    Clock,
+}
+
+impl Default for EvictionPolicy {
+    /// LRU-2: a page has to be read twice before it can push out the working set.
+    fn default() -> Self {
+        EvictionPolicy::LruK(2)
+    }
+}
+
+impl EvictionPolicy {
+    pub fn evictor(self) -> Box<dyn Evictor> {
+        match self {
+            EvictionPolicy::Fifo => Box::new(FifoEvictor::new()),
+            EvictionPolicy::LruK(k) => Box::new(LruKEvictor::new(k)),
+            EvictionPolicy::Clock => Box::new(ClockEvictor::new()),
+        }
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvictionPolicy::Fifo => write!(f, "fifo"),
+            EvictionPolicy::LruK(k) => write!(f, "lru-{}", k),
+            EvictionPolicy::Clock => write!(f, "clock"),
+        }
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    /// Accepts `fifo`, `clock`, `lru-k` (K = 2) or `lru-<K>`, e.g. `lru-3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fifo" => Ok(EvictionPolicy::Fifo),
+            "clock" => Ok(EvictionPolicy::Clock),
+            "lru-k" | "lruk" => Ok(EvictionPolicy::default()),
+            other => other
+                .strip_prefix("lru-")
+                .and_then(|k| k.parse().ok())
+                .filter(|&k| k > 0)
+                .map(EvictionPolicy::LruK)
+                .ok_or_else(|| format!("Unknown eviction policy: {}", s)),
+        }
+    }
+}
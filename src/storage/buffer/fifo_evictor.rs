@@ -60,6 +60,22 @@ impl Evictor for FifoEvictor {
         );
     }
 
+    // FIFO order only depends on when a frame was loaded
+    fn notify_frame_access(&mut self, _frame_id: u32) {}
+
+    fn mark_frame_cold(&mut self, frame_id: u32) {
+        let Some(&queue_idx) = self.fid_idx_map.get(&frame_id) else {
+            return;
+        };
+        self.victim_queue.remove(queue_idx);
+        self.victim_queue.push_front(frame_id);
+
+        for i in 0..=queue_idx {
+            let fid = self.victim_queue[i];
+            self.fid_idx_map.insert(fid, i);
+        }
+    }
+
     fn set_frame_evictable(&mut self, frame: &Frame, evictable: bool) {
         let frame_id = frame.fid();
         if let Some(frame_meta) = self.frames_meta.get_mut(&frame_id) {
@@ -0,0 +1,87 @@
+use crate::storage::buffer::{Evictor, Frame};
+use std::collections::{HashMap, VecDeque};
+
+struct FrameHistory {
+    // logical timestamps of the last k accesses, oldest first
+    accesses: VecDeque<u64>,
+    evictable: bool,
+}
+
+/// LRU-K: evicts the frame whose k-th most recent access is furthest in the past.
+/// Frames accessed fewer than k times count as infinitely old and go first (oldest first),
+/// so pages a scan touches once leave before the pages that keep being reused.
+pub struct LruKEvictor {
+    k: usize,
+    now: u64,
+    frames: HashMap<u32, FrameHistory>, // frame_id -> history
+}
+
+impl LruKEvictor {
+    pub fn new(k: usize) -> Self {
+        assert!(k > 0, "LRU-K needs k >= 1");
+        Self {
+            k,
+            now: 0,
+            frames: HashMap::new(),
+        }
+    }
+
+    fn record_access(&mut self, frame_id: u32) {
+        self.now += 1;
+        if let Some(history) = self.frames.get_mut(&frame_id) {
+            history.accesses.push_back(self.now);
+            if history.accesses.len() > self.k {
+                history.accesses.pop_front();
+            }
+        }
+    }
+}
+
+impl Evictor for LruKEvictor {
+    fn pick_victim(&mut self) -> Option<u32> {
+        // Frames with a full history sort after the rest; within each group the one with
+        // the oldest remembered access goes first.
+        self.frames
+            .iter()
+            .filter(|(_, history)| history.evictable)
+            .min_by_key(|(fid, history)| {
+                let full = history.accesses.len() >= self.k;
+                (full, history.accesses.front().copied(), **fid)
+            })
+            .map(|(fid, _)| *fid)
+    }
+
+    fn notify_frame_alloc(&mut self, frame: &Frame) {
+        // Loading the page is its first access
+        self.frames.insert(
+            frame.fid(),
+            FrameHistory {
+                accesses: VecDeque::with_capacity(self.k),
+                evictable: false,
+            },
+        );
+        self.record_access(frame.fid());
+    }
+
+    fn notify_frame_access(&mut self, frame_id: u32) {
+        self.record_access(frame_id);
+    }
+
+    fn mark_frame_cold(&mut self, frame_id: u32) {
+        // A single access at the dawn of time sorts before every other frame
+        if let Some(history) = self.frames.get_mut(&frame_id) {
+            history.accesses.clear();
+            history.accesses.push_back(0);
+        }
+    }
+
+    fn set_frame_evictable(&mut self, frame: &Frame, evictable: bool) {
+        if let Some(history) = self.frames.get_mut(&frame.fid()) {
+            history.evictable = evictable;
+        }
+    }
+
+    fn notify_frame_destroy(&mut self, frame_id: u32) {
+        self.frames.remove(&frame_id);
+    }
+}
@@ -1,8 +1,18 @@
+pub mod bg_writer;
 pub mod buffer_pool;
+pub mod checkpoint;
+pub mod clock_evictor;
 pub mod evict;
 pub mod fifo_evictor;
+pub mod lru_k_evictor;
+pub mod strategy;
 
+pub use bg_writer::{BackgroundWriter, WriterConfig, checkpoint_in_batches};
 pub use buffer_pool::BufferPool;
 pub use buffer_pool::BufferPoolCore;
 pub use buffer_pool::Frame;
+pub use buffer_pool::FrameLatches;
+pub use buffer_pool::PoolStats;
+pub use evict::EvictionPolicy;
 pub use evict::Evictor;
+pub use strategy::{AccessStrategy, BufferRing};
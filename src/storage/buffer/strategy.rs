@@ -0,0 +1,68 @@
+/// How a caller is going to use the pages it fetches.
+pub enum AccessStrategy<'a> {
+    /// Point lookups and index probes: pages compete for the whole pool under the
+    /// eviction policy.
+    Normal,
+    /// Sequential scans and bulk writes: a miss reuses one of the ring's own frames
+    /// instead of evicting someone else's page, and hits do not count as reuse.
+    Ring(&'a mut BufferRing),
+}
+
+impl AccessStrategy<'_> {
+    /// Lets a strategy be passed down more than once without giving it up.
+    pub fn reborrow(&mut self) -> AccessStrategy<'_> {
+        match self {
+            AccessStrategy::Normal => AccessStrategy::Normal,
+            AccessStrategy::Ring(ring) => AccessStrategy::Ring(ring),
+        }
+    }
+}
+
+/// The frames a scan or bulk load has read its pages into, recycled round-robin.
+/// Owned by whoever drives the scan, so concurrent scans do not share a ring.
+pub struct BufferRing {
+    slots: Vec<(u32, u64)>, // (frame_id, file_offset) of pages this ring loaded
+    capacity: usize,
+    next: usize,
+}
+
+impl BufferRing {
+    /// Large enough that a page is still resident while the scan reads all its tuples,
+    /// small enough to leave the rest of the pool alone.
+    pub const DEFAULT_SIZE: usize = 16;
+
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "A buffer ring needs at least one frame");
+        Self {
+            slots: Vec::with_capacity(capacity),
+            capacity,
+            next: 0,
+        }
+    }
+
+    /// The frame the next miss should reuse, once the ring is full.
+    /// The page it held may since have been evicted, so callers check the offset.
+    pub(crate) fn victim(&self) -> Option<(u32, u64)> {
+        if self.slots.len() < self.capacity {
+            None
+        } else {
+            Some(self.slots[self.next])
+        }
+    }
+
+    /// Remembers that the page at `file_offset` was loaded into `frame_id` through this ring.
+    pub(crate) fn record(&mut self, frame_id: u32, file_offset: u64) {
+        if self.slots.len() < self.capacity {
+            self.slots.push((frame_id, file_offset));
+        } else {
+            self.slots[self.next] = (frame_id, file_offset);
+            self.next = (self.next + 1) % self.capacity;
+        }
+    }
+}
+
+impl Default for BufferRing {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SIZE)
+    }
+}
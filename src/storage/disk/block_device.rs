@@ -0,0 +1,88 @@
+#[cfg(feature = "encryption")]
+use crate::storage::disk::EncryptedDevice;
+#[cfg(feature = "encryption")]
+use crate::storage::disk::encrypted_device::DEFAULT_KDF_ITERATIONS;
+use crate::storage::disk::{FileManager, MemoryDevice};
+use std::io;
+
+/// Where the buffer pool keeps its pages. Storage is addressed in whole pages: offsets
+/// are page indexes (NOT byte offsets) and buffers are exactly one page long.
+pub trait BlockDevice: Send {
+    /// The database file, or None for storage that is not a file. Files that belong
+    /// to the database, like the checkpoint record, are named after it.
+    fn path(&self) -> Option<&str>;
+
+    /// # Safety
+    /// `buf` must be a page-aligned PageBuf: devices may read into it with direct I/O.
+    unsafe fn read_block_into(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+
+    fn write_block_from(&mut self, offset: u64, buf: &[u8]) -> io::Result<()>;
+
+    /// Makes the writes so far durable.
+    fn sync(&self) -> io::Result<()>;
+
+    /// Number of pages the device holds
+    fn page_count(&self) -> io::Result<u64>;
+
+    /// Cuts the device down to its first `page_count` pages
+    fn truncate(&mut self, page_count: u64) -> io::Result<()>;
+
+    /// Adds a zeroed page at the end and returns its offset
+    fn allocate_new_page_offset(&mut self) -> io::Result<u64>;
+
+    /// Opens segment `file_id`, a device of its own that belongs to the same database.
+    /// It lives in directory `location` if given (see tablespaces), else next to this
+    /// device. Without `create`, a segment that does not exist is a NotFound error; with
+    /// it, one that already exists is an AlreadyExists error.
+    fn open_segment(
+        &self,
+        file_id: u32,
+        location: Option<&str>,
+        create: bool,
+    ) -> io::Result<Box<dyn BlockDevice>>;
+
+    /// Deletes segment `file_id`, found as in `open_segment`, with everything in it.
+    fn remove_segment(&self, file_id: u32, location: Option<&str>) -> io::Result<()>;
+}
+
+/// The path that opens a database in memory instead of a file.
+pub const MEMORY_PATH: &str = ":memory:";
+
+/// Opens the storage behind `path`: a `MemoryDevice` for `MEMORY_PATH`, else the file.
+pub fn open_device(path: &str) -> io::Result<Box<dyn BlockDevice>> {
+    if path == MEMORY_PATH {
+        return Ok(Box::new(MemoryDevice::new()));
+    }
+    #[allow(unused_mut)]
+    let mut file = FileManager::new(path.to_string())?;
+    #[cfg(feature = "encryption")]
+    if EncryptedDevice::is_encrypted(&mut file)? {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "database is encrypted, open it with its passphrase",
+        ));
+    }
+    Ok(Box::new(file))
+}
+
+/// Like `open_device`, but with every page encrypted under `passphrase`. A new database
+/// is encrypted from the start, see `EncryptedDevice`.
+#[cfg(feature = "encryption")]
+pub fn open_encrypted_device(path: &str, passphrase: &str) -> io::Result<Box<dyn BlockDevice>> {
+    let device: Box<dyn BlockDevice> = if path == MEMORY_PATH {
+        Box::new(MemoryDevice::new())
+    } else {
+        Box::new(FileManager::new(path.to_string())?)
+    };
+    let device = EncryptedDevice::open(device, passphrase, DEFAULT_KDF_ITERATIONS)?;
+    Ok(Box::new(device))
+}
+
+/// Without the `encryption` feature, no database can be opened with a passphrase.
+#[cfg(not(feature = "encryption"))]
+pub fn open_encrypted_device(_path: &str, _passphrase: &str) -> io::Result<Box<dyn BlockDevice>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "built without the encryption feature",
+    ))
+}
@@ -0,0 +1,257 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How the pages of a table are stored on disk
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None = 0,
+    /// LZ4 block format, see `compress`
+    Lz4 = 1,
+}
+
+impl Compression {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Lz4 => write!(f, "lz4"),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "lz4" => Ok(Compression::Lz4),
+            _ => Err(format!("Unknown compression: {}", s)),
+        }
+    }
+}
+
+pub mod errors {
+    #[derive(Debug, PartialEq)]
+    pub enum DecompressError {
+        Truncated,
+        BadOffset,
+        Overflow,
+    }
+}
+
+const MIN_MATCH: usize = 4;
+// The format wants the last 5 bytes as literals and no match starting in the last 12
+const LAST_LITERALS: usize = 5;
+const MATCH_LIMIT: usize = 12;
+const HASH_BITS: u32 = 12;
+
+fn read_u32(buf: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(buf[pos..pos + 4].try_into().unwrap())
+}
+
+fn hash(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn write_length(dst: &mut Vec<u8>, mut n: usize) {
+    while n >= 255 {
+        dst.push(255);
+        n -= 255;
+    }
+    dst.push(n as u8);
+}
+
+fn write_literals(dst: &mut Vec<u8>, token: &mut u8, literals: &[u8]) {
+    *token |= (literals.len().min(15) as u8) << 4;
+    dst.push(*token);
+    if literals.len() >= 15 {
+        write_length(dst, literals.len() - 15);
+    }
+    dst.extend_from_slice(literals);
+}
+
+/// Compresses `src` into `dst` in the LZ4 block format: greedy matching through a hash
+/// of the next four bytes, which is plenty for pages full of similar rows.
+pub fn compress(src: &[u8], dst: &mut Vec<u8>) {
+    dst.clear();
+    // Position + 1 of the last place each hash was seen, 0 for never
+    let mut table = [0u32; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    while pos + MATCH_LIMIT < src.len() {
+        let sequence = read_u32(src, pos);
+        let slot = &mut table[hash(sequence)];
+        let candidate = *slot as usize;
+        *slot = pos as u32 + 1;
+        if candidate == 0
+            || pos - (candidate - 1) > u16::MAX as usize
+            || read_u32(src, candidate - 1) != sequence
+        {
+            pos += 1;
+            continue;
+        }
+
+        let start = candidate - 1;
+        let mut len = MIN_MATCH;
+        while pos + len < src.len() - LAST_LITERALS && src[start + len] == src[pos + len] {
+            len += 1;
+        }
+
+        let mut token = (len - MIN_MATCH).min(15) as u8;
+        write_literals(dst, &mut token, &src[anchor..pos]);
+        dst.extend_from_slice(&((pos - start) as u16).to_le_bytes());
+        if len - MIN_MATCH >= 15 {
+            write_length(dst, len - MIN_MATCH - 15);
+        }
+        pos += len;
+        anchor = pos;
+    }
+
+    write_literals(dst, &mut 0, &src[anchor..]);
+}
+
+fn read_length(src: &[u8], i: &mut usize) -> Result<usize, errors::DecompressError> {
+    let mut n = 0;
+    loop {
+        let byte = *src.get(*i).ok_or(errors::DecompressError::Truncated)?;
+        *i += 1;
+        n += byte as usize;
+        if byte != 255 {
+            return Ok(n);
+        }
+    }
+}
+
+/// Reverses `compress` into `dst`. Returns how many bytes were written, which is less
+/// than `dst` holds if the data was shorter.
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, errors::DecompressError> {
+    use errors::DecompressError;
+
+    let mut i = 0;
+    let mut out = 0;
+    loop {
+        let token = *src.get(i).ok_or(DecompressError::Truncated)?;
+        i += 1;
+
+        let mut literals = (token >> 4) as usize;
+        if literals == 15 {
+            literals += read_length(src, &mut i)?;
+        }
+        let bytes = src.get(i..i + literals).ok_or(DecompressError::Truncated)?;
+        dst.get_mut(out..out + literals)
+            .ok_or(DecompressError::Overflow)?
+            .copy_from_slice(bytes);
+        i += literals;
+        out += literals;
+        if i == src.len() {
+            return Ok(out);
+        }
+
+        let offset = match src.get(i..i + 2) {
+            Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]) as usize,
+            None => return Err(DecompressError::Truncated),
+        };
+        i += 2;
+        if offset == 0 || offset > out {
+            return Err(DecompressError::BadOffset);
+        }
+        let mut len = (token & 15) as usize + MIN_MATCH;
+        if token & 15 == 15 {
+            len += read_length(src, &mut i)?;
+        }
+        if out + len > dst.len() {
+            return Err(DecompressError::Overflow);
+        }
+        // Byte by byte: the match may overlap what it is copying
+        for k in out..out + len {
+            dst[k] = dst[k - offset];
+        }
+        out += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(src: &[u8]) -> usize {
+        let mut packed = Vec::new();
+        compress(src, &mut packed);
+        let mut out = vec![0u8; src.len()];
+        assert_eq!(decompress(&packed, &mut out), Ok(src.len()));
+        assert_eq!(out, src);
+        packed.len()
+    }
+
+    #[test]
+    fn test_roundtrip_and_ratio() {
+        assert_eq!(roundtrip(&[]), 1);
+        roundtrip(b"short");
+
+        let zeros = vec![0u8; 4096];
+        assert!(roundtrip(&zeros) < 64);
+
+        let log: Vec<u8> = (0..100)
+            .flat_map(|i| {
+                format!("2024-01-01 12:00:{:02} INFO request served\n", i % 60).into_bytes()
+            })
+            .take(4096)
+            .collect();
+        assert!(roundtrip(&log) < 4096 / 4);
+
+        // Noise does not compress, but still comes back intact
+        let mut state = 0x2545F491u32;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        assert!(roundtrip(&noise) > 4096);
+    }
+
+    #[test]
+    fn test_rejects_damaged_input() {
+        let mut packed = Vec::new();
+        compress(&[7u8; 4096], &mut packed);
+        let mut out = vec![0u8; 4096];
+        assert_eq!(
+            decompress(&packed[..packed.len() - 1], &mut out),
+            Err(errors::DecompressError::Truncated)
+        );
+        assert_eq!(
+            decompress(&packed, &mut out[..100]),
+            Err(errors::DecompressError::Overflow)
+        );
+        // A match reaching back before the start
+        assert_eq!(
+            decompress(&[0x00, 0x05, 0x00], &mut out),
+            Err(errors::DecompressError::BadOffset)
+        );
+    }
+
+    #[test]
+    fn test_parse_compression() {
+        assert_eq!("LZ4".parse::<Compression>(), Ok(Compression::Lz4));
+        assert_eq!("none".parse::<Compression>(), Ok(Compression::None));
+        assert!("zstd".parse::<Compression>().is_err());
+        assert_eq!(
+            Compression::from_u8(Compression::Lz4 as u8),
+            Some(Compression::Lz4)
+        );
+    }
+}
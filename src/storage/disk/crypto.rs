@@ -0,0 +1,102 @@
+//! The primitives behind `EncryptedDevice`: ChaCha20-Poly1305 (RFC 8439) to seal pages
+//! and PBKDF2-HMAC-SHA256 (RFC 8018) to turn a passphrase into a key. Both come from the
+//! RustCrypto crates; this module only fixes the sizes and the calling convention.
+
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use sha2::Sha256;
+
+pub const KEY_SIZE: usize = 32;
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+
+pub type Key = [u8; KEY_SIZE];
+pub type Nonce = [u8; NONCE_SIZE];
+pub type Tag = [u8; TAG_SIZE];
+
+pub mod errors {
+    #[derive(Debug, PartialEq)]
+    pub enum OpenError {
+        /// The data or what it was sealed with is not what the tag was made for
+        TagMismatch,
+    }
+}
+
+/// Encrypts `data` in place and returns the tag that authenticates it along with `aad`.
+/// A nonce must never be used twice with the same key.
+pub fn seal(key: &Key, nonce: &Nonce, aad: &[u8], data: &mut [u8]) -> Tag {
+    ChaCha20Poly1305::new(key.into())
+        .encrypt_in_place_detached(nonce.into(), aad, data)
+        .expect("A page is far below the ChaCha20 length limit")
+        .into()
+}
+
+/// Reverses `seal` in place. `data` is left untouched if the tag does not match.
+pub fn open(
+    key: &Key,
+    nonce: &Nonce,
+    aad: &[u8],
+    data: &mut [u8],
+    tag: &Tag,
+) -> Result<(), errors::OpenError> {
+    ChaCha20Poly1305::new(key.into())
+        .decrypt_in_place_detached(nonce.into(), aad, data, tag.into())
+        .map_err(|_| errors::OpenError::TagMismatch)
+}
+
+/// PBKDF2-HMAC-SHA256 with a single block of output: a key for `passphrase`. Each of
+/// the `iterations` makes guessing the passphrase that much slower.
+pub fn derive_key(passphrase: &[u8], salt: &[u8], iterations: u32) -> Key {
+    let mut key = [0u8; KEY_SIZE];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase, salt, iterations, &mut key);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_aead_vector_and_tampering() {
+        // RFC 8439, 2.8.2
+        let key: Key = (0x80..0xa0).collect::<Vec<u8>>().try_into().unwrap();
+        let nonce: Nonce = hex("070000004041424344454647").try_into().unwrap();
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only \
+one tip for the future, sunscreen would be it.";
+        let mut data = plaintext.to_vec();
+        let tag = seal(&key, &nonce, &aad, &mut data);
+        assert_eq!(tag.to_vec(), hex("1ae10b594f09e26a7e902ecbd0600691"));
+        assert_eq!(data[..16].to_vec(), hex("d31a8d34648e60db7b86afbc53ef7ec2"));
+
+        let mut tampered = data.clone();
+        tampered[7] ^= 1;
+        assert_eq!(
+            open(&key, &nonce, &aad, &mut tampered, &tag),
+            Err(errors::OpenError::TagMismatch)
+        );
+        assert!(open(&key, &nonce, b"other", &mut data.clone(), &tag).is_err());
+        open(&key, &nonce, &aad, &mut data, &tag).unwrap();
+        assert_eq!(data, plaintext);
+    }
+
+    #[test]
+    fn test_pbkdf2_vectors() {
+        assert_eq!(
+            derive_key(b"password", b"salt", 1).to_vec(),
+            hex("120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b")
+        );
+        assert_eq!(
+            derive_key(b"password", b"salt", 4096).to_vec(),
+            hex("c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a")
+        );
+    }
+}
@@ -1,26 +1,242 @@
-use crate::storage::disk::file_manager::FileManager;
-use std::path::PathBuf;
+use crate::storage::disk::{BlockDevice, PackedSegment, Slot};
+use std::collections::HashMap;
+use std::io;
 
+/// The database file. Every other file id names a segment, a file of its own that holds
+/// one table or index (see `DiskManager`).
+pub const MAIN_FILE_ID: u32 = 0;
+
+/// Where a page lives: the file id in the high 32 bits, the page index within that file in
+/// the low ones. Pages of the database file keep their plain page index as address.
+pub fn page_address(file_id: u32, index: u64) -> u64 {
+    debug_assert!(
+        index <= u32::MAX as u64,
+        "page index {} out of range",
+        index
+    );
+    ((file_id as u64) << 32) | index
+}
+
+pub fn address_file(address: u64) -> u32 {
+    (address >> 32) as u32
+}
+
+pub fn address_index(address: u64) -> u64 {
+    address & u32::MAX as u64
+}
+
+/// The files of one database: the database file itself and its segments. Pages are
+/// addressed across all of them (see `page_address`). Segments are opened on first use,
+/// so one placed in another directory must be `place_segment`ed before then, and the
+/// pages of a compressed one are only found once it is `pack_segment`ed.
 pub struct DiskManager {
-    data_dir: String,
-    files: Vec<FileManager>,
+    main: Box<dyn BlockDevice>,
+    segments: HashMap<u32, Box<dyn BlockDevice>>,
+    // Directories of the segments that do not sit next to the database file
+    locations: HashMap<u32, String>,
+    packed: HashMap<u32, PackedSegment>,
 }
 
 impl DiskManager {
-    pub fn new(data_dir: String) -> Self {
+    pub fn new(main: Box<dyn BlockDevice>) -> Self {
         Self {
-            data_dir,
-            files: Vec::new(),
+            main,
+            segments: HashMap::new(),
+            locations: HashMap::new(),
+            packed: HashMap::new(),
         }
     }
 
-    pub fn add_file(&mut self, name: String) {
-        let mut path = PathBuf::from(&self.data_dir);
-        path.push(name);
+    /// Records that segment `file_id` lives in directory `location`.
+    pub fn place_segment(&mut self, file_id: u32, location: &str) {
+        self.locations.insert(file_id, location.to_string());
+    }
+
+    /// The database file, see `BlockDevice::path`.
+    pub fn path(&self) -> Option<&str> {
+        self.main.path()
+    }
 
-        match FileManager::new(path.to_string_lossy().into()) {
-            Ok(file_manager) => self.files.push(file_manager),
-            Err(e) => eprintln!("Failed to add file: {}", e),
+    fn device(&mut self, file_id: u32) -> io::Result<&mut Box<dyn BlockDevice>> {
+        if file_id == MAIN_FILE_ID {
+            return Ok(&mut self.main);
+        }
+        if !self.segments.contains_key(&file_id) {
+            let location = self.locations.get(&file_id).map(String::as_str);
+            let segment = self.main.open_segment(file_id, location, false)?;
+            self.segments.insert(file_id, segment);
         }
+        Ok(self.segments.get_mut(&file_id).unwrap())
+    }
+
+    /// # Safety
+    /// See `BlockDevice::read_block_into`.
+    pub unsafe fn read_block_into(&mut self, address: u64, buf: &mut [u8]) -> io::Result<()> {
+        debug_assert!(
+            !self.is_packed(address),
+            "packed pages are read with read_packed"
+        );
+        let device = self.device(address_file(address))?;
+        unsafe { device.read_block_into(address_index(address), buf) }
+    }
+
+    pub fn write_block_from(&mut self, address: u64, buf: &[u8]) -> io::Result<()> {
+        debug_assert!(
+            !self.is_packed(address),
+            "packed pages are written with write_packed"
+        );
+        self.device(address_file(address))?
+            .write_block_from(address_index(address), buf)
+    }
+
+    /// Stores the pages of segment `file_id` compressed, in the slots recorded for them.
+    /// A segment is packed from its creation on, and again every time it is opened.
+    pub fn pack_segment(&mut self, file_id: u32, slots: Vec<(u64, Slot)>) {
+        assert_ne!(file_id, MAIN_FILE_ID, "the database file is not a segment");
+        self.packed.insert(file_id, PackedSegment::new(slots));
+    }
+
+    /// Whether the page at `address` is kept in a slot of a packed segment. Their header
+    /// pages are not.
+    pub fn is_packed(&self, address: u64) -> bool {
+        address_index(address) != 0 && self.packed.contains_key(&address_file(address))
+    }
+
+    /// Where the packed page at `address` is stored, if it has been written yet
+    pub fn packed_slot(&self, address: u64) -> Option<Slot> {
+        self.packed
+            .get(&address_file(address))?
+            .slot(address_index(address))
+    }
+
+    /// Reads what was stored for the packed page at `address` into `data`.
+    pub fn read_packed(&mut self, address: u64, data: &mut Vec<u8>) -> io::Result<()> {
+        let file_id = address_file(address);
+        self.device(file_id)?;
+        let device = self.segments.get_mut(&file_id).unwrap();
+        self.packed
+            .get_mut(&file_id)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?
+            .read(device.as_mut(), address_index(address), data)
+    }
+
+    /// Stores `data` for the packed page at `address`. Returns its new slot if it moved.
+    pub fn write_packed(&mut self, address: u64, data: &[u8]) -> io::Result<Option<Slot>> {
+        let file_id = address_file(address);
+        self.device(file_id)?;
+        let device = self.segments.get_mut(&file_id).unwrap();
+        self.packed
+            .get_mut(&file_id)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?
+            .write(device.as_mut(), address_index(address), data)
+    }
+
+    /// Gives up the slot of the packed page at `address`, see `PackedSegment::forget`.
+    pub fn forget_packed(&mut self, address: u64) {
+        if let Some(segment) = self.packed.get_mut(&address_file(address)) {
+            segment.forget(address_index(address));
+        }
+    }
+
+    /// Frees the sectors packed pages have left behind, see `PackedSegment::recycle`.
+    pub fn recycle_packed(&mut self) {
+        self.packed.values_mut().for_each(PackedSegment::recycle);
+    }
+
+    /// Makes the writes so far durable, in the database file and every open segment.
+    pub fn sync(&self) -> io::Result<()> {
+        self.main.sync()?;
+        self.segments
+            .values()
+            .try_for_each(|segment| segment.sync())
+    }
+
+    /// Number of pages in file `file_id`
+    pub fn page_count(&mut self, file_id: u32) -> io::Result<u64> {
+        self.device(file_id)?.page_count()
+    }
+
+    /// Cuts file `file_id` down to its first `page_count` pages
+    pub fn truncate(&mut self, file_id: u32, page_count: u64) -> io::Result<()> {
+        self.device(file_id)?.truncate(page_count)
+    }
+
+    /// Adds a zeroed page at the end of file `file_id` and returns its address. A packed
+    /// segment only hands out an address, the page is stored when it is first written.
+    pub fn allocate_page(&mut self, file_id: u32) -> io::Result<u64> {
+        let index = match self.packed.get_mut(&file_id) {
+            Some(segment) => segment.allocate_index(),
+            None => self.device(file_id)?.allocate_new_page_offset()?,
+        };
+        if index > u32::MAX as u64 {
+            return Err(io::Error::other("file has run out of page addresses"));
+        }
+        Ok(page_address(file_id, index))
+    }
+
+    /// Creates segment `file_id`, empty, in directory `location` or else next to the
+    /// database file. Whatever a segment by that id held before, e.g. one left behind by
+    /// a crash, is thrown away.
+    pub fn create_segment(&mut self, file_id: u32, location: Option<&str>) -> io::Result<()> {
+        assert_ne!(file_id, MAIN_FILE_ID, "the database file is not a segment");
+        let mut segment = self.main.open_segment(file_id, location, true)?;
+        segment.truncate(0)?;
+        self.segments.insert(file_id, segment);
+        self.packed.remove(&file_id);
+        match location {
+            Some(location) => self.place_segment(file_id, location),
+            None => {
+                self.locations.remove(&file_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes segment `file_id` with everything in it.
+    pub fn remove_segment(&mut self, file_id: u32) -> io::Result<()> {
+        assert_ne!(file_id, MAIN_FILE_ID, "the database file is not a segment");
+        self.segments.remove(&file_id);
+        self.packed.remove(&file_id);
+        let location = self.locations.remove(&file_id);
+        self.main.remove_segment(file_id, location.as_deref())
+    }
+
+    /// The segments opened so far.
+    pub fn open_segments(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.segments.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::storage::PAGE_SIZE;
+    use crate::storage::disk::SimulatedDisk;
+
+    #[test]
+    fn test_segments_are_addressed_apart() {
+        let disk = SimulatedDisk::new();
+        let mut dm = DiskManager::new(Box::new(disk.device()));
+        assert_eq!(dm.allocate_page(MAIN_FILE_ID).unwrap(), 0);
+        dm.create_segment(7, None).unwrap();
+        let address = dm.allocate_page(7).unwrap();
+        assert_eq!((address_file(address), address_index(address)), (7, 0));
+
+        dm.write_block_from(0, &[1; PAGE_SIZE]).unwrap();
+        dm.write_block_from(address, &[2; PAGE_SIZE]).unwrap();
+        dm.sync().unwrap();
+
+        // A fresh manager finds the segment on disk
+        let mut dm = DiskManager::new(Box::new(disk.device()));
+        let mut buf = [0u8; PAGE_SIZE];
+        unsafe { dm.read_block_into(address, &mut buf).unwrap() };
+        assert_eq!(buf[0], 2);
+        assert_eq!(dm.page_count(MAIN_FILE_ID).unwrap(), 1);
+
+        dm.remove_segment(7).unwrap();
+        assert!(unsafe { dm.read_block_into(address, &mut buf) }.is_err());
+        assert_eq!(disk.file_ids(), vec![MAIN_FILE_ID]);
     }
 }
@@ -0,0 +1,520 @@
+use crate::constants::storage::PAGE_SIZE;
+use crate::storage::disk::BlockDevice;
+use crate::storage::disk::crypto::{self, Key, NONCE_SIZE, Nonce, TAG_SIZE, Tag};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read};
+use std::sync::Arc;
+
+/// PBKDF2 rounds for the key of a new database, see `crypto::derive_key`
+pub const DEFAULT_KDF_ITERATIONS: u32 = 100_000;
+
+const MAGIC: &[u8; 8] = b"NIMBUSEC";
+const VERSION: u32 = 3;
+const SALT_SIZE: usize = 16;
+// What the key check tag is made over: proves the passphrase right without decrypting pages
+const KEY_CHECK: &[u8] = b"nimbus key check";
+
+// Each page is sealed with a nonce and a tag of its own, and counts its writes. They are
+// kept in a seal block ahead of the pages it covers, which ends with a seal of its own.
+// Every page has two seals there: that of its last write, then that of the one before.
+const SEAL_SIZE: usize = 32;
+const ENTRY_SIZE: usize = 2 * SEAL_SIZE;
+const PAGES_PER_GROUP: u64 = (PAGE_SIZE / ENTRY_SIZE) as u64 - 1;
+const SEALS_END: usize = PAGES_PER_GROUP as usize * ENTRY_SIZE;
+const WRITES_AT: usize = NONCE_SIZE + TAG_SIZE;
+
+// Devices may read into it with direct I/O
+#[repr(C, align(4096))]
+struct Block([u8; PAGE_SIZE]);
+
+impl Block {
+    fn zeroed() -> Box<Self> {
+        Box::new(Block([0; PAGE_SIZE]))
+    }
+}
+
+/// The key of a database, and the header block every one of its files starts with
+struct Keyring {
+    key: Key,
+    header: Box<Block>,
+}
+
+/// Encrypts the pages of another device with ChaCha20-Poly1305, under a key made from a
+/// passphrase. Each page is authenticated along with its file id, its page offset and the
+/// number of times it has been written, so a page copied over another one, in the same
+/// file or not, or an older copy of the page itself, fails to read back.
+///
+/// Layout of every file of the database:
+///
+/// ```text
+/// | header | seals 0 | pages 0..63 | seals 1 | pages 63..126 | ...
+/// ```
+///
+/// The header holds the salt and the number of PBKDF2 rounds to make the key with, and a
+/// tag that tells whether a passphrase is the right one. The seal block ahead of each group
+/// of 63 pages holds their nonces, tags and write counts, and ends with a tag over all of
+/// them and the group's place, so no seal can be put back on its own.
+///
+/// A page's new seal is made durable before the page is written, and the seal of what the
+/// page held until then is kept next to it. A crash before the page lands leaves the page
+/// as it was, which that seal still opens, so a write either happens or it does not.
+///
+/// Rollback is only caught within a group, and not for the last write of a page: the
+/// counts live in the file, so an older copy of a whole group, seal block and pages
+/// together, or of the whole file, still reads fine, as does the copy of a page from
+/// before its last write.
+pub struct EncryptedDevice {
+    inner: Box<dyn BlockDevice>,
+    file_id: u32,
+    keyring: Arc<Keyring>,
+    seals: HashMap<u64, Box<Block>>, // Seal blocks read so far, by group
+    // Pages known to hold their last write: read or written since the device was opened
+    settled: HashSet<u64>,
+    nonce_prefix: [u8; 8],
+    nonce_counter: u32,
+    block: Box<Block>,
+}
+
+fn random_bytes(buf: &mut [u8]) -> io::Result<()> {
+    std::fs::File::open("/dev/urandom")?.read_exact(buf)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl EncryptedDevice {
+    /// Whether `device` holds an encrypted database
+    pub fn is_encrypted(device: &mut dyn BlockDevice) -> io::Result<bool> {
+        if device.page_count()? == 0 {
+            return Ok(false);
+        }
+        let mut block = Block::zeroed();
+        unsafe { device.read_block_into(0, &mut block.0)? };
+        Ok(&block.0[..MAGIC.len()] == MAGIC)
+    }
+
+    /// Opens the encrypted database on `inner` with `passphrase`. An empty device is set
+    /// up for encryption, with a key made in `kdf_iterations` rounds.
+    pub fn open(
+        mut inner: Box<dyn BlockDevice>,
+        passphrase: &str,
+        kdf_iterations: u32,
+    ) -> io::Result<Self> {
+        let mut header = Block::zeroed();
+        let key = if inner.page_count()? == 0 {
+            let mut salt = [0u8; SALT_SIZE];
+            random_bytes(&mut salt)?;
+            let key = crypto::derive_key(passphrase.as_bytes(), &salt, kdf_iterations);
+            let h = &mut header.0;
+            h[..8].copy_from_slice(MAGIC);
+            h[8..12].copy_from_slice(&VERSION.to_le_bytes());
+            h[12..16].copy_from_slice(&kdf_iterations.to_le_bytes());
+            h[16..32].copy_from_slice(&salt);
+            h[32..48].copy_from_slice(&crypto::seal(&key, &[0; NONCE_SIZE], KEY_CHECK, &mut []));
+            inner.write_block_from(0, &header.0)?;
+            key
+        } else {
+            unsafe { inner.read_block_into(0, &mut header.0)? };
+            let h = &header.0;
+            if &h[..8] != MAGIC {
+                return Err(invalid_data("database is not encrypted"));
+            }
+            let version = u32::from_le_bytes(h[8..12].try_into().unwrap());
+            if version != VERSION {
+                return Err(invalid_data("database is encrypted in an unknown format"));
+            }
+            let iterations = u32::from_le_bytes(h[12..16].try_into().unwrap());
+            let key = crypto::derive_key(passphrase.as_bytes(), &h[16..32], iterations);
+            let check: Tag = h[32..48].try_into().unwrap();
+            if crypto::open(&key, &[0; NONCE_SIZE], KEY_CHECK, &mut [], &check).is_err() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "wrong passphrase for encrypted database",
+                ));
+            }
+            key
+        };
+        Self::with_keyring(inner, 0, Arc::new(Keyring { key, header }))
+    }
+
+    fn with_keyring(
+        inner: Box<dyn BlockDevice>,
+        file_id: u32,
+        keyring: Arc<Keyring>,
+    ) -> io::Result<Self> {
+        let mut nonce_prefix = [0u8; 8];
+        random_bytes(&mut nonce_prefix)?;
+        Ok(Self {
+            inner,
+            file_id,
+            keyring,
+            seals: HashMap::new(),
+            settled: HashSet::new(),
+            nonce_prefix,
+            nonce_counter: 0,
+            block: Block::zeroed(),
+        })
+    }
+
+    /// A nonce never used before with this key: a random prefix for each device opened,
+    /// and a counter for the pages it writes
+    fn next_nonce(&mut self) -> io::Result<Nonce> {
+        if self.nonce_counter == u32::MAX {
+            random_bytes(&mut self.nonce_prefix)?;
+            self.nonce_counter = 0;
+        }
+        self.nonce_counter += 1;
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce[..8].copy_from_slice(&self.nonce_prefix);
+        nonce[8..].copy_from_slice(&self.nonce_counter.to_le_bytes());
+        Ok(nonce)
+    }
+
+    // Where the seal block of `group` is on the inner device
+    fn seal_block(group: u64) -> u64 {
+        1 + group * (PAGES_PER_GROUP + 1)
+    }
+
+    fn physical(offset: u64) -> u64 {
+        Self::seal_block(offset / PAGES_PER_GROUP) + 1 + offset % PAGES_PER_GROUP
+    }
+
+    fn aad(&self, offset: u64, writes: u32) -> [u8; 16] {
+        let mut aad = [0u8; 16];
+        aad[..4].copy_from_slice(&self.file_id.to_le_bytes());
+        aad[4..12].copy_from_slice(&offset.to_le_bytes());
+        aad[12..].copy_from_slice(&writes.to_le_bytes());
+        aad
+    }
+
+    // What the tag at the end of a seal block is made over
+    fn group_aad(file_id: u32, group: u64, seals: &Block) -> Vec<u8> {
+        let mut aad = Vec::with_capacity(12 + SEALS_END);
+        aad.extend_from_slice(&file_id.to_le_bytes());
+        aad.extend_from_slice(&group.to_le_bytes());
+        aad.extend_from_slice(&seals.0[..SEALS_END]);
+        aad
+    }
+
+    fn seals(&mut self, group: u64) -> io::Result<&mut Block> {
+        if !self.seals.contains_key(&group) {
+            let mut block = Block::zeroed();
+            let at = Self::seal_block(group);
+            if at < self.inner.page_count()? {
+                unsafe { self.inner.read_block_into(at, &mut block.0)? };
+                let trailer = &block.0[SEALS_END..];
+                let nonce: Nonce = trailer[..NONCE_SIZE].try_into().unwrap();
+                let tag: Tag = trailer[NONCE_SIZE..WRITES_AT].try_into().unwrap();
+                let aad = Self::group_aad(self.file_id, group, &block);
+                if crypto::open(&self.keyring.key, &nonce, &aad, &mut [], &tag).is_err() {
+                    return Err(invalid_data("seal block failed authentication"));
+                }
+            }
+            self.seals.insert(group, block);
+        }
+        Ok(self.seals.get_mut(&group).unwrap())
+    }
+
+    // Where the seals of the page at `offset` start in its seal block
+    fn entry(offset: u64) -> usize {
+        (offset % PAGES_PER_GROUP) as usize * ENTRY_SIZE
+    }
+
+    // The nonce, tag and write count of a seal, the count 0 for a page never written
+    fn parse_seal(seal: &[u8]) -> (Nonce, Tag, u32) {
+        (
+            seal[..NONCE_SIZE].try_into().unwrap(),
+            seal[NONCE_SIZE..WRITES_AT].try_into().unwrap(),
+            u32::from_le_bytes(seal[WRITES_AT..SEAL_SIZE].try_into().unwrap()),
+        )
+    }
+
+    /// Decrypts the page at `offset`, read from the inner device into `buf`, with `seal`.
+    /// The seal of a page never written only opens zeroes, as such a page reads.
+    fn open_page(&self, offset: u64, seal: &[u8], buf: &mut [u8]) -> bool {
+        let (nonce, tag, writes) = Self::parse_seal(seal);
+        if writes == 0 {
+            return buf.iter().all(|&b| b == 0);
+        }
+        crypto::open(
+            &self.keyring.key,
+            &nonce,
+            &self.aad(offset, writes),
+            buf,
+            &tag,
+        )
+        .is_ok()
+    }
+
+    /// Whether the page at `offset` on the inner device is the one its last seal is for,
+    /// rather than the one before it, left there by a crash
+    fn holds_last_write(&mut self, offset: u64, entry: &[u8]) -> io::Result<bool> {
+        if self.settled.contains(&offset) {
+            return Ok(true);
+        }
+        let at = Self::physical(offset);
+        if at >= self.inner.page_count()? {
+            self.block.0.fill(0);
+        } else {
+            unsafe { self.inner.read_block_into(at, &mut self.block.0)? };
+        }
+        let mut block = std::mem::replace(&mut self.block, Block::zeroed());
+        let held = self.open_page(offset, &entry[..SEAL_SIZE], &mut block.0);
+        self.block = block;
+        Ok(held)
+    }
+}
+
+impl BlockDevice for EncryptedDevice {
+    fn path(&self) -> Option<&str> {
+        self.inner.path()
+    }
+
+    unsafe fn read_block_into(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        if offset >= self.page_count()? {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        let at = Self::entry(offset);
+        let seals = self.seals(offset / PAGES_PER_GROUP)?;
+        let entry = seals.0[at..at + ENTRY_SIZE].to_vec();
+        // Never sealed, like a hole the inner device filled with zeroes
+        if Self::parse_seal(&entry).2 == 0 {
+            buf.fill(0);
+            return Ok(());
+        }
+
+        let at = Self::physical(offset);
+        if at >= self.inner.page_count()? {
+            buf.fill(0);
+        } else {
+            unsafe { self.inner.read_block_into(at, buf)? };
+        }
+        if self.open_page(offset, &entry[..SEAL_SIZE], buf) {
+            self.settled.insert(offset);
+            return Ok(());
+        }
+        // The last write did not land before a crash
+        if self.open_page(offset, &entry[SEAL_SIZE..], buf) {
+            return Ok(());
+        }
+        Err(invalid_data("page failed authentication"))
+    }
+
+    /// Seals the page under a new seal and keeps the seal of what the page holds now next
+    /// to it. The seal block is synced before the page is written: were the page to land
+    /// first, a crash could leave it with no seal that opens it.
+    fn write_block_from(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let group = offset / PAGES_PER_GROUP;
+        let at = Self::entry(offset);
+        let entry = self.seals(group)?.0[at..at + ENTRY_SIZE].to_vec();
+        let kept = if self.holds_last_write(offset, &entry)? {
+            &entry[..SEAL_SIZE]
+        } else {
+            &entry[SEAL_SIZE..]
+        };
+        let writes = match Self::parse_seal(&entry).2 {
+            u32::MAX => 1,
+            writes => writes + 1,
+        };
+        let nonce = self.next_nonce()?;
+        let seals_nonce = self.next_nonce()?;
+        let aad = self.aad(offset, writes);
+        self.block.0.copy_from_slice(buf);
+        let tag = crypto::seal(&self.keyring.key, &nonce, &aad, &mut self.block.0);
+
+        let seals = self.seals.get_mut(&group).unwrap();
+        seals.0[at + SEAL_SIZE..at + ENTRY_SIZE].copy_from_slice(kept);
+        seals.0[at..at + NONCE_SIZE].copy_from_slice(&nonce);
+        seals.0[at + NONCE_SIZE..at + WRITES_AT].copy_from_slice(&tag);
+        seals.0[at + WRITES_AT..at + SEAL_SIZE].copy_from_slice(&writes.to_le_bytes());
+        let aad = Self::group_aad(self.file_id, group, seals);
+        let seals_tag = crypto::seal(&self.keyring.key, &seals_nonce, &aad, &mut []);
+        seals.0[SEALS_END..SEALS_END + NONCE_SIZE].copy_from_slice(&seals_nonce);
+        seals.0[SEALS_END + NONCE_SIZE..SEALS_END + WRITES_AT].copy_from_slice(&seals_tag);
+        self.inner
+            .write_block_from(Self::seal_block(group), &seals.0)?;
+        self.inner.sync()?;
+        self.inner
+            .write_block_from(Self::physical(offset), &self.block.0)?;
+        self.settled.insert(offset);
+        Ok(())
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.inner.sync()
+    }
+
+    fn page_count(&self) -> io::Result<u64> {
+        let blocks = self.inner.page_count()?.saturating_sub(1);
+        let groups = blocks / (PAGES_PER_GROUP + 1);
+        let rest = blocks % (PAGES_PER_GROUP + 1);
+        Ok(groups * PAGES_PER_GROUP + rest.saturating_sub(1))
+    }
+
+    fn truncate(&mut self, page_count: u64) -> io::Result<()> {
+        let blocks = match page_count % PAGES_PER_GROUP {
+            0 => Self::seal_block(page_count / PAGES_PER_GROUP),
+            _ => Self::physical(page_count - 1) + 1,
+        };
+        self.seals
+            .retain(|&group, _| group * PAGES_PER_GROUP < page_count);
+        self.settled.retain(|&offset| offset < page_count);
+        self.inner.truncate(blocks)
+    }
+
+    fn allocate_new_page_offset(&mut self) -> io::Result<u64> {
+        let offset = self.page_count()?;
+        self.write_block_from(offset, &[0; PAGE_SIZE])?;
+        Ok(offset)
+    }
+
+    // Segments start with the same header as the database file, and use the same key
+    fn open_segment(
+        &self,
+        file_id: u32,
+        location: Option<&str>,
+        create: bool,
+    ) -> io::Result<Box<dyn BlockDevice>> {
+        let mut inner = self.inner.open_segment(file_id, location, create)?;
+        let header = &self.keyring.header.0;
+        if create || inner.page_count()? == 0 {
+            inner.write_block_from(0, header)?;
+        } else {
+            let mut block = Block::zeroed();
+            unsafe { inner.read_block_into(0, &mut block.0)? };
+            if block.0 != *header {
+                return Err(invalid_data("segment is encrypted with another key"));
+            }
+        }
+        Ok(Box::new(Self::with_keyring(
+            inner,
+            file_id,
+            self.keyring.clone(),
+        )?))
+    }
+
+    fn remove_segment(&self, file_id: u32, location: Option<&str>) -> io::Result<()> {
+        self.inner.remove_segment(file_id, location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::disk::{MemoryDevice, SimulatedDisk};
+
+    fn read(device: &mut dyn BlockDevice, offset: u64) -> io::Result<u8> {
+        let mut block = Block::zeroed();
+        unsafe { device.read_block_into(offset, &mut block.0)? };
+        Ok(block.0[100])
+    }
+
+    #[test]
+    fn test_pages_are_sealed_and_bound_to_their_place() {
+        let disk = SimulatedDisk::new();
+        let mut device = EncryptedDevice::open(Box::new(disk.device()), "secret", 10).unwrap();
+        for i in 0..200u8 {
+            assert_eq!(device.allocate_new_page_offset().unwrap(), i as u64);
+            device.write_block_from(i as u64, &[i; PAGE_SIZE]).unwrap();
+        }
+        assert_eq!(device.page_count().unwrap(), 200);
+        assert_eq!(read(&mut device, 150).unwrap(), 150);
+        device.sync().unwrap();
+
+        // Nothing of the page is left in the clear
+        let mut raw = disk.device();
+        let mut block = Block::zeroed();
+        unsafe { raw.read_block_into(EncryptedDevice::physical(7), &mut block.0) }.unwrap();
+        assert!(block.0.iter().filter(|&&b| b == 7).count() < 64);
+
+        // A page copied over another fails to authenticate
+        raw.write_block_from(EncryptedDevice::physical(8), &block.0)
+            .unwrap();
+        raw.sync().unwrap();
+        let mut device = EncryptedDevice::open(Box::new(disk.device()), "secret", 10).unwrap();
+        assert_eq!(read(&mut device, 7).unwrap(), 7);
+        let err = read(&mut device, 8).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // So does a copy of a page older than its last write, even along with its old seals
+        let mut old_page = Block::zeroed();
+        let mut old_seals = Block::zeroed();
+        unsafe { raw.read_block_into(EncryptedDevice::physical(20), &mut old_page.0) }.unwrap();
+        unsafe { raw.read_block_into(1, &mut old_seals.0) }.unwrap();
+        device.write_block_from(20, &[42; PAGE_SIZE]).unwrap();
+        device.write_block_from(20, &[43; PAGE_SIZE]).unwrap();
+        device.sync().unwrap();
+        raw.write_block_from(EncryptedDevice::physical(20), &old_page.0)
+            .unwrap();
+        let mut device = EncryptedDevice::open(Box::new(disk.device()), "secret", 10).unwrap();
+        assert!(read(&mut device, 20).is_err());
+        let at = EncryptedDevice::entry(20);
+        unsafe { raw.read_block_into(1, &mut block.0) }.unwrap();
+        block.0[at..at + ENTRY_SIZE].copy_from_slice(&old_seals.0[at..at + ENTRY_SIZE]);
+        raw.write_block_from(1, &block.0).unwrap();
+        let mut device = EncryptedDevice::open(Box::new(disk.device()), "secret", 10).unwrap();
+        let err = read(&mut device, 7).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(read(&mut device, 20).is_err());
+
+        device.truncate(130).unwrap();
+        assert_eq!(device.page_count().unwrap(), 130);
+        assert_eq!(read(&mut device, 129).unwrap(), 129);
+        assert!(read(&mut device, 130).is_err());
+        assert_eq!(device.allocate_new_page_offset().unwrap(), 130);
+        assert_eq!(read(&mut device, 130).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_crash_before_the_page_lands_keeps_it() {
+        let disk = SimulatedDisk::new();
+        let mut device = EncryptedDevice::open(Box::new(disk.device()), "secret", 10).unwrap();
+        device.allocate_new_page_offset().unwrap();
+        device.write_block_from(0, &[1; PAGE_SIZE]).unwrap();
+        device.sync().unwrap();
+
+        // Twice in a row, the new seal is durable but the page write never happens
+        for _ in 0..2 {
+            disk.crash_at_write(2, None);
+            assert!(device.write_block_from(0, &[2; PAGE_SIZE]).is_err());
+            device = EncryptedDevice::open(Box::new(disk.device()), "secret", 10).unwrap();
+            assert_eq!(read(&mut device, 0).unwrap(), 1);
+        }
+
+        device.write_block_from(0, &[3; PAGE_SIZE]).unwrap();
+        device.sync().unwrap();
+        let mut device = EncryptedDevice::open(Box::new(disk.device()), "secret", 10).unwrap();
+        assert_eq!(read(&mut device, 0).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_passphrase_is_checked() {
+        let disk = SimulatedDisk::new();
+        let device = EncryptedDevice::open(Box::new(disk.device()), "secret", 10).unwrap();
+        let mut segment = device.open_segment(3, None, true).unwrap();
+        segment.allocate_new_page_offset().unwrap();
+        segment.write_block_from(0, &[9; PAGE_SIZE]).unwrap();
+        segment.sync().unwrap();
+        device.sync().unwrap();
+
+        let err = EncryptedDevice::open(Box::new(disk.device()), "guess", 10)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(EncryptedDevice::is_encrypted(&mut disk.device()).unwrap());
+        assert!(!EncryptedDevice::is_encrypted(&mut MemoryDevice::new()).unwrap());
+
+        let device = EncryptedDevice::open(Box::new(disk.device()), "secret", 10).unwrap();
+        let mut segment = device.open_segment(3, None, false).unwrap();
+        assert_eq!(read(segment.as_mut(), 0).unwrap(), 9);
+
+        // A plain database is not taken for an encrypted one
+        let mut plain = MemoryDevice::new();
+        plain.allocate_new_page_offset().unwrap();
+        let err = EncryptedDevice::open(Box::new(plain), "secret", 10)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
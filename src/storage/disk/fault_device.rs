@@ -0,0 +1,334 @@
+use crate::constants::storage::PAGE_SIZE;
+use crate::storage::disk::BlockDevice;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+type Page = Box<[u8; PAGE_SIZE]>;
+
+/// The database file or one of its segments
+#[derive(Default)]
+struct FileState {
+    /// What is on the platter: survives a crash
+    durable: Vec<Page>,
+    /// Writes since the last sync, by page offset. A crash loses them.
+    pending: BTreeMap<u64, Page>,
+    /// Page count as the database sees it, including unsynced growth and truncation
+    len: u64,
+}
+
+impl FileState {
+    fn page(&self, offset: u64) -> Option<&[u8; PAGE_SIZE]> {
+        if offset >= self.len {
+            return None;
+        }
+        let page = self
+            .pending
+            .get(&offset)
+            .or(self.durable.get(offset as usize));
+        Some(page.map_or(&[0; PAGE_SIZE], |page| &**page))
+    }
+
+    fn crash(&mut self) {
+        self.pending.clear();
+        self.len = self.durable.len() as u64;
+    }
+}
+
+#[derive(Default)]
+struct DiskState {
+    /// By file id, 0 being the database file
+    files: BTreeMap<u32, FileState>,
+    writes: u64,
+    crash_at_write: Option<u64>,
+    tear_at_byte: Option<usize>,
+    failing_reads: HashSet<(u32, u64)>,
+    /// Bumped by every crash: devices handed out before it are dead
+    generation: u64,
+}
+
+impl DiskState {
+    fn crash(&mut self) {
+        self.files.values_mut().for_each(FileState::crash);
+        self.crash_at_write = None;
+        self.tear_at_byte = None;
+        self.failing_reads.clear();
+        self.generation += 1;
+    }
+}
+
+/// A disk for crash tests. Writes only survive a crash once synced, a crash can tear the
+/// page being written, and reads of chosen pages fail with EIO. Clones are handles to the
+/// same disk: hand `device()` to a buffer pool, `crash()` the disk, then open a new pool on
+/// a fresh `device()` to see what a restart would find. Segments live on the same disk and
+/// crash with it.
+#[derive(Clone, Default)]
+pub struct SimulatedDisk {
+    state: Arc<Mutex<DiskState>>,
+}
+
+impl SimulatedDisk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A device on the disk as it is now. It stops working at the next crash.
+    pub fn device(&self) -> FaultDevice {
+        let mut state = self.lock();
+        state.files.entry(0).or_default();
+        FaultDevice {
+            disk: self.clone(),
+            file_id: 0,
+            generation: state.generation,
+        }
+    }
+
+    /// Loses power: unsynced writes are gone and every device handed out so far fails
+    /// from now on. Pending faults are cleared.
+    pub fn crash(&self) {
+        self.lock().crash();
+    }
+
+    /// Crashes during the `n`th write from now, counting page allocations. With a tear,
+    /// only the first `torn_bytes` bytes of that write reach the platter, otherwise none.
+    pub fn crash_at_write(&self, n: u64, torn_bytes: Option<usize>) {
+        assert!(n > 0, "Writes are counted from 1");
+        assert!(
+            torn_bytes.is_none_or(|b| b < PAGE_SIZE),
+            "A tear must lose some bytes"
+        );
+        let mut state = self.lock();
+        state.crash_at_write = Some(state.writes + n);
+        state.tear_at_byte = torn_bytes;
+    }
+
+    /// Makes every read of the page at `offset` in file `file_id` fail with EIO until the
+    /// next crash.
+    pub fn fail_reads_of(&self, file_id: u32, offset: u64) {
+        self.lock().failing_reads.insert((file_id, offset));
+    }
+
+    /// Writes done on the disk so far, counting page allocations.
+    pub fn writes(&self) -> u64 {
+        self.lock().writes
+    }
+
+    /// Whether the disk has crashed since `device` was handed out.
+    pub fn has_crashed(&self, device: &FaultDevice) -> bool {
+        self.lock().generation != device.generation
+    }
+
+    /// Ids of the files on the disk, the database file first.
+    pub fn file_ids(&self) -> Vec<u32> {
+        self.lock().files.keys().copied().collect()
+    }
+
+    /// Number of pages of file `file_id` that would survive a crash right now.
+    pub fn durable_page_count(&self, file_id: u32) -> u64 {
+        self.lock()
+            .files
+            .get(&file_id)
+            .map_or(0, |file| file.durable.len() as u64)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DiskState> {
+        // A failed test must not take the other handles down with it
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The `BlockDevice` side of a `SimulatedDisk`, for one of its files.
+pub struct FaultDevice {
+    disk: SimulatedDisk,
+    file_id: u32,
+    generation: u64,
+}
+
+impl FaultDevice {
+    /// The state of the disk, or EIO once it has crashed under this device
+    fn state(&self) -> io::Result<MutexGuard<'_, DiskState>> {
+        let state = self.disk.lock();
+        if state.generation != self.generation {
+            return Err(io::Error::other("simulated disk has crashed"));
+        }
+        if !state.files.contains_key(&self.file_id) {
+            return Err(io::Error::from(io::ErrorKind::NotFound));
+        }
+        Ok(state)
+    }
+
+    fn with_file<T>(&self, f: impl FnOnce(&mut FileState) -> T) -> io::Result<T> {
+        let mut state = self.state()?;
+        Ok(f(state.files.get_mut(&self.file_id).unwrap()))
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let mut state = self.state()?;
+        state.writes += 1;
+        let crashing = state.crash_at_write == Some(state.writes);
+        let tear = state.tear_at_byte;
+        let file = state.files.get_mut(&self.file_id).unwrap();
+        if crashing {
+            // The tear lands on what is on the platter, unsynced writes never got there
+            if let Some(torn) = tear {
+                while file.durable.len() <= offset as usize {
+                    file.durable.push(Box::new([0; PAGE_SIZE]));
+                }
+                file.durable[offset as usize][..torn].copy_from_slice(&buf[..torn]);
+            }
+            state.crash();
+            return Err(io::Error::other("simulated disk crashed"));
+        }
+
+        let mut page: Page = Box::new([0; PAGE_SIZE]);
+        page.copy_from_slice(buf);
+        file.pending.insert(offset, page);
+        file.len = file.len.max(offset + 1);
+        Ok(())
+    }
+}
+
+impl BlockDevice for FaultDevice {
+    fn path(&self) -> Option<&str> {
+        None
+    }
+
+    unsafe fn read_block_into(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let state = self.state()?;
+        if state.failing_reads.contains(&(self.file_id, offset)) {
+            return Err(io::Error::from_raw_os_error(libc::EIO));
+        }
+        let page = state.files[&self.file_id]
+            .page(offset)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        buf.copy_from_slice(page);
+        Ok(())
+    }
+
+    fn write_block_from(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        self.write(offset, buf)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.with_file(|file| {
+            let len = file.len as usize;
+            file.durable.truncate(len);
+            while file.durable.len() < len {
+                file.durable.push(Box::new([0; PAGE_SIZE]));
+            }
+            for (offset, page) in std::mem::take(&mut file.pending) {
+                if (offset as usize) < len {
+                    file.durable[offset as usize] = page;
+                }
+            }
+        })
+    }
+
+    fn page_count(&self) -> io::Result<u64> {
+        self.with_file(|file| file.len)
+    }
+
+    fn truncate(&mut self, page_count: u64) -> io::Result<()> {
+        self.with_file(|file| {
+            file.len = page_count;
+            file.pending.retain(|&offset, _| offset < page_count);
+        })
+    }
+
+    fn allocate_new_page_offset(&mut self) -> io::Result<u64> {
+        let offset = self.with_file(|file| file.len)?;
+        self.write(offset, &[0; PAGE_SIZE])?;
+        Ok(offset)
+    }
+
+    // Every file is on the same simulated disk, wherever it is placed
+    fn open_segment(
+        &self,
+        file_id: u32,
+        _location: Option<&str>,
+        create: bool,
+    ) -> io::Result<Box<dyn BlockDevice>> {
+        let mut state = self.state()?;
+        if create {
+            if state.files.contains_key(&file_id) {
+                return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+            }
+            // Creating the file is a metadata update the disk keeps right away
+            state.files.insert(file_id, Default::default());
+        } else if !state.files.contains_key(&file_id) {
+            return Err(io::Error::from(io::ErrorKind::NotFound));
+        }
+        Ok(Box::new(FaultDevice {
+            disk: self.disk.clone(),
+            file_id,
+            generation: self.generation,
+        }))
+    }
+
+    fn remove_segment(&self, file_id: u32, _location: Option<&str>) -> io::Result<()> {
+        self.state()?
+            .files
+            .remove(&file_id)
+            .map(|_| ())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(device: &mut FaultDevice, offset: u64) -> io::Result<u8> {
+        let mut buf = [0u8; PAGE_SIZE];
+        unsafe { device.read_block_into(offset, &mut buf)? };
+        Ok(buf[PAGE_SIZE - 1])
+    }
+
+    #[test]
+    fn test_crash_keeps_only_synced_writes() {
+        let disk = SimulatedDisk::new();
+        let mut device = disk.device();
+        for _ in 0..3 {
+            device.allocate_new_page_offset().unwrap();
+        }
+        device.write_block_from(0, &[1; PAGE_SIZE]).unwrap();
+        device.sync().unwrap();
+        device.write_block_from(0, &[2; PAGE_SIZE]).unwrap();
+        device.write_block_from(3, &[3; PAGE_SIZE]).unwrap();
+        assert_eq!(read(&mut device, 0).unwrap(), 2);
+        assert_eq!(device.page_count().unwrap(), 4);
+
+        disk.crash();
+        assert!(disk.has_crashed(&device));
+        assert!(read(&mut device, 0).is_err());
+        assert!(device.write_block_from(0, &[4; PAGE_SIZE]).is_err());
+
+        let mut device = disk.device();
+        assert_eq!(device.page_count().unwrap(), 3);
+        assert_eq!(read(&mut device, 0).unwrap(), 1);
+        assert!(read(&mut device, 3).is_err());
+    }
+
+    #[test]
+    fn test_torn_write_and_read_errors() {
+        let disk = SimulatedDisk::new();
+        let mut device = disk.device();
+        device.write_block_from(0, &[1; PAGE_SIZE]).unwrap();
+        device.sync().unwrap();
+
+        disk.crash_at_write(2, Some(100));
+        device.write_block_from(0, &[2; PAGE_SIZE]).unwrap();
+        assert!(device.write_block_from(0, &[3; PAGE_SIZE]).is_err());
+
+        // The synced page had the first 100 bytes of the crashing write land on it
+        let mut device = disk.device();
+        let mut buf = [0u8; PAGE_SIZE];
+        unsafe { device.read_block_into(0, &mut buf).unwrap() };
+        assert!(buf[..100].iter().all(|&b| b == 3));
+        assert!(buf[100..].iter().all(|&b| b == 1));
+
+        disk.fail_reads_of(0, 0);
+        let err = read(&mut device, 0).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EIO));
+    }
+}
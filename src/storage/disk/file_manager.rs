@@ -1,16 +1,63 @@
 use crate::constants;
-use std::fs::File;
+use crate::storage::disk::BlockDevice;
+use std::fs::{self, File};
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
 
 pub struct FileManager {
-    #[allow(unused)]
     file_path: String,
     pub file: File,
+    direct: bool, // Opened with O_DIRECT
 }
 
 impl FileManager {
+    /// Opens the file with O_DIRECT, or through the page cache on file systems that
+    /// reject it.
     pub fn new(file_path: String) -> io::Result<Self> {
+        match Self::open(file_path.clone(), true, false) {
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => Self::open(file_path, false, false),
+            res => res,
+        }
+    }
+
+    /// Opens the file through the page cache.
+    pub fn buffered(file_path: String) -> io::Result<Self> {
+        Self::open(file_path, false, false)
+    }
+
+    /// Deletes the database file at `file_path` along with the segments next to it.
+    /// Segments in other directories (see tablespaces) are left alone.
+    pub fn remove_database(file_path: &str) -> io::Result<()> {
+        let path = Path::new(file_path);
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if let (Some(name), Ok(entries)) = (name, fs::read_dir(dir)) {
+            for entry in entries.flatten() {
+                let entry_name = entry.file_name();
+                let is_segment = entry_name
+                    .to_string_lossy()
+                    .strip_prefix(&name)
+                    .and_then(|rest| rest.strip_prefix('.'))
+                    .is_some_and(|id| id.parse::<u32>().is_ok());
+                if is_segment {
+                    fs::remove_file(entry.path())?;
+                }
+            }
+        }
+        match fs::remove_file(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => res,
+        }
+    }
+
+    /// `create_new` refuses a file that already exists instead of opening it.
+    fn open(file_path: String, direct: bool, create_new: bool) -> io::Result<Self> {
         if cfg!(windows) {
             panic!("Non UNIX systems are not supported");
         }
@@ -18,16 +65,59 @@ impl FileManager {
         let file = File::options()
             .read(true)
             .write(true)
-            .create(true)
-            .custom_flags(libc::O_DIRECT)
-            .open(&file_path)?;
+            .create(!create_new)
+            .create_new(create_new)
+            .custom_flags(if direct { libc::O_DIRECT } else { 0 })
+            .open(&file_path)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => {
+                    io::Error::new(e.kind(), format!("{} already exists", file_path))
+                }
+                _ => e,
+            })?;
+
+        Ok(Self {
+            file_path,
+            file,
+            direct,
+        })
+    }
 
-        Ok(Self { file_path, file })
+    pub fn is_direct(&self) -> bool {
+        self.direct
+    }
+
+    /// Segments are named after the database file: nimbus.db.101, next to it unless they
+    /// live in another directory
+    fn segment_path(&self, file_id: u32, location: Option<&str>) -> String {
+        match location {
+            None => format!("{}.{}", self.file_path, file_id),
+            Some(dir) => {
+                let name = Path::new(&self.file_path)
+                    .file_name()
+                    .map_or(self.file_path.as_str().into(), |name| {
+                        name.to_string_lossy()
+                    });
+                let path = Path::new(dir).join(format!("{}.{}", name, file_id));
+                path.to_string_lossy().into_owned()
+            }
+        }
+    }
+}
+
+impl BlockDevice for FileManager {
+    fn path(&self) -> Option<&str> {
+        Some(&self.file_path)
+    }
+
+    /// O_DIRECT skips the page cache but not the drive's, so durability still needs this.
+    fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
     }
 
     /// buf: Should be a PageBuf slice
     /// offset: Page Index (NOT byte offset)
-    pub unsafe fn read_block_into(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    unsafe fn read_block_into(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
         let byte_offset = offset * constants::storage::PAGE_SIZE as u64;
         self.file.seek(SeekFrom::Start(byte_offset))?;
         self.file.read_exact(buf)?;
@@ -37,7 +127,7 @@ impl FileManager {
 
     /// buf: Should be a PageBuf slice
     /// offset: Page Index (NOT byte offset)
-    pub fn write_block_from(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+    fn write_block_from(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
         let byte_offset = offset * constants::storage::PAGE_SIZE as u64;
         self.file.seek(SeekFrom::Start(byte_offset))?;
 
@@ -46,8 +136,19 @@ impl FileManager {
         Ok(())
     }
 
+    /// Number of pages the file holds
+    fn page_count(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len() / constants::storage::PAGE_SIZE as u64)
+    }
+
+    /// Cuts the file down to its first `page_count` pages
+    fn truncate(&mut self, page_count: u64) -> io::Result<()> {
+        self.file
+            .set_len(page_count * constants::storage::PAGE_SIZE as u64)
+    }
+
     // adds a new page to the file
-    pub fn allocate_new_page_offset(&mut self) -> io::Result<u64> {
+    fn allocate_new_page_offset(&mut self) -> io::Result<u64> {
         // go to the end of the file
         let current_size = self.file.seek(SeekFrom::End(0))?;
 
@@ -60,4 +161,87 @@ impl FileManager {
         // FIX: Return Page Index (Bytes / PageSize), NOT raw Bytes
         Ok(current_size / constants::storage::PAGE_SIZE as u64)
     }
+
+    fn open_segment(
+        &self,
+        file_id: u32,
+        location: Option<&str>,
+        create: bool,
+    ) -> io::Result<Box<dyn BlockDevice>> {
+        let path = self.segment_path(file_id, location);
+        if !create && !Path::new(&path).exists() {
+            return Err(io::Error::from(io::ErrorKind::NotFound));
+        }
+        // A new segment must not pick up the pages of a file that happens to be there
+        Ok(Box::new(Self::open(path, self.direct, create)?))
+    }
+
+    fn remove_segment(&self, file_id: u32, location: Option<&str>) -> io::Result<()> {
+        fs::remove_file(self.segment_path(file_id, location))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_buffered_file_roundtrip() {
+        let mut path = std::env::temp_dir();
+        path.push("nimbus_test_buffered_file.db");
+        let path = path.to_str().unwrap().to_string();
+        let _ = fs::remove_file(&path);
+
+        {
+            let mut fm = FileManager::buffered(path.clone()).unwrap();
+            assert!(!fm.is_direct());
+            assert_eq!(fm.allocate_new_page_offset().unwrap(), 0);
+            assert_eq!(fm.allocate_new_page_offset().unwrap(), 1);
+            // Any buffer will do without O_DIRECT
+            let buf = vec![3u8; constants::storage::PAGE_SIZE];
+            fm.write_block_from(1, &buf).unwrap();
+            fm.sync().unwrap();
+        }
+
+        let mut fm = FileManager::new(path.clone()).unwrap();
+        assert_eq!(fm.page_count().unwrap(), 2);
+        let mut buf = vec![0u8; constants::storage::PAGE_SIZE];
+        if !fm.is_direct() {
+            unsafe { fm.read_block_into(1, &mut buf).unwrap() };
+            assert!(buf.iter().all(|&b| b == 3));
+        }
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_new_segments_refuse_leftover_files() {
+        let mut path = std::env::temp_dir();
+        path.push("nimbus_test_leftover_segment.db");
+        let path = path.to_str().unwrap().to_string();
+        FileManager::remove_database(&path).unwrap();
+
+        // A segment left behind by an earlier database with the same name
+        fs::write(
+            format!("{}.100", path),
+            vec![7u8; constants::storage::PAGE_SIZE],
+        )
+        .unwrap();
+        let fm = FileManager::buffered(path.clone()).unwrap();
+        let err = fm.open_segment(100, None, true).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(fm.open_segment(100, None, false).is_ok());
+        fm.open_segment(101, None, true).unwrap();
+        assert!(fm.open_segment(101, None, true).is_err());
+
+        FileManager::remove_database(&path).unwrap();
+        for file in [
+            path.clone(),
+            format!("{}.100", path),
+            format!("{}.101", path),
+        ] {
+            assert!(!Path::new(&file).exists(), "{} was not removed", file);
+        }
+    }
 }
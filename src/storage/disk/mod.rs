@@ -1,5 +1,21 @@
+pub mod block_device;
+pub mod compression;
+#[cfg(feature = "encryption")]
+pub mod crypto;
 pub mod disk_manager;
+#[cfg(feature = "encryption")]
+pub mod encrypted_device;
+pub mod fault_device;
 pub mod file_manager;
+pub mod memory_device;
+pub mod packed_segment;
 
-pub use disk_manager::DiskManager;
+pub use block_device::{BlockDevice, MEMORY_PATH, open_device, open_encrypted_device};
+pub use compression::Compression;
+pub use disk_manager::{DiskManager, MAIN_FILE_ID, address_file, address_index, page_address};
+#[cfg(feature = "encryption")]
+pub use encrypted_device::EncryptedDevice;
+pub use fault_device::{FaultDevice, SimulatedDisk};
 pub use file_manager::FileManager;
+pub use memory_device::MemoryDevice;
+pub use packed_segment::{PackedSegment, Slot};
@@ -0,0 +1,300 @@
+use crate::constants::storage::PAGE_SIZE;
+use crate::storage::disk::BlockDevice;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+/// Unit the pages of a compressed segment are packed in
+pub const SECTOR_SIZE: usize = 512;
+const SECTORS_PER_BLOCK: u32 = (PAGE_SIZE / SECTOR_SIZE) as u32;
+// Each slot starts with the length of what it holds
+const LEN_PREFIX: usize = 2;
+
+/// Where a page of a compressed segment is stored: `sectors` sectors from `sector` on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slot {
+    pub sector: u32,
+    pub sectors: u32,
+}
+
+// Devices may read into it with direct I/O
+#[repr(C, align(4096))]
+struct Block([u8; PAGE_SIZE]);
+
+/// A segment whose pages are stored compressed, packed into sectors instead of taking a
+/// block each. The header page (index 0) stays a plain block at the start of the file.
+///
+/// A page is rewritten in place while it fits its slot and moves to a new one when it
+/// outgrows it. Slots live here and in the directory (see `DirectoryEntry`), which lags
+/// behind until the moves are recorded in it, so the sectors a page leaves behind only
+/// become free again through `recycle`, once no durable directory entry can point at them.
+pub struct PackedSegment {
+    slots: HashMap<u64, Slot>, // By page index
+    next_index: u64,
+    end: u32,                 // First sector past every slot
+    free: BTreeMap<u32, u32>, // First sector -> length of each free run below `end`
+    released: Vec<Slot>,
+    block: Box<Block>,
+}
+
+impl PackedSegment {
+    /// Takes over the slots recorded for the pages of the segment. The sectors between
+    /// them are free.
+    pub fn new(slots: impl IntoIterator<Item = (u64, Slot)>) -> Self {
+        let slots: HashMap<u64, Slot> = slots.into_iter().collect();
+        let mut taken: Vec<Slot> = slots.values().copied().collect();
+        taken.sort_unstable_by_key(|slot| slot.sector);
+
+        let mut free = BTreeMap::new();
+        let mut end = SECTORS_PER_BLOCK;
+        for slot in taken {
+            if slot.sector > end {
+                free.insert(end, slot.sector - end);
+            }
+            end = end.max(slot.sector + slot.sectors);
+        }
+        Self {
+            next_index: slots.keys().max().map_or(1, |index| index + 1),
+            slots,
+            end,
+            free,
+            released: Vec::new(),
+            block: Box::new(Block([0; PAGE_SIZE])),
+        }
+    }
+
+    /// A new page index. Nothing is stored for it until its first `write`.
+    pub fn allocate_index(&mut self) -> u64 {
+        self.next_index += 1;
+        self.next_index - 1
+    }
+
+    pub fn slot(&self, index: u64) -> Option<Slot> {
+        self.slots.get(&index).copied()
+    }
+
+    /// Stores `data` as page `index`. Returns the new slot if the page got one.
+    pub fn write(
+        &mut self,
+        device: &mut dyn BlockDevice,
+        index: u64,
+        data: &[u8],
+    ) -> io::Result<Option<Slot>> {
+        let needed = (LEN_PREFIX + data.len()).div_ceil(SECTOR_SIZE) as u32;
+        let current = self.slot(index);
+        let slot = match current {
+            Some(slot) if slot.sectors >= needed => slot,
+            _ => self.take_sectors(needed),
+        };
+
+        let mut bytes = Vec::with_capacity(slot.sectors as usize * SECTOR_SIZE);
+        bytes.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes.resize(needed as usize * SECTOR_SIZE, 0);
+        if let Err(e) = self.write_sectors(device, slot.sector, &bytes) {
+            if current != Some(slot) {
+                self.free_run(slot);
+            }
+            return Err(e);
+        }
+
+        if current == Some(slot) {
+            return Ok(None);
+        }
+        self.slots.insert(index, slot);
+        self.released.extend(current);
+        Ok(Some(slot))
+    }
+
+    /// Reads what was last stored as page `index` into `data`.
+    pub fn read(
+        &mut self,
+        device: &mut dyn BlockDevice,
+        index: u64,
+        data: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let slot = self
+            .slot(index)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        let start = slot.sector as usize * SECTOR_SIZE;
+        data.clear();
+        for block in
+            start / PAGE_SIZE..(start + slot.sectors as usize * SECTOR_SIZE).div_ceil(PAGE_SIZE)
+        {
+            unsafe { device.read_block_into(block as u64, &mut self.block.0)? };
+            data.extend_from_slice(&self.block.0);
+        }
+        data.drain(..start % PAGE_SIZE);
+
+        let len = u16::from_le_bytes([data[0], data[1]]) as usize;
+        if LEN_PREFIX + len > slot.sectors as usize * SECTOR_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stored page is longer than its slot",
+            ));
+        }
+        data.truncate(LEN_PREFIX + len);
+        data.drain(..LEN_PREFIX);
+        Ok(())
+    }
+
+    /// Gives up the slot of page `index`, which is no longer used.
+    pub fn forget(&mut self, index: u64) {
+        self.released.extend(self.slots.remove(&index));
+    }
+
+    /// Frees the sectors released so far. The directory must not point at them any more,
+    /// not even the copy on disk.
+    pub fn recycle(&mut self) {
+        for slot in std::mem::take(&mut self.released) {
+            self.free_run(slot);
+        }
+    }
+
+    fn take_sectors(&mut self, count: u32) -> Slot {
+        let fit = self
+            .free
+            .iter()
+            .find(|&(_, &len)| len >= count)
+            .map(|(&sector, &len)| (sector, len));
+        match fit {
+            Some((sector, len)) => {
+                self.free.remove(&sector);
+                if len > count {
+                    self.free.insert(sector + count, len - count);
+                }
+                Slot {
+                    sector,
+                    sectors: count,
+                }
+            }
+            None => {
+                self.end += count;
+                Slot {
+                    sector: self.end - count,
+                    sectors: count,
+                }
+            }
+        }
+    }
+
+    fn free_run(&mut self, slot: Slot) {
+        let mut start = slot.sector;
+        let mut len = slot.sectors;
+        if let Some((&before, &before_len)) = self.free.range(..start).next_back()
+            && before + before_len == start
+        {
+            self.free.remove(&before);
+            start = before;
+            len += before_len;
+        }
+        if let Some(after_len) = self.free.remove(&(start + len)) {
+            len += after_len;
+        }
+        if start + len == self.end {
+            self.end = start;
+        } else {
+            self.free.insert(start, len);
+        }
+    }
+
+    /// Writes whole sectors, reading back the blocks they only partly cover.
+    fn write_sectors(
+        &mut self,
+        device: &mut dyn BlockDevice,
+        sector: u32,
+        bytes: &[u8],
+    ) -> io::Result<()> {
+        let start = sector as usize * SECTOR_SIZE;
+        let end = start + bytes.len();
+        let page_count = device.page_count()?;
+        for block in start / PAGE_SIZE..end.div_ceil(PAGE_SIZE) {
+            let block_start = block * PAGE_SIZE;
+            let lo = start.max(block_start);
+            let hi = end.min(block_start + PAGE_SIZE);
+            if hi - lo < PAGE_SIZE {
+                if (block as u64) < page_count {
+                    unsafe { device.read_block_into(block as u64, &mut self.block.0)? };
+                } else {
+                    self.block.0.fill(0);
+                }
+            }
+            self.block.0[lo - block_start..hi - block_start]
+                .copy_from_slice(&bytes[lo - start..hi - start]);
+            device.write_block_from(block as u64, &self.block.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::disk::MemoryDevice;
+
+    #[test]
+    fn test_pages_move_when_they_outgrow_their_slot() {
+        let mut device = MemoryDevice::new();
+        device.allocate_new_page_offset().unwrap(); // The header page
+        let mut segment = PackedSegment::new([]);
+        let (a, b) = (segment.allocate_index(), segment.allocate_index());
+        assert_eq!((a, b), (1, 2));
+
+        let first = segment.write(&mut device, a, &[1; 100]).unwrap().unwrap();
+        assert_eq!(
+            first,
+            Slot {
+                sector: 8,
+                sectors: 1
+            }
+        );
+        segment.write(&mut device, b, &[2; 1000]).unwrap().unwrap();
+        // Still fits: rewritten in place
+        assert_eq!(segment.write(&mut device, a, &[3; 500]).unwrap(), None);
+        // Does not any more: moves past b, and its old sector is only free after recycling
+        let moved = segment.write(&mut device, a, &[4; 600]).unwrap().unwrap();
+        assert_eq!(
+            moved,
+            Slot {
+                sector: 11,
+                sectors: 2
+            }
+        );
+        let c = segment.allocate_index();
+        assert_eq!(
+            segment
+                .write(&mut device, c, &[5; 10])
+                .unwrap()
+                .unwrap()
+                .sector,
+            13
+        );
+        segment.recycle();
+        let d = segment.allocate_index();
+        assert_eq!(
+            segment
+                .write(&mut device, d, &[6; 10])
+                .unwrap()
+                .unwrap()
+                .sector,
+            8
+        );
+
+        let mut data = Vec::new();
+        segment.read(&mut device, a, &mut data).unwrap();
+        assert_eq!(data, vec![4; 600]);
+        segment.read(&mut device, b, &mut data).unwrap();
+        assert_eq!(data, vec![2; 1000]);
+        assert_eq!(device.page_count().unwrap(), 2);
+
+        // Reopened from the recorded slots, the gap left by `c` is reused
+        segment.forget(c);
+        let slots = [a, b, d].map(|index| (index, segment.slot(index).unwrap()));
+        let mut segment = PackedSegment::new(slots);
+        assert_eq!(segment.allocate_index(), 5);
+        let e = segment.write(&mut device, 5, &[7; 10]).unwrap().unwrap();
+        assert_eq!(e.sector, 13);
+        segment.read(&mut device, d, &mut data).unwrap();
+        assert_eq!(data, vec![6; 10]);
+        assert!(segment.read(&mut device, c, &mut data).is_err());
+    }
+}
@@ -0,0 +1,499 @@
+use crate::storage::buffer::{AccessStrategy, BufferPool, Frame};
+use crate::storage::disk;
+use crate::storage::page::base::{Page, PageId, PageKind};
+use crate::storage::page::{HashBucket, HashDirectory};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[derive(Debug)]
+pub enum HashIndexError {
+    FetchPage(String),
+    UnpinPage(String),
+    AllocPage(String),
+    InvalidPageType,
+    InsertError(String),
+    FreePage(String),
+}
+
+/// Extendible hash index mapping fixed-size keys to row ids.
+/// The directory page never moves, so its page id is a stable handle for the whole index.
+/// Keys may repeat: every (key, row id) pair is an entry of its own.
+/// New pages go to the file the directory lives in.
+pub struct HashIndex<'a> {
+    pub bpm: Pin<&'a mut BufferPool>,
+    pub directory_page_id: PageId,
+    // Looked up from the directory on the first allocation unless given
+    segment: Option<u32>,
+}
+
+/// FNV-1a over the key bytes. Bucket placement is persisted, so unlike std's
+/// `DefaultHasher` this must stay stable across builds.
+pub fn hash_key(key: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+impl<'a> HashIndex<'a> {
+    pub fn new(bpm: Pin<&'a mut BufferPool>, directory_page_id: PageId) -> Self {
+        Self {
+            bpm,
+            directory_page_id,
+            segment: None,
+        }
+    }
+
+    /// Allocates an empty index (a directory of depth 0 and one bucket) for keys of `key_size`
+    /// bytes, with its pages in file `segment`.
+    pub fn create(
+        bpm: Pin<&'a mut BufferPool>,
+        key_size: u32,
+        segment: u32,
+        page_id_counter: &AtomicU32,
+    ) -> Result<Self, HashIndexError> {
+        let mut index = Self::new(bpm, 0);
+        index.segment = Some(segment);
+        let bucket_id = index.alloc_bucket(key_size, 0, page_id_counter)?;
+
+        let dir_id = page_id_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let frame = index.alloc_page(PageKind::HashDirectory, dir_id)?;
+        if let Page::HashDirectory(mut dir) = frame.page_view() {
+            dir.init(dir_id, key_size, bucket_id);
+        }
+        let fid = frame.fid();
+        let offset = frame.file_offset();
+        index.bpm.as_mut().unpin_frame(fid).ok();
+        index.register_page(dir_id, offset, page_id_counter)?;
+
+        index.directory_page_id = dir_id;
+        Ok(index)
+    }
+
+    /// Every value stored under `key`.
+    pub fn get_values(&mut self, key: &[u8]) -> Result<Vec<u64>, HashIndexError> {
+        let bucket_id = self.bucket_for(key)?;
+        let mut values = Vec::new();
+        for page_id in self.chain(bucket_id)? {
+            values.extend(self.with_bucket(page_id, false, |b| b.get_values(key))?);
+        }
+        Ok(values)
+    }
+
+    pub fn insert(
+        &mut self,
+        key: &[u8],
+        value: u64,
+        page_id_counter: &AtomicU32,
+    ) -> Result<(), HashIndexError> {
+        let key_size = self.with_directory(false, |d| d.get_key_size())?;
+        if key.len() != key_size as usize {
+            return Err(HashIndexError::InsertError("Key size mismatch".into()));
+        }
+
+        loop {
+            let bucket_id = self.bucket_for(key)?;
+            let chain = self.chain(bucket_id)?;
+
+            let mut has_room = false;
+            // Entries with one hash, such as repeats of a key, stay together whatever
+            // the split, so only an overflow page makes room for more of them
+            let mut one_hash = true;
+            let hash = hash_key(key);
+            for &page_id in &chain {
+                let (full, same) = self.with_bucket(page_id, false, |b| {
+                    let same =
+                        (0..b.num_entries() as usize).all(|i| hash_key(b.get_key_at(i)) == hash);
+                    (b.is_full(), same)
+                })?;
+                has_room |= !full;
+                one_hash &= same;
+            }
+
+            let local_depth = self.with_bucket(bucket_id, false, |b| b.local_depth())?;
+            if has_room || one_hash || local_depth >= HashDirectory::MAX_GLOBAL_DEPTH {
+                // Once the directory cannot double any more, full buckets grow an overflow chain
+                return self.append(bucket_id, key, value, page_id_counter);
+            }
+
+            self.split_bucket(bucket_id, page_id_counter)?;
+        }
+    }
+
+    /// Removes the entry of `key` with `value` if present. Buckets are never merged back
+    /// together.
+    pub fn delete(&mut self, key: &[u8], value: u64) -> Result<(), HashIndexError> {
+        let bucket_id = self.bucket_for(key)?;
+        for page_id in self.chain(bucket_id)? {
+            if self.with_bucket(page_id, false, |b| b.get_values(key).contains(&value))? {
+                self.with_bucket(page_id, true, |b| b.remove(key, value))?;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Frees the directory and every bucket page, leaving the index empty.
+    pub fn destroy(&mut self) -> Result<(), HashIndexError> {
+        if self.directory_page_id == 0 {
+            return Ok(());
+        }
+
+        let mut buckets = self.with_directory(false, |d| {
+            (0..d.num_slots())
+                .map(|i| d.bucket_at(i))
+                .collect::<Vec<_>>()
+        })?;
+        buckets.sort_unstable();
+        buckets.dedup();
+
+        let mut pages = Vec::new();
+        for bucket_id in buckets {
+            pages.extend(self.chain(bucket_id)?);
+        }
+        pages.push(self.directory_page_id);
+
+        for page_id in pages {
+            self.bpm
+                .as_mut()
+                .free_page(page_id)
+                .map_err(HashIndexError::FreePage)?;
+        }
+
+        self.directory_page_id = 0;
+        Ok(())
+    }
+
+    /// Primary bucket page the key hashes to.
+    fn bucket_for(&mut self, key: &[u8]) -> Result<PageId, HashIndexError> {
+        let hash = hash_key(key);
+        self.with_directory(false, |d| d.bucket_at(d.slot_for(hash)))
+    }
+
+    /// The primary bucket page followed by its overflow pages.
+    fn chain(&mut self, bucket_id: PageId) -> Result<Vec<PageId>, HashIndexError> {
+        let mut pages = vec![bucket_id];
+        let mut current = bucket_id;
+        while let Some(next) = self.with_bucket(current, false, |b| b.overflow_page_id())? {
+            pages.push(next);
+            current = next;
+        }
+        Ok(pages)
+    }
+
+    /// Stores an entry in the first page of the chain with room, extending the chain if needed.
+    fn append(
+        &mut self,
+        bucket_id: PageId,
+        key: &[u8],
+        value: u64,
+        page_id_counter: &AtomicU32,
+    ) -> Result<(), HashIndexError> {
+        let chain = self.chain(bucket_id)?;
+        for &page_id in &chain {
+            if !self.with_bucket(page_id, false, |b| b.is_full())? {
+                self.with_bucket(page_id, true, |b| b.push(key, value))?;
+                return Ok(());
+            }
+        }
+
+        let tail = *chain.last().unwrap();
+        let local_depth = self.with_bucket(tail, false, |b| b.local_depth())?;
+        let overflow_id = self.alloc_bucket(key.len() as u32, local_depth, page_id_counter)?;
+        self.with_bucket(overflow_id, true, |b| b.push(key, value))?;
+        self.with_bucket(tail, true, |b| b.set_overflow_page_id(Some(overflow_id)))?;
+        Ok(())
+    }
+
+    /// Splits a full bucket on its next hash bit, doubling the directory first if the
+    /// bucket already uses every bit the directory does.
+    fn split_bucket(
+        &mut self,
+        bucket_id: PageId,
+        page_id_counter: &AtomicU32,
+    ) -> Result<(), HashIndexError> {
+        let local_depth = self.with_bucket(bucket_id, false, |b| b.local_depth())?;
+        let key_size = self.with_directory(false, |d| d.get_key_size())?;
+
+        let chain = self.chain(bucket_id)?;
+        let mut entries = Vec::new();
+        for &page_id in &chain {
+            entries.extend(self.with_bucket(page_id, false, |b| b.entries())?);
+        }
+
+        let slots = self.with_directory(true, |d| {
+            if d.global_depth() == local_depth {
+                d.grow();
+            }
+            (0..d.num_slots())
+                .filter(|&i| d.bucket_at(i) == bucket_id)
+                .collect::<Vec<_>>()
+        })?;
+
+        let new_depth = local_depth + 1;
+        let sibling_id = self.alloc_bucket(key_size, new_depth, page_id_counter)?;
+        self.with_directory(true, |d| {
+            for &slot in &slots {
+                if (slot >> local_depth) & 1 == 1 {
+                    d.set_bucket_at(slot, sibling_id);
+                }
+            }
+        })?;
+
+        self.with_bucket(bucket_id, true, |b| {
+            b.clear();
+            b.set_local_depth(new_depth);
+            b.set_overflow_page_id(None);
+        })?;
+        for &page_id in &chain[1..] {
+            self.bpm
+                .as_mut()
+                .free_page(page_id)
+                .map_err(HashIndexError::FreePage)?;
+        }
+
+        for (key, value) in entries {
+            let target = if (hash_key(&key) >> local_depth) & 1 == 1 {
+                sibling_id
+            } else {
+                bucket_id
+            };
+            self.append(target, &key, value, page_id_counter)?;
+        }
+        Ok(())
+    }
+
+    fn alloc_bucket(
+        &mut self,
+        key_size: u32,
+        local_depth: u16,
+        page_id_counter: &AtomicU32,
+    ) -> Result<PageId, HashIndexError> {
+        let page_id = page_id_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let frame = self.alloc_page(PageKind::HashBucket, page_id)?;
+        if let Page::HashBucket(mut bucket) = frame.page_view() {
+            bucket.init(page_id, key_size, local_depth);
+        }
+        let fid = frame.fid();
+        let offset = frame.file_offset();
+        self.bpm.as_mut().unpin_frame(fid).ok();
+        self.register_page(page_id, offset, page_id_counter)?;
+        Ok(page_id)
+    }
+
+    /// Allocates a page in the index's file, pinned.
+    fn alloc_page(
+        &mut self,
+        kind: PageKind,
+        page_id: PageId,
+    ) -> Result<&mut Frame, HashIndexError> {
+        let segment = match self.segment {
+            Some(segment) => segment,
+            None if self.directory_page_id == 0 => disk::MAIN_FILE_ID,
+            None => {
+                let segment = self
+                    .bpm
+                    .as_mut()
+                    .segment_of(self.directory_page_id)
+                    .map_err(HashIndexError::AllocPage)?;
+                *self.segment.insert(segment)
+            }
+        };
+        self.bpm
+            .as_mut()
+            .alloc_new_page_in(kind, page_id, segment, AccessStrategy::Normal)
+            .map_err(|e| HashIndexError::AllocPage(format!("{:?}", e)))
+    }
+
+    /// Records a freshly allocated page in the page directory so it can be located after eviction.
+    fn register_page(
+        &mut self,
+        page_id: PageId,
+        file_offset: u64,
+        page_id_counter: &AtomicU32,
+    ) -> Result<(), HashIndexError> {
+        // Index pages never hold heap tuples, so they advertise no free space.
+        self.bpm
+            .as_mut()
+            .expand_directory_and_register(page_id, file_offset, 0, page_id_counter)
+            .map_err(HashIndexError::AllocPage)
+    }
+
+    /// Runs `f` on a pinned bucket page, marking it dirty if `dirty` is set.
+    fn with_bucket<R>(
+        &mut self,
+        page_id: PageId,
+        dirty: bool,
+        f: impl FnOnce(&mut HashBucket) -> R,
+    ) -> Result<R, HashIndexError> {
+        let frame = self
+            .bpm
+            .as_mut()
+            .fetch_page(page_id, AccessStrategy::Normal)
+            .map_err(|e| HashIndexError::FetchPage(format!("{:?}", e)))?;
+        let fid = frame.fid();
+        let result = match frame.page_view() {
+            Page::HashBucket(mut bucket) => Some(f(&mut bucket)),
+            _ => None,
+        };
+        self.finish(fid, dirty && result.is_some())?;
+        result.ok_or(HashIndexError::InvalidPageType)
+    }
+
+    /// Runs `f` on the pinned directory page, marking it dirty if `dirty` is set.
+    fn with_directory<R>(
+        &mut self,
+        dirty: bool,
+        f: impl FnOnce(&mut HashDirectory) -> R,
+    ) -> Result<R, HashIndexError> {
+        if self.directory_page_id == 0 {
+            return Err(HashIndexError::InvalidPageType);
+        }
+        let frame = self
+            .bpm
+            .as_mut()
+            .fetch_page(self.directory_page_id, AccessStrategy::Normal)
+            .map_err(|e| HashIndexError::FetchPage(format!("{:?}", e)))?;
+        let fid = frame.fid();
+        let result = match frame.page_view() {
+            Page::HashDirectory(mut dir) => Some(f(&mut dir)),
+            _ => None,
+        };
+        self.finish(fid, dirty && result.is_some())?;
+        result.ok_or(HashIndexError::InvalidPageType)
+    }
+
+    fn finish(&mut self, frame_id: u32, dirty: bool) -> Result<(), HashIndexError> {
+        if dirty {
+            self.bpm.as_mut().mark_frame_dirty(frame_id);
+        }
+        self.bpm
+            .as_mut()
+            .unpin_frame(frame_id)
+            .map_err(|e| HashIndexError::UnpinPage(format!("{:?}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::buffer::fifo_evictor::FifoEvictor;
+    use crate::storage::disk::FileManager;
+    use crate::storage::page_locator;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn setup_bp(test_name: &str) -> (PathBuf, Pin<Box<BufferPool>>, AtomicU32) {
+        let file_name = format!("test_hash_index_{}.db", test_name);
+        let _ = fs::remove_file(&file_name);
+        let file_manager = FileManager::new(file_name.clone()).unwrap();
+        let evictor = Box::new(FifoEvictor::new());
+        let locator = Box::new(page_locator::locator::DirectoryPageLocator::new());
+        let mut bp = Box::pin(BufferPool::new(Box::new(file_manager), evictor, locator));
+
+        let frame = bp
+            .as_mut()
+            .alloc_new_page(PageKind::Directory, 1)
+            .expect("Failed to allocate root directory page");
+        let fid = frame.fid();
+        bp.as_mut().unpin_frame(fid).unwrap();
+
+        (PathBuf::from(file_name), bp, AtomicU32::new(1))
+    }
+
+    #[test]
+    fn test_hash_index_insert_split_delete() {
+        let (path, mut bp, counter) = setup_bp("split");
+        let mut index = HashIndex::create(bp.as_mut(), 4, disk::MAIN_FILE_ID, &counter).unwrap();
+
+        let n = 3000u32;
+        for i in 0..n {
+            index.insert(&i.to_be_bytes(), i as u64, &counter).unwrap();
+        }
+        // More keys than one bucket holds, so the directory must have doubled
+        assert!(index.with_directory(false, |d| d.global_depth()).unwrap() > 0);
+
+        for i in 0..n {
+            assert_eq!(index.get_values(&i.to_be_bytes()).unwrap(), vec![i as u64]);
+        }
+        assert!(index.get_values(&n.to_be_bytes()).unwrap().is_empty());
+
+        // A repeated key is another entry, and deleting one entry keeps the other
+        index.insert(&7u32.to_be_bytes(), 700, &counter).unwrap();
+        assert_eq!(index.get_values(&7u32.to_be_bytes()).unwrap(), vec![7, 700]);
+        index.delete(&7u32.to_be_bytes(), 7).unwrap();
+
+        for i in (0..n).step_by(2) {
+            index.delete(&i.to_be_bytes(), i as u64).unwrap();
+        }
+        for i in 0..n {
+            let expected = if i % 2 == 0 {
+                vec![]
+            } else if i == 7 {
+                vec![700]
+            } else {
+                vec![i as u64]
+            };
+            assert_eq!(index.get_values(&i.to_be_bytes()).unwrap(), expected);
+        }
+
+        index.destroy().unwrap();
+        assert_eq!(index.directory_page_id, 0);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_hash_index_overflow_chains() {
+        let (path, mut bp, counter) = setup_bp("overflow");
+        // Two entries per bucket page, so buckets overflow once the directory is maxed out
+        let key_size = 2000;
+        let mut index =
+            HashIndex::create(bp.as_mut(), key_size, disk::MAIN_FILE_ID, &counter).unwrap();
+
+        let key = |i: u32| {
+            let mut k = vec![0u8; key_size as usize];
+            k[..4].copy_from_slice(&i.to_be_bytes());
+            k
+        };
+
+        let n = 1500u32;
+        for i in 0..n {
+            index.insert(&key(i), i as u64, &counter).unwrap();
+        }
+        assert_eq!(
+            index.with_directory(false, |d| d.global_depth()).unwrap(),
+            HashDirectory::MAX_GLOBAL_DEPTH
+        );
+
+        for i in 0..n {
+            assert_eq!(index.get_values(&key(i)).unwrap(), vec![i as u64]);
+        }
+
+        index.destroy().unwrap();
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_hash_index_repeated_keys_overflow_without_splitting() {
+        let (path, mut bp, counter) = setup_bp("repeated");
+        let mut index = HashIndex::create(bp.as_mut(), 4, disk::MAIN_FILE_ID, &counter).unwrap();
+
+        // Several bucket pages worth of one key
+        let n = HashBucket::max_entries_for(4) as u64 * 3;
+        for rid in 0..n {
+            index.insert(&42u32.to_be_bytes(), rid, &counter).unwrap();
+        }
+        assert_eq!(
+            index.with_directory(false, |d| d.global_depth()).unwrap(),
+            0
+        );
+        let mut values = index.get_values(&42u32.to_be_bytes()).unwrap();
+        values.sort_unstable();
+        assert_eq!(values, (0..n).collect::<Vec<_>>());
+
+        index.destroy().unwrap();
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod extendible;
+pub use extendible::HashIndex;
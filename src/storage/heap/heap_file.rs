@@ -1,10 +1,11 @@
 use super::row::RowId;
-use crate::storage::buffer::BufferPool;
+use crate::storage::buffer::{AccessStrategy, BufferPool};
 use crate::storage::heap::iterator::HeapIterator;
 use crate::storage::page::{
     self,
     base::DiskPage,
     base::{PageId, PageKind},
+    slotted_data::{SlotKind, errors::UpdateSlotError},
 };
 use std::pin::Pin;
 use std::sync::atomic::{AtomicU32, Ordering};
@@ -15,6 +16,28 @@ pub struct HeapFile {
     pub last_page_id: PageId,
 }
 
+/// What a VACUUM pass reclaimed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VacuumStats {
+    pub pages_scanned: usize,
+    /// Space freed up inside pages that are still in use
+    pub bytes_reclaimed: u64,
+    /// Pages left empty, unlinked from their heap and put on the free list
+    pub pages_freed: usize,
+    /// Pages cut off the end of the file
+    pub pages_truncated: u64,
+}
+
+/// Where `HeapFile::update` put the new version of a tuple.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdatePlacement {
+    /// Over the old version
+    InPlace,
+    /// Somewhere else, in the same page or behind a redirect. The space of the old version
+    /// is garbage until VACUUM.
+    Relocated,
+}
+
 #[derive(Debug)]
 pub enum HeapError {
     FetchPage(String),
@@ -25,6 +48,7 @@ pub enum HeapError {
     RegisterPage(String),
     FindSpace(String),
     UpdateSpace(String),
+    FreePage(String),
 }
 
 impl HeapFile {
@@ -39,7 +63,26 @@ impl HeapFile {
         HeapIterator::new(bpm, self.first_page_id)
     }
 
+    /// Reads the tuple at `rid`, following its redirect if it has moved.
     pub fn get(mut bpm: Pin<&mut BufferPool>, rid: RowId) -> Result<Vec<u8>, HeapError> {
+        match Self::read_slot(bpm.as_mut(), rid)? {
+            (SlotKind::Tuple, data) => Ok(data),
+            (SlotKind::Redirect, data) => {
+                let target = Self::redirect_target(&data).ok_or(HeapError::InvalidPage)?;
+                match Self::read_slot(bpm, target)? {
+                    (SlotKind::Moved, data) => Ok(data),
+                    _ => Err(HeapError::InvalidPage),
+                }
+            }
+            _ => Err(HeapError::InvalidPage),
+        }
+    }
+
+    /// Reads the slot at `rid` as stored, without following a redirect.
+    fn read_slot(
+        mut bpm: Pin<&mut BufferPool>,
+        rid: RowId,
+    ) -> Result<(SlotKind, Vec<u8>), HeapError> {
         let page_id = rid.page_id();
         let slot_num = rid.slot_num() as usize;
 
@@ -58,16 +101,18 @@ impl HeapFile {
         let frame_id = frame.fid();
         let mut page_view = frame.page_view();
 
-        use crate::storage::page::base::DiskPage;
         let data = if let page::base::Page::SlottedData(slotted_page) = &mut page_view {
             if slotted_page.header().page_id() != page_id {
                 bpm.as_mut().unpin_frame(frame_id).ok();
                 return Err(HeapError::InvalidPage);
             }
-            slotted_page
-                .slot_data(slot_num)
-                .map(|bytes| bytes.to_vec())
-                .ok_or(HeapError::InvalidPage)
+            match (
+                slotted_page.slot_kind(slot_num),
+                slotted_page.slot_data(slot_num),
+            ) {
+                (Some(kind), Some(bytes)) => Ok((kind, bytes.to_vec())),
+                _ => Err(HeapError::InvalidPage),
+            }
         } else {
             Err(HeapError::InvalidPage)
         };
@@ -79,58 +124,113 @@ impl HeapFile {
         data
     }
 
+    /// Writes `data` into the live slot at `rid`, as a slot of `kind`.
+    /// Returns false, changing nothing, when its page has no room for the data.
+    fn write_slot(
+        mut bpm: Pin<&mut BufferPool>,
+        rid: RowId,
+        data: &[u8],
+        kind: SlotKind,
+    ) -> Result<bool, HeapError> {
+        let page_id = rid.page_id();
+        let frame = bpm
+            .as_mut()
+            .fetch_page(page_id, AccessStrategy::Normal)
+            .map_err(|e| HeapError::FetchPage(format!("{:?}", e)))?;
+        let frame_id = frame.fid();
+
+        let (res, free_space) = match frame.page_view() {
+            page::base::Page::SlottedData(mut slotted) => (
+                slotted.update_slot(rid.slot_num() as usize, data, kind),
+                slotted.free_space(),
+            ),
+            _ => {
+                bpm.as_mut().unpin_frame(frame_id).ok();
+                return Err(HeapError::InvalidPage);
+            }
+        };
+        let written = match res {
+            Ok(()) => true,
+            Err(UpdateSlotError::InsufficientSpace) => false,
+            Err(_) => {
+                bpm.as_mut().unpin_frame(frame_id).ok();
+                return Err(HeapError::InvalidPage);
+            }
+        };
+        if written {
+            bpm.as_mut().mark_frame_dirty(frame_id);
+            let (core, locator) = bpm.as_mut().get_core_and_locator();
+            locator
+                .update_page_free_space(page_id, free_space, core)
+                .map_err(|e| HeapError::UpdateSpace(format!("{:?}", e)))?;
+        }
+        bpm.as_mut()
+            .unpin_frame(frame_id)
+            .map_err(|e| HeapError::UnpinPage(format!("{:?}", e)))?;
+
+        Ok(written)
+    }
+
+    /// The RowId stored in a redirect slot.
+    fn redirect_target(data: &[u8]) -> Option<RowId> {
+        let bytes: [u8; 8] = data.try_into().ok()?;
+        Some(RowId::from_u64(u64::from_le_bytes(bytes)))
+    }
+
+    /// Appends `data` to the heap. Bulk loads pass a ring so the pages they fill
+    /// do not push the rest of the pool out.
     pub fn insert(
         &mut self,
         mut bpm: Pin<&mut BufferPool>,
         page_id_counter: &AtomicU32,
         data: &[u8],
+        mut strategy: AccessStrategy,
     ) -> Result<RowId, HeapError> {
         let required_space =
             data.len() as u32 + page::slotted_data::SlottedData::SLOT_META_SIZE as u32;
 
         let mut insert_page_id = 0;
 
-        // --- A. Check Last Page (Prioritize last page in chain via direct fetch) ---
-        if self.last_page_id != 0 {
-            let last_page_id = self.last_page_id;
-            let fetch_result = bpm.as_mut().fetch_page(last_page_id);
-
-            if let Ok(frame) = fetch_result {
-                let frame_id = frame.fid();
-                let page_view = frame.page_view();
-
-                let space = if let page::base::Page::SlottedData(slotted) = page_view {
-                    slotted.free_space()
-                } else {
-                    0
-                };
-
-                bpm.as_mut().unpin_frame(frame_id).ok();
+        // --- A. Walk the chain from the last known page for one with room ---
+        // The directory tracks free space too, but for every page in the file, so a page it
+        // offers may belong to another heap. Walking also finds the real tail to link onto.
+        let mut page_id = if self.last_page_id != 0 {
+            self.last_page_id
+        } else {
+            self.first_page_id
+        };
+        while page_id != 0 {
+            let frame = bpm
+                .as_mut()
+                .fetch_page(page_id, strategy.reborrow())
+                .map_err(|e| HeapError::FetchPage(format!("{:?}", e)))?;
+            let frame_id = frame.fid();
 
-                if space >= required_space {
-                    insert_page_id = last_page_id;
+            let (space, next_page_id) = match frame.page_view() {
+                page::base::Page::SlottedData(slotted) => {
+                    (slotted.free_space(), slotted.header().next_page_id())
                 }
-            }
-        }
-
-        // --- B. Search Reusable Pages (Run 18 Logic: Directory Check) ---
-        if insert_page_id == 0 {
-            let (core, locator) = bpm.as_mut().get_core_and_locator();
-            let reusable_id_opt: Option<PageId> = locator
-                .find_page_with_space(required_space, core)
-                .map_err(|e| HeapError::FindSpace(format!("{:?}", e)))?;
+                _ => {
+                    bpm.as_mut().unpin_frame(frame_id).ok();
+                    return Err(HeapError::InvalidPage);
+                }
+            };
+            bpm.as_mut().unpin_frame(frame_id).ok();
 
-            if let Some(reusable_id) = reusable_id_opt {
-                insert_page_id = reusable_id;
+            if space >= required_space {
+                insert_page_id = page_id;
+                break;
             }
+            self.last_page_id = page_id;
+            page_id = next_page_id;
         }
 
-        // --- C. Execute Insert on Existing Page (Reusable Page or Last Page) ---
+        // --- B. Execute Insert on Existing Page ---
         if insert_page_id != 0 {
             let page_id = insert_page_id;
             let frame = bpm
                 .as_mut()
-                .fetch_page(page_id)
+                .fetch_page(page_id, strategy.reborrow())
                 .map_err(|e| HeapError::FetchPage(format!("{:?}", e)))?;
             let frame_id = frame.fid();
 
@@ -158,14 +258,20 @@ impl HeapFile {
             return Ok(RowId::new(page_id, slot_num as u32));
         }
 
-        // --- D. ALLOCATION FALLBACK (New Page) ---
+        // --- C. ALLOCATION FALLBACK (New Page) ---
 
-        // Allocate a new page (Fallback - only reached if all existing pages are full)
+        // Allocate a new page (Fallback - only reached if every page in the chain is full)
+        // in the same file as the rest of the heap
         let new_page_id = page_id_counter.fetch_add(1, Ordering::SeqCst) + 1;
 
         let frame = bpm
             .as_mut()
-            .alloc_new_page(PageKind::SlottedData, new_page_id)
+            .alloc_new_page_near(
+                PageKind::SlottedData,
+                new_page_id,
+                self.first_page_id,
+                strategy.reborrow(),
+            )
             .map_err(|e| HeapError::AllocPage(format!("{:?}", e)))?;
 
         let new_frame_id = frame.fid();
@@ -203,7 +309,7 @@ impl HeapFile {
         if self.last_page_id != 0 {
             let prev_frame = bpm
                 .as_mut()
-                .fetch_page(self.last_page_id)
+                .fetch_page(self.last_page_id, strategy)
                 .map_err(|e| HeapError::FetchPage(format!("{:?}", e)))?;
             let prev_fid = prev_frame.fid();
 
@@ -237,6 +343,89 @@ impl HeapFile {
         Ok(RowId::new(new_page_id, slot_num as u32))
     }
 
+    /// Replaces the tuple at `rid` with `data`, keeping its RowId so indexes stay valid.
+    /// A tuple that no longer fits its page moves to another one and leaves a redirect
+    /// behind; a moved tuple that fits back into its home page returns there.
+    pub fn update(
+        &mut self,
+        mut bpm: Pin<&mut BufferPool>,
+        page_id_counter: &AtomicU32,
+        rid: RowId,
+        data: &[u8],
+    ) -> Result<UpdatePlacement, HeapError> {
+        let (kind, old) = Self::read_slot(bpm.as_mut(), rid)?;
+        match kind {
+            SlotKind::Tuple => {
+                if Self::write_slot(bpm.as_mut(), rid, data, SlotKind::Tuple)? {
+                    return Ok(if data.len() <= old.len() {
+                        UpdatePlacement::InPlace
+                    } else {
+                        UpdatePlacement::Relocated
+                    });
+                }
+                let target = self.insert_moved(bpm.as_mut(), page_id_counter, data)?;
+                let redirect = target.to_u64().to_le_bytes();
+                if !Self::write_slot(bpm.as_mut(), rid, &redirect, SlotKind::Redirect)? {
+                    // A tuple shorter than a RowId, on a page without a byte to spare
+                    self.delete(bpm, target)?;
+                    return Err(HeapError::AddSlot("No room for a redirect".to_string()));
+                }
+                Ok(UpdatePlacement::Relocated)
+            }
+            SlotKind::Redirect => {
+                let target = Self::redirect_target(&old).ok_or(HeapError::InvalidPage)?;
+                if Self::write_slot(bpm.as_mut(), rid, data, SlotKind::Tuple)? {
+                    self.delete(bpm, target)?;
+                    return Ok(UpdatePlacement::Relocated);
+                }
+                let (_, moved) = Self::read_slot(bpm.as_mut(), target)?;
+                if Self::write_slot(bpm.as_mut(), target, data, SlotKind::Moved)? {
+                    return Ok(if data.len() <= moved.len() {
+                        UpdatePlacement::InPlace
+                    } else {
+                        UpdatePlacement::Relocated
+                    });
+                }
+                // Point the redirect straight at the new copy, so there are never chains
+                let new_target = self.insert_moved(bpm.as_mut(), page_id_counter, data)?;
+                let redirect = new_target.to_u64().to_le_bytes();
+                if !Self::write_slot(bpm.as_mut(), rid, &redirect, SlotKind::Redirect)? {
+                    return Err(HeapError::InvalidPage);
+                }
+                self.delete(bpm, target)?;
+                Ok(UpdatePlacement::Relocated)
+            }
+            _ => Err(HeapError::InvalidPage),
+        }
+    }
+
+    /// Inserts the new home of a tuple that outgrew its page.
+    fn insert_moved(
+        &mut self,
+        mut bpm: Pin<&mut BufferPool>,
+        page_id_counter: &AtomicU32,
+        data: &[u8],
+    ) -> Result<RowId, HeapError> {
+        let rid = self.insert(bpm.as_mut(), page_id_counter, data, AccessStrategy::Normal)?;
+        let frame = bpm
+            .as_mut()
+            .fetch_page(rid.page_id(), AccessStrategy::Normal)
+            .map_err(|e| HeapError::FetchPage(format!("{:?}", e)))?;
+        let frame_id = frame.fid();
+        let res = match frame.page_view() {
+            page::base::Page::SlottedData(mut slotted) => slotted
+                .set_slot_kind(rid.slot_num() as usize, SlotKind::Moved)
+                .map_err(|_| HeapError::InvalidPage),
+            _ => Err(HeapError::InvalidPage),
+        };
+        bpm.as_mut().mark_frame_dirty(frame_id);
+        bpm.as_mut()
+            .unpin_frame(frame_id)
+            .map_err(|e| HeapError::UnpinPage(format!("{:?}", e)))?;
+
+        res.map(|_| rid)
+    }
+
     // --- HeapFile::delete update to include Directory update ---
     pub fn delete(&mut self, mut bpm: Pin<&mut BufferPool>, rid: RowId) -> Result<(), HeapError> {
         let page_id = rid.page_id();
@@ -244,19 +433,26 @@ impl HeapFile {
 
         let frame = bpm
             .as_mut()
-            .fetch_page(page_id)
+            .fetch_page(page_id, AccessStrategy::Normal)
             .map_err(|e| HeapError::FetchPage(format!("{:?}", e)))?;
         let frame_id = frame.fid();
 
-        let (res, new_free_space) = {
+        let (res, new_free_space, moved_to) = {
             let mut page_view = frame.page_view();
             if let page::base::Page::SlottedData(slotted) = &mut page_view {
+                // A moved tuple goes with its redirect
+                let moved_to = match slotted.slot_kind(slot_num as usize) {
+                    Some(SlotKind::Redirect) => slotted
+                        .slot_data(slot_num as usize)
+                        .and_then(Self::redirect_target),
+                    _ => None,
+                };
                 let result = slotted
                     .mark_dead(slot_num as usize) // Mark slot dead (tombstone)
                     .map_err(|_| HeapError::InvalidPage);
-                (result, slotted.free_space())
+                (result, slotted.free_space(), moved_to)
             } else {
-                (Err(HeapError::InvalidPage), 0)
+                (Err(HeapError::InvalidPage), 0, None)
             }
         };
 
@@ -276,8 +472,184 @@ impl HeapFile {
             .unpin_frame(frame_id)
             .map_err(|e| HeapError::UnpinPage(format!("{:?}", e)))?;
 
+        if res.is_ok()
+            && let Some(target) = moved_to
+        {
+            self.delete(bpm, target)?;
+        }
+        res
+    }
+
+    /// Replaces the tuple at `rid` with `data` of the same length, without moving it.
+    pub fn overwrite(
+        mut bpm: Pin<&mut BufferPool>,
+        rid: RowId,
+        data: &[u8],
+    ) -> Result<(), HeapError> {
+        let frame = bpm
+            .as_mut()
+            .fetch_page(rid.page_id(), AccessStrategy::Normal)
+            .map_err(|e| HeapError::FetchPage(format!("{:?}", e)))?;
+        let frame_id = frame.fid();
+
+        let res = match frame.page_view() {
+            page::base::Page::SlottedData(mut slotted) => {
+                match slotted.slot_data_mut(rid.slot_num() as usize) {
+                    Some(bytes) if bytes.len() == data.len() => {
+                        bytes.copy_from_slice(data);
+                        Ok(())
+                    }
+                    _ => Err(HeapError::InvalidPage),
+                }
+            }
+            _ => Err(HeapError::InvalidPage),
+        };
+        if res.is_ok() {
+            bpm.as_mut().mark_frame_dirty(frame_id);
+        }
+        bpm.as_mut()
+            .unpin_frame(frame_id)
+            .map_err(|e| HeapError::UnpinPage(format!("{:?}", e)))?;
+
         res
     }
+
+    /// Compacts every page of the heap and frees the ones left empty.
+    /// Tuples keep their slots, so RowIds held by indexes stay valid. The first page is
+    /// kept even when empty since the catalog refers to it.
+    pub fn vacuum(&self, mut bpm: Pin<&mut BufferPool>) -> Result<VacuumStats, HeapError> {
+        let mut stats = VacuumStats::default();
+        let mut prev_page_id = 0;
+        let mut page_id = self.first_page_id;
+
+        while page_id != 0 {
+            (page_id, prev_page_id) =
+                self.vacuum_page(bpm.as_mut(), prev_page_id, page_id, &mut stats)?;
+        }
+
+        Ok(stats)
+    }
+
+    /// Vacuums the single page `page_id`, whose predecessor in the chain is `prev_page_id`
+    /// (0 for the first page). Returns the next page to visit and its predecessor, so a
+    /// caller can walk the heap a page at a time and let go of the pool in between.
+    /// An empty page is only unlinked when its back link still names `prev_page_id`.
+    pub fn vacuum_page(
+        &self,
+        mut bpm: Pin<&mut BufferPool>,
+        prev_page_id: PageId,
+        page_id: PageId,
+        stats: &mut VacuumStats,
+    ) -> Result<(PageId, PageId), HeapError> {
+        let frame = bpm
+            .as_mut()
+            .fetch_page(page_id, AccessStrategy::Normal)
+            .map_err(|e| HeapError::FetchPage(format!("{:?}", e)))?;
+        let frame_id = frame.fid();
+
+        let (reclaimed, empty, free_space, linked_prev, next_page_id) = match frame.page_view() {
+            page::base::Page::SlottedData(mut slotted) => {
+                let reclaimed = slotted.compact();
+                (
+                    reclaimed,
+                    slotted.num_slots() == 0,
+                    slotted.free_space(),
+                    slotted.header().prev_page_id(),
+                    slotted.header().next_page_id(),
+                )
+            }
+            _ => {
+                bpm.as_mut().unpin_frame(frame_id).ok();
+                return Err(HeapError::InvalidPage);
+            }
+        };
+        if reclaimed > 0 {
+            bpm.as_mut().mark_frame_dirty(frame_id);
+        }
+        bpm.as_mut()
+            .unpin_frame(frame_id)
+            .map_err(|e| HeapError::UnpinPage(format!("{:?}", e)))?;
+        stats.pages_scanned += 1;
+
+        if empty && prev_page_id != 0 && linked_prev == prev_page_id {
+            Self::unlink(bpm.as_mut(), prev_page_id, next_page_id)?;
+            bpm.as_mut()
+                .free_page(page_id)
+                .map_err(HeapError::FreePage)?;
+            stats.pages_freed += 1;
+            return Ok((next_page_id, prev_page_id));
+        }
+
+        if reclaimed > 0 {
+            stats.bytes_reclaimed += reclaimed as u64;
+            let (core, locator) = bpm.as_mut().get_core_and_locator();
+            locator
+                .update_page_free_space(page_id, free_space, core)
+                .map_err(|e| HeapError::UpdateSpace(format!("{:?}", e)))?;
+        }
+        Ok((next_page_id, page_id))
+    }
+
+    /// Links `prev_page_id` and `next_page_id` to each other, dropping the page between them.
+    fn unlink(
+        mut bpm: Pin<&mut BufferPool>,
+        prev_page_id: PageId,
+        next_page_id: PageId,
+    ) -> Result<(), HeapError> {
+        let links = [
+            (prev_page_id, next_page_id, true),
+            (next_page_id, prev_page_id, false),
+        ];
+        for (page_id, link, forward) in links {
+            if page_id == 0 {
+                continue;
+            }
+            let frame = bpm
+                .as_mut()
+                .fetch_page(page_id, AccessStrategy::Normal)
+                .map_err(|e| HeapError::FetchPage(format!("{:?}", e)))?;
+            let frame_id = frame.fid();
+            {
+                let mut page_view = frame.page_view();
+                let header = page_view.header_mut();
+                if forward {
+                    header.set_next_page_id(link);
+                } else {
+                    header.set_prev_page_id(link);
+                }
+            }
+            bpm.as_mut().mark_frame_dirty(frame_id);
+            bpm.as_mut()
+                .unpin_frame(frame_id)
+                .map_err(|e| HeapError::UnpinPage(format!("{:?}", e)))?;
+        }
+        Ok(())
+    }
+
+    /// Gives every page of the heap back to the free list, for when its table goes away.
+    pub fn free_pages(&self, mut bpm: Pin<&mut BufferPool>) -> Result<(), HeapError> {
+        let mut pages = Vec::new();
+        let mut page_id = self.first_page_id;
+        while page_id != 0 {
+            let frame = bpm
+                .as_mut()
+                .fetch_page(page_id, AccessStrategy::Normal)
+                .map_err(|e| HeapError::FetchPage(format!("{:?}", e)))?;
+            let frame_id = frame.fid();
+            let next_page_id = frame.page_view().header().next_page_id();
+            bpm.as_mut().unpin_frame(frame_id).ok();
+
+            pages.push(page_id);
+            page_id = next_page_id;
+        }
+
+        for page_id in pages {
+            bpm.as_mut()
+                .free_page(page_id)
+                .map_err(HeapError::FreePage)?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -296,7 +668,7 @@ mod tests {
         let file_manager = FileManager::new(file_name.clone()).unwrap();
         let evictor = Box::new(FifoEvictor::new());
         let locator = Box::new(DirectoryPageLocator::new());
-        let mut bp = Box::pin(BufferPool::new(file_manager, evictor, locator));
+        let mut bp = Box::pin(BufferPool::new(Box::new(file_manager), evictor, locator));
 
         let dir_page_id = 1;
         let frame = bp
@@ -323,7 +695,7 @@ mod tests {
 
         // 1. Insert -> Allocates New Page (ID 2)
         let rid1 = heap
-            .insert(bp.as_mut(), &counter, &data_small)
+            .insert(bp.as_mut(), &counter, &data_small, AccessStrategy::Normal)
             .expect("Insert 1 failed");
 
         let page1_id = rid1.page_id();
@@ -336,7 +708,7 @@ mod tests {
 
         // 2. Insert again -> Should reuse Page 2
         let rid2 = heap
-            .insert(bp.as_mut(), &counter, &data_small)
+            .insert(bp.as_mut(), &counter, &data_small, AccessStrategy::Normal)
             .expect("Insert 2 failed");
         let page2_id = rid2.page_id();
 
@@ -348,4 +720,202 @@ mod tests {
 
         defer_delete(&path);
     }
+
+    #[test]
+    fn test_heap_vacuum() {
+        let (path, mut bp, counter) = setup_heap_test("vacuum");
+        let mut heap = HeapFile::new(0, 0);
+
+        let rows: Vec<(RowId, Vec<u8>)> = (0..150u8)
+            .map(|i| {
+                let data = vec![i; 100];
+                let rid = heap
+                    .insert(bp.as_mut(), &counter, &data, AccessStrategy::Normal)
+                    .unwrap();
+                (rid, data)
+            })
+            .collect();
+        let pages: Vec<PageId> = rows.iter().map(|(rid, _)| rid.page_id()).collect();
+        let (first, second, last) = (pages[0], pages[50], pages[149]);
+        assert!(
+            first != second && second != last,
+            "Rows should span several pages"
+        );
+
+        // Empty the second page, thin out the first
+        let mut kept = Vec::new();
+        for (i, (rid, data)) in rows.iter().enumerate() {
+            let page_id = rid.page_id();
+            if page_id == second || (page_id == first && i % 2 == 0) {
+                heap.delete(bp.as_mut(), *rid).unwrap();
+            } else {
+                kept.push((*rid, data.clone()));
+            }
+        }
+
+        let stats = heap.vacuum(bp.as_mut()).unwrap();
+        assert_eq!(stats.pages_freed, 1);
+        assert!(stats.bytes_reclaimed >= 100 * 15);
+        // The emptied page sits in the middle of the file, so nothing can be cut off yet
+        assert_eq!(bp.as_mut().trim_free_pages().unwrap(), 0);
+
+        // Rows keep their RowIds and the chain skips the freed page
+        for (rid, data) in &kept {
+            assert_eq!(&HeapFile::get(bp.as_mut(), *rid).unwrap(), data);
+        }
+        let mut scanned = Vec::new();
+        let mut iter = heap.scan(bp.as_mut());
+        while let Some(row) = iter.next() {
+            scanned.push(row.unwrap().0);
+        }
+        assert_eq!(
+            scanned,
+            kept.iter().map(|(rid, _)| *rid).collect::<Vec<_>>()
+        );
+
+        // Emptying the last page lets the file shrink
+        let size_before = fs::metadata(&path).unwrap().len();
+        for (rid, _) in kept.iter().filter(|(rid, _)| rid.page_id() == last) {
+            heap.delete(bp.as_mut(), *rid).unwrap();
+        }
+        assert_eq!(heap.vacuum(bp.as_mut()).unwrap().pages_freed, 1);
+        assert!(bp.as_mut().trim_free_pages().unwrap() >= 1);
+        assert!(fs::metadata(&path).unwrap().len() < size_before);
+
+        defer_delete(&path);
+    }
+
+    #[test]
+    fn test_heap_update_keeps_row_id() {
+        let (path, mut bp, counter) = setup_heap_test("update");
+        let mut heap = HeapFile::new(0, 0);
+
+        // 38 rows of 100 bytes leave 112 bytes free on the first page
+        let mut rows: Vec<(RowId, Vec<u8>)> = (0..38u8)
+            .map(|i| {
+                let data = vec![i; 100];
+                let rid = heap
+                    .insert(bp.as_mut(), &counter, &data, AccessStrategy::Normal)
+                    .unwrap();
+                (rid, data)
+            })
+            .collect();
+        let home = rows[0].0.page_id();
+        assert!(rows.iter().all(|(rid, _)| rid.page_id() == home));
+        heap.first_page_id = home;
+        heap.last_page_id = home;
+
+        fn update(
+            heap: &mut HeapFile,
+            rows: &mut [(RowId, Vec<u8>)],
+            bp: Pin<&mut BufferPool>,
+            counter: &AtomicU32,
+            i: usize,
+            data: Vec<u8>,
+        ) -> UpdatePlacement {
+            let placement = heap.update(bp, counter, rows[i].0, &data).unwrap();
+            rows[i].1 = data;
+            placement
+        }
+
+        // Same size, smaller, then larger but still fitting the page
+        let placement = update(
+            &mut heap,
+            &mut rows,
+            bp.as_mut(),
+            &counter,
+            0,
+            vec![200; 100],
+        );
+        assert_eq!(placement, UpdatePlacement::InPlace);
+        let placement = update(
+            &mut heap,
+            &mut rows,
+            bp.as_mut(),
+            &counter,
+            0,
+            vec![201; 60],
+        );
+        assert_eq!(placement, UpdatePlacement::InPlace);
+        let placement = update(
+            &mut heap,
+            &mut rows,
+            bp.as_mut(),
+            &counter,
+            1,
+            vec![202; 110],
+        );
+        assert_eq!(placement, UpdatePlacement::Relocated);
+
+        // Too big for the page: the tuple moves behind a redirect
+        let placement = update(
+            &mut heap,
+            &mut rows,
+            bp.as_mut(),
+            &counter,
+            2,
+            vec![203; 300],
+        );
+        assert_eq!(placement, UpdatePlacement::Relocated);
+        // The moved copy shrinks where it is
+        let placement = update(
+            &mut heap,
+            &mut rows,
+            bp.as_mut(),
+            &counter,
+            2,
+            vec![204; 250],
+        );
+        assert_eq!(placement, UpdatePlacement::InPlace);
+
+        let check = |bp: &mut Pin<Box<BufferPool>>, rows: &[(RowId, Vec<u8>)]| {
+            for (rid, data) in rows {
+                assert_eq!(&HeapFile::get(bp.as_mut(), *rid).unwrap(), data);
+            }
+            let mut scanned = Vec::new();
+            let mut iter = HeapIterator::new(bp.as_mut(), home);
+            while let Some(row) = iter.next() {
+                scanned.push(row.unwrap());
+            }
+            assert_eq!(scanned, rows.to_vec(), "Each row once, under its own RowId");
+        };
+        check(&mut bp, &rows);
+
+        // With room made on the home page the tuple comes back
+        for (rid, _) in rows.drain(30..) {
+            heap.delete(bp.as_mut(), rid).unwrap();
+        }
+        heap.vacuum(bp.as_mut()).unwrap();
+        let placement = update(
+            &mut heap,
+            &mut rows,
+            bp.as_mut(),
+            &counter,
+            2,
+            vec![205; 120],
+        );
+        assert_eq!(placement, UpdatePlacement::Relocated);
+        check(&mut bp, &rows);
+        // The page it had moved to is empty again
+        assert_eq!(heap.vacuum(bp.as_mut()).unwrap().pages_freed, 1);
+        heap.last_page_id = home; // The catalog starts every heap at its first page
+
+        // Deleting a moved tuple through its RowId removes both slots
+        let placement = update(
+            &mut heap,
+            &mut rows,
+            bp.as_mut(),
+            &counter,
+            3,
+            vec![206; 1500],
+        );
+        assert_eq!(placement, UpdatePlacement::Relocated);
+        let (rid, _) = rows.remove(3);
+        heap.delete(bp.as_mut(), rid).unwrap();
+        assert!(HeapFile::get(bp.as_mut(), rid).is_err());
+        check(&mut bp, &rows);
+        assert_eq!(heap.vacuum(bp.as_mut()).unwrap().pages_freed, 1);
+
+        defer_delete(&path);
+    }
 }
@@ -1,9 +1,11 @@
 use crate::storage::bplus_tree::BPlusTree;
-use crate::storage::buffer::BufferPool;
-use crate::storage::heap::heap_file::HeapError;
+use crate::storage::buffer::{AccessStrategy, BufferPool};
+use crate::storage::heap::heap_file::{HeapError, HeapFile};
 use crate::storage::heap::row::RowId;
 use crate::storage::page::base::DiskPage;
 use crate::storage::page::base::{Page, PageId};
+use crate::storage::page::slotted_data::SlotKind;
+use std::ops::Bound;
 use std::pin::Pin;
 
 pub struct HeapIterator<'a> {
@@ -22,14 +24,18 @@ impl<'a> HeapIterator<'a> {
         }
     }
 
-    /// Advances the iterator and returns the next tuple as bytes
+    /// Advances the iterator and returns the next tuple as bytes.
+    /// A moved tuple is returned where its redirect is, under the RowId it always had.
     pub fn next(&mut self) -> Option<Result<(RowId, Vec<u8>), HeapError>> {
         loop {
             if self.current_page_id == 0 {
                 return None;
             }
 
-            let frame_result = self.bpm.as_mut().fetch_page(self.current_page_id);
+            let frame_result = self
+                .bpm
+                .as_mut()
+                .fetch_page(self.current_page_id, AccessStrategy::Normal);
             if let Err(e) = frame_result {
                 return Some(Err(HeapError::FetchPage(format!("{:?}", e))));
             }
@@ -38,7 +44,7 @@ impl<'a> HeapIterator<'a> {
             let frame_id = frame.fid();
 
             let mut next_page_id = 0;
-            let mut found_data = None;
+            let mut found_data = None; // Some(None) for a redirect still to follow
             let mut found_slot_num = 0; // Track the slot number
 
             {
@@ -51,11 +57,14 @@ impl<'a> HeapIterator<'a> {
                         let idx = self.current_slot_index as usize;
                         self.current_slot_index += 1;
 
-                        if let Some(data) = slotted.slot_data(idx) {
-                            found_data = Some(data.to_vec());
-                            found_slot_num = idx as u32; // Capture slot
-                            break;
-                        }
+                        let data = match slotted.slot_kind(idx) {
+                            Some(SlotKind::Tuple) => slotted.slot_data(idx).map(|d| d.to_vec()),
+                            Some(SlotKind::Redirect) => None,
+                            _ => continue,
+                        };
+                        found_data = Some(data);
+                        found_slot_num = idx as u32; // Capture slot
+                        break;
                     }
 
                     if found_data.is_none() {
@@ -74,7 +83,10 @@ impl<'a> HeapIterator<'a> {
             if let Some(data) = found_data {
                 // Construct RowId
                 let rid = RowId::new(self.current_page_id, found_slot_num);
-                return Some(Ok((rid, data)));
+                return match data {
+                    Some(data) => Some(Ok((rid, data))),
+                    None => Some(HeapFile::get(self.bpm.as_mut(), rid).map(|data| (rid, data))),
+                };
             }
 
             self.current_page_id = next_page_id;
@@ -83,178 +95,234 @@ impl<'a> HeapIterator<'a> {
     }
 }
 
+/// Where a new `BTreeIterator` places its cursor.
+enum Seek<'k> {
+    First,
+    Key(&'k [u8]),
+    Last,
+}
+
+/// A cursor over the leaf chain. It sits between two entries: `next` returns the one after
+/// it and `prev` the one before it, so the two can be mixed freely.
 pub struct BTreeIterator<'a> {
     bpm: Pin<&'a mut BufferPool>,
     current_page_id: PageId,
-    current_idx: u16,
+    current_idx: u16, // u16::MAX stands for "after the last entry" of a leaf not read yet
+    lower: Bound<Vec<u8>>,
+    upper: Bound<Vec<u8>>,
 }
 
 impl<'a> BTreeIterator<'a> {
     /// Initialize iterator starting at the leaf containing `start_key`.
     /// If `start_key` is None, it starts at the left-most leaf (Full Scan).
+    /// The cursor lands before the first key >= `start_key`, so `prev` from here walks
+    /// the keys below it, largest first.
     pub fn new(tree: BPlusTree<'a>, start_key: Option<&[u8]>) -> Self {
-        // 1. Destructure the tree to get raw access to BPM and Root
-        // We do this to avoid borrowing `tree` (which would lock BPM) while trying to use BPM.
-        let BPlusTree {
-            mut bpm,
-            root_page_id,
-        } = tree;
-
-        if root_page_id == 0 {
-            return Self {
-                bpm,
-                current_page_id: 0,
-                current_idx: 0,
-            };
+        match start_key {
+            Some(key) => Self::seek(tree, Seek::Key(key)),
+            None => Self::seek(tree, Seek::First),
         }
+    }
 
-        // 2. Find the starting PageId
-        // We must manually traverse here because we decomposed the Tree struct.
-        let mut current_page_id = root_page_id;
+    /// Initialize iterator after the last entry in the tree, for walking it backwards with `prev`.
+    pub fn last(tree: BPlusTree<'a>) -> Self {
+        Self::seek(tree, Seek::Last)
+    }
+
+    fn seek(tree: BPlusTree<'a>, target: Seek) -> Self {
+        // Iterators walk the leaves on their own, with the pool the tree borrowed
+        let root_page_id = tree.root_page_id();
+        let mut bpm = tree
+            .into_pool()
+            .expect("Only trees over a borrowed pool can be iterated");
 
         // Traversal Loop (Root -> Leaf)
-        loop {
-            // Fetch Page
-            let frame = bpm.as_mut().fetch_page(current_page_id);
-            if frame.is_err() {
-                // If we can't fetch the page (IO Error), we return an empty iterator
-                // In a real impl, we might want to return Result<Self>, but Iterator::new is usually infallible
-                return Self {
-                    bpm,
-                    current_page_id: 0,
-                    current_idx: 0,
-                };
-            }
-            let frame = frame.unwrap();
+        let mut current_page_id = root_page_id;
+        let mut current_idx = 0;
+        while current_page_id != 0 {
+            // If we can't fetch a page (IO Error) the iterator is empty. Iterator::new is
+            // infallible, so there is nowhere to report it.
+            let Ok(frame) = bpm
+                .as_mut()
+                .fetch_page(current_page_id, AccessStrategy::Normal)
+            else {
+                current_page_id = 0;
+                break;
+            };
             let frame_id = frame.fid();
-            let page_view = frame.page_view();
 
-            let next_child = match page_view {
+            let mut child = None;
+            match frame.page_view() {
                 Page::BPlusInner(inner) => {
-                    if let Some(key) = start_key {
-                        // Point Query / Range Scan: Find child for key
-                        let idx = inner.find_child_for_key(key);
-                        inner.get_child_at(idx)
-                    } else {
-                        // Full Scan: Always go Left (Child 0)
-                        inner.get_child_at(0)
-                    }
+                    let idx = match target {
+                        Seek::First => 0,
+                        Seek::Key(key) => inner.find_child_for_key(key),
+                        Seek::Last => inner.num_entries() as usize,
+                    };
+                    child = Some(inner.get_child_at(idx).unwrap_or(0));
                 }
-                Page::BPlusLeaf(_) => {
-                    // We reached the leaf
-                    None
+                Page::BPlusLeaf(leaf) => {
+                    let n = leaf.num_entries();
+                    current_idx = match target {
+                        Seek::First => 0,
+                        Seek::Key(key) => (0..n)
+                            .find(|&i| leaf.get_key_at(i as usize).as_slice() >= key)
+                            .unwrap_or(n),
+                        Seek::Last => n,
+                    };
                 }
-                _ => None, // Invalid page type
-            };
+                _ => current_page_id = 0, // Invalid page type
+            }
 
-            // Unpin current page before moving down
             bpm.as_mut().unpin_frame(frame_id).ok();
 
-            if let Some(child_id) = next_child {
-                current_page_id = child_id;
-            } else {
-                // We are at the leaf (or error), break loop
-                break;
+            match child {
+                Some(child_id) => current_page_id = child_id,
+                None => break,
             }
         }
 
-        // 3. Find the Starting Index within the Leaf
-        let mut idx = 0;
-        if current_page_id != 0 {
-            if let Ok(frame) = bpm.as_mut().fetch_page(current_page_id) {
-                let view = frame.page_view();
-                if let Page::BPlusLeaf(leaf) = view {
-                    if let Some(key) = start_key {
-                        for i in 0..leaf.num_entries() {
-                            if leaf.get_key_at(i as usize) >= key {
-                                idx = i;
-                                break;
-                            }
-                        }
-
-                        if idx == 0 && leaf.num_entries() > 0 && leaf.get_key_at(0) < key {
-                            let mut found = false;
-                            for i in 0..leaf.num_entries() {
-                                if leaf.get_key_at(i as usize) >= key {
-                                    idx = i;
-                                    found = true;
-                                    break;
-                                }
-                            }
-                            if !found {
-                                idx = leaf.num_entries();
-                            }
-                        }
-                    } else {
-                        // Full scan: start at 0
-                        idx = 0;
-                    }
-                }
-                let fid = frame.fid();
-                bpm.as_mut().unpin_frame(fid).ok();
-            }
+        Self {
+            bpm,
+            current_page_id,
+            current_idx,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
         }
+    }
 
+    /// Recreates an iterator at a position previously returned by `position`.
+    /// Lets callers that only borrow the pool per call (executors) keep their place.
+    /// Bounds are not part of the position and have to be set again.
+    pub fn resume(bpm: Pin<&'a mut BufferPool>, position: (PageId, u16)) -> Self {
         Self {
             bpm,
-            current_page_id,
-            current_idx: idx,
+            current_page_id: position.0,
+            current_idx: position.1,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
         }
     }
 
+    /// Ends forward iteration: `next` returns None instead of a key past `bound`.
+    pub fn with_upper_bound(mut self, bound: Bound<Vec<u8>>) -> Self {
+        self.upper = bound;
+        self
+    }
+
+    /// Ends backward iteration: `prev` returns None instead of a key before `bound`.
+    pub fn with_lower_bound(mut self, bound: Bound<Vec<u8>>) -> Self {
+        self.lower = bound;
+        self
+    }
+
+    /// The (leaf page, entry index) the cursor is at.
+    pub fn position(&self) -> (PageId, u16) {
+        (self.current_page_id, self.current_idx)
+    }
+
     /// Returns the next (Key, Value) pair in the tree.
     pub fn next(&mut self) -> Option<(Vec<u8>, u64)> {
+        self.next_with_payload().map(|(key, val, _)| (key, val))
+    }
+
+    /// Returns the next (Key, Value, Payload) entry in the tree.
+    /// The payload is empty unless the index stores INCLUDE columns.
+    pub fn next_with_payload(&mut self) -> Option<(Vec<u8>, u64, Vec<u8>)> {
+        self.step(true)
+    }
+
+    /// Returns the previous (Key, Value) pair in the tree.
+    pub fn prev(&mut self) -> Option<(Vec<u8>, u64)> {
+        self.prev_with_payload().map(|(key, val, _)| (key, val))
+    }
+
+    /// Returns the previous (Key, Value, Payload) entry in the tree.
+    pub fn prev_with_payload(&mut self) -> Option<(Vec<u8>, u64, Vec<u8>)> {
+        self.step(false)
+    }
+
+    fn step(&mut self, forward: bool) -> Option<(Vec<u8>, u64, Vec<u8>)> {
         loop {
             if self.current_page_id == 0 {
                 return None;
             }
 
-            let frame_res = self.bpm.as_mut().fetch_page(self.current_page_id);
-            if frame_res.is_err() {
-                return None;
-            }
-            let frame = frame_res.unwrap();
+            let frame = self
+                .bpm
+                .as_mut()
+                .fetch_page(self.current_page_id, AccessStrategy::Normal)
+                .ok()?;
             let frame_id = frame.fid();
 
+            // (entry, cursor index after reading it)
             let mut result = None;
             let mut jump_to_sibling = None;
 
-            {
-                let page_view = frame.page_view();
-                if let Page::BPlusLeaf(leaf) = page_view {
-                    if self.current_idx < leaf.num_entries() {
-                        let key = leaf.get_key_at(self.current_idx as usize).to_vec();
-                        let val = leaf.get_value(&key).unwrap();
-                        result = Some((key, val));
-                        self.current_idx += 1;
+            match frame.page_view() {
+                Page::BPlusLeaf(leaf) => {
+                    let n = leaf.num_entries();
+                    let idx = self.current_idx.min(n);
+                    let at = if forward {
+                        Some(idx).filter(|&i| i < n)
+                    } else {
+                        idx.checked_sub(1)
+                    };
+                    if let Some(at) = at {
+                        let (key, val, payload) = leaf.get_entry_at(at as usize);
+                        let after = if forward { at + 1 } else { at };
+                        result = Some(((key, val, payload.to_vec()), after));
+                    } else if forward {
+                        jump_to_sibling = leaf.next_sibling();
                     } else {
-                        if let Some(next_id) = leaf.next_sibling() {
-                            jump_to_sibling = Some(next_id);
-                        } else {
-                            self.current_page_id = 0;
-                        }
+                        jump_to_sibling = leaf.prev_sibling();
                     }
-                } else {
-                    self.current_page_id = 0;
                 }
+                _ => self.current_page_id = 0,
             }
 
             self.bpm.as_mut().unpin_frame(frame_id).ok();
 
-            if let Some(res) = result {
-                return Some(res);
+            if let Some((entry, after)) = result {
+                // Leave the cursor where it was, so a bounded scan can be resumed or reversed
+                let in_bounds = if forward {
+                    within(&entry.0, &self.upper, |k, b| k <= b, |k, b| k < b)
+                } else {
+                    within(&entry.0, &self.lower, |k, b| k >= b, |k, b| k > b)
+                };
+                if !in_bounds {
+                    return None;
+                }
+                self.current_idx = after;
+                return Some(entry);
             }
 
-            if let Some(next_id) = jump_to_sibling {
-                self.current_page_id = next_id;
-                self.current_idx = 0;
-            } else if self.current_page_id == 0 {
-                return None;
+            match jump_to_sibling {
+                Some(sibling_id) => {
+                    self.current_page_id = sibling_id;
+                    self.current_idx = if forward { 0 } else { u16::MAX };
+                }
+                // Stay on the end leaf so `prev`/`next` can still turn around from here
+                None => return None,
             }
         }
     }
 }
 
+/// Whether `key` satisfies `bound`, using `inclusive`/`exclusive` as the comparison.
+fn within(
+    key: &[u8],
+    bound: &Bound<Vec<u8>>,
+    inclusive: fn(&[u8], &[u8]) -> bool,
+    exclusive: fn(&[u8], &[u8]) -> bool,
+) -> bool {
+    match bound {
+        Bound::Included(b) => inclusive(key, b),
+        Bound::Excluded(b) => exclusive(key, b),
+        Bound::Unbounded => true,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -272,7 +340,7 @@ mod tests {
         let file_manager = FileManager::new(file_name.clone()).unwrap();
         let evictor = Box::new(FifoEvictor::new());
         let locator = Box::new(DirectoryPageLocator::new());
-        let bp = Box::pin(BufferPool::new(file_manager, evictor, locator));
+        let bp = Box::pin(BufferPool::new(Box::new(file_manager), evictor, locator));
 
         (PathBuf::from(file_name), bp, AtomicU32::new(0))
     }
@@ -243,8 +243,8 @@ mod tests {
     use crate::rt_type::primitives::{
         AttributeKind, AttributeValue, LayoutAttrData, TableAttribute, TableLayout, TableType,
     };
-    use crate::storage::buffer::BufferPool;
     use crate::storage::buffer::fifo_evictor::FifoEvictor;
+    use crate::storage::buffer::{AccessStrategy, BufferPool};
     use crate::storage::disk::FileManager;
     use crate::storage::heap::heap_file::HeapFile;
     use crate::storage::page::base::PageKind;
@@ -466,7 +466,7 @@ mod tests {
         let file_manager = FileManager::new(test_file.to_string()).unwrap();
         let evictor = Box::new(FifoEvictor::new());
         let locator = Box::new(DirectoryPageLocator::new());
-        let mut bp = Box::pin(BufferPool::new(file_manager, evictor, locator));
+        let mut bp = Box::pin(BufferPool::new(Box::new(file_manager), evictor, locator));
 
         // Bootstrap directory
         let frame = bp.as_mut().alloc_new_page(PageKind::Directory, 1).unwrap();
@@ -487,7 +487,7 @@ mod tests {
         let mut heap = HeapFile::new(0, 0);
         let bytes = t1.to_bytes(&schema).unwrap();
         let rid = heap
-            .insert(bp.as_mut(), &counter, &bytes)
+            .insert(bp.as_mut(), &counter, &bytes, AccessStrategy::Normal)
             .expect("Insert failed");
 
         // Simulate restart (flush and re-read)
@@ -5,4 +5,6 @@ pub mod page_locator;
 pub mod util;
 
 pub mod bplus_tree;
+pub mod hash_index;
 pub mod heap;
+pub mod transaction;
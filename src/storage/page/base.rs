@@ -1,7 +1,8 @@
 use crate::{
     constants,
     storage::page::{
-        bplus_inner::BPlusInner, bplus_leaf::BPlusLeaf, directory::Directory, header::PageHeader,
+        bplus_inner::BPlusInner, bplus_leaf::BPlusLeaf, directory::Directory,
+        hash_bucket::HashBucket, hash_directory::HashDirectory, header::PageHeader,
         slotted_data::SlottedData,
     },
 };
@@ -17,6 +18,12 @@ pub enum PageKind {
     SlottedData = 2,
     BPlusInner = 3,
     BPlusLeaf = 4,
+    HashDirectory = 5,
+    HashBucket = 6,
+    /// On the free list, waiting to be reused (see `PageLocator::release_page`)
+    Free = 7,
+    /// The header of the database file (see `Superblock`)
+    Superblock = 8,
 }
 
 pub trait DiskPage {
@@ -46,6 +53,8 @@ pub enum Page<'a> {
     SlottedData(SlottedData<'a>),
     BPlusInner(BPlusInner<'a>),
     BPlusLeaf(BPlusLeaf<'a>),
+    HashDirectory(HashDirectory<'a>),
+    HashBucket(HashBucket<'a>),
 }
 
 impl<'a> Page<'a> {
@@ -55,6 +64,8 @@ impl<'a> Page<'a> {
             Page::SlottedData(page) => page.raw(),
             Page::BPlusInner(page) => page.raw(),
             Page::BPlusLeaf(page) => page.raw(),
+            Page::HashDirectory(page) => page.raw(),
+            Page::HashBucket(page) => page.raw(),
             Page::Invalid() => panic!("Cannot get raw() from Page::Invalid"),
         }
     }
@@ -65,6 +76,8 @@ impl<'a> Page<'a> {
             Page::SlottedData(page) => page.header(),
             Page::BPlusInner(page) => page.header(),
             Page::BPlusLeaf(page) => page.header(),
+            Page::HashDirectory(page) => page.header(),
+            Page::HashBucket(page) => page.header(),
             Page::Invalid() => panic!("Cannot get header() from Page::Invalid"),
         }
     }
@@ -75,6 +88,8 @@ impl<'a> Page<'a> {
             Page::SlottedData(page) => page.header_mut(),
             Page::BPlusInner(page) => page.header_mut(),
             Page::BPlusLeaf(page) => page.header_mut(),
+            Page::HashDirectory(page) => page.header_mut(),
+            Page::HashBucket(page) => page.header_mut(),
             Page::Invalid() => panic!("Cannot get header_mut() from Page::Invalid"),
         }
     }
@@ -85,6 +100,8 @@ impl<'a> Page<'a> {
             Page::SlottedData(page) => page.raw_mut(),
             Page::BPlusInner(page) => page.raw_mut(),
             Page::BPlusLeaf(page) => page.raw_mut(),
+            Page::HashDirectory(page) => page.raw_mut(),
+            Page::HashBucket(page) => page.raw_mut(),
             Page::Invalid() => panic!("Cannot get raw_mut() from Page::Invalid"),
         }
     }
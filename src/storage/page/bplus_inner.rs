@@ -1,8 +1,12 @@
+use crate::storage::page::bplus_key::{
+    FORMAT_PLAIN, FORMAT_PREFIX, cmp_encoded, common_prefix_len, significant_len,
+};
 use crate::storage::page::header::PageHeader;
 use crate::{
     constants,
     storage::page::base::{self, DiskPage, PageId},
 };
+use std::cmp::Ordering;
 
 pub struct BPlusInner<'a> {
     raw: &'a mut base::PageBuf,
@@ -34,9 +38,13 @@ impl<'a> BPlusInner<'a> {
     // ---------+-----------+-----------+-----------+-----------|
     // 32..35   |              first_child_page_id (u32)          |
     // ---------+-----------+-----------+-----------+-----------|
-    // 36..     | Key 0 (N bytes) | Child 1 PageId (u32)        |
+    // 36..39   | prefix_len (u16)      | key_len (u16)         |
+    // ---------+-----------+-----------+-----------+-----------|
+    // 40..     | Prefix (p bytes, shared by every key)         |
+    // ---------+-----------+-----------+-----------+-----------|
+    // ...      | Suffix 0 (L-p bytes) | Child 1 PageId (u32)   |
     // ---------+-----------+-----------+-----------+-----------|
-    // ...      | Key 1 (N bytes) | Child 2 PageId (u32)        |
+    // ...      | Suffix 1 (L-p bytes) | Child 2 PageId (u32)   |
     // ---------+-----------+-----------+-----------+-----------|
     // ...      | (Entry array grows downwards)                 |
     // ---------+-----------------------------------------------|
@@ -44,46 +52,193 @@ impl<'a> BPlusInner<'a> {
     // ---------+-----------------------------------------------|
     // 4095     | (End of Page)                                   |
     // ---------------------------------------------------------|
+    //
+    // key_len (L) is the longest key once trailing zeros are dropped; the bytes past it
+    // read back as zeros. Separators are suffix-truncated, so L is often much less than N.
+    // Pages in FORMAT_PLAIN (written before prefix compression) have no 36..39 metadata:
+    // entries start at 36 and hold whole N-byte keys.
 
     /// The "value" in an inner node is a PageId, which is u32 (4 bytes).
     const VALUE_SIZE: usize = std::mem::size_of::<base::PageId>();
 
     const FIRST_CHILD_ID_START: usize = Self::DATA_START; // 32
     const FIRST_CHILD_ID_END: usize = Self::FIRST_CHILD_ID_START + Self::VALUE_SIZE; // 36
-    const DATA_START_ENTRIES: usize = Self::FIRST_CHILD_ID_END; // 36
+    const PREFIX_LEN_OFFSET: usize = Self::FIRST_CHILD_ID_END; // 36
+    const KEY_LEN_OFFSET: usize = Self::PREFIX_LEN_OFFSET + 2; // 38
+    const PREFIX_START: usize = Self::KEY_LEN_OFFSET + 2; // 40
+
+    fn is_plain(&self) -> bool {
+        self.header().format_version() == FORMAT_PLAIN
+    }
+
+    fn read_u16(&self, off: usize) -> usize {
+        u16::from_le_bytes([self.raw[off], self.raw[off + 1]]) as usize
+    }
+
+    /// Length of the key prefix stored once for the whole page.
+    pub fn prefix_len(&self) -> usize {
+        if self.is_plain() {
+            0
+        } else {
+            self.read_u16(Self::PREFIX_LEN_OFFSET)
+        }
+    }
+
+    /// Bytes of each key kept on the page, prefix included.
+    fn key_len(&self) -> usize {
+        if self.is_plain() {
+            self.get_key_size() as usize
+        } else {
+            self.read_u16(Self::KEY_LEN_OFFSET)
+        }
+    }
+
+    fn prefix(&self) -> &[u8] {
+        &self.raw[Self::PREFIX_START..Self::PREFIX_START + self.prefix_len()]
+    }
+
+    /// Offset of the first entry, right after the shared prefix.
+    fn entries_start(&self) -> usize {
+        if self.is_plain() {
+            Self::FIRST_CHILD_ID_END
+        } else {
+            Self::PREFIX_START + self.prefix_len()
+        }
+    }
 
-    /// Returns the size of one key + child_id entry.
+    fn suffix_size(&self) -> usize {
+        self.key_len() - self.prefix_len()
+    }
+
+    /// Returns the size of one key suffix + child_id entry.
     fn entry_size(&self) -> usize {
-        self.get_key_size() as usize + Self::VALUE_SIZE
+        self.suffix_size() + Self::VALUE_SIZE
     }
 
-    /// Returns a slice to the key at the given *entry* index (which is key index).
-    pub fn get_key_at(&self, index: usize) -> &[u8] {
-        let key_size = self.get_key_size() as usize;
-        let entry_size = self.entry_size();
-        let offset = Self::DATA_START_ENTRIES + index * entry_size;
-        &self.raw[offset..offset + key_size]
+    fn entry_offset(&self, index: usize) -> usize {
+        self.entries_start() + index * self.entry_size()
+    }
+
+    fn suffix_at(&self, index: usize) -> &[u8] {
+        let offset = self.entry_offset(index);
+        &self.raw[offset..offset + self.suffix_size()]
+    }
+
+    /// Returns the full key at the given *entry* index (which is key index).
+    pub fn get_key_at(&self, index: usize) -> Vec<u8> {
+        let mut key = self.prefix().to_vec();
+        key.extend_from_slice(self.suffix_at(index));
+        key.resize(self.get_key_size() as usize, 0);
+        key
+    }
+
+    fn cmp_key_at(&self, index: usize, key: &[u8]) -> Ordering {
+        cmp_encoded(self.prefix(), self.suffix_at(index), key)
     }
 
     /// Returns the child page ID at the given *entry* index (which is child index + 1).
     pub fn get_child_id_at_entry(&self, index: usize) -> base::PageId {
-        let key_size = self.get_key_size() as usize;
-        let entry_size = self.entry_size();
-        let offset = Self::DATA_START_ENTRIES + index * entry_size + key_size;
+        let offset = self.entry_offset(index) + self.suffix_size();
         let bytes = self.raw[offset..offset + Self::VALUE_SIZE]
             .try_into()
             .expect("Invalid value slice");
         base::PageId::from_le_bytes(bytes)
     }
 
-    /// Writes a key-child_id entry to the given logical index.
-    pub fn set_entry(&mut self, index: usize, key: &[u8], child_id: base::PageId) {
-        let key_size = self.get_key_size() as usize;
+    /// Writes a key-child_id entry in place. The key must fit the current encoding.
+    fn write_entry(&mut self, index: usize, key: &[u8], child_id: base::PageId) {
+        let (prefix_len, key_len) = (self.prefix_len(), self.key_len());
         let entry_size = self.entry_size();
-        let offset = Self::DATA_START_ENTRIES + index * entry_size;
+        let offset = self.entry_offset(index);
+        let suffix_end = offset + key_len - prefix_len;
+
+        self.raw[offset..suffix_end].copy_from_slice(&key[prefix_len..key_len]);
+        self.raw[suffix_end..offset + entry_size].copy_from_slice(&child_id.to_le_bytes());
+    }
+
+    /// Writes a key-child_id entry to the given logical index, re-encoding the page when
+    /// the key does not fit the current prefix and key length.
+    pub fn set_entry(&mut self, index: usize, key: &[u8], child_id: base::PageId) {
+        if self.encoding_with(key) == (self.prefix_len(), self.key_len()) {
+            self.write_entry(index, key, child_id);
+            return;
+        }
+        let (mut keys, mut children) = self.keys_and_children();
+        keys[index] = key.to_vec();
+        children[index] = child_id;
+        self.rewrite(&keys, &children);
+    }
+
+    /// All keys, and the child to the right of each.
+    fn keys_and_children(&self) -> (Vec<Vec<u8>>, Vec<PageId>) {
+        let n = self.num_entries() as usize;
+        let keys = (0..n).map(|i| self.get_key_at(i)).collect();
+        let children = (0..n).map(|i| self.get_child_id_at_entry(i)).collect();
+        (keys, children)
+    }
+
+    /// Tightest (prefix_len, key_len) for sorted `keys`.
+    fn encoding_for(keys: &[Vec<u8>]) -> (usize, usize) {
+        let key_len = keys.iter().map(|k| significant_len(k)).max().unwrap_or(0);
+        let prefix_len = match (keys.first(), keys.last()) {
+            (Some(first), Some(last)) => common_prefix_len(first, last).min(key_len),
+            _ => 0,
+        };
+        (prefix_len, key_len)
+    }
 
-        self.raw[offset..offset + key_size].copy_from_slice(key);
-        self.raw[offset + key_size..offset + entry_size].copy_from_slice(&child_id.to_le_bytes());
+    /// Bytes a compressed inner page takes up holding `count` keys that share `prefix_len`
+    /// bytes and have at most `key_len` significant bytes.
+    pub fn bytes_needed(prefix_len: usize, key_len: usize, count: usize) -> usize {
+        Self::PREFIX_START + prefix_len + count * (key_len - prefix_len + Self::VALUE_SIZE)
+    }
+
+    /// Whether sorted `keys` fit in one inner page once compressed.
+    fn fits(keys: &[Vec<u8>]) -> bool {
+        let (prefix_len, key_len) = Self::encoding_for(keys);
+        Self::bytes_needed(prefix_len, key_len, keys.len()) <= constants::storage::PAGE_SIZE
+    }
+
+    /// (prefix_len, key_len) the page needs once `key` is stored on it.
+    fn encoding_with(&self, key: &[u8]) -> (usize, usize) {
+        if self.is_plain() {
+            return (0, self.get_key_size() as usize);
+        }
+        let sig = significant_len(key);
+        if self.num_entries() == 0 {
+            return (sig, sig);
+        }
+        let key_len = self.key_len().max(sig);
+        let prefix_len = common_prefix_len(self.prefix(), key).min(key_len);
+        (prefix_len, key_len)
+    }
+
+    /// Bytes the page takes up with `count` keys in the given encoding.
+    fn bytes_with(&self, (prefix_len, key_len): (usize, usize), count: usize) -> usize {
+        if self.is_plain() {
+            Self::FIRST_CHILD_ID_END + count * (key_len + Self::VALUE_SIZE)
+        } else {
+            Self::bytes_needed(prefix_len, key_len, count)
+        }
+    }
+
+    /// Re-encodes the page to hold exactly sorted `keys`, where `children[i]` is the child
+    /// right of `keys[i]`. The first child is left alone.
+    fn rewrite(&mut self, keys: &[Vec<u8>], children: &[PageId]) {
+        let (prefix_len, key_len) = Self::encoding_for(keys);
+        self.header_mut().set_format_version(FORMAT_PREFIX);
+        let off = Self::PREFIX_LEN_OFFSET;
+        self.raw[off..off + 2].copy_from_slice(&(prefix_len as u16).to_le_bytes());
+        let off = Self::KEY_LEN_OFFSET;
+        self.raw[off..off + 2].copy_from_slice(&(key_len as u16).to_le_bytes());
+        if let Some(first) = keys.first() {
+            self.raw[Self::PREFIX_START..Self::PREFIX_START + prefix_len]
+                .copy_from_slice(&first[..prefix_len]);
+        }
+        for (i, (key, child)) in keys.iter().zip(children).enumerate() {
+            self.write_entry(i, key, *child);
+        }
+        self.header_mut().set_num_entries(keys.len() as u16);
     }
 
     /// Creates a new BPlusInner page view from a raw buffer.
@@ -102,14 +257,15 @@ impl<'a> BPlusInner<'a> {
         self.header_mut().init(page_id, base::PageKind::BPlusInner);
         self.header_mut().set_level(level);
         self.header_mut().set_key_size(key_size);
-        // Zero out the first child pointer area
-        self.raw[Self::FIRST_CHILD_ID_START..Self::FIRST_CHILD_ID_END].fill(0);
+        self.header_mut().set_format_version(FORMAT_PREFIX);
+        // Zero out the first child pointer and the prefix/key lengths
+        self.raw[Self::FIRST_CHILD_ID_START..Self::PREFIX_START].fill(0);
     }
 
     /// Calculates the amount of free space.
     pub fn free_space(&self) -> u32 {
         let data_used = self.num_entries() as u32 * self.entry_size() as u32;
-        let data_start = Self::DATA_START_ENTRIES as u32;
+        let data_start = self.entries_start() as u32;
         (constants::storage::PAGE_SIZE as u32 - data_start) - data_used
     }
 
@@ -171,12 +327,19 @@ impl<'a> BPlusInner<'a> {
         if self.num_entries() == 0 {
             return None;
         }
-        Some(self.get_key_at(0).to_vec())
+        Some(self.get_key_at(0))
     }
 
+    /// Number of separator keys that fit with the page's current encoding.
     pub fn calculate_max_keys(&self) -> u16 {
-        let space = constants::storage::PAGE_SIZE - Self::DATA_START_ENTRIES;
-        (space / self.entry_size()) as u16
+        ((constants::storage::PAGE_SIZE - self.entries_start()) / self.entry_size()) as u16
+    }
+
+    /// Number of separator keys that fit in an inner node holding keys of `key_size` bytes,
+    /// with nothing compressed.
+    pub fn max_keys_for(key_size: u32) -> u16 {
+        let space = constants::storage::PAGE_SIZE - Self::PREFIX_START;
+        (space / (key_size as usize + Self::VALUE_SIZE)) as u16
     }
 
     pub fn min_keys(&self) -> u16 {
@@ -191,8 +354,36 @@ impl<'a> BPlusInner<'a> {
         self.num_entries() > self.min_keys()
     }
 
-    pub fn has_space_for_key(&self) -> bool {
-        self.free_space() >= (self.entry_size() as u32)
+    /// Checks if `key` can be inserted without a split.
+    pub fn has_space_for_key(&self, key: &[u8]) -> bool {
+        let count = self.num_entries() as usize + 1;
+        self.bytes_with(self.encoding_with(key), count) <= constants::storage::PAGE_SIZE
+    }
+
+    /// Checks if one more key fits whatever it is, i.e. even if it shares no prefix and
+    /// has no trailing zeros.
+    pub fn has_space_for_any_key(&self) -> bool {
+        let count = self.num_entries() as usize + 1;
+        let worst = (0, self.get_key_size() as usize);
+        self.bytes_with(worst, count) <= constants::storage::PAGE_SIZE
+    }
+
+    /// Checks if the key at `index` can be replaced by `key` without overflowing.
+    pub fn can_set_key_at(&self, index: usize, key: &[u8]) -> bool {
+        if self.is_plain() {
+            return true;
+        }
+        let (mut keys, _) = self.keys_and_children();
+        keys[index] = key.to_vec();
+        Self::fits(&keys)
+    }
+
+    /// Whether `separator_key` and every entry of `other` fit in this page.
+    pub fn can_merge_from(&self, other: &BPlusInner, separator_key: &[u8]) -> bool {
+        let (mut keys, _) = self.keys_and_children();
+        keys.push(separator_key.to_vec());
+        keys.extend(other.keys_and_children().0);
+        Self::fits(&keys)
     }
 
     /// Returns the key associated with the child index (separator key).
@@ -202,7 +393,7 @@ impl<'a> BPlusInner<'a> {
         if child_idx == 0 {
             None
         } else {
-            Some(self.get_key_at(child_idx - 1).to_vec())
+            Some(self.get_key_at(child_idx - 1))
         }
     }
 
@@ -234,10 +425,7 @@ impl<'a> BPlusInner<'a> {
 
         if index == 0 {
             // Get First Child ID
-            let bytes = self.raw[Self::FIRST_CHILD_ID_START..Self::FIRST_CHILD_ID_END]
-                .try_into()
-                .unwrap();
-            let val = u32::from_le_bytes(bytes);
+            let val = self.first_child_raw();
             if val == 0 { None } else { Some(val) }
         } else {
             // Get child ID from entry (index - 1)
@@ -245,6 +433,13 @@ impl<'a> BPlusInner<'a> {
         }
     }
 
+    fn first_child_raw(&self) -> base::PageId {
+        let bytes = self.raw[Self::FIRST_CHILD_ID_START..Self::FIRST_CHILD_ID_END]
+            .try_into()
+            .unwrap();
+        u32::from_le_bytes(bytes)
+    }
+
     /// Set child at logical index (0 to num_entries inclusive)
     pub fn set_child_at(&mut self, index: usize, child_id: base::PageId) {
         let num_keys = self.num_entries() as usize;
@@ -259,9 +454,7 @@ impl<'a> BPlusInner<'a> {
                 .copy_from_slice(&child_id.to_le_bytes());
         } else {
             // Set child ID in entry (index - 1)
-            let key_size = self.get_key_size() as usize;
-            let entry_size = self.entry_size();
-            let offset = Self::DATA_START_ENTRIES + (index - 1) * entry_size + key_size;
+            let offset = self.entry_offset(index - 1) + self.suffix_size();
             self.raw[offset..offset + Self::VALUE_SIZE].copy_from_slice(&child_id.to_le_bytes());
         }
     }
@@ -277,16 +470,15 @@ impl<'a> BPlusInner<'a> {
 
         while left < right {
             let mid = left + (right - left) / 2;
-            let mid_key = self.get_key_at(mid);
 
-            match mid_key.cmp(key) {
-                std::cmp::Ordering::Less | std::cmp::Ordering::Equal => {
+            match self.cmp_key_at(mid, key) {
+                Ordering::Less | Ordering::Equal => {
                     // This key is <= our key, so we must go right.
                     // The child at `mid + 1` is the correct one.
                     result = mid + 1;
                     left = mid + 1;
                 }
-                std::cmp::Ordering::Greater => {
+                Ordering::Greater => {
                     // This key is > our key. This *might* be the one,
                     // but we check the left side.
                     right = mid;
@@ -297,97 +489,81 @@ impl<'a> BPlusInner<'a> {
     }
 
     /// Inserts a new (key, child_id) pair at the specified *entry* index.
+    /// Re-encodes the page when the key does not fit the current prefix and key length.
     pub fn insert_at(&mut self, entry_index: usize, key: &[u8], child_id: PageId) {
         let num_keys = self.num_entries() as usize;
-        let entry_size = self.entry_size();
+
+        let encoding = self.encoding_with(key);
+        if !self.is_plain() && (num_keys == 0 || encoding != (self.prefix_len(), self.key_len())) {
+            let (mut keys, mut children) = self.keys_and_children();
+            keys.insert(entry_index, key.to_vec());
+            children.insert(entry_index, child_id);
+            self.rewrite(&keys, &children);
+            return;
+        }
 
         // Shift entries to the right
         if entry_index < num_keys {
-            let src = Self::DATA_START_ENTRIES + entry_index * entry_size;
-            let dst = Self::DATA_START_ENTRIES + (entry_index + 1) * entry_size;
-            let count = (num_keys - entry_index) * entry_size;
+            let src = self.entry_offset(entry_index);
+            let dst = self.entry_offset(entry_index + 1);
+            let count = (num_keys - entry_index) * self.entry_size();
             self.raw.copy_within(src..src + count, dst);
         }
 
         // Insert new entry
-        self.set_entry(entry_index, key, child_id);
+        self.write_entry(entry_index, key, child_id);
         self.header_mut().set_num_entries((num_keys + 1) as u16);
     }
 
     /// Splits the inner node, inserting the new key/value, and returns the split data.
+    /// Pushes up the key nearest the middle that leaves both halves fitting.
     pub fn split_and_get_new_entries(
         &mut self,
         key: &[u8],
         child_id: base::PageId,
     ) -> BPlusInnerSplitData {
-        let curr_sz = self.num_entries() as usize;
-        let total_keys = curr_sz + 1;
-        let split_point = (total_keys + 1) / 2;
-
-        let mut all_keys = Vec::with_capacity(total_keys);
-        let mut all_children = Vec::with_capacity(total_keys + 1);
-
-        // Find insertion point
         let insert_pos = self.find_child_for_key(key);
 
-        // Copy children and keys before insertion point
-        all_children.push(self.get_child_at(0).unwrap());
-        for i in 0..insert_pos {
-            if i < curr_sz {
-                all_keys.push(self.get_key_at(i).to_vec());
-                all_children.push(self.get_child_at(i + 1).unwrap());
-            }
-        }
-
-        // Add the new entry
+        let (mut all_keys, children) = self.keys_and_children();
+        let mut all_children = vec![self.first_child_raw()];
+        all_children.extend(children);
         all_keys.insert(insert_pos, key.to_vec());
         all_children.insert(insert_pos + 1, child_id);
+        let total_keys = all_keys.len();
 
-        // Copy remaining children and keys
-        for i in insert_pos..curr_sz {
-            all_keys.push(self.get_key_at(i).to_vec());
-            all_children.push(self.get_child_at(i + 1).unwrap());
-        }
+        // One key moves up, so rounding down leaves both halves at or above min_keys
+        let even = total_keys / 2;
+        let split_point = (1..total_keys.saturating_sub(1))
+            .filter(|&s| Self::fits(&all_keys[..s]) && Self::fits(&all_keys[s + 1..]))
+            .min_by_key(|&s| s.abs_diff(even))
+            .unwrap_or(even);
 
-        // The key to push up is the one at the split point
-        let key_to_push_up = all_keys.remove(split_point);
+        let key_to_push_up = all_keys[split_point].clone();
 
         // Overwrite this (left) page
         self.set_child_at(0, all_children[0]);
-        for i in 0..split_point {
-            self.set_entry(i, &all_keys[i], all_children[i + 1]);
-        }
-        self.header_mut().set_num_entries(split_point as u16);
-
-        // Get entries for the new (right) page
-        let new_page_keys = all_keys[split_point..].to_vec();
-        let new_page_children = all_children[split_point + 1..].to_vec();
-
-        // The first child of the new page is special
-        let new_page_first_child = all_children[split_point];
-
-        let mut children_for_data = vec![new_page_first_child];
-        children_for_data.extend(new_page_children);
+        self.rewrite(&all_keys[..split_point], &all_children[1..=split_point]);
 
+        // The first child of the new page is the one right of the pushed-up key
         BPlusInnerSplitData {
             key_to_push_up,
-            new_page_keys,
-            new_page_children: children_for_data,
+            new_page_keys: all_keys[split_point + 1..].to_vec(),
+            new_page_children: all_children[split_point + 1..].to_vec(),
         }
     }
 
     /// Removes the key (and its *right* child) at the given *entry* index.
+    /// The shared prefix and key length are kept, they still cover the remaining keys.
     pub fn remove_at(&mut self, entry_index: usize) -> PageId {
         let num_keys = self.num_entries() as usize;
-        let entry_size = self.entry_size();
 
         let removed_child_id = self.get_child_id_at_entry(entry_index);
 
         // Shift entries left
         if entry_index < num_keys - 1 {
-            let src = Self::DATA_START_ENTRIES + (entry_index + 1) * entry_size;
-            let dst = Self::DATA_START_ENTRIES + entry_index * entry_size;
-            let count = (num_keys - 1 - entry_index) * entry_size;
+            let src = self.entry_offset(entry_index + 1);
+            let dst = self.entry_offset(entry_index);
+            let count = (num_keys - 1 - entry_index) * self.entry_size();
             self.raw.copy_within(src..src + count, dst);
         }
 
@@ -395,6 +571,16 @@ impl<'a> BPlusInner<'a> {
         removed_child_id
     }
 
+    /// Whether the last key can rotate through `separator_key` into the front of `target`.
+    pub fn can_move_last_to(&self, target: &BPlusInner, separator_key: &[u8]) -> bool {
+        self.num_entries() > 0 && target.has_space_for_key(separator_key)
+    }
+
+    /// Whether the first key can rotate through `separator_key` onto the end of `target`.
+    pub fn can_move_first_to(&self, target: &BPlusInner, separator_key: &[u8]) -> bool {
+        self.num_entries() > 0 && target.has_space_for_key(separator_key)
+    }
+
     pub fn move_last_to_beginning_of(
         &mut self,
         target: &mut BPlusInner,
@@ -402,7 +588,7 @@ impl<'a> BPlusInner<'a> {
     ) -> Vec<u8> {
         let num_keys = self.num_entries() as usize;
 
-        let last_key = self.get_key_at(num_keys - 1).to_vec();
+        let last_key = self.get_key_at(num_keys - 1);
         let last_child = self.get_child_at(num_keys).unwrap();
 
         self.header_mut().set_num_entries((num_keys - 1) as u16);
@@ -421,8 +607,7 @@ impl<'a> BPlusInner<'a> {
         target: &mut BPlusInner,
         separator_key: &[u8],
     ) -> Vec<u8> {
-
-        let first_key = self.get_key_at(0).to_vec();
+        let first_key = self.get_key_at(0);
         let first_child = self.get_child_at(0).unwrap();
         let second_child = self.get_child_at(1).unwrap();
 
@@ -437,21 +622,54 @@ impl<'a> BPlusInner<'a> {
     }
 
     /// Merges all entries from `other_node` into this one, using the `separator_key`.
+    /// Callers check `can_merge_from` first.
     pub fn merge_from(&mut self, other_node: &mut BPlusInner, separator_key: &[u8]) {
-        let num_keys = self.num_entries() as usize;
-        let other_num_keys = other_node.num_entries() as usize;
+        let (mut keys, mut children) = self.keys_and_children();
+        let (other_keys, other_children) = other_node.keys_and_children();
 
-        // Add separator key and other's first child
-        let other_first_child = other_node.get_child_at(0).unwrap();
-        self.insert_at(num_keys, separator_key, other_first_child);
-
-        // Add all remaining keys and children from other
-        for i in 0..other_num_keys {
-            let key = other_node.get_key_at(i);
-            let child = other_node.get_child_at(i + 1).unwrap();
-            self.insert_at(num_keys + 1 + i, key, child);
-        }
+        // Add separator key and other's first child, then all of other's entries
+        keys.push(separator_key.to_vec());
+        children.push(other_node.get_child_at(0).unwrap());
+        keys.extend(other_keys);
+        children.extend(other_children);
+        self.rewrite(&keys, &children);
 
         other_node.header_mut().set_num_entries(0);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inner_truncated_keys() {
+        let mut buf = [0u8; constants::storage::PAGE_SIZE];
+        let mut inner = BPlusInner::new(&mut buf);
+        inner.init(1, 1, 64);
+        inner.set_child_at(0, 100);
+
+        // Suffix-truncated separators: a shared prefix and a couple of significant bytes
+        let sep = |i: u16| {
+            let mut k = b"tenant-7/".to_vec();
+            k.extend_from_slice(&i.to_be_bytes());
+            k.resize(64, 0);
+            k
+        };
+        let mut n = 0;
+        while inner.has_space_for_key(&sep(n)) {
+            inner.insert_at(n as usize, &sep(n), 101 + n as u32);
+            n += 1;
+        }
+        assert!(n > BPlusInner::max_keys_for(64) * 5);
+        assert_eq!(inner.get_key_at(3), sep(3));
+        assert_eq!(inner.find_child_for_key(&sep(3)), 4);
+        assert_eq!(inner.get_child_at(4), Some(104));
+
+        let split = inner.split_and_get_new_entries(&sep(n), 101 + n as u32);
+        let left = inner.num_entries() as usize;
+        assert_eq!(split.key_to_push_up, sep(left as u16));
+        assert_eq!(left + 1 + split.new_page_keys.len(), n as usize + 1);
+        assert_eq!(split.new_page_children.len(), split.new_page_keys.len() + 1);
+    }
+}
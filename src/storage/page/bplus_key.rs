@@ -0,0 +1,69 @@
+use std::cmp::Ordering;
+
+/// Format of a B+ tree page, kept in the upper bits of the header flags.
+/// Pages written before the format was recorded read as `PLAIN`: the layout of the first
+/// release, whose leaves have no metadata area and no payloads.
+pub const FORMAT_PLAIN: u8 = 0;
+/// Keys share a per-page prefix that is stored once; inner pages also drop trailing zeros.
+/// Leaves carry their payload size and prefix length after the header.
+pub const FORMAT_PREFIX: u8 = 1;
+
+/// Number of leading bytes `a` and `b` have in common.
+pub fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+/// Length of `key` without its trailing zero bytes.
+pub fn significant_len(key: &[u8]) -> usize {
+    key.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1)
+}
+
+/// Shortest key `s` with `lower < s <= upper`, zero-padded to `upper.len()` (suffix
+/// truncation). Used as a separator it keeps only the bytes needed to tell the two apart,
+/// so inner pages drop the rest. `lower` must sort before `upper`.
+pub fn shortest_separator(lower: &[u8], upper: &[u8]) -> Vec<u8> {
+    let keep = (common_prefix_len(lower, upper) + 1).min(upper.len());
+    let mut sep = upper[..keep].to_vec();
+    sep.resize(upper.len(), 0);
+    sep
+}
+
+/// Compares the key stored as `prefix ++ suffix`, zero-padded to `key.len()`, with `key`.
+pub fn cmp_encoded(prefix: &[u8], suffix: &[u8], key: &[u8]) -> Ordering {
+    let (key_prefix, rest) = key.split_at(prefix.len().min(key.len()));
+    match prefix.cmp(key_prefix) {
+        Ordering::Equal => {}
+        other => return other,
+    }
+    let (key_suffix, tail) = rest.split_at(suffix.len().min(rest.len()));
+    match suffix.cmp(key_suffix) {
+        Ordering::Equal => {}
+        other => return other,
+    }
+    if tail.iter().all(|&b| b == 0) {
+        Ordering::Equal
+    } else {
+        Ordering::Less
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shortest_separator() {
+        let sep = shortest_separator(b"apple\0\0\0", b"apricot\0");
+        assert_eq!(sep, b"apr\0\0\0\0\0");
+        assert!(b"apple\0\0\0".as_slice() < sep.as_slice());
+        assert!(sep.as_slice() <= b"apricot\0".as_slice());
+        assert_eq!(significant_len(&sep), 3);
+
+        // Keys that differ only in the last byte cannot be shortened
+        assert_eq!(shortest_separator(&[1, 2, 3], &[1, 2, 4]), vec![1, 2, 4]);
+
+        assert_eq!(cmp_encoded(b"ap", b"r", b"apr\0\0"), Ordering::Equal);
+        assert_eq!(cmp_encoded(b"ap", b"r", b"apr\0a"), Ordering::Less);
+        assert_eq!(cmp_encoded(b"ap", b"s", b"apr\0a"), Ordering::Greater);
+    }
+}
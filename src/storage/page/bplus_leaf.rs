@@ -1,9 +1,16 @@
 use crate::storage::bplus_tree::SplitResult;
+use crate::storage::page::bplus_key::{
+    FORMAT_PLAIN, FORMAT_PREFIX, cmp_encoded, common_prefix_len, shortest_separator,
+};
 use crate::storage::page::header::PageHeader;
 use crate::{
     constants,
     storage::page::base::{self, DiskPage, PageId},
 };
+use std::cmp::Ordering;
+
+/// An owned (key, packed RowId, payload) leaf entry, used when entries move between pages.
+pub type LeafEntry = (Vec<u8>, u64, Vec<u8>);
 
 pub struct BPlusLeaf<'a> {
     raw: &'a mut base::PageBuf,
@@ -11,7 +18,7 @@ pub struct BPlusLeaf<'a> {
 
 impl<'a> base::DiskPage for BPlusLeaf<'a> {
     const PAGE_KIND: u8 = base::PageKind::BPlusLeaf as u8;
-    const DATA_START: usize = PageHeader::SIZE; // Data starts after the 32-byte header
+    const DATA_START: usize = PageHeader::SIZE + 8; // Data starts after the header and leaf metadata
 
     fn raw(self: &Self) -> &[u8; constants::storage::PAGE_SIZE] {
         &self.raw
@@ -27,9 +34,14 @@ impl<'a> BPlusLeaf<'a> {
     // 0..31    |              PageHeader (32 bytes)              |
     //          | (page_kind = BPlusLeaf, level = 0)            |
     // ---------+-----------+-----------+-----------+-----------|
-    // 32..     | Key 0 (N bytes) | RowId 0 (8 bytes)           |
+    // 32..39   | payload_size (u16)    | prefix_len (u16)      |
+    //          | reserved (4 bytes)                            |
+    // ---------+-----------+-----------+-----------+-----------|
+    // 40..     | Prefix (p bytes, shared by every key)         |
+    // ---------+-----------+-----------+-----------+-----------|
+    // ...      | Suffix 0 (N-p) | RowId 0 (8) | Payload 0 (P)  |
     // ---------+-----------+-----------+-----------+-----------|
-    // ...      | Key 1 (N bytes) | RowId 1 (8 bytes)           |
+    // ...      | Suffix 1 (N-p) | RowId 1 (8) | Payload 1 (P)  |
     // ---------+-----------+-----------+-----------+-----------|
     // ...      | (Entry array grows downwards)                 |
     // ---------+-----------------------------------------------|
@@ -37,49 +49,183 @@ impl<'a> BPlusLeaf<'a> {
     // ---------+-----------------------------------------------|
     // 4095     | (End of Page)                                 |
     // ---------------------------------------------------------|
+    //
+    // Pages in FORMAT_PLAIN (written before page formats were recorded) have no leaf
+    // metadata and no payloads: entries start right after the header at byte 32 and hold
+    // the whole key and the RowId. They are read in place and re-encoded on the first
+    // rewrite, so only FORMAT_PREFIX pages have the 32..39 metadata.
     /// The value in a leaf node is a RowId, which we've packed into a u64.
     const VALUE_SIZE: usize = std::mem::size_of::<u64>(); // 8 bytes for RowId
 
-    /// Returns the size of one key + value(RowId) entry.
+    /// Offset of the per-leaf payload size, right after the common header.
+    const PAYLOAD_SIZE_OFFSET: usize = PageHeader::SIZE;
+    /// Offset of the length of the key prefix shared by every entry.
+    const PREFIX_LEN_OFFSET: usize = PageHeader::SIZE + 2;
+
+    /// Length of the key prefix stored once for the whole page.
+    pub fn prefix_len(&self) -> usize {
+        if self.header().format_version() == FORMAT_PLAIN {
+            return 0;
+        }
+        let off = Self::PREFIX_LEN_OFFSET;
+        u16::from_le_bytes([self.raw[off], self.raw[off + 1]]) as usize
+    }
+
+    fn prefix(&self) -> &[u8] {
+        &self.raw[Self::DATA_START..Self::DATA_START + self.prefix_len()]
+    }
+
+    /// Offset of the first entry, right after the shared prefix.
+    fn entries_start(&self) -> usize {
+        if self.header().format_version() == FORMAT_PLAIN {
+            return PageHeader::SIZE;
+        }
+        Self::DATA_START + self.prefix_len()
+    }
+
+    /// Bytes of each key stored in the entry itself.
+    fn suffix_size(&self) -> usize {
+        self.get_key_size() as usize - self.prefix_len()
+    }
+
+    /// Returns the size of one key suffix + value(RowId) + payload entry.
     fn entry_size(&self) -> usize {
-        self.get_key_size() as usize + Self::VALUE_SIZE
+        self.suffix_size() + Self::VALUE_SIZE + self.payload_size() as usize
     }
 
-    /// Returns a slice to the key at the given logical index.
-    pub fn get_key_at(&self, index: usize) -> &[u8] {
-        let key_size = self.get_key_size() as usize;
-        let entry_size = self.entry_size();
-        let offset = Self::DATA_START + index * entry_size;
-        &self.raw[offset..offset + key_size]
+    fn entry_offset(&self, index: usize) -> usize {
+        self.entries_start() + index * self.entry_size()
+    }
+
+    fn suffix_at(&self, index: usize) -> &[u8] {
+        let offset = self.entry_offset(index);
+        &self.raw[offset..offset + self.suffix_size()]
+    }
+
+    /// Returns the full key at the given logical index.
+    pub fn get_key_at(&self, index: usize) -> Vec<u8> {
+        let mut key = self.prefix().to_vec();
+        key.extend_from_slice(self.suffix_at(index));
+        key
+    }
+
+    /// Compares the key at the given logical index with `key` without rebuilding it.
+    fn cmp_key_at(&self, index: usize, key: &[u8]) -> Ordering {
+        cmp_encoded(self.prefix(), self.suffix_at(index), key)
     }
 
     /// Returns the value (packed RowId) at the given logical index.
     fn get_value_at(&self, index: usize) -> u64 {
-        let key_size = self.get_key_size() as usize;
-        let entry_size = self.entry_size();
-        let offset = Self::DATA_START + index * entry_size + key_size;
+        let offset = self.entry_offset(index) + self.suffix_size();
         let bytes = self.raw[offset..offset + Self::VALUE_SIZE]
             .try_into()
             .expect("Invalid value slice");
         u64::from_le_bytes(bytes)
     }
 
-    /// Writes a key-value entry to the given logical index.
-    fn set_entry(&mut self, index: usize, key: &[u8], value: u64) {
-        let key_size = self.get_key_size() as usize;
-        let entry_size = self.entry_size();
-        let offset = Self::DATA_START + index * entry_size;
+    /// Returns the covering payload stored next to the RowId at the given logical index.
+    pub fn get_payload_at(&self, index: usize) -> &[u8] {
+        let offset = self.entry_offset(index) + self.suffix_size() + Self::VALUE_SIZE;
+        &self.raw[offset..offset + self.payload_size() as usize]
+    }
 
-        self.raw[offset..offset + key_size].copy_from_slice(key);
-        self.raw[offset + key_size..offset + entry_size].copy_from_slice(&value.to_le_bytes());
+    /// Returns the (key, value, payload) entry at the given logical index.
+    pub fn get_entry_at(&self, index: usize) -> (Vec<u8>, u64, &[u8]) {
+        (
+            self.get_key_at(index),
+            self.get_value_at(index),
+            self.get_payload_at(index),
+        )
     }
 
-    /// Sets the value for an *existing* key at the given logical index.
-    fn set_value_at(&mut self, index: usize, value: u64) {
-        let key_size = self.get_key_size() as usize;
+    /// Writes a key-value entry to the given logical index. The key must start with the
+    /// page prefix. An empty `payload` zero-fills the payload area.
+    fn set_entry(&mut self, index: usize, key: &[u8], value: u64, payload: &[u8]) {
+        let prefix_len = self.prefix_len();
+        let suffix_size = self.suffix_size();
         let entry_size = self.entry_size();
-        let offset = Self::DATA_START + index * entry_size + key_size;
-        self.raw[offset..offset + Self::VALUE_SIZE].copy_from_slice(&value.to_le_bytes());
+        let offset = self.entry_offset(index);
+        let payload_start = offset + suffix_size + Self::VALUE_SIZE;
+
+        self.raw[offset..offset + suffix_size].copy_from_slice(&key[prefix_len..]);
+        self.raw[offset + suffix_size..payload_start].copy_from_slice(&value.to_le_bytes());
+        if payload.is_empty() {
+            self.raw[payload_start..offset + entry_size].fill(0);
+        } else {
+            self.raw[payload_start..offset + entry_size].copy_from_slice(payload);
+        }
+    }
+
+    fn entries(&self) -> Vec<LeafEntry> {
+        (0..self.num_entries() as usize)
+            .map(|i| {
+                (
+                    self.get_key_at(i),
+                    self.get_value_at(i),
+                    self.get_payload_at(i).to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    /// Longest prefix shared by sorted `entries`: the one shared by the first and last.
+    fn shared_prefix_len(entries: &[LeafEntry]) -> usize {
+        match (entries.first(), entries.last()) {
+            (Some(first), Some(last)) => common_prefix_len(&first.0, &last.0),
+            _ => 0,
+        }
+    }
+
+    /// Bytes a leaf takes up holding `count` entries whose keys share `prefix_len` bytes.
+    pub fn bytes_needed(
+        key_size: usize,
+        payload_size: usize,
+        prefix_len: usize,
+        count: usize,
+    ) -> usize {
+        Self::DATA_START
+            + prefix_len
+            + count * (key_size - prefix_len + Self::VALUE_SIZE + payload_size)
+    }
+
+    /// Whether sorted `entries` fit in one leaf once compressed.
+    fn fits(&self, entries: &[LeafEntry]) -> bool {
+        Self::bytes_needed(
+            self.get_key_size() as usize,
+            self.payload_size() as usize,
+            Self::shared_prefix_len(entries),
+            entries.len(),
+        ) <= constants::storage::PAGE_SIZE
+    }
+
+    /// Re-encodes the page to hold exactly the sorted `entries`, with the longest shared prefix.
+    fn rewrite(&mut self, entries: &[LeafEntry]) {
+        let prefix_len = Self::shared_prefix_len(entries);
+        let payload_size = self.payload_size();
+        self.header_mut().set_format_version(FORMAT_PREFIX);
+        // A plain page had its first entry where the metadata goes
+        self.set_payload_size(payload_size);
+        let off = Self::PREFIX_LEN_OFFSET;
+        self.raw[off..off + 2].copy_from_slice(&(prefix_len as u16).to_le_bytes());
+        if let Some((first, _, _)) = entries.first() {
+            self.raw[Self::DATA_START..Self::DATA_START + prefix_len]
+                .copy_from_slice(&first[..prefix_len]);
+        }
+        for (i, (key, value, payload)) in entries.iter().enumerate() {
+            self.set_entry(i, key, *value, payload);
+        }
+        self.header_mut().set_num_entries(entries.len() as u16);
+    }
+
+    /// Prefix length the page would have after `key` is added to it.
+    fn prefix_len_with(&self, key: &[u8]) -> usize {
+        if self.header().format_version() == FORMAT_PLAIN {
+            0
+        } else if self.num_entries() == 0 {
+            key.len()
+        } else {
+            common_prefix_len(self.prefix(), key)
+        }
     }
 
     /// Creates a new BPlusLeaf page view from a raw buffer.
@@ -98,13 +244,15 @@ impl<'a> BPlusLeaf<'a> {
         self.header_mut().init(page_id, base::PageKind::BPlusLeaf);
         self.header_mut().set_level(0);
         self.header_mut().set_key_size(key_size);
-        // Data area is implicitly zeroed by init()
+        self.header_mut().set_format_version(FORMAT_PREFIX);
+        self.set_payload_size(0);
+        // Data area (and prefix_len) is implicitly zeroed by init()
     }
 
     /// Calculates the amount of free space.
     pub fn free_space(&self) -> u32 {
         let data_used = self.num_entries() as u32 * self.entry_size() as u32;
-        let data_start = Self::DATA_START as u32;
+        let data_start = self.entries_start() as u32;
         (constants::storage::PAGE_SIZE as u32 - data_start) - data_used
     }
 
@@ -129,6 +277,14 @@ impl<'a> BPlusLeaf<'a> {
     pub fn get_key_size(&self) -> u32 {
         self.header().key_size()
     }
+    /// Bytes of INCLUDE column data stored after each RowId. Zero for plain indexes.
+    pub fn payload_size(&self) -> u16 {
+        if self.header().format_version() == FORMAT_PLAIN {
+            return 0;
+        }
+        let off = Self::PAYLOAD_SIZE_OFFSET;
+        u16::from_le_bytes([self.raw[off], self.raw[off + 1]])
+    }
 
     // --- Header Setters ---
     pub fn set_page_id(&mut self, id: base::PageId) {
@@ -146,16 +302,29 @@ impl<'a> BPlusLeaf<'a> {
     pub fn set_key_size(&mut self, key_size: u32) {
         self.header_mut().set_key_size(key_size);
     }
+    /// Must be set before the first entry is inserted, it changes the entry stride.
+    pub fn set_payload_size(&mut self, payload_size: u16) {
+        let off = Self::PAYLOAD_SIZE_OFFSET;
+        self.raw[off..off + 2].copy_from_slice(&payload_size.to_le_bytes());
+    }
 
     // --- B+ Tree Logic ---
 
+    /// Half of `calculate_max_keys`, so it also follows the page's current prefix.
     pub fn min_keys(&self) -> u16 {
         self.calculate_max_keys() / 2
     }
 
+    /// Number of entries that fit with the page's current prefix.
     pub fn calculate_max_keys(&self) -> u16 {
+        ((constants::storage::PAGE_SIZE - self.entries_start()) / self.entry_size()) as u16
+    }
+
+    /// Number of entries that fit in a leaf holding keys of `key_size` bytes
+    /// and `payload_size` bytes of covering data per entry, with no shared prefix.
+    pub fn max_keys_for(key_size: u32, payload_size: u16) -> u16 {
         let space = constants::storage::PAGE_SIZE - Self::DATA_START;
-        (space / self.entry_size()) as u16
+        (space / (key_size as usize + Self::VALUE_SIZE + payload_size as usize)) as u16
     }
 
     pub fn is_underflow(&self) -> bool {
@@ -172,35 +341,70 @@ impl<'a> BPlusLeaf<'a> {
             .map(|pos| self.get_value_at(pos))
     }
 
-    /// Checks if the page has space for one more entry.
-    pub fn has_space_for_key(&self) -> bool {
-        self.free_space() >= (self.entry_size() as u32)
+    /// Checks if `key` can be inserted without a split. Adding a key can shorten the
+    /// shared prefix and so grow every entry.
+    pub fn has_space_for_key(&self, key: &[u8]) -> bool {
+        if self.find_key_position(key).is_some() {
+            return true;
+        }
+        Self::bytes_needed(
+            self.get_key_size() as usize,
+            self.payload_size() as usize,
+            self.prefix_len_with(key),
+            self.num_entries() as usize + 1,
+        ) <= constants::storage::PAGE_SIZE
+    }
+
+    /// Whether every entry of `other` fits in this page alongside its own.
+    pub fn can_merge_from(&self, other: &BPlusLeaf) -> bool {
+        let mut entries = self.entries();
+        entries.extend(other.entries());
+        self.fits(&entries)
+    }
+
+    /// Gets the covering payload for a given key.
+    pub fn get_payload(&self, key: &[u8]) -> Option<&[u8]> {
+        self.find_key_position(key)
+            .map(|pos| self.get_payload_at(pos))
     }
 
     /// Inserts a key-value pair, maintaining sorted order.
     pub fn insert_sorted(&mut self, key: &[u8], value: u64) {
+        self.insert_sorted_with_payload(key, value, &[]);
+    }
+
+    /// Inserts a key-value pair with its covering payload, maintaining sorted order.
+    /// Re-encodes the page when the key does not share the current prefix.
+    pub fn insert_sorted_with_payload(&mut self, key: &[u8], value: u64, payload: &[u8]) {
         let curr_size = self.num_entries() as usize;
 
         if let Some(pos) = self.find_key_position(key) {
-            // Key already exists. Update its value.
-            self.set_value_at(pos, value);
+            // Key already exists. Update its value and payload.
+            self.set_entry(pos, key, value, payload);
             return;
         }
 
         // Key not found, find insertion position
         let insert_pos = self.find_insert_position(key);
-        let entry_size = self.entry_size();
+
+        let is_plain = self.header().format_version() == FORMAT_PLAIN;
+        if !is_plain && (curr_size == 0 || self.prefix_len_with(key) != self.prefix_len()) {
+            let mut entries = self.entries();
+            entries.insert(insert_pos, (key.to_vec(), value, payload.to_vec()));
+            self.rewrite(&entries);
+            return;
+        }
 
         // Shift entries to the right
         if insert_pos < curr_size {
-            let src = Self::DATA_START + insert_pos * entry_size;
-            let dst = Self::DATA_START + (insert_pos + 1) * entry_size;
-            let count = (curr_size - insert_pos) * entry_size;
+            let src = self.entry_offset(insert_pos);
+            let dst = self.entry_offset(insert_pos + 1);
+            let count = (curr_size - insert_pos) * self.entry_size();
             self.raw.copy_within(src..src + count, dst);
         }
 
         // Insert new entry
-        self.set_entry(insert_pos, key, value);
+        self.set_entry(insert_pos, key, value, payload);
 
         // Update metadata
         self.header_mut().set_num_entries((curr_size + 1) as u16);
@@ -216,8 +420,7 @@ impl<'a> BPlusLeaf<'a> {
 
         while left < right {
             let mid = left + (right - left) / 2;
-            let mid_key = self.get_key_at(mid);
-            if mid_key < key {
+            if self.cmp_key_at(mid, key) == Ordering::Less {
                 left = mid + 1;
             } else {
                 right = mid;
@@ -227,18 +430,17 @@ impl<'a> BPlusLeaf<'a> {
     }
 
     /// Removes a key. Returns true if key was found and removed.
+    /// The shared prefix is kept, it still covers the remaining keys.
     pub fn remove_key(&mut self, key: &[u8]) -> bool {
         let curr_size = self.num_entries() as usize;
 
         // Find the key
         if let Some(pos) = self.find_key_position(key) {
-            let entry_size = self.entry_size();
-
             // Shift entries to the left
             if pos < curr_size - 1 {
-                let src = Self::DATA_START + (pos + 1) * entry_size;
-                let dst = Self::DATA_START + pos * entry_size;
-                let count = (curr_size - 1 - pos) * entry_size;
+                let src = self.entry_offset(pos + 1);
+                let dst = self.entry_offset(pos);
+                let count = (curr_size - 1 - pos) * self.entry_size();
                 self.raw.copy_within(src..src + count, dst);
             }
 
@@ -260,58 +462,56 @@ impl<'a> BPlusLeaf<'a> {
 
         while left < right {
             let mid = left + (right - left) / 2;
-            let mid_key = self.get_key_at(mid);
-            match mid_key.cmp(key) {
-                std::cmp::Ordering::Less => left = mid + 1,
-                std::cmp::Ordering::Equal => return Some(mid),
-                std::cmp::Ordering::Greater => right = mid,
+            match self.cmp_key_at(mid, key) {
+                Ordering::Less => left = mid + 1,
+                Ordering::Equal => return Some(mid),
+                Ordering::Greater => right = mid,
             }
         }
         None
     }
 
+    /// Picks where to split sorted `entries`: the most even split whose halves both fit.
+    fn choose_split_point(&self, entries: &[LeafEntry]) -> usize {
+        let even = entries.len().div_ceil(2);
+        (1..entries.len())
+            .filter(|&point| self.fits(&entries[..point]) && self.fits(&entries[point..]))
+            .min_by_key(|&point| point.abs_diff(even))
+            .unwrap_or(even)
+    }
+
     /// Splits the page, inserting the new key/value, and returns the split data.
+    /// The split key is the shortest key separating the two halves.
     pub fn split_and_get_new_entries(
         &mut self,
         key: &[u8],
         value: u64,
-    ) -> Result<(SplitResult, Vec<(Vec<u8>, u64)>), &'static str> {
+        payload: &[u8],
+    ) -> Result<(SplitResult, Vec<LeafEntry>), &'static str> {
         let key_size = self.get_key_size() as usize;
         if key.len() != key_size {
             return Err("split_and_get_new_entries: key length mismatch");
         }
 
-        let curr_size = self.num_entries() as usize;
-        let total_entries = curr_size + 1;
-
         // Build vector of all entries INCLUDING THE NEW ONE
-        let mut all_entries: Vec<(Vec<u8>, u64)> = Vec::with_capacity(total_entries);
-        for i in 0..curr_size {
-            all_entries.push((self.get_key_at(i).to_vec(), self.get_value_at(i)));
-        }
+        let mut all_entries = self.entries();
 
         // Find insert pos and add new entry
         let insert_pos = all_entries
-            .binary_search_by(|(k, _)| k.as_slice().cmp(key))
+            .binary_search_by(|(k, _, _)| k.as_slice().cmp(key))
             .unwrap_or_else(|e| e);
-        all_entries.insert(insert_pos, (key.to_vec(), value));
+        all_entries.insert(insert_pos, (key.to_vec(), value, payload.to_vec()));
 
-        // Find split point
-        let split_point = (total_entries + 1) / 2;
+        let split_point = self.choose_split_point(&all_entries);
 
         // Overwrite this (left) page with entries [0..split_point)
-        for i in 0..split_point {
-            let (key, value) = &all_entries[i];
-            self.set_entry(i, key, *value);
-        }
-
-        // Update metadata for this page
-        self.header_mut().set_num_entries(split_point as u16);
+        self.rewrite(&all_entries[..split_point]);
 
         // Create vector for the new (right) page - entries [split_point..total_entries)
-        let new_page_entries: Vec<(Vec<u8>, u64)> = all_entries[split_point..].to_vec();
+        let new_page_entries: Vec<LeafEntry> = all_entries[split_point..].to_vec();
 
-        let split_key = all_entries[split_point].0.clone();
+        let split_key =
+            shortest_separator(&all_entries[split_point - 1].0, &all_entries[split_point].0);
 
         Ok((SplitResult { split_key }, new_page_entries))
     }
@@ -321,7 +521,7 @@ impl<'a> BPlusLeaf<'a> {
         if self.num_entries() == 0 {
             return None;
         }
-        Some(self.get_key_at(0).to_vec())
+        Some(self.get_key_at(0))
     }
 
     /// Get the last key in this leaf
@@ -330,24 +530,37 @@ impl<'a> BPlusLeaf<'a> {
         if curr_size == 0 {
             return None;
         }
-        Some(self.get_key_at(curr_size - 1).to_vec())
+        Some(self.get_key_at(curr_size - 1))
+    }
+
+    /// Whether the last entry fits at the start of `target`.
+    pub fn can_move_last_to(&self, target: &BPlusLeaf) -> bool {
+        let n = self.num_entries() as usize;
+        n > 0 && target.has_space_for_key(&self.get_key_at(n - 1))
+    }
+
+    /// Whether the first entry fits at the end of `target`.
+    pub fn can_move_first_to(&self, target: &BPlusLeaf) -> bool {
+        self.num_entries() > 0 && target.has_space_for_key(&self.get_key_at(0))
+    }
+
+    /// Separator between this leaf and its right sibling once the last entry has moved over.
+    pub fn separator_if_last_moved(&self) -> Vec<u8> {
+        let n = self.num_entries() as usize;
+        shortest_separator(&self.get_key_at(n - 2), &self.get_key_at(n - 1))
+    }
+
+    /// Separator between a left sibling and this leaf once the first entry has moved over.
+    pub fn separator_if_first_moved(&self) -> Vec<u8> {
+        shortest_separator(&self.get_key_at(0), &self.get_key_at(1))
     }
 
     /// Move the last key-value pair to another leaf (for borrowing)
     pub fn move_last_to(&mut self, target: &mut BPlusLeaf) -> Option<Vec<u8>> {
-        let curr_size = self.num_entries() as usize;
-        if curr_size == 0 {
+        if self.num_entries() == 0 {
             return None;
         }
-        let last_idx = curr_size - 1;
-        let last_key = self.get_key_at(last_idx).to_vec();
-        let last_value = self.get_value_at(last_idx);
-
-        target.insert_at_beginning(&last_key, last_value);
-        self.header_mut().set_num_entries(last_idx as u16); // This "removes" the last key
-
-        // Return the key that was moved
-        Some(last_key)
+        Some(self.move_last_to_beginning_of(target))
     }
 
     /// Move the first key-value pair to another leaf (for borrowing)
@@ -357,18 +570,14 @@ impl<'a> BPlusLeaf<'a> {
             return None;
         }
 
-        let first_key = self.get_key_at(0).to_vec();
-        let first_value = self.get_value_at(0);
+        let (first_key, first_value, first_payload) = self.get_entry_at(0);
+        let first_payload = first_payload.to_vec();
 
-        target.insert_sorted(&first_key, first_value); // Insert at its correct sorted pos
+        target.insert_sorted_with_payload(&first_key, first_value, &first_payload); // Insert at its correct sorted pos
         self.remove_key(&first_key); // This handles shifting
 
         // Return the new first key of this leaf (if any)
-        if self.num_entries() > 0 {
-            Some(self.get_key_at(0).to_vec())
-        } else {
-            None
-        }
+        self.get_first_key()
     }
 
     /// Move the last key-value pair to the beginning of the target leaf
@@ -376,10 +585,10 @@ impl<'a> BPlusLeaf<'a> {
     pub fn move_last_to_beginning_of(&mut self, target: &mut BPlusLeaf) -> Vec<u8> {
         let curr_size = self.num_entries() as usize;
         let last_idx = curr_size - 1;
-        let last_key = self.get_key_at(last_idx).to_vec();
-        let last_value = self.get_value_at(last_idx);
+        let (last_key, last_value, last_payload) = self.get_entry_at(last_idx);
+        let last_payload = last_payload.to_vec();
 
-        target.insert_at_beginning(&last_key, last_value);
+        target.insert_sorted_with_payload(&last_key, last_value, &last_payload);
         self.header_mut().set_num_entries(last_idx as u16);
         last_key
     }
@@ -387,42 +596,94 @@ impl<'a> BPlusLeaf<'a> {
     /// Move the first key-value pair to the end of the target leaf
     /// Returns the *new* first key of this leaf (for parent update)
     pub fn move_first_to_end_of(&mut self, target: &mut BPlusLeaf) -> Vec<u8> {
-        let first_key = self.get_key_at(0).to_vec();
-        let first_value = self.get_value_at(0);
+        let (first_key, first_value, first_payload) = self.get_entry_at(0);
+        let first_payload = first_payload.to_vec();
 
-        target.insert_sorted(&first_key, first_value);
+        target.insert_sorted_with_payload(&first_key, first_value, &first_payload);
         self.remove_key(&first_key);
 
-        self.get_key_at(0).to_vec()
+        self.get_key_at(0)
     }
 
-    /// Insert a key-value pair at the beginning of the leaf
-    fn insert_at_beginning(&mut self, key: &[u8], value: u64) {
-        let curr_size = self.num_entries() as usize;
-        let entry_size = self.entry_size();
-
-        // Shift all entries right by one
-        let src = Self::DATA_START;
-        let dst = Self::DATA_START + entry_size;
-        let count = curr_size * entry_size;
-        self.raw.copy_within(src..src + count, dst);
-
-        // Insert at position 0
-        self.set_entry(0, key, value);
+    /// Merge all entries from other_leaf into this one. Callers check `can_merge_from` first.
+    pub fn merge_from(&mut self, other_leaf: &mut BPlusLeaf) {
+        let mut entries = self.entries();
+        entries.extend(other_leaf.entries());
+        self.rewrite(&entries);
 
-        self.header_mut().set_num_entries((curr_size + 1) as u16);
+        other_leaf.header_mut().set_num_entries(0);
+        self.set_next_sibling(other_leaf.next_sibling());
     }
+}
 
-    /// Merge all entries from other_leaf into this one
-    pub fn merge_from(&mut self, other_leaf: &mut BPlusLeaf) {
-        let other_size = other_leaf.num_entries() as usize;
-        for i in 0..other_size {
-            let key = other_leaf.get_key_at(i);
-            let value = other_leaf.get_value_at(i);
-            self.insert_sorted(key, value);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leaf_prefix_compression() {
+        let mut buf = [0u8; constants::storage::PAGE_SIZE];
+        let mut leaf = BPlusLeaf::new(&mut buf);
+        leaf.init(1, 32);
+
+        let key = |i: u32| {
+            let mut k = b"customer/0000000000/".to_vec();
+            k.extend_from_slice(&i.to_be_bytes());
+            k.resize(32, 0);
+            k
+        };
+
+        let uncompressed = BPlusLeaf::max_keys_for(32, 0) as u32;
+        let mut n = 0;
+        while leaf.has_space_for_key(&key(n)) {
+            leaf.insert_sorted(&key(n), n as u64);
+            n += 1;
+        }
+        assert!(leaf.prefix_len() >= 20);
+        assert!(
+            n > uncompressed * 2,
+            "{} entries vs {} uncompressed",
+            n,
+            uncompressed
+        );
+        for i in 0..n {
+            assert_eq!(leaf.get_value(&key(i)), Some(i as u64));
         }
 
-        other_leaf.header_mut().set_num_entries(0);
-        self.set_next_sibling(other_leaf.next_sibling());
+        // A key outside the prefix shortens it and the page no longer has room
+        let mut outlier = b"order/".to_vec();
+        outlier.resize(32, 0);
+        assert!(!leaf.has_space_for_key(&outlier));
+
+        // Plain pages from before page formats were recorded keep working: their entries
+        // start right after the header
+        let mut old = [0u8; constants::storage::PAGE_SIZE];
+        PageHeader::from_buf_mut(&mut old).init(2, base::PageKind::BPlusLeaf);
+        PageHeader::from_buf_mut(&mut old).set_key_size(32);
+        for i in 0..2u32 {
+            let at = PageHeader::SIZE + i as usize * 40;
+            old[at..at + 32].copy_from_slice(&key(i));
+            old[at + 32..at + 40].copy_from_slice(&(i as u64 + 7).to_le_bytes());
+        }
+        PageHeader::from_buf_mut(&mut old).set_num_entries(2);
+        let mut plain = BPlusLeaf::new(&mut old);
+        assert_eq!(plain.prefix_len(), 0);
+        assert_eq!(plain.payload_size(), 0);
+        assert_eq!(plain.get_key_at(0), key(0));
+        assert_eq!(plain.get_value(&key(1)), Some(8));
+        assert_eq!(
+            plain.calculate_max_keys() as usize,
+            (constants::storage::PAGE_SIZE - PageHeader::SIZE) / 40
+        );
+        plain.insert_sorted(&key(2), 9);
+        assert_eq!(plain.get_value(&key(0)), Some(7));
+        assert_eq!(plain.get_value(&key(2)), Some(9));
+
+        // Re-encoding converts it to the current format without losing entries
+        let entries = plain.entries();
+        plain.rewrite(&entries);
+        assert_eq!(plain.header().format_version(), FORMAT_PREFIX);
+        assert_eq!(plain.payload_size(), 0);
+        assert_eq!(plain.entries(), entries);
     }
 }
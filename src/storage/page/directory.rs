@@ -9,12 +9,14 @@ pub struct Directory<'a> {
     raw: &'a mut base::PageBuf,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 #[repr(C)]
 pub struct DirectoryEntry {
     pub page_id: base::PageId, // 4 bytes
+    pub slot_sector: u32,      // 4 bytes (where a page of a compressed segment is stored)
     pub file_offset: u64,      // 8 bytes (0 means invalid/NonZero)
     pub free_space: u32,       // 4 bytes
+    pub slot_sectors: u32,     // 4 bytes (0 unless the page is stored compressed)
 }
 
 impl<'a> DiskPage for Directory<'a> {
@@ -37,10 +39,12 @@ impl<'a> Directory<'a> {
     //          | (page_kind = Directory)                       |
     //          | (num_entries = N)                             |
     //          | (next_page_id = P)                            |
+    //          | (parent_page_id = first free page, root only) |
     // ---------+-----------+-----------+-----------+-----------|
-    // 32..47   | Entry 0 (page_id: u32, offset: u64, free: u32)|
+    // 32..55   | Entry 0 (page_id: u32, slot_sector: u32,      |
+    //          |   offset: u64, free: u32, slot_sectors: u32)  |
     // ---------+-----------+-----------+-----------+-----------|
-    // 48..63   | Entry 1 (page_id: u32, offset: u64, free: u32)|
+    // 56..79   | Entry 1                                       |
     // ---------+-----------+-----------+-----------+-----------|
     // ...      | (Entry array grows downwards)                 |
     // ---------+-----------------------------------------------|
@@ -49,7 +53,7 @@ impl<'a> Directory<'a> {
     // 4095     | (End of Page)                                 |
     // ---------------------------------------------------------|
 
-    pub const ENTRY_SIZE: usize = std::mem::size_of::<DirectoryEntry>(); // 16 bytes
+    pub const ENTRY_SIZE: usize = std::mem::size_of::<DirectoryEntry>(); // 24 bytes
 
     /// Creates a new Directory page view from a raw buffer.
     pub fn new<'b: 'a>(raw: &'b mut base::PageBuf) -> Self {
@@ -77,6 +81,17 @@ impl<'a> Directory<'a> {
         if id == 0 { None } else { Some(id) }
     }
 
+    /// Page index of the first page on the free list of this file, if any. Only the
+    /// first directory page keeps the list, or in a segment its header page.
+    pub fn free_list_head(&self) -> Option<u64> {
+        let offset = self.header().parent_page_id();
+        if offset == 0 {
+            None
+        } else {
+            Some(offset as u64)
+        }
+    }
+
     /// Gets the number of entries from the header.
     pub fn num_entries(&self) -> u16 {
         self.header().num_entries()
@@ -126,8 +141,10 @@ impl<'a> Directory<'a> {
             let entry = *self.entry_ptr(idx);
             Some(DirectoryEntry {
                 page_id: PageId::from_le(entry.page_id),
+                slot_sector: u32::from_le(entry.slot_sector),
                 file_offset: u64::from_le(entry.file_offset),
                 free_space: u32::from_le(entry.free_space),
+                slot_sectors: u32::from_le(entry.slot_sectors),
             })
         }
     }
@@ -144,6 +161,14 @@ impl<'a> Directory<'a> {
         self.header_mut().set_next_page_id(id.unwrap_or(0));
     }
 
+    /// Sets the first page of the free list. Offsets past `u32::MAX` cannot be stored.
+    pub fn set_free_list_head(&mut self, offset: Option<u64>) -> Result<(), errors::FreeListError> {
+        let offset = u32::try_from(offset.unwrap_or(0))
+            .map_err(|_| errors::FreeListError::OffsetTooLarge)?;
+        self.header_mut().set_parent_page_id(offset);
+        Ok(())
+    }
+
     /// Sets the free space value for a given entry.
     pub fn set_entry_free_space(&mut self, idx: usize, free: u32) {
         if idx >= self.num_entries() as usize {
@@ -154,6 +179,18 @@ impl<'a> Directory<'a> {
         }
     }
 
+    /// Sets where a page of a compressed segment is stored, see `PackedSegment`.
+    pub fn set_entry_slot(&mut self, idx: usize, sector: u32, sectors: u32) {
+        if idx >= self.num_entries() as usize {
+            panic!("set_entry_slot: index out of bounds");
+        }
+        unsafe {
+            let entry = &mut *self.entry_ptr_mut(idx);
+            entry.slot_sector = sector.to_le();
+            entry.slot_sectors = sectors.to_le();
+        }
+    }
+
     // === Indirect setters ===
 
     /// Adds a new entry to the end of the entry list.
@@ -167,8 +204,10 @@ impl<'a> Directory<'a> {
         unsafe {
             *self.entry_ptr_mut(num_entries as usize) = DirectoryEntry {
                 page_id: entry.page_id.to_le(),
+                slot_sector: entry.slot_sector.to_le(),
                 file_offset: entry.file_offset.to_le(),
                 free_space: entry.free_space.to_le(),
+                slot_sectors: entry.slot_sectors.to_le(),
             };
         }
 
@@ -215,4 +254,9 @@ pub mod errors {
     pub enum RemoveEntryError {
         IndexOutOfBounds,
     }
+
+    #[derive(Debug)]
+    pub enum FreeListError {
+        OffsetTooLarge,
+    }
 }
@@ -0,0 +1,173 @@
+use crate::storage::page::header::PageHeader;
+use crate::{
+    constants,
+    storage::page::base::{self, DiskPage, PageId},
+};
+
+pub struct HashBucket<'a> {
+    raw: &'a mut base::PageBuf,
+}
+
+impl<'a> base::DiskPage for HashBucket<'a> {
+    const PAGE_KIND: u8 = base::PageKind::HashBucket as u8;
+    const DATA_START: usize = PageHeader::SIZE + 8; // Data starts after the header and bucket metadata
+
+    fn raw(&self) -> &[u8; constants::storage::PAGE_SIZE] {
+        self.raw
+    }
+    fn raw_mut(&mut self) -> &mut [u8; constants::storage::PAGE_SIZE] {
+        self.raw
+    }
+}
+
+impl<'a> HashBucket<'a> {
+    // Bytes:   | +0        | +1        | +2        | +3        |
+    // ---------+-----------+-----------+-----------+-----------|
+    // 0..31    |              PageHeader (32 bytes)            |
+    //          | (page_kind = HashBucket)                      |
+    //          | (next_page_id = overflow page, 0 if none)     |
+    // ---------+-----------+-----------+-----------+-----------|
+    // 32..39   | local_depth (u16)     | reserved (6 bytes)    |
+    // ---------+-----------+-----------+-----------+-----------|
+    // 40..     | Key 0 (N bytes) | RowId 0 (8 bytes)           |
+    // ---------+-----------+-----------+-----------+-----------|
+    // ...      | (Entries are unordered, deletes swap in last)  |
+    // ---------+-----------------------------------------------|
+    //          |          <<< FREE SPACE >>>                   |
+    // ---------+-----------------------------------------------|
+    // 4095     | (End of Page)                                 |
+    // ---------------------------------------------------------|
+    const VALUE_SIZE: usize = std::mem::size_of::<u64>(); // 8 bytes for RowId
+
+    const LOCAL_DEPTH_OFFSET: usize = PageHeader::SIZE;
+
+    fn entry_size(&self) -> usize {
+        self.get_key_size() as usize + Self::VALUE_SIZE
+    }
+
+    fn entry_offset(&self, index: usize) -> usize {
+        Self::DATA_START + index * self.entry_size()
+    }
+
+    /// Creates a new HashBucket page view from a raw buffer.
+    pub fn new(raw: &'a mut base::PageBuf) -> Self {
+        Self { raw }
+    }
+
+    /// Initializes an empty bucket. Overflow pages share the local depth of their primary page.
+    pub fn init(&mut self, page_id: PageId, key_size: u32, local_depth: u16) {
+        self.header_mut().init(page_id, base::PageKind::HashBucket);
+        self.header_mut().set_key_size(key_size);
+        self.raw[Self::LOCAL_DEPTH_OFFSET..Self::DATA_START].fill(0);
+        self.set_local_depth(local_depth);
+    }
+
+    // --- Header Getters ---
+    pub fn page_id(&self) -> PageId {
+        self.header().page_id()
+    }
+    pub fn num_entries(&self) -> u16 {
+        self.header().num_entries()
+    }
+    pub fn get_key_size(&self) -> u32 {
+        self.header().key_size()
+    }
+    /// Next page in this bucket's overflow chain.
+    pub fn overflow_page_id(&self) -> Option<PageId> {
+        let id = self.header().next_page_id();
+        if id == 0 { None } else { Some(id) }
+    }
+    /// Number of low hash bits shared by every key in this bucket.
+    pub fn local_depth(&self) -> u16 {
+        let off = Self::LOCAL_DEPTH_OFFSET;
+        u16::from_le_bytes([self.raw[off], self.raw[off + 1]])
+    }
+
+    // --- Header Setters ---
+    pub fn set_overflow_page_id(&mut self, id: Option<PageId>) {
+        self.header_mut().set_next_page_id(id.unwrap_or(0));
+    }
+    pub fn set_local_depth(&mut self, depth: u16) {
+        let off = Self::LOCAL_DEPTH_OFFSET;
+        self.raw[off..off + 2].copy_from_slice(&depth.to_le_bytes());
+    }
+
+    // --- Entries ---
+
+    /// Number of entries that fit in one bucket page holding keys of `key_size` bytes.
+    pub fn max_entries_for(key_size: u32) -> u16 {
+        let space = constants::storage::PAGE_SIZE - Self::DATA_START;
+        (space / (key_size as usize + Self::VALUE_SIZE)) as u16
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.num_entries() >= Self::max_entries_for(self.get_key_size())
+    }
+
+    pub fn get_key_at(&self, index: usize) -> &[u8] {
+        let offset = self.entry_offset(index);
+        &self.raw[offset..offset + self.get_key_size() as usize]
+    }
+
+    pub fn get_value_at(&self, index: usize) -> u64 {
+        let offset = self.entry_offset(index) + self.get_key_size() as usize;
+        let bytes = self.raw[offset..offset + Self::VALUE_SIZE]
+            .try_into()
+            .expect("Invalid value slice");
+        u64::from_le_bytes(bytes)
+    }
+
+    fn set_entry(&mut self, index: usize, key: &[u8], value: u64) {
+        let offset = self.entry_offset(index);
+        let key_size = self.get_key_size() as usize;
+        self.raw[offset..offset + key_size].copy_from_slice(key);
+        self.raw[offset + key_size..offset + key_size + Self::VALUE_SIZE]
+            .copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Values stored under `key` in this page. A key may be stored more than once.
+    pub fn get_values(&self, key: &[u8]) -> Vec<u64> {
+        (0..self.num_entries() as usize)
+            .filter(|&i| self.get_key_at(i) == key)
+            .map(|i| self.get_value_at(i))
+            .collect()
+    }
+
+    /// Appends an entry. Returns false if the page is full.
+    pub fn push(&mut self, key: &[u8], value: u64) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        let n = self.num_entries() as usize;
+        self.set_entry(n, key, value);
+        self.header_mut().set_num_entries((n + 1) as u16);
+        true
+    }
+
+    /// Removes the entry of `key` with `value`. Returns true if it was found and removed.
+    pub fn remove(&mut self, key: &[u8], value: u64) -> bool {
+        let Some(pos) = (0..self.num_entries() as usize)
+            .find(|&i| self.get_key_at(i) == key && self.get_value_at(i) == value)
+        else {
+            return false;
+        };
+        let last = self.num_entries() as usize - 1;
+        if pos != last {
+            let (k, v) = (self.get_key_at(last).to_vec(), self.get_value_at(last));
+            self.set_entry(pos, &k, v);
+        }
+        self.header_mut().set_num_entries(last as u16);
+        true
+    }
+
+    /// Copies out every entry, used when a bucket is split.
+    pub fn entries(&self) -> Vec<(Vec<u8>, u64)> {
+        (0..self.num_entries() as usize)
+            .map(|i| (self.get_key_at(i).to_vec(), self.get_value_at(i)))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.header_mut().set_num_entries(0);
+    }
+}
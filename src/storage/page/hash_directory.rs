@@ -0,0 +1,111 @@
+use crate::storage::page::header::PageHeader;
+use crate::{
+    constants,
+    storage::page::base::{self, DiskPage, PageId},
+};
+
+pub struct HashDirectory<'a> {
+    raw: &'a mut base::PageBuf,
+}
+
+impl<'a> base::DiskPage for HashDirectory<'a> {
+    const PAGE_KIND: u8 = base::PageKind::HashDirectory as u8;
+    const DATA_START: usize = PageHeader::SIZE + 8; // Data starts after the header and directory metadata
+
+    fn raw(&self) -> &[u8; constants::storage::PAGE_SIZE] {
+        self.raw
+    }
+    fn raw_mut(&mut self) -> &mut [u8; constants::storage::PAGE_SIZE] {
+        self.raw
+    }
+}
+
+impl<'a> HashDirectory<'a> {
+    // Bytes:   | +0        | +1        | +2        | +3        |
+    // ---------+-----------+-----------+-----------+-----------|
+    // 0..31    |              PageHeader (32 bytes)            |
+    //          | (page_kind = HashDirectory, key_size = N)     |
+    // ---------+-----------+-----------+-----------+-----------|
+    // 32..39   | global_depth (u16)    | reserved (6 bytes)    |
+    // ---------+-----------+-----------+-----------+-----------|
+    // 40..43   |          Bucket PageId for slot 0 (u32)       |
+    // ---------+-----------+-----------+-----------+-----------|
+    // ...      | (2^global_depth slots, several may share a    |
+    // ...      |  bucket whose local depth is lower)           |
+    // ---------+-----------------------------------------------|
+    // 4095     | (End of Page)                                 |
+    // ---------------------------------------------------------|
+    const SLOT_SIZE: usize = std::mem::size_of::<PageId>();
+
+    const GLOBAL_DEPTH_OFFSET: usize = PageHeader::SIZE;
+
+    /// Largest global depth whose slot array still fits in one page.
+    pub const MAX_GLOBAL_DEPTH: u16 = {
+        let slots = (constants::storage::PAGE_SIZE - Self::DATA_START) / Self::SLOT_SIZE;
+        (usize::BITS - 1 - slots.leading_zeros()) as u16
+    };
+
+    /// Creates a new HashDirectory page view from a raw buffer.
+    pub fn new(raw: &'a mut base::PageBuf) -> Self {
+        Self { raw }
+    }
+
+    /// Initializes a directory of depth 0 whose single slot points at `first_bucket`.
+    pub fn init(&mut self, page_id: PageId, key_size: u32, first_bucket: PageId) {
+        self.header_mut()
+            .init(page_id, base::PageKind::HashDirectory);
+        self.header_mut().set_key_size(key_size);
+        self.raw[Self::GLOBAL_DEPTH_OFFSET..Self::DATA_START].fill(0);
+        self.set_bucket_at(0, first_bucket);
+    }
+
+    pub fn page_id(&self) -> PageId {
+        self.header().page_id()
+    }
+    pub fn get_key_size(&self) -> u32 {
+        self.header().key_size()
+    }
+    pub fn global_depth(&self) -> u16 {
+        let off = Self::GLOBAL_DEPTH_OFFSET;
+        u16::from_le_bytes([self.raw[off], self.raw[off + 1]])
+    }
+    fn set_global_depth(&mut self, depth: u16) {
+        let off = Self::GLOBAL_DEPTH_OFFSET;
+        self.raw[off..off + 2].copy_from_slice(&depth.to_le_bytes());
+    }
+
+    /// Number of slots currently in use (2^global_depth).
+    pub fn num_slots(&self) -> usize {
+        1 << self.global_depth()
+    }
+
+    /// Slot a hash maps to under the current global depth.
+    pub fn slot_for(&self, hash: u64) -> usize {
+        (hash & (self.num_slots() as u64 - 1)) as usize
+    }
+
+    pub fn bucket_at(&self, slot: usize) -> PageId {
+        let off = Self::DATA_START + slot * Self::SLOT_SIZE;
+        PageId::from_le_bytes(self.raw[off..off + Self::SLOT_SIZE].try_into().unwrap())
+    }
+
+    pub fn set_bucket_at(&mut self, slot: usize, bucket: PageId) {
+        let off = Self::DATA_START + slot * Self::SLOT_SIZE;
+        self.raw[off..off + Self::SLOT_SIZE].copy_from_slice(&bucket.to_le_bytes());
+    }
+
+    /// Doubles the slot array, mirroring the lower half into the upper half.
+    /// Returns false if the directory is already at `MAX_GLOBAL_DEPTH`.
+    pub fn grow(&mut self) -> bool {
+        let depth = self.global_depth();
+        if depth >= Self::MAX_GLOBAL_DEPTH {
+            return false;
+        }
+        let n = self.num_slots();
+        let src = Self::DATA_START;
+        self.raw
+            .copy_within(src..src + n * Self::SLOT_SIZE, src + n * Self::SLOT_SIZE);
+        self.set_global_depth(depth + 1);
+        true
+    }
+}
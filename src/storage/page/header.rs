@@ -17,7 +17,7 @@ use crate::storage::page::base::{PageId, PageKind};
 // ---------+-----------+-----------+-----------+-----------|
 // 24..27   |              key_size (u32)                   |
 // ---------+-----------+-----------+-----------+-----------|
-// 28..31   |              reserved (u32)                   |
+// 28..31   |              checksum (u32)                   |
 // ---------+-----------------------------------------------|
 // 32       | (Header Ends)                                 |
 //          | (Data Area Begins for B+ Tree pages)          |
@@ -36,14 +36,19 @@ pub struct PageHeader {
     page_kind: u8,           // 1 byte
     flags: u8,               // 1 byte
     key_size: u32,           // 4 bytes
-    reserved: u32,           // 4 bytes (Padding)
+    checksum: u32,           // 4 bytes, CRC32C of the page when it was last written
 }
 
 // Flags
 const FLAG_IS_ROOT: u8 = 0b0000_0001;
+// Set once the page has been written with a checksum
+const FLAG_HAS_CHECKSUM: u8 = 0b0000_0010;
+// The upper four bits hold the page format version
+const FORMAT_SHIFT: u8 = 4;
 
 impl PageHeader {
     pub const SIZE: usize = 32;
+    pub const CHECKSUM_OFFSET: usize = 28;
 
     /// Gets an immutable reference to the PageHeader from a raw page buffer.
     pub fn from_buf(buf: &[u8; PAGE_SIZE]) -> &Self {
@@ -68,7 +73,7 @@ impl PageHeader {
             page_kind: kind as u8,
             flags: 0,
             key_size: 0u32.to_le(),
-            reserved: 0u32.to_le(),
+            checksum: 0u32.to_le(),
         };
     }
 
@@ -104,6 +109,16 @@ impl PageHeader {
     pub fn is_root(&self) -> bool {
         (self.flags & FLAG_IS_ROOT) != 0
     }
+    pub fn has_checksum(&self) -> bool {
+        (self.flags & FLAG_HAS_CHECKSUM) != 0
+    }
+    pub fn checksum(&self) -> u32 {
+        u32::from_le(self.checksum)
+    }
+    /// Layout version of the page body, 0 for pages written before versioning.
+    pub fn format_version(&self) -> u8 {
+        self.flags >> FORMAT_SHIFT
+    }
 
     // --- Setters (with little-endian conversion) ---
     pub fn set_page_id(&mut self, id: PageId) {
@@ -131,6 +146,16 @@ impl PageHeader {
         self.key_size = size.to_le();
     }
 
+    pub fn set_format_version(&mut self, version: u8) {
+        self.flags = (self.flags & !(0xF << FORMAT_SHIFT)) | (version << FORMAT_SHIFT);
+    }
+
+    /// Stores `checksum` and marks the page as carrying one (see `page::checksum`).
+    pub fn set_checksum(&mut self, checksum: u32) {
+        self.flags |= FLAG_HAS_CHECKSUM;
+        self.checksum = checksum.to_le();
+    }
+
     pub fn set_root(&mut self, is_root: bool) {
         if is_root {
             self.flags |= FLAG_IS_ROOT;
@@ -162,6 +187,10 @@ impl From<u8> for PageKind {
             2 => PageKind::SlottedData,
             3 => PageKind::BPlusInner,
             4 => PageKind::BPlusLeaf,
+            5 => PageKind::HashDirectory,
+            6 => PageKind::HashBucket,
+            7 => PageKind::Free,
+            8 => PageKind::Superblock,
             _ => PageKind::Invalid,
         }
     }
@@ -1,10 +1,18 @@
 pub mod base;
 pub mod bplus_inner;
+pub mod bplus_key;
 pub mod bplus_leaf;
+pub mod checksum;
 pub mod directory;
+pub mod hash_bucket;
+pub mod hash_directory;
 pub mod slotted_data;
+pub mod superblock;
 pub use bplus_inner::BPlusInner;
 pub use bplus_leaf::BPlusLeaf;
 pub use directory::Directory;
+pub use hash_bucket::HashBucket;
+pub use hash_directory::HashDirectory;
 pub use slotted_data::SlottedData;
+pub use superblock::Superblock;
 pub mod header;
@@ -3,6 +3,24 @@ use crate::storage::page::base::DiskPage;
 use crate::storage::page::{base, header::PageHeader};
 pub const SLOT_META_SIZE: usize = 4;
 
+// The top bits of a slot's length say what the slot holds
+const REDIRECT_FLAG: u16 = 0x8000;
+const MOVED_FLAG: u16 = 0x4000;
+const LEN_MASK: u16 = 0x3FFF;
+
+/// What a slot holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotKind {
+    /// Deleted, or never used
+    Dead,
+    Tuple,
+    /// A tuple that outgrew its page. Only reachable through the redirect left behind.
+    Moved,
+    /// Where a tuple used to be: the data is the RowId it moved to, so the tuple keeps
+    /// its RowId
+    Redirect,
+}
+
 pub struct SlottedData<'a> {
     raw: &'a mut base::PageBuf,
 }
@@ -25,7 +43,7 @@ impl<'a> SlottedData<'a> {
     // ---------+-----------+-----------+-----------+-----------|
     // 0..31    |            PageHeader (32 bytes)              |
     // ---------+-----------+-----------+-----------+-----------|
-    // 32..35   | Slot 0 offset(u16) | Slot 0 len(u16)          |
+    // 32..35   | Slot 0 offset(u16) | Slot 0 flags+len(u16)    |
     // ---------+-----------+-----------+-----------+-----------|
     // 36..39   | Slot 1 offset(u16) | Slot 1 len(u16)          |
     // ---------+-----------+-----------+-----------+-----------|
@@ -42,6 +60,7 @@ impl<'a> SlottedData<'a> {
     // ---------+-----------+-----------+-----------+-----------|
     // 4095     | (End of Page)                                 |
     // ---------------------------------------------------------|
+    // The top two bits of a slot's len are its Redirect and Moved flags.
     pub const SLOT_META_SIZE: usize = 4; // u16 offset + u16 len
 
     /// Creates a new SlottedData page view from a raw buffer.
@@ -96,12 +115,31 @@ impl<'a> SlottedData<'a> {
 
     /// Gets the size of the data for a given slot.
     pub fn slot_size(&self, idx: usize) -> Option<u16> {
+        self.slot_len_raw(idx).map(|len| len & LEN_MASK)
+    }
+
+    /// The slot's length with its flags.
+    fn slot_len_raw(&self, idx: usize) -> Option<u16> {
         if idx >= self.num_slots() as usize {
             return None;
         }
         unsafe { Some(u16::from_le(*self.slot_len_ptr(idx))) }
     }
 
+    /// What the slot holds, or None past the end of the slot array.
+    pub fn slot_kind(&self, idx: usize) -> Option<SlotKind> {
+        let len = self.slot_len_raw(idx)?;
+        Some(if len & LEN_MASK == 0 {
+            SlotKind::Dead
+        } else if len & REDIRECT_FLAG != 0 {
+            SlotKind::Redirect
+        } else if len & MOVED_FLAG != 0 {
+            SlotKind::Moved
+        } else {
+            SlotKind::Tuple
+        })
+    }
+
     // === Indirect Getters ===
 
     /// Gets an immutable slice to the data in the specified slot.
@@ -186,6 +224,65 @@ impl<'a> SlottedData<'a> {
         Ok(num_slots)
     }
 
+    /// Replaces the data of a live slot, keeping its number, and makes it a slot of `kind`.
+    /// Data that fits in the old space is written over it; longer data goes to the free
+    /// space, leaving the old bytes for `compact` to reclaim.
+    pub fn update_slot(
+        &mut self,
+        idx: usize,
+        data: &[u8],
+        kind: SlotKind,
+    ) -> Result<(), errors::UpdateSlotError> {
+        if data.is_empty() || kind == SlotKind::Dead {
+            return Err(errors::UpdateSlotError::DataEmpty);
+        }
+        if !self.is_live(idx) {
+            return Err(errors::UpdateSlotError::NotLive);
+        }
+
+        let old_offset = self.slot_offset(idx).unwrap();
+        let old_size = self.slot_size(idx).unwrap() as usize;
+        let offset = if data.len() <= old_size {
+            old_offset
+        } else if data.len() <= self.free_space() as usize {
+            let offset = self.header().free_space_pointer() - data.len() as u16;
+            self.header_mut().set_free_space_pointer(offset);
+            offset
+        } else {
+            return Err(errors::UpdateSlotError::InsufficientSpace);
+        };
+
+        self.raw[offset as usize..offset as usize + data.len()].copy_from_slice(data);
+        self.set_slot_offset_unchecked(idx, offset);
+        self.set_slot_size_unchecked(idx, data.len() as u16 | Self::kind_flags(kind));
+        Ok(())
+    }
+
+    /// Changes what a live slot holds without touching its data.
+    pub fn set_slot_kind(
+        &mut self,
+        idx: usize,
+        kind: SlotKind,
+    ) -> Result<(), errors::UpdateSlotError> {
+        if kind == SlotKind::Dead {
+            return Err(errors::UpdateSlotError::DataEmpty);
+        }
+        if !self.is_live(idx) {
+            return Err(errors::UpdateSlotError::NotLive);
+        }
+        let size = self.slot_size(idx).unwrap();
+        self.set_slot_size_unchecked(idx, size | Self::kind_flags(kind));
+        Ok(())
+    }
+
+    fn kind_flags(kind: SlotKind) -> u16 {
+        match kind {
+            SlotKind::Redirect => REDIRECT_FLAG,
+            SlotKind::Moved => MOVED_FLAG,
+            SlotKind::Tuple | SlotKind::Dead => 0,
+        }
+    }
+
     /// Removes a slot by swapping it with the last slot.
     /// Note: This does not reclaim the data space (no compaction).
     pub fn remove_slot_at(&mut self, idx: usize) -> Result<(), errors::RemoveSlotError> {
@@ -197,7 +294,7 @@ impl<'a> SlottedData<'a> {
         if idx != (num_slots - 1) as usize {
             // Not the last slot, so swap with last
             let last_slot_offset = self.slot_offset(num_slots as usize - 1).unwrap();
-            let last_slot_size = self.slot_size(num_slots as usize - 1).unwrap();
+            let last_slot_size = self.slot_len_raw(num_slots as usize - 1).unwrap();
 
             self.set_slot_offset_unchecked(idx, last_slot_offset);
             self.set_slot_size_unchecked(idx, last_slot_size);
@@ -212,6 +309,44 @@ impl<'a> SlottedData<'a> {
         Ok(())
     }
 
+    /// Whether the slot still holds a tuple.
+    pub fn is_live(&self, idx: usize) -> bool {
+        matches!(self.slot_size(idx), Some(size) if size > 0)
+    }
+
+    /// Moves the live tuples together at the end of the page, turning the holes left by dead
+    /// ones back into free space. Slot numbers do not change, so RowIds stay valid; only dead
+    /// slots at the end of the slot array are dropped. Returns the number of bytes reclaimed.
+    pub fn compact(&mut self) -> u32 {
+        let before = self.free_space();
+        let mut old = *self.raw;
+        let old_page = SlottedData::new(&mut old);
+
+        let mut num_slots = self.num_slots() as usize;
+        while num_slots > 0 && !old_page.is_live(num_slots - 1) {
+            num_slots -= 1;
+        }
+
+        let mut free_ptr = constants::storage::PAGE_SIZE;
+        for idx in 0..num_slots {
+            match old_page.slot_data(idx) {
+                Some(data) => {
+                    free_ptr -= data.len();
+                    self.raw[free_ptr..free_ptr + data.len()].copy_from_slice(data);
+                    self.set_slot_offset_unchecked(idx, free_ptr as u16);
+                }
+                None => {
+                    self.set_slot_offset_unchecked(idx, 0);
+                    self.set_slot_size_unchecked(idx, 0);
+                }
+            }
+        }
+
+        self.header_mut().set_num_entries(num_slots as u16);
+        self.header_mut().set_free_space_pointer(free_ptr as u16);
+        self.free_space() - before
+    }
+
     pub fn mark_dead(&mut self, idx: usize) -> Result<(), errors::RemoveSlotError> {
         if idx >= self.num_slots() as usize {
             return Err(errors::RemoveSlotError::IndexOutOfBounds);
@@ -235,4 +370,11 @@ pub mod errors {
     pub enum RemoveSlotError {
         IndexOutOfBounds,
     }
+
+    #[derive(Debug)]
+    pub enum UpdateSlotError {
+        NotLive,
+        InsufficientSpace,
+        DataEmpty,
+    }
 }
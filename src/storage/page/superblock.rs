@@ -0,0 +1,238 @@
+use crate::constants::storage::PAGE_SIZE;
+use crate::storage::page::base::{PageBuf, PageKind};
+use crate::storage::page::checksum;
+use crate::storage::page::header::PageHeader;
+use std::fmt;
+
+/// Where the superblock lives: the first page of the database file
+pub const SUPERBLOCK_OFFSET: u64 = 0;
+pub const MAGIC: &[u8; 8] = b"NIMBUSDB";
+/// Layout of the database file this build reads and writes. Version 1 is the layout from
+/// before the superblock, which had the first directory page at offset 0; such files
+/// are upgraded in place when opened. From version 3 on every page carries a checksum.
+pub const FORMAT_VERSION: u32 = 3;
+/// The last version whose pages may carry no checksum, having been written before there
+/// were any. Files upgraded from version 1 stay at it.
+pub const UNCHECKSUMMED_VERSION: u32 = 2;
+/// Format features this build understands. None are defined yet; a file that needs any
+/// other is refused instead of misread.
+pub const KNOWN_FEATURES: u32 = 0;
+
+// Bytes:   | +0        | +1        | +2        | +3        |
+// ---------+-----------+-----------+-----------+-----------|
+// 0..31    |              PageHeader (32 bytes)            |
+//          | (page_kind = Superblock)                      |
+// ---------+-----------+-----------+-----------+-----------|
+// 32..39   |              magic ("NIMBUSDB")               |
+// ---------+-----------+-----------+-----------+-----------|
+// 40..43   |              format_version (u32)             |
+// 44..47   |              page_size (u32)                  |
+// 48..51   |              features (u32)                   |
+// 52..55   |              next_oid (u32)                   |
+// 56..63   |              root_directory (u64)             |
+// 64..67   |              next_page_id (u32)               |
+// ---------+-----------------------------------------------|
+// 68..4095 | (Unused)                                      |
+// ---------------------------------------------------------|
+const MAGIC_AT: usize = PageHeader::SIZE;
+const VERSION_AT: usize = MAGIC_AT + 8;
+const PAGE_SIZE_AT: usize = VERSION_AT + 4;
+const FEATURES_AT: usize = PAGE_SIZE_AT + 4;
+const NEXT_OID_AT: usize = FEATURES_AT + 4;
+const ROOT_DIRECTORY_AT: usize = NEXT_OID_AT + 4;
+const NEXT_PAGE_ID_AT: usize = ROOT_DIRECTORY_AT + 8;
+
+/// The header of a database file: what it is, how it is laid out and what it was created
+/// with, so a build can tell a file it must not touch before reading anything else.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Superblock {
+    pub format_version: u32,
+    pub page_size: u32,
+    /// Format features the file was created with, see `KNOWN_FEATURES`
+    pub features: u32,
+    /// The next oid the catalog hands out. The catalog also looks at the oids it finds,
+    /// so this only has to be written before an oid past it is.
+    pub next_oid: u32,
+    /// Address of the first directory page
+    pub root_directory: u64,
+    /// No page id from here on has been handed out: pages only get one after it is moved
+    /// past it, see `BufferPoolCore::reserve_page_id`. 0 if the file does not record it
+    /// yet, in which case the directory knows the page ids in use.
+    pub next_page_id: u32,
+}
+
+impl Superblock {
+    /// The superblock of a new database in the current format.
+    pub fn new(root_directory: u64, next_oid: u32) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            page_size: PAGE_SIZE as u32,
+            features: 0,
+            next_oid,
+            root_directory,
+            next_page_id: 0,
+        }
+    }
+
+    /// Whether every page of the file has to carry a checksum, see `UNCHECKSUMMED_VERSION`.
+    pub fn checksums_required(&self) -> bool {
+        self.format_version > UNCHECKSUMMED_VERSION
+    }
+
+    /// Lays the superblock out in `buf` as a page of its own, checksum included.
+    pub fn write_to(&self, buf: &mut PageBuf) {
+        buf.fill(0);
+        PageHeader::from_buf_mut(buf).init(0, PageKind::Superblock);
+        buf[MAGIC_AT..VERSION_AT].copy_from_slice(MAGIC);
+        buf[VERSION_AT..PAGE_SIZE_AT].copy_from_slice(&self.format_version.to_le_bytes());
+        buf[PAGE_SIZE_AT..FEATURES_AT].copy_from_slice(&self.page_size.to_le_bytes());
+        buf[FEATURES_AT..NEXT_OID_AT].copy_from_slice(&self.features.to_le_bytes());
+        buf[NEXT_OID_AT..ROOT_DIRECTORY_AT].copy_from_slice(&self.next_oid.to_le_bytes());
+        buf[ROOT_DIRECTORY_AT..ROOT_DIRECTORY_AT + 8]
+            .copy_from_slice(&self.root_directory.to_le_bytes());
+        buf[NEXT_PAGE_ID_AT..NEXT_PAGE_ID_AT + 4].copy_from_slice(&self.next_page_id.to_le_bytes());
+        checksum::stamp(buf);
+    }
+
+    /// Reads the superblock from the first page of a database file, refusing files this
+    /// build would misread.
+    pub fn read_from(buf: &PageBuf) -> Result<Self, errors::FormatError> {
+        use errors::FormatError;
+
+        if &buf[MAGIC_AT..VERSION_AT] != MAGIC {
+            let header = PageHeader::from_buf(buf);
+            let unversioned = header.page_kind() == PageKind::Directory
+                && header.page_id() == 0
+                && checksum::verify(buf, false);
+            return Err(if unversioned {
+                FormatError::Unversioned
+            } else {
+                FormatError::NotADatabase
+            });
+        }
+
+        let u32_at = |at: usize| u32::from_le_bytes(buf[at..at + 4].try_into().unwrap());
+        // Checked first: a newer format may well have moved everything else
+        let format_version = u32_at(VERSION_AT);
+        if format_version > FORMAT_VERSION {
+            return Err(FormatError::NewerFormat(format_version));
+        }
+        let page_size = u32_at(PAGE_SIZE_AT);
+        if page_size != PAGE_SIZE as u32 {
+            return Err(FormatError::PageSize(page_size));
+        }
+        // Superblocks have been stamped since there were any
+        if !checksum::verify(buf, true) {
+            return Err(FormatError::ChecksumMismatch);
+        }
+        let features = u32_at(FEATURES_AT);
+        if features & !KNOWN_FEATURES != 0 {
+            return Err(FormatError::UnknownFeatures(features & !KNOWN_FEATURES));
+        }
+
+        Ok(Self {
+            format_version,
+            page_size,
+            features,
+            next_oid: u32_at(NEXT_OID_AT),
+            root_directory: u64::from_le_bytes(
+                buf[ROOT_DIRECTORY_AT..ROOT_DIRECTORY_AT + 8]
+                    .try_into()
+                    .unwrap(),
+            ),
+            next_page_id: u32_at(NEXT_PAGE_ID_AT),
+        })
+    }
+}
+
+pub mod errors {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    pub enum FormatError {
+        /// Written before database files had a superblock, see `FORMAT_VERSION`
+        Unversioned,
+        NotADatabase,
+        NewerFormat(u32),
+        PageSize(u32),
+        UnknownFeatures(u32),
+        ChecksumMismatch,
+    }
+
+    impl fmt::Display for FormatError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                FormatError::Unversioned => write!(f, "database file predates format versions"),
+                FormatError::NotADatabase => write!(f, "not a nimbus database file"),
+                FormatError::NewerFormat(version) => write!(
+                    f,
+                    "database file has format version {}, this build reads up to {}",
+                    version, FORMAT_VERSION
+                ),
+                FormatError::PageSize(size) => write!(
+                    f,
+                    "database file has {} byte pages, this build uses {}",
+                    size, PAGE_SIZE
+                ),
+                FormatError::UnknownFeatures(features) => write!(
+                    f,
+                    "database file uses format features this build does not know ({:#x})",
+                    features
+                ),
+                FormatError::ChecksumMismatch => write!(f, "database file header is damaged"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::page::base::init_page_buf;
+    use errors::FormatError;
+
+    #[test]
+    fn test_superblock_roundtrip_and_refusals() {
+        let mut buf = [0u8; PAGE_SIZE];
+        let mut superblock = Superblock::new(1, 123);
+        superblock.next_page_id = 4567;
+        superblock.write_to(&mut buf);
+        assert_eq!(Superblock::read_from(&buf), Ok(superblock));
+
+        let patched = |at: usize, value: u32| {
+            let mut buf = buf;
+            buf[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            checksum::stamp(&mut buf);
+            Superblock::read_from(&buf)
+        };
+        assert_eq!(
+            patched(VERSION_AT, FORMAT_VERSION + 1),
+            Err(FormatError::NewerFormat(FORMAT_VERSION + 1))
+        );
+        assert_eq!(
+            patched(PAGE_SIZE_AT, 8192),
+            Err(FormatError::PageSize(8192))
+        );
+        assert_eq!(
+            patched(FEATURES_AT, 0b100),
+            Err(FormatError::UnknownFeatures(0b100))
+        );
+
+        let mut torn = buf;
+        torn[ROOT_DIRECTORY_AT] ^= 1;
+        assert_eq!(
+            Superblock::read_from(&torn),
+            Err(FormatError::ChecksumMismatch)
+        );
+
+        // The first page of an old file is the first directory page
+        let mut old = [0u8; PAGE_SIZE];
+        init_page_buf(&mut old, PageKind::Directory);
+        checksum::stamp(&mut old);
+        assert_eq!(Superblock::read_from(&old), Err(FormatError::Unversioned));
+        assert_eq!(
+            Superblock::read_from(&[b'x'; PAGE_SIZE]),
+            Err(FormatError::NotADatabase)
+        );
+    }
+}
@@ -1,9 +1,10 @@
-use crate::storage::buffer::buffer_pool::BufferPoolCore;
+use crate::storage::buffer::buffer_pool::{self, BufferPoolCore};
+use crate::storage::disk;
 use crate::storage::page::{
-    self,
-    base,
+    self, base,
     directory::{Directory, DirectoryEntry},
 };
+use std::collections::{HashMap, HashSet};
 use std::pin::Pin;
 
 pub mod errors {
@@ -41,9 +42,52 @@ pub mod errors {
         PageNotFoundError,
         UnpinError(buffer_pool::errors::UnpinFrameError),
     }
+
+    #[derive(Debug)]
+    pub enum UnregisterPageError {
+        PageFetchError(buffer_pool::errors::FetchPageError),
+        PageNotFoundError,
+        RemoveEntryError,
+    }
+
+    #[derive(Debug)]
+    pub enum UnregisterFileError {
+        PageFetchError(buffer_pool::errors::FetchPageError),
+        FindOffsetError(FindOffsetError),
+        RemoveEntryError,
+    }
+
+    #[derive(Debug)]
+    pub enum SlotError {
+        PageFetchError(buffer_pool::errors::FetchPageError),
+        FindOffsetError(FindOffsetError),
+        PageNotFoundError,
+    }
+
+    #[derive(Debug)]
+    pub enum ScanError {
+        PageFetchError(buffer_pool::errors::FetchPageError),
+        FindOffsetError(FindOffsetError),
+    }
+
+    #[derive(Debug)]
+    pub enum FreeListError {
+        PageFetchError(buffer_pool::errors::FetchPageError),
+        FreePageError(buffer_pool::errors::FreePageError),
+        NoDirectory,
+        OffsetTooLarge,
+        IOError,
+    }
 }
 
-pub trait PageLocator {
+pub trait PageLocator: Send {
+    /// Address of the first directory page, where every lookup starts
+    fn root_directory(&self) -> u64;
+
+    /// Moves the first directory page to `file_offset`, as the superblock of the database
+    /// file says. Must be called before the directory is used.
+    fn set_root_directory(&mut self, file_offset: u64);
+
     /// Finds the physical file offset for a given logical page ID
     fn find_file_offset(
         &mut self,
@@ -74,21 +118,185 @@ pub trait PageLocator {
         new_free_space: u32,
         bp: Pin<&mut BufferPoolCore>,
     ) -> Result<(), errors::UpdateSpaceError>;
+
+    /// Records where a page of a compressed segment is now stored
+    fn update_page_slot(
+        &mut self,
+        page_id: base::PageId,
+        slot: disk::Slot,
+        bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<(), errors::SlotError>;
+
+    /// The recorded slots of the pages in compressed segment `file_id`, by page index
+    fn file_slots(
+        &mut self,
+        file_id: u32,
+        bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<Vec<(u64, disk::Slot)>, errors::SlotError>;
+
+    /// The highest page id with a mapping, directory pages included
+    fn max_page_id(
+        &mut self,
+        bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<base::PageId, errors::ScanError>;
+
+    /// The ids of the pages mapped into file `file_id`, directory pages included
+    fn file_page_ids(
+        &mut self,
+        file_id: u32,
+        bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<Vec<base::PageId>, errors::ScanError>;
+
+    /// Removes the mapping for a logical page ID so it can no longer be located
+    fn unregister_page(
+        &mut self,
+        page_id: base::PageId,
+        bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<(), errors::UnregisterPageError>;
+
+    /// Removes the mappings of every page in file `file_id`, a segment that is going away
+    fn unregister_file(
+        &mut self,
+        file_id: u32,
+        bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<(), errors::UnregisterFileError>;
+
+    /// Puts the page at `file_offset` on the free list of its file. Nothing may refer to it
+    /// any more.
+    fn release_page(
+        &mut self,
+        file_offset: u64,
+        bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<(), errors::FreeListError>;
+
+    /// Takes a page off the free list of file `file_id`, or None if the file has to grow
+    /// instead
+    fn reuse_page(
+        &mut self,
+        file_id: u32,
+        bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<Option<u64>, errors::FreeListError>;
+
+    /// Shrinks file `file_id` by the free pages at its end. Returns how many pages were
+    /// cut off.
+    fn trim_free_pages(
+        &mut self,
+        file_id: u32,
+        bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<u64, errors::FreeListError>;
 }
 
+/// Maps page ids to addresses through a chain of directory pages in the database file,
+/// which also cover the pages of every segment. Each file keeps its own free list, headed
+/// in its first page: the first directory page, or in a segment a header page that is an
+/// empty directory page.
 pub struct DirectoryPageLocator {
     dir_page_1_offset: u64,
+    // Heads of the free lists once read from disk, by file. Only this locator moves them,
+    // so allocations with nothing to reuse need not touch the directory.
+    free_list_heads: HashMap<u32, Option<u64>>,
 }
 
 impl DirectoryPageLocator {
     pub fn new() -> Self {
         Self {
-            dir_page_1_offset: 0, // Offset 0 is always the first directory page
+            // Where files from before the superblock keep it, see `set_root_directory`
+            dir_page_1_offset: 0,
+            free_list_heads: HashMap::new(),
         }
     }
 }
 
+impl DirectoryPageLocator {
+    /// Address of the page that holds the head of the free list of file `file_id`
+    fn header_address(&self, file_id: u32) -> u64 {
+        match file_id {
+            disk::MAIN_FILE_ID => self.dir_page_1_offset,
+            segment => disk::page_address(segment, 0),
+        }
+    }
+
+    /// Runs `f` on the first page of file `file_id`, which holds the head of its free list.
+    /// Returns None if the file does not have that page yet.
+    fn with_file_header<R>(
+        &self,
+        file_id: u32,
+        mut bp: Pin<&mut BufferPoolCore>,
+        modifies: bool,
+        f: impl FnOnce(&mut Directory) -> R,
+    ) -> Result<Option<R>, errors::FreeListError> {
+        let header_address = self.header_address(file_id);
+        let page_count = bp
+            .as_mut()
+            .page_count(file_id)
+            .map_err(|_| errors::FreeListError::PageFetchError(Default::default()))?;
+        if page_count <= disk::address_index(header_address) {
+            return Ok(None);
+        }
+
+        let frame = bp
+            .as_mut()
+            .fetch_page_at_offset(header_address)
+            .map_err(errors::FreeListError::PageFetchError)?;
+        let frame_id = frame.fid();
+        let mut page_view = frame.page_view();
+
+        let result = if let page::base::Page::Directory(dir_page) = &mut page_view {
+            Some(f(dir_page))
+        } else {
+            None
+        };
+
+        if modifies && result.is_some() {
+            bp.as_mut().mark_frame_dirty(frame_id);
+        }
+        bp.as_mut().unpin_frame(frame_id).ok();
+        Ok(result)
+    }
+
+    fn get_free_list_head(
+        &mut self,
+        file_id: u32,
+        bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<Option<u64>, errors::FreeListError> {
+        if let Some(&head) = self.free_list_heads.get(&file_id) {
+            return Ok(head);
+        }
+        // A file without a directory has nothing on its free list either
+        let head = self
+            .with_file_header(file_id, bp, false, |dir| dir.free_list_head())?
+            .flatten()
+            .map(|index| disk::page_address(file_id, index));
+        self.free_list_heads.insert(file_id, head);
+        Ok(head)
+    }
+
+    fn set_free_list_head(
+        &mut self,
+        file_id: u32,
+        bp: Pin<&mut BufferPoolCore>,
+        head: Option<u64>,
+    ) -> Result<(), errors::FreeListError> {
+        let index = head.map(disk::address_index);
+        self.with_file_header(file_id, bp, true, |dir| dir.set_free_list_head(index))?
+            .ok_or(errors::FreeListError::NoDirectory)?
+            .map_err(|_| errors::FreeListError::OffsetTooLarge)?;
+        self.free_list_heads.insert(file_id, head);
+        Ok(())
+    }
+}
+
 impl PageLocator for DirectoryPageLocator {
+    fn root_directory(&self) -> u64 {
+        self.dir_page_1_offset
+    }
+
+    fn set_root_directory(&mut self, file_offset: u64) {
+        self.dir_page_1_offset = file_offset;
+        // The head of the free list of the database file is kept there
+        self.free_list_heads.remove(&disk::MAIN_FILE_ID);
+    }
+
     fn find_file_offset(
         &mut self,
         page_id: base::PageId,
@@ -145,10 +353,13 @@ impl PageLocator for DirectoryPageLocator {
             return Err(errors::RegisterPageError::InvalidOffset);
         }
 
+        let slot = bp.packed_slot(file_offset);
         let entry = DirectoryEntry {
             page_id,
+            slot_sector: slot.map_or(0, |slot| slot.sector),
             file_offset,
             free_space,
+            slot_sectors: slot.map_or(0, |slot| slot.sectors),
         };
 
         let mut curr_dir_offset = self.dir_page_1_offset;
@@ -163,7 +374,13 @@ impl PageLocator for DirectoryPageLocator {
             let mut page_view = curr_frame.page_view();
 
             if let page::base::Page::Directory(dir_page) = &mut page_view {
-                if dir_page.free_space() >= (Directory::ENTRY_SIZE as u32) {
+                // The tail page keeps one slot free for the entry of the next directory page,
+                // otherwise the chain could never be extended
+                let reserved = match dir_page.next_directory_page_id() {
+                    Some(_) => 0,
+                    None => Directory::ENTRY_SIZE as u32,
+                };
+                if dir_page.free_space() >= Directory::ENTRY_SIZE as u32 + reserved {
                     dir_page
                         .add_entry(entry)
                         .map_err(|_| errors::RegisterPageError::AddEntryError)?;
@@ -292,4 +509,392 @@ impl PageLocator for DirectoryPageLocator {
             }
         }
     }
+
+    fn update_page_slot(
+        &mut self,
+        page_id: base::PageId,
+        slot: disk::Slot,
+        mut bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<(), errors::SlotError> {
+        let mut curr_dir_offset = self.dir_page_1_offset;
+
+        loop {
+            let curr_frame = bp
+                .as_mut()
+                .fetch_page_at_offset(curr_dir_offset)
+                .map_err(errors::SlotError::PageFetchError)?;
+
+            let curr_frame_id = curr_frame.fid();
+            let mut page_view = curr_frame.page_view();
+
+            let page::base::Page::Directory(dir_page) = &mut page_view else {
+                bp.as_mut().unpin_frame(curr_frame_id).ok();
+                return Err(errors::SlotError::PageFetchError(Default::default()));
+            };
+
+            for i in 0..dir_page.num_entries() as usize {
+                if dir_page.entry_page_id(i) == Some(page_id) {
+                    dir_page.set_entry_slot(i, slot.sector, slot.sectors);
+                    bp.as_mut().mark_frame_dirty(curr_frame_id);
+                    bp.as_mut().unpin_frame(curr_frame_id).ok();
+                    return Ok(());
+                }
+            }
+            let next_page_id = dir_page.next_directory_page_id();
+            bp.as_mut().unpin_frame(curr_frame_id).ok();
+
+            match next_page_id {
+                Some(next_page_id) => {
+                    curr_dir_offset = self
+                        .find_file_offset(next_page_id, bp.as_mut())
+                        .map_err(errors::SlotError::FindOffsetError)?;
+                }
+                None => return Err(errors::SlotError::PageNotFoundError),
+            }
+        }
+    }
+
+    fn file_slots(
+        &mut self,
+        file_id: u32,
+        mut bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<Vec<(u64, disk::Slot)>, errors::SlotError> {
+        let mut slots = Vec::new();
+        let mut curr_dir_offset = self.dir_page_1_offset;
+
+        loop {
+            let curr_frame = bp
+                .as_mut()
+                .fetch_page_at_offset(curr_dir_offset)
+                .map_err(errors::SlotError::PageFetchError)?;
+
+            let curr_frame_id = curr_frame.fid();
+            let mut page_view = curr_frame.page_view();
+
+            let page::base::Page::Directory(dir_page) = &mut page_view else {
+                bp.as_mut().unpin_frame(curr_frame_id).ok();
+                return Err(errors::SlotError::PageFetchError(Default::default()));
+            };
+
+            // Pages never written back have no slot yet
+            for i in 0..dir_page.num_entries() as usize {
+                let entry = dir_page.entry_at(i).unwrap();
+                if disk::address_file(entry.file_offset) == file_id && entry.slot_sectors > 0 {
+                    let slot = disk::Slot {
+                        sector: entry.slot_sector,
+                        sectors: entry.slot_sectors,
+                    };
+                    slots.push((disk::address_index(entry.file_offset), slot));
+                }
+            }
+            let next_page_id = dir_page.next_directory_page_id();
+            bp.as_mut().unpin_frame(curr_frame_id).ok();
+
+            match next_page_id {
+                Some(next_page_id) => {
+                    curr_dir_offset = self
+                        .find_file_offset(next_page_id, bp.as_mut())
+                        .map_err(errors::SlotError::FindOffsetError)?;
+                }
+                None => return Ok(slots),
+            }
+        }
+    }
+
+    fn max_page_id(
+        &mut self,
+        mut bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<base::PageId, errors::ScanError> {
+        let mut max_page_id = 0;
+        let mut curr_dir_offset = self.dir_page_1_offset;
+
+        loop {
+            let curr_frame = bp
+                .as_mut()
+                .fetch_page_at_offset(curr_dir_offset)
+                .map_err(errors::ScanError::PageFetchError)?;
+
+            let curr_frame_id = curr_frame.fid();
+            let mut page_view = curr_frame.page_view();
+
+            let page::base::Page::Directory(dir_page) = &mut page_view else {
+                bp.as_mut().unpin_frame(curr_frame_id).ok();
+                return Err(errors::ScanError::PageFetchError(Default::default()));
+            };
+
+            for i in 0..dir_page.num_entries() as usize {
+                max_page_id = max_page_id.max(dir_page.entry_page_id(i).unwrap_or(0));
+            }
+            let next_page_id = dir_page.next_directory_page_id();
+            bp.as_mut().unpin_frame(curr_frame_id).ok();
+
+            match next_page_id {
+                Some(next_page_id) => {
+                    curr_dir_offset = self
+                        .find_file_offset(next_page_id, bp.as_mut())
+                        .map_err(errors::ScanError::FindOffsetError)?;
+                }
+                None => return Ok(max_page_id),
+            }
+        }
+    }
+
+    fn file_page_ids(
+        &mut self,
+        file_id: u32,
+        mut bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<Vec<base::PageId>, errors::ScanError> {
+        let mut page_ids = Vec::new();
+        let mut curr_dir_offset = self.dir_page_1_offset;
+
+        loop {
+            let curr_frame = bp
+                .as_mut()
+                .fetch_page_at_offset(curr_dir_offset)
+                .map_err(errors::ScanError::PageFetchError)?;
+
+            let curr_frame_id = curr_frame.fid();
+            let mut page_view = curr_frame.page_view();
+
+            let page::base::Page::Directory(dir_page) = &mut page_view else {
+                bp.as_mut().unpin_frame(curr_frame_id).ok();
+                return Err(errors::ScanError::PageFetchError(Default::default()));
+            };
+
+            for i in 0..dir_page.num_entries() as usize {
+                let entry = dir_page.entry_at(i).unwrap();
+                if disk::address_file(entry.file_offset) == file_id {
+                    page_ids.push(entry.page_id);
+                }
+            }
+            let next_page_id = dir_page.next_directory_page_id();
+            bp.as_mut().unpin_frame(curr_frame_id).ok();
+
+            match next_page_id {
+                Some(next_page_id) => {
+                    curr_dir_offset = self
+                        .find_file_offset(next_page_id, bp.as_mut())
+                        .map_err(errors::ScanError::FindOffsetError)?;
+                }
+                None => return Ok(page_ids),
+            }
+        }
+    }
+
+    fn unregister_page(
+        &mut self,
+        page_id: base::PageId,
+        mut bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<(), errors::UnregisterPageError> {
+        let mut curr_dir_offset = self.dir_page_1_offset;
+
+        loop {
+            let curr_frame = bp
+                .as_mut()
+                .fetch_page_at_offset(curr_dir_offset)
+                .map_err(errors::UnregisterPageError::PageFetchError)?;
+
+            let curr_frame_id = curr_frame.fid();
+            let mut page_view = curr_frame.page_view();
+
+            if let page::base::Page::Directory(dir_page) = &mut page_view {
+                let num_entries = dir_page.num_entries();
+                for i in 0..num_entries {
+                    if dir_page.entry_page_id(i as usize) == Some(page_id) {
+                        let res = dir_page
+                            .remove_entry_at(i as usize)
+                            .map_err(|_| errors::UnregisterPageError::RemoveEntryError);
+
+                        bp.as_mut().mark_frame_dirty(curr_frame_id);
+                        bp.as_mut().unpin_frame(curr_frame_id).ok();
+                        return res;
+                    }
+                }
+
+                if let Some(next_page_id) = dir_page.next_directory_page_id() {
+                    bp.as_mut().unpin_frame(curr_frame_id).ok();
+                    match self.find_file_offset(next_page_id, bp.as_mut()) {
+                        Ok(offset) => curr_dir_offset = offset,
+                        Err(_) => return Err(errors::UnregisterPageError::PageNotFoundError),
+                    }
+                } else {
+                    bp.as_mut().unpin_frame(curr_frame_id).ok();
+                    return Err(errors::UnregisterPageError::PageNotFoundError);
+                }
+            } else {
+                bp.as_mut().unpin_frame(curr_frame_id).ok();
+                return Err(errors::UnregisterPageError::PageFetchError(
+                    Default::default(),
+                ));
+            }
+        }
+    }
+
+    fn unregister_file(
+        &mut self,
+        file_id: u32,
+        mut bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<(), errors::UnregisterFileError> {
+        self.free_list_heads.remove(&file_id);
+        let mut curr_dir_offset = self.dir_page_1_offset;
+
+        loop {
+            let curr_frame = bp
+                .as_mut()
+                .fetch_page_at_offset(curr_dir_offset)
+                .map_err(errors::UnregisterFileError::PageFetchError)?;
+
+            let curr_frame_id = curr_frame.fid();
+            let mut page_view = curr_frame.page_view();
+
+            let page::base::Page::Directory(dir_page) = &mut page_view else {
+                bp.as_mut().unpin_frame(curr_frame_id).ok();
+                return Err(errors::UnregisterFileError::PageFetchError(
+                    Default::default(),
+                ));
+            };
+
+            // Backwards, so removing an entry does not move the ones still to be looked at
+            let mut removed = false;
+            for i in (0..dir_page.num_entries() as usize).rev() {
+                let in_file = dir_page
+                    .entry_at(i)
+                    .is_some_and(|entry| disk::address_file(entry.file_offset) == file_id);
+                if in_file {
+                    if dir_page.remove_entry_at(i).is_err() {
+                        bp.as_mut().unpin_frame(curr_frame_id).ok();
+                        return Err(errors::UnregisterFileError::RemoveEntryError);
+                    }
+                    removed = true;
+                }
+            }
+            let next_page_id = dir_page.next_directory_page_id();
+
+            if removed {
+                bp.as_mut().mark_frame_dirty(curr_frame_id);
+            }
+            bp.as_mut().unpin_frame(curr_frame_id).ok();
+
+            match next_page_id {
+                Some(next_page_id) => {
+                    curr_dir_offset = self
+                        .find_file_offset(next_page_id, bp.as_mut())
+                        .map_err(errors::UnregisterFileError::FindOffsetError)?;
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn release_page(
+        &mut self,
+        file_offset: u64,
+        mut bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<(), errors::FreeListError> {
+        let file_id = disk::address_file(file_offset);
+        let head = self.get_free_list_head(file_id, bp.as_mut())?;
+
+        // The page goes to disk before the head moves, so a crash in between only leaks it
+        bp.as_mut()
+            .write_free_page(file_offset, head)
+            .map_err(errors::FreeListError::FreePageError)?;
+
+        self.set_free_list_head(file_id, bp, Some(file_offset))
+    }
+
+    fn reuse_page(
+        &mut self,
+        file_id: u32,
+        mut bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<Option<u64>, errors::FreeListError> {
+        let Some(head) = self.get_free_list_head(file_id, bp.as_mut())? else {
+            return Ok(None);
+        };
+
+        let next = match bp.as_mut().read_free_page(head) {
+            Ok(next) => next,
+            // A damaged list only costs us the pages still on it, so drop it and let the
+            // file grow rather than fail every allocation from here on
+            Err(
+                buffer_pool::errors::FreePageError::NotFree
+                | buffer_pool::errors::FreePageError::ChecksumMismatch,
+            ) => {
+                self.set_free_list_head(file_id, bp, None)?;
+                return Ok(None);
+            }
+            Err(e) => return Err(errors::FreeListError::FreePageError(e)),
+        };
+
+        self.set_free_list_head(file_id, bp, next)?;
+        Ok(Some(head))
+    }
+
+    fn trim_free_pages(
+        &mut self,
+        file_id: u32,
+        mut bp: Pin<&mut BufferPoolCore>,
+    ) -> Result<u64, errors::FreeListError> {
+        let page_count = bp
+            .as_mut()
+            .page_count(file_id)
+            .map_err(|_| errors::FreeListError::IOError)?;
+
+        // Pages are looked at by their index within the file from here on
+        let mut free = Vec::new();
+        let mut next = self.get_free_list_head(file_id, bp.as_mut())?;
+        while let Some(offset) = next {
+            // More entries than pages means the list loops back on itself
+            if free.len() as u64 >= page_count {
+                return Err(errors::FreeListError::FreePageError(
+                    buffer_pool::errors::FreePageError::NotFree,
+                ));
+            }
+            free.push(disk::address_index(offset));
+            next = bp
+                .as_mut()
+                .read_free_page(offset)
+                .map_err(errors::FreeListError::FreePageError)?;
+        }
+
+        let free_set: HashSet<u64> = free.iter().copied().collect();
+        let header_index = disk::address_index(self.header_address(file_id));
+        let mut new_count = page_count;
+        while new_count > header_index + 1 && free_set.contains(&(new_count - 1)) {
+            new_count -= 1;
+        }
+        if new_count == page_count {
+            return Ok(0);
+        }
+
+        // Unlink the pages past the new end, rewriting only those whose successor changes
+        let address = |index: u64| disk::page_address(file_id, index);
+        let kept: Vec<usize> = (0..free.len()).filter(|&i| free[i] < new_count).collect();
+        for (k, &i) in kept.iter().enumerate() {
+            let successor = kept.get(k + 1).map(|&j| free[j]);
+            if free.get(i + 1).copied() != successor {
+                bp.as_mut()
+                    .write_free_page(address(free[i]), successor.map(address))
+                    .map_err(errors::FreeListError::FreePageError)?;
+            }
+        }
+        self.set_free_list_head(
+            file_id,
+            bp.as_mut(),
+            kept.first().map(|&i| address(free[i])),
+        )?;
+
+        // The head on disk must not point past the end of the file
+        let frame = bp
+            .as_mut()
+            .fetch_page_at_offset(self.header_address(file_id))
+            .map_err(errors::FreeListError::PageFetchError)?;
+        let frame_id = frame.fid();
+        let flushed = bp.as_mut().flush_frame(frame_id);
+        bp.as_mut().unpin_frame(frame_id).ok();
+        flushed.map_err(|_| errors::FreeListError::IOError)?;
+
+        bp.truncate(file_id, new_count)
+            .map_err(|_| errors::FreeListError::IOError)?;
+        Ok(page_count - new_count)
+    }
 }
@@ -1,4 +1,4 @@
-use crate::storage::buffer::BufferPool;
+use crate::storage::buffer::{AccessStrategy, BufferPool};
 use crate::storage::page::base::{PageId, PageKind};
 use std::pin::Pin;
 use std::sync::Arc;
@@ -15,7 +15,7 @@ pub enum WriteOperation {
         page_kind: PageKind,
         new_data: Vec<u8>,
     },
-    /// Mark a page as free/available for reuse (future work)
+    /// Put a page on the free list for reuse
     PageDeallocation { page_id: PageId },
 }
 
@@ -42,7 +42,7 @@ impl Transaction {
 
     /// Applies the deferred writes to the BufferPool and ensures disk persistence (Atomicity).
     /// Returns the highest PageId/OID allocated by this transaction for system updates.
-    pub fn commit(mut self, mut bpm: Pin<&mut BufferPool>) -> Result<u32, String> {
+    pub fn commit(self, mut bpm: Pin<&mut BufferPool>) -> Result<u32, String> {
         let mut max_oid = 0;
 
         // --- PHASE 1: APPLY WRITES TO MEMORY (Bypassing standard cycle) ---
@@ -53,7 +53,9 @@ impl Transaction {
                     page_id, new_data, ..
                 } => (page_id, new_data),
                 WriteOperation::PageDeallocation { page_id } => {
-                    // Log the max OID but skip application for now
+                    bpm.as_mut()
+                        .free_page(page_id)
+                        .map_err(|e| format!("Commit failed (free page): {}", e))?;
                     if page_id > max_oid {
                         max_oid = page_id;
                     }
@@ -64,14 +66,15 @@ impl Transaction {
             // 1. Fetch the page (will load if not in cache, pinning it)
             let frame = bpm
                 .as_mut()
-                .fetch_page(page_id)
+                .fetch_page(page_id, AccessStrategy::Normal)
                 .map_err(|e| format!("Commit failed (fetch page): {:?}", e))?;
             let fid = frame.fid();
 
             // 2. Overwrite the raw buffer directly (using unsafe access to the frame pointer)
             unsafe {
                 // Assumes the Frame pointer is safe to use directly
-                std::ptr::copy_nonoverlapping(new_data.as_ptr(), frame.buf_ptr, new_data.len());
+                let dst = frame.buf_ptr().cast::<u8>();
+                std::ptr::copy_nonoverlapping(new_data.as_ptr(), dst, new_data.len());
             }
 
             // 3. Mark dirty and unpin (we assume the caller expects the frame to be unpinned after commit)
@@ -87,6 +90,9 @@ impl Transaction {
         bpm.as_mut()
             .flush_all()
             .map_err(|e| format!("Commit failed (flush): {:?}", e))?;
+        // Written back is not durable yet: a power loss can still take the pages with it
+        bpm.sync()
+            .map_err(|e| format!("Commit failed (sync): {:?}", e))?;
 
         // The maximum OID allocated by this TX is returned for the Catalog to update the global counter.
         Ok(max_oid)
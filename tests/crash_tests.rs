@@ -0,0 +1,414 @@
+//! Crash consistency: random workloads run against a simulated disk that loses power,
+//! then the database is reopened and checked against what was known to be durable.
+
+use nimbus::catalog::manager::Catalog;
+use nimbus::constants::storage::PAGE_SIZE;
+use nimbus::rt_type::primitives::{
+    AttributeKind, AttributeValue, TableAttribute, TableLayout, TableType,
+};
+use nimbus::storage::buffer::buffer_pool::MIN_FRAME_COUNT;
+use nimbus::storage::buffer::buffer_pool::errors::FetchPageError;
+use nimbus::storage::buffer::fifo_evictor::FifoEvictor;
+use nimbus::storage::buffer::{AccessStrategy, BufferPool};
+use nimbus::storage::disk::SimulatedDisk;
+use nimbus::storage::heap::iterator::HeapIterator;
+use nimbus::storage::heap::row::RowId;
+use nimbus::storage::heap::tuple::Tuple;
+use nimbus::storage::page_locator::locator::DirectoryPageLocator;
+use nimbus::storage::transaction::{Transaction, WriteOperation};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::atomic::AtomicU32;
+use std::sync::{Arc, Mutex};
+
+const SEEDS: u64 = 24;
+
+/// xorshift64*: the workloads only need to be random-looking and repeatable.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) % n
+    }
+}
+
+fn schema() -> TableType {
+    let column = |name: &str, kind| TableAttribute {
+        name: name.into(),
+        kind,
+        nullable: false,
+        is_internal: false,
+    };
+    TableType {
+        attributes: vec![
+            column("id", AttributeKind::U32),
+            column("payload", AttributeKind::Varchar),
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    }
+}
+
+/// A small pool, so the workloads also write pages back through eviction.
+fn open(disk: &SimulatedDisk) -> (Arc<Mutex<BufferPool>>, Catalog) {
+    let bp = Arc::new(Mutex::new(BufferPool::with_frame_count(
+        Box::new(disk.device()),
+        Box::new(FifoEvictor::new()),
+        Box::new(DirectoryPageLocator::new()),
+        MIN_FRAME_COUNT,
+    )));
+    let catalog = Catalog::new(bp.clone());
+    (bp, catalog)
+}
+
+fn checkpoint(bp: &Arc<Mutex<BufferPool>>) -> Result<(), String> {
+    let mut bp_guard = bp.lock().unwrap();
+    let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    pinned_bp
+        .checkpoint()
+        .map(|_| ())
+        .map_err(|e| format!("{:?}", e))
+}
+
+/// Every row of `items` by id, with where it lives.
+fn scan(
+    bp: &Arc<Mutex<BufferPool>>,
+    catalog: &Catalog,
+) -> Result<BTreeMap<u32, (RowId, String)>, String> {
+    let oid = catalog.get_table_oid("items").ok_or("items is missing")?;
+    let root = catalog
+        .get_table_root_page(oid)
+        .ok_or("items has no root")?;
+    let mut bp_guard = bp.lock().unwrap();
+    let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+
+    let mut rows = BTreeMap::new();
+    let mut iter = HeapIterator::new(pinned_bp, root);
+    while let Some(row) = iter.next() {
+        let (rid, bytes) = row.map_err(|e| format!("{:?}", e))?;
+        let tuple = Tuple::from_bytes(&bytes, &schema())?;
+        match &tuple.values[..] {
+            [AttributeValue::U32(id), AttributeValue::Varchar(payload)] => {
+                if rows.insert(*id, (rid, payload.clone())).is_some() {
+                    return Err(format!("id {} is stored twice", id));
+                }
+            }
+            other => return Err(format!("unexpected row {:?}", other)),
+        }
+    }
+    Ok(rows)
+}
+
+/// A fresh database holding an indexed `items` table, made durable.
+fn create_items(disk: &SimulatedDisk) -> (Arc<Mutex<BufferPool>>, Catalog) {
+    let (bp, mut catalog) = open(disk);
+    catalog.create_table("items", schema()).unwrap();
+    catalog.create_index("items_id", "items", "id").unwrap();
+    checkpoint(&bp).unwrap();
+    (bp, catalog)
+}
+
+/// Runs one random statement against `items` and the model of its contents.
+/// Payload lengths vary a lot, so updates both fit in place and relocate rows.
+fn random_statement(
+    rng: &mut Rng,
+    bp: &Arc<Mutex<BufferPool>>,
+    catalog: &Catalog,
+    model: &mut BTreeMap<u32, String>,
+    next_id: &mut u32,
+) -> Result<(), String> {
+    let oid = catalog.get_table_oid("items").unwrap();
+    let payload = |rng: &mut Rng, id: u32| {
+        let len = 1 + rng.below(300) as usize;
+        format!("{}:{}", id, "x".repeat(len))
+    };
+    let pick = |rng: &mut Rng, model: &BTreeMap<u32, String>| {
+        let n = rng.below(model.len() as u64) as usize;
+        *model.keys().nth(n).unwrap()
+    };
+
+    let roll = rng.below(100);
+    if roll < 50 || model.is_empty() {
+        let id = *next_id;
+        *next_id += 1;
+        let text = payload(rng, id);
+        let tuple = Tuple::new(vec![
+            AttributeValue::U32(id),
+            AttributeValue::Varchar(text.clone()),
+        ]);
+        let mut bp_guard = bp.lock().unwrap();
+        let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        catalog.insert_tuple(oid, &tuple, &schema(), pinned_bp)?;
+        model.insert(id, text);
+    } else if roll < 75 {
+        let id = pick(rng, model);
+        let (rid, _) = scan(bp, catalog)?[&id].clone();
+        let mut bp_guard = bp.lock().unwrap();
+        let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        catalog.delete_tuple(oid, rid, pinned_bp)?;
+        model.remove(&id);
+    } else {
+        let id = pick(rng, model);
+        let (rid, _) = scan(bp, catalog)?[&id].clone();
+        let text = payload(rng, id);
+        let tuple = Tuple::new(vec![
+            AttributeValue::U32(id),
+            AttributeValue::Varchar(text.clone()),
+        ]);
+        let mut bp_guard = bp.lock().unwrap();
+        let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        catalog.update_tuple(oid, rid, &tuple, &schema(), pinned_bp)?;
+        model.insert(id, text);
+    }
+    Ok(())
+}
+
+/// The reopened database holds exactly `expected`, and its index agrees with the heap.
+fn assert_recovered(disk: &SimulatedDisk, expected: &BTreeMap<u32, String>, seed: u64) {
+    let (bp, catalog) = open(disk);
+    let rows = scan(&bp, &catalog).unwrap_or_else(|e| panic!("seed {}: {}", seed, e));
+    let payloads: BTreeMap<u32, String> = rows.into_iter().map(|(id, (_, p))| (id, p)).collect();
+    assert_eq!(
+        &payloads, expected,
+        "seed {}: rows differ after the crash",
+        seed
+    );
+
+    let report = catalog.verify_index("items_id").unwrap();
+    assert!(
+        report.problems.is_empty(),
+        "seed {}: {:?}",
+        seed,
+        report.problems
+    );
+    assert_eq!(
+        report.entries,
+        expected.len(),
+        "seed {}: index misses rows",
+        seed
+    );
+}
+
+#[test]
+fn test_crash_between_statements_keeps_last_checkpoint() {
+    for seed in 0..SEEDS {
+        let mut rng = Rng::new(seed);
+        let disk = SimulatedDisk::new();
+        let (bp, catalog) = create_items(&disk);
+
+        let mut model = BTreeMap::new();
+        let mut durable = BTreeMap::new();
+        let mut next_id = 0;
+        let crash_at = 20 + rng.below(180);
+        for _ in 0..crash_at {
+            if rng.below(10) == 0 {
+                checkpoint(&bp).unwrap();
+                durable = model.clone();
+            } else {
+                random_statement(&mut rng, &bp, &catalog, &mut model, &mut next_id).unwrap();
+            }
+        }
+
+        disk.crash();
+        drop(catalog);
+        drop(bp);
+        assert_recovered(&disk, &durable, seed);
+    }
+}
+
+#[test]
+fn test_crash_at_random_write() {
+    for seed in 0..SEEDS {
+        let mut rng = Rng::new(seed + 1000);
+        let disk = SimulatedDisk::new();
+        let (bp, catalog) = create_items(&disk);
+
+        let mut model = BTreeMap::new();
+        let mut durable = BTreeMap::new();
+        // Every version an id ever had, since a half-done checkpoint may bring back any
+        let mut history: HashMap<u32, HashSet<String>> = HashMap::new();
+        // Ids a statement has touched since the last checkpoint that went through
+        let mut touched: HashSet<u32> = HashSet::new();
+        let mut next_id = 0;
+        disk.crash_at_write(1 + rng.below(400), None);
+
+        let mut crashed_in_checkpoint = false;
+        for _ in 0..1000 {
+            let result = if rng.below(10) == 0 {
+                let result = checkpoint(&bp);
+                if result.is_ok() {
+                    durable = model.clone();
+                    touched.clear();
+                } else {
+                    crashed_in_checkpoint = true;
+                }
+                result
+            } else {
+                let before = model.clone();
+                let result = random_statement(&mut rng, &bp, &catalog, &mut model, &mut next_id);
+                for (id, payload) in &model {
+                    history.entry(*id).or_default().insert(payload.clone());
+                }
+                let ids = before.keys().chain(model.keys());
+                touched.extend(ids.filter(|id| before.get(id) != model.get(id)));
+                result
+            };
+            if result.is_err() {
+                break;
+            }
+        }
+        drop(catalog);
+        drop(bp);
+
+        if !crashed_in_checkpoint {
+            assert_recovered(&disk, &durable, seed);
+            continue;
+        }
+
+        // Checkpoints are not atomic: a crash halfway leaves old and new pages side by
+        // side. The database still opens and scans, nothing reads back that was never
+        // written, and rows no statement touched since the last checkpoint are all there.
+        let (bp, catalog) = open(&disk);
+        let rows = scan(&bp, &catalog)
+            .unwrap_or_else(|e| panic!("seed {}: scan after the crash failed: {}", seed, e));
+        for (id, (_, payload)) in &rows {
+            let known = history.get(id).is_some_and(|h| h.contains(payload));
+            assert!(known, "seed {}: id {} came back as {:?}", seed, id, payload);
+        }
+        for (id, payload) in durable.iter().filter(|(id, _)| !touched.contains(*id)) {
+            let found = rows.get(id).map(|(_, p)| p);
+            assert_eq!(found, Some(payload), "seed {}: id {} was lost", seed, id);
+        }
+    }
+}
+
+#[test]
+fn test_torn_page_is_detected() {
+    let disk = SimulatedDisk::new();
+    let (bp, catalog) = create_items(&disk);
+    let oid = catalog.get_table_oid("items").unwrap();
+    let root = catalog.get_table_root_page(oid).unwrap();
+    let torn = {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        // Tuples fill the page from the end, so this one lies past the torn part
+        let tuple = Tuple::new(vec![
+            AttributeValue::U32(1),
+            AttributeValue::Varchar("a".repeat(1000)),
+        ]);
+        catalog
+            .insert_tuple(oid, &tuple, &schema(), pinned_bp.as_mut())
+            .unwrap();
+        // The checkpoint writes the dirty pages in this order
+        let dirty = pinned_bp.dirty_page_table();
+        let pos = dirty.iter().position(|page| page.page_id == root).unwrap();
+        disk.crash_at_write(pos as u64 + 1, Some(512));
+        assert!(pinned_bp.checkpoint().is_err());
+        dirty[pos]
+    };
+    drop(catalog);
+    drop(bp);
+
+    // A bare pool: the catalog would not get far with a torn system page
+    let mut bp = Box::pin(BufferPool::new(
+        Box::new(disk.device()),
+        Box::new(FifoEvictor::new()),
+        Box::new(DirectoryPageLocator::new()),
+    ));
+    let err = bp
+        .as_mut()
+        .fetch_page_at_offset(torn.file_offset)
+        .map(|_| ())
+        .unwrap_err();
+    assert!(matches!(err, FetchPageError::ChecksumMismatch), "{:?}", err);
+}
+
+#[test]
+fn test_read_errors_surface() {
+    let disk = SimulatedDisk::new();
+    let (bp, catalog) = create_items(&disk);
+    let oid = catalog.get_table_oid("items").unwrap();
+    let mut model = BTreeMap::new();
+    let mut next_id = 0;
+    let mut rng = Rng::new(7);
+    for _ in 0..200 {
+        random_statement(&mut rng, &bp, &catalog, &mut model, &mut next_id).unwrap();
+    }
+    checkpoint(&bp).unwrap();
+    drop(catalog);
+    drop(bp);
+
+    // Reopen so the table's pages are no longer in the pool, then fail every read
+    let (bp, catalog) = open(&disk);
+    for file_id in disk.file_ids() {
+        for offset in 0..disk.durable_page_count(file_id) {
+            disk.fail_reads_of(file_id, offset);
+        }
+    }
+    assert!(scan(&bp, &catalog).is_err());
+
+    let tuple = Tuple::new(vec![
+        AttributeValue::U32(9999),
+        AttributeValue::Varchar("b".into()),
+    ]);
+    let mut bp_guard = bp.lock().unwrap();
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    assert!(
+        catalog
+            .insert_tuple(oid, &tuple, &schema(), pinned_bp.as_mut())
+            .is_err()
+    );
+}
+
+#[test]
+fn test_committed_transaction_survives_crash() {
+    let disk = SimulatedDisk::new();
+    let (bp, catalog) = create_items(&disk);
+    let oid = catalog.get_table_oid("items").unwrap();
+    let root = catalog.get_table_root_page(oid).unwrap();
+    let mut data = {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        let frame = pinned_bp
+            .as_mut()
+            .fetch_page(root, AccessStrategy::Normal)
+            .unwrap();
+        let data = unsafe { (*frame.buf_ptr()).to_vec() };
+        let fid = frame.fid();
+        pinned_bp.unpin_frame(fid).unwrap();
+        data
+    };
+
+    // The last byte is free space on an empty heap page
+    data[PAGE_SIZE - 1] = 42;
+    let mut tx = Transaction::new(Arc::new(AtomicU32::new(root)));
+    tx.write_set.push(WriteOperation::PageUpdate {
+        page_id: root,
+        new_data: data,
+    });
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        tx.commit(pinned_bp).unwrap();
+    }
+
+    disk.crash();
+    drop(catalog);
+    drop(bp);
+
+    let (bp, _catalog) = open(&disk);
+    let mut bp_guard = bp.lock().unwrap();
+    let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    let frame = pinned_bp.fetch_page(root, AccessStrategy::Normal).unwrap();
+    let last = unsafe { (*frame.buf_ptr())[PAGE_SIZE - 1] };
+    assert_eq!(last, 42, "The committed write was lost");
+}
@@ -1,4 +1,6 @@
-use nimbus::catalog::manager::Catalog;
+use nimbus::catalog::manager::{
+    Catalog, EqualityTerm, IndexMethod, IndexOptions, KeyExpr, TableOptions,
+};
 use nimbus::catalog::schema::SYSTEM_TABLES_ID;
 use nimbus::execution::delete::DeleteExecutor;
 use nimbus::execution::executor::Executor;
@@ -9,13 +11,21 @@ use nimbus::execution::projection::ProjectionExecutor;
 use nimbus::execution::seq_scan::SeqScanExecutor;
 use nimbus::execution::update::UpdateExecutor;
 use nimbus::execution::values::ValuesExecutor;
+use nimbus::parser::{AstExpr, AstStatement, AstValue, parse};
+use nimbus::planner::Planner;
 use nimbus::rt_type::primitives::{
     AttributeKind, AttributeValue, TableAttribute, TableLayout, TableType,
 };
-use nimbus::storage::buffer::BufferPool;
+use nimbus::storage::buffer::checkpoint::CheckpointRecord;
 use nimbus::storage::buffer::fifo_evictor::FifoEvictor;
-use nimbus::storage::disk::FileManager;
+use nimbus::storage::buffer::{AccessStrategy, BufferPool};
+#[cfg(feature = "encryption")]
+use nimbus::storage::disk::EncryptedDevice;
+use nimbus::storage::disk::{Compression, FileManager};
+use nimbus::storage::heap::heap_file::HeapFile;
+use nimbus::storage::heap::iterator::HeapIterator;
 use nimbus::storage::heap::tuple::Tuple;
+use nimbus::storage::page::base::PageKind;
 use nimbus::storage::page_locator::locator::DirectoryPageLocator;
 use std::fs;
 use std::fs::metadata;
@@ -26,11 +36,11 @@ fn setup_catalog(db_name: &str) -> (Arc<Mutex<BufferPool>>, Catalog) {
     let _ = fs::create_dir_all("test_db");
 
     let file_path = format!("test_db/{}", db_name);
-    let _ = fs::remove_file(&file_path);
+    let _ = FileManager::remove_database(&file_path);
 
     let fm = FileManager::new(file_path).unwrap();
     let bp = Arc::new(Mutex::new(BufferPool::new(
-        fm,
+        Box::new(fm),
         Box::new(FifoEvictor::new()),
         Box::new(DirectoryPageLocator::new()),
     )));
@@ -40,17 +50,16 @@ fn setup_catalog(db_name: &str) -> (Arc<Mutex<BufferPool>>, Catalog) {
 
 #[test]
 fn test_seq_scan_system_tables() {
-    let (bp, catalog) = setup_catalog("test_scan_sys.db");
+    let (bp, catalog) = Catalog::open_in_memory();
 
     let mut bp_guard = bp.lock().unwrap();
     let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
 
     let mut scan = SeqScanExecutor::new(&catalog, SYSTEM_TABLES_ID).expect("Failed to create scan");
-    scan.init(); 
+    scan.init();
 
     let mut count = 0;
-    while let Some(tuple) = scan.next(pinned_bp.as_mut()) {
-        
+    while let Some(tuple) = scan.next(pinned_bp.as_mut()).unwrap() {
         println!("Found system row: {:?}", tuple);
         count += 1;
     }
@@ -63,7 +72,7 @@ fn test_seq_scan_system_tables() {
 
 #[test]
 fn test_insert_and_filter() {
-    let (bp, mut catalog) = setup_catalog("test_filter.db");
+    let (bp, mut catalog) = Catalog::open_in_memory();
 
     // 1. Create Table
     let schema = TableType {
@@ -91,13 +100,13 @@ fn test_insert_and_filter() {
     let mut bp_guard = bp.lock().unwrap(); // Lock for insert
     let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
 
-    let mut insert_exec = InsertExecutor::new(values_exec, &catalog, table_oid).unwrap(); 
+    let mut insert_exec = InsertExecutor::new(values_exec, &catalog, table_oid).unwrap();
 
-    insert_exec.init(); 
-    insert_exec.next(pinned_bp.as_mut()); // Execute insert, pass bpm
+    insert_exec.init();
+    insert_exec.next(pinned_bp.as_mut()).unwrap(); // Execute insert, pass bpm
 
     // 3. Scan & Filter
-    let scan_exec = Box::new(SeqScanExecutor::new(&catalog, table_oid).unwrap()); 
+    let scan_exec = Box::new(SeqScanExecutor::new(&catalog, table_oid).unwrap());
 
     // Filter: WHERE age > 20
     let mut filter_exec = FilterExecutor::new(scan_exec, |t: &Tuple| match t.values[0] {
@@ -105,11 +114,10 @@ fn test_insert_and_filter() {
         _ => false,
     });
 
-    filter_exec.init(); 
+    filter_exec.init();
 
     let mut output_rows = 0;
-    while let Some(t) = filter_exec.next(pinned_bp.as_mut()) {
-        
+    while let Some(t) = filter_exec.next(pinned_bp.as_mut()).unwrap() {
         if let AttributeValue::U32(age) = t.values[0] {
             assert!(age > 20);
         }
@@ -121,7 +129,7 @@ fn test_insert_and_filter() {
 
 #[test]
 fn test_filter_execution() {
-    let (bp, mut catalog) = setup_catalog("test_filter_exec.db");
+    let (bp, mut catalog) = Catalog::open_in_memory();
 
     // 1. Create Table
     let schema = TableType {
@@ -149,24 +157,23 @@ fn test_filter_execution() {
     let mut bp_guard = bp.lock().unwrap();
     let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
 
-    let mut insert_exec = InsertExecutor::new(values_exec, &catalog, table_oid).unwrap(); 
+    let mut insert_exec = InsertExecutor::new(values_exec, &catalog, table_oid).unwrap();
 
-    insert_exec.init(); 
-    insert_exec.next(pinned_bp.as_mut()); // Execute insert, pass bpm
+    insert_exec.init();
+    insert_exec.next(pinned_bp.as_mut()).unwrap(); // Execute insert, pass bpm
 
     // 3. Filter: WHERE age > 20
-    let scan_exec = Box::new(SeqScanExecutor::new(&catalog, table_oid).unwrap()); 
+    let scan_exec = Box::new(SeqScanExecutor::new(&catalog, table_oid).unwrap());
 
     let mut filter_exec = FilterExecutor::new(scan_exec, |t: &Tuple| match t.values[0] {
         AttributeValue::U32(age) => age > 20,
         _ => false,
     });
 
-    filter_exec.init(); 
+    filter_exec.init();
 
     let mut output_rows = 0;
-    while let Some(t) = filter_exec.next(pinned_bp.as_mut()) {
-        
+    while let Some(t) = filter_exec.next(pinned_bp.as_mut()).unwrap() {
         println!("Filtered Row: {:?}", t);
         if let AttributeValue::U32(age) = t.values[0] {
             assert!(age > 20);
@@ -179,7 +186,7 @@ fn test_filter_execution() {
 
 #[test]
 fn test_projection_execution() {
-    let (bp, mut catalog) = setup_catalog("test_projection.db");
+    let (bp, mut catalog) = Catalog::open_in_memory();
 
     let schema = TableType {
         attributes: vec![
@@ -219,35 +226,37 @@ fn test_projection_execution() {
     let mut bp_guard = bp.lock().unwrap();
     let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
 
-    let mut insert_exec = InsertExecutor::new(values_exec, &catalog, table_oid).unwrap(); 
-    insert_exec.init(); 
-    insert_exec.next(pinned_bp.as_mut()); 
+    let mut insert_exec = InsertExecutor::new(values_exec, &catalog, table_oid).unwrap();
+    insert_exec.init();
+    insert_exec.next(pinned_bp.as_mut()).unwrap();
 
     // 3. Scan & Project: SELECT age FROM users
     // "age" is at index 1
-    let scan_exec = Box::new(SeqScanExecutor::new(&catalog, table_oid).unwrap()); 
+    let scan_exec = Box::new(SeqScanExecutor::new(&catalog, table_oid).unwrap());
 
     let mut proj_exec = ProjectionExecutor::new(scan_exec, vec![1]); // Keep only column 1 (age)
-    proj_exec.init(); 
+    proj_exec.init();
 
     let t1 = proj_exec
         .next(pinned_bp.as_mut())
-        .expect("Should have result"); 
+        .unwrap()
+        .expect("Should have result");
     assert_eq!(t1.values.len(), 1);
     assert_eq!(t1.values[0], AttributeValue::U32(30));
 
     let t2 = proj_exec
         .next(pinned_bp.as_mut())
-        .expect("Should have result"); 
+        .unwrap()
+        .expect("Should have result");
     assert_eq!(t2.values.len(), 1);
     assert_eq!(t2.values[0], AttributeValue::U32(20));
 
-    assert!(proj_exec.next(pinned_bp.as_mut()).is_none()); 
+    assert!(proj_exec.next(pinned_bp.as_mut()).unwrap().is_none());
 }
 
 #[test]
 fn test_index_maintenance() {
-    let (bp, mut catalog) = setup_catalog("test_idx_maint.db");
+    let (bp, mut catalog) = Catalog::open_in_memory();
 
     // 1. Create Table
     let schema = TableType {
@@ -285,20 +294,19 @@ fn test_index_maintenance() {
     let mut bp_guard = bp.lock().unwrap();
     let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
 
-    let mut insert_exec = InsertExecutor::new(values_exec, &catalog, table_oid).unwrap(); 
+    let mut insert_exec = InsertExecutor::new(values_exec, &catalog, table_oid).unwrap();
 
-    insert_exec.init(); 
-    insert_exec.next(pinned_bp.as_mut()); 
+    insert_exec.init();
+    insert_exec.next(pinned_bp.as_mut()).unwrap();
 
     // 4. Verify via Index Scan (Lookup 200)
-    // Key: 200
-    let key_bytes = 200u32.to_be_bytes().to_vec();
-    let mut idx_scan = IndexScanExecutor::new(&catalog, idx_oid, key_bytes).unwrap(); 
+    let mut idx_scan = IndexScanExecutor::new(&catalog, idx_oid, AttributeValue::U32(200)).unwrap();
 
-    idx_scan.init(); 
+    idx_scan.init();
     let tuple = idx_scan
         .next(pinned_bp.as_mut())
-        .expect("Index lookup failed for key 200"); 
+        .unwrap()
+        .expect("Index lookup failed for key 200");
 
     assert_eq!(tuple.values[0], AttributeValue::U32(200));
     assert_eq!(tuple.values[1], AttributeValue::U32(2));
@@ -306,7 +314,7 @@ fn test_index_maintenance() {
 
 #[test]
 fn test_update_execution() {
-    let (bp, mut catalog) = setup_catalog("test_update.db");
+    let (bp, mut catalog) = Catalog::open_in_memory();
 
     // 1. Create Table & Index
     let schema = TableType {
@@ -342,28 +350,29 @@ fn test_update_execution() {
     let mut bp_guard = bp.lock().unwrap();
     let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
 
-    let mut insert_exec = InsertExecutor::new(values_exec, &catalog, table_oid).unwrap(); 
-    insert_exec.init(); 
-    insert_exec.next(pinned_bp.as_mut()); 
+    let mut insert_exec = InsertExecutor::new(values_exec, &catalog, table_oid).unwrap();
+    insert_exec.init();
+    insert_exec.next(pinned_bp.as_mut()).unwrap();
 
     // 3. Update: SET val = 200 WHERE id = 1
     // Scan part
     // Index Scan for id=1
-    let key_bytes = 1u32.to_be_bytes().to_vec();
-    let scan_exec = Box::new(IndexScanExecutor::new(&catalog, idx_oid, key_bytes).unwrap()); 
+    let scan_exec =
+        Box::new(IndexScanExecutor::new(&catalog, idx_oid, AttributeValue::U32(1)).unwrap());
 
     // Update Logic: Change val (col 1) to 200
     let mut update_exec = UpdateExecutor::new(scan_exec, &catalog, table_oid, |old_t| {
         let mut new_vals = old_t.values.clone();
         new_vals[1] = AttributeValue::U32(200); // Update val
         Tuple::new(new_vals)
-    }) 
+    })
     .unwrap();
 
-    update_exec.init(); 
+    update_exec.init();
     let res = update_exec
         .next(pinned_bp.as_mut())
-        .expect("Update should return count"); 
+        .unwrap()
+        .expect("Update should return count");
 
     if let AttributeValue::U32(count) = res.values[0] {
         assert_eq!(count, 1);
@@ -371,7 +380,7 @@ fn test_update_execution() {
 
     // 4. Verify: Scan should see (1, 200)
     // Note: We need a new scan executor because the old one is consumed
-    let scan_check = SeqScanExecutor::new(&catalog, table_oid).unwrap(); 
+    let scan_check = SeqScanExecutor::new(&catalog, table_oid).unwrap();
     let mut filter_check = FilterExecutor::new(Box::new(scan_check), |t| {
         if let AttributeValue::U32(id) = t.values[0] {
             id == 1
@@ -380,16 +389,17 @@ fn test_update_execution() {
         }
     });
 
-    filter_check.init(); 
+    filter_check.init();
     let updated_tuple = filter_check
         .next(pinned_bp.as_mut())
-        .expect("Should find updated row"); 
+        .unwrap()
+        .expect("Should find updated row");
     assert_eq!(updated_tuple.values[1], AttributeValue::U32(200));
 }
 
 #[test]
 fn test_delete_updates_index() {
-    let (bp, mut catalog) = setup_catalog("test_delete_idx.db");
+    let (bp, mut catalog) = Catalog::open_in_memory();
 
     // 1. Create Table
     let schema = TableType {
@@ -426,13 +436,13 @@ fn test_delete_updates_index() {
 
     let mut insert_exec = InsertExecutor::new(values_exec, &catalog, table_oid).unwrap();
     insert_exec.init();
-    insert_exec.next(pinned_bp.as_mut());
+    insert_exec.next(pinned_bp.as_mut()).unwrap();
 
     // 4. Verify Insert: Index Scan for id=1 should return data
-    let key_bytes = 1u32.to_be_bytes().to_vec();
-    let mut idx_scan = IndexScanExecutor::new(&catalog, idx_oid, key_bytes.clone()).unwrap();
+    let key = AttributeValue::U32(1);
+    let mut idx_scan = IndexScanExecutor::new(&catalog, idx_oid, key.clone()).unwrap();
     idx_scan.init();
-    let res = idx_scan.next(pinned_bp.as_mut());
+    let res = idx_scan.next(pinned_bp.as_mut()).unwrap();
     assert!(res.is_some(), "Index scan should find inserted tuple");
 
     // 5. DELETE WHERE id = 1
@@ -450,6 +460,7 @@ fn test_delete_updates_index() {
     delete_exec.init();
     let del_res = delete_exec
         .next(pinned_bp.as_mut())
+        .unwrap()
         .expect("Delete should return count");
 
     if let AttributeValue::U32(count) = del_res.values[0] {
@@ -457,9 +468,9 @@ fn test_delete_updates_index() {
     }
 
     // 6. Verify Delete: Index Scan for id=1 should NOW return None
-    let mut idx_scan_check = IndexScanExecutor::new(&catalog, idx_oid, key_bytes).unwrap();
+    let mut idx_scan_check = IndexScanExecutor::new(&catalog, idx_oid, key).unwrap();
     idx_scan_check.init();
-    let res_check = idx_scan_check.next(pinned_bp.as_mut());
+    let res_check = idx_scan_check.next(pinned_bp.as_mut()).unwrap();
     assert!(
         res_check.is_none(),
         "Index scan should NOT find deleted tuple"
@@ -468,122 +479,1870 @@ fn test_delete_updates_index() {
     // 7. Verify Heap Scan also returns empty
     let mut seq_scan = SeqScanExecutor::new(&catalog, table_oid).unwrap();
     seq_scan.init();
-    let seq_res = seq_scan.next(pinned_bp.as_mut());
+    let seq_res = seq_scan.next(pinned_bp.as_mut()).unwrap();
     assert!(seq_res.is_none(), "Seq scan should not find deleted tuple");
 }
 
-#[allow(dead_code)]
-fn get_file_size(file_path: &str) -> u64 {
-    metadata(file_path).unwrap().len()
+#[test]
+fn test_index_errors_reach_the_caller() {
+    let (bp, mut catalog) = Catalog::open_in_memory();
+    let schema = TableType {
+        attributes: vec![TableAttribute {
+            name: "id".into(),
+            kind: AttributeKind::U32,
+            nullable: false,
+            is_internal: false,
+        }],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let table_oid = catalog.create_table("items", schema.clone()).unwrap();
+    let hash = IndexOptions {
+        method: IndexMethod::Hash,
+        ..Default::default()
+    };
+    let idx_oid = catalog
+        .create_index_with("idx_id", "items", "id", &hash)
+        .unwrap();
+
+    let mut bp_guard = bp.lock().unwrap();
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    let tuple = Tuple::new(vec![AttributeValue::U32(1)]);
+    catalog
+        .insert_tuple(table_oid, &tuple, &schema, pinned_bp.as_mut())
+        .unwrap();
+    let rid = HeapIterator::new(
+        pinned_bp.as_mut(),
+        catalog.get_table_root_page(table_oid).unwrap(),
+    )
+    .next()
+    .unwrap()
+    .unwrap()
+    .0;
+
+    // Pull the directory out from under the index
+    let directory = catalog.get_index_meta(idx_oid).unwrap().root_page_id;
+    pinned_bp.as_mut().free_page(directory).unwrap();
+
+    let mut scan = IndexScanExecutor::new(&catalog, idx_oid, AttributeValue::U32(1)).unwrap();
+    scan.init();
+    assert!(scan.next(pinned_bp.as_mut()).is_err());
+    assert!(
+        catalog
+            .delete_tuple(table_oid, rid, pinned_bp.as_mut())
+            .is_err()
+    );
+}
+
+#[test]
+fn test_covering_index_only_scan() {
+    let (bp, mut catalog) = Catalog::open_in_memory();
+
+    let schema = TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "id".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "name".into(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "age".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let table_oid = catalog.create_table("people", schema.clone()).unwrap();
+
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for i in 0..10u32 {
+            let row = Tuple::new(vec![
+                AttributeValue::U32(i),
+                AttributeValue::Varchar(format!("person_{}", i)),
+                AttributeValue::U32(20 + i),
+            ]);
+            catalog
+                .insert_tuple(table_oid, &row, &schema, pinned_bp.as_mut())
+                .unwrap();
+        }
+    }
+
+    // Variable length columns cannot live in the leaves
+    let ast = parse("CREATE INDEX idx_bad ON people(id) INCLUDE (name)").unwrap();
+    if let AstStatement::CreateIndex {
+        include_columns, ..
+    } = &ast
+    {
+        assert_eq!(include_columns, &vec!["name".to_string()]);
+    }
+    assert!(
+        catalog
+            .create_covering_index("idx_bad", "people", "id", &["name".to_string()])
+            .is_err()
+    );
+
+    catalog
+        .create_covering_index("idx_id_age", "people", "id", &["age".to_string()])
+        .unwrap();
+
+    let mut bp_guard = bp.lock().unwrap();
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+
+    // Rows inserted after the index also carry their payload
+    let late = Tuple::new(vec![
+        AttributeValue::U32(42),
+        AttributeValue::Varchar("late".into()),
+        AttributeValue::U32(99),
+    ]);
+    catalog
+        .insert_tuple(table_oid, &late, &schema, pinned_bp.as_mut())
+        .unwrap();
+
+    let run = |sql: &str, pinned_bp: Pin<&mut BufferPool>| {
+        let mut exec = Planner::new(&catalog).plan(parse(sql).unwrap()).unwrap();
+        exec.init();
+        exec.next(pinned_bp).unwrap()
+    };
+
+    let row = run(
+        "SELECT age, id FROM people WHERE id = 7",
+        pinned_bp.as_mut(),
+    )
+    .unwrap();
+    assert_eq!(
+        row.values,
+        vec![AttributeValue::U32(27), AttributeValue::U32(7)]
+    );
+    let row = run("SELECT age FROM people WHERE id = 42", pinned_bp.as_mut()).unwrap();
+    assert_eq!(row.values, vec![AttributeValue::U32(99)]);
+    assert!(run("SELECT age FROM people WHERE id = 500", pinned_bp.as_mut()).is_none());
+
+    // Remove the heap row behind the index's back: only a plan that never reads the heap
+    // can still answer, while one needing `name` has to go through the heap and comes back empty.
+    let rid = run("SELECT id FROM people WHERE id = 3", pinned_bp.as_mut())
+        .unwrap()
+        .rid
+        .unwrap();
+    HeapFile::new(0, 0).delete(pinned_bp.as_mut(), rid).unwrap();
+
+    let row = run("SELECT age FROM people WHERE id = 3", pinned_bp.as_mut()).unwrap();
+    assert_eq!(row.values, vec![AttributeValue::U32(23)]);
+    assert!(run("SELECT name FROM people WHERE id = 3", pinned_bp.as_mut()).is_none());
+}
+
+#[test]
+fn test_hash_index_equality_lookup() {
+    let (bp, mut catalog) = Catalog::open_in_memory();
+
+    let schema = TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "id".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "score".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let table_oid = catalog.create_table("scores", schema.clone()).unwrap();
+
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for i in 0..500u32 {
+            let row = Tuple::new(vec![AttributeValue::U32(i), AttributeValue::U32(i * 10)]);
+            catalog
+                .insert_tuple(table_oid, &row, &schema, pinned_bp.as_mut())
+                .unwrap();
+        }
+    }
+
+    let ast = parse("CREATE INDEX idx_hash ON scores USING HASH (id)").unwrap();
+    assert!(matches!(
+        ast,
+        AstStatement::CreateIndex {
+            using_hash: true,
+            ..
+        }
+    ));
+    assert!(parse("CREATE INDEX idx_bad ON scores USING GIN (id)").is_err());
+    let hash = IndexOptions {
+        method: IndexMethod::Hash,
+        ..Default::default()
+    };
+    let hash_with_include = IndexOptions {
+        include_columns: vec!["score".to_string()],
+        ..hash.clone()
+    };
+    assert!(
+        catalog
+            .create_index_with("idx_bad", "scores", "id", &hash_with_include)
+            .is_err()
+    );
+
+    // With both access methods on the column, equality lookups go through the hash index
+    let btree_oid = catalog.create_index("idx_tree", "scores", "id").unwrap();
+    let hash_oid = catalog
+        .create_index_with("idx_hash", "scores", "id", &hash)
+        .unwrap();
+    assert_eq!(
+        catalog.find_index_for_column("scores", "id"),
+        Some(hash_oid)
+    );
+
+    let mut bp_guard = bp.lock().unwrap();
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+
+    let run = |sql: &str, mut pinned_bp: Pin<&mut BufferPool>| {
+        let mut exec = Planner::new(&catalog).plan(parse(sql).unwrap()).unwrap();
+        exec.init();
+        let mut rows = Vec::new();
+        while let Some(row) = exec.next(pinned_bp.as_mut()).unwrap() {
+            rows.push(row);
+        }
+        rows
+    };
+
+    // Backfilled rows and rows inserted afterwards are both found
+    let late = Tuple::new(vec![AttributeValue::U32(9000), AttributeValue::U32(1)]);
+    catalog
+        .insert_tuple(table_oid, &late, &schema, pinned_bp.as_mut())
+        .unwrap();
+    let rows = run(
+        "SELECT score FROM scores WHERE id = 123",
+        pinned_bp.as_mut(),
+    );
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].values, vec![AttributeValue::U32(1230)]);
+    let rows = run(
+        "SELECT score FROM scores WHERE id = 9000",
+        pinned_bp.as_mut(),
+    );
+    assert_eq!(rows[0].values, vec![AttributeValue::U32(1)]);
+
+    run("DELETE FROM scores WHERE id = 123", pinned_bp.as_mut());
+    assert!(
+        run(
+            "SELECT score FROM scores WHERE id = 123",
+            pinned_bp.as_mut()
+        )
+        .is_empty()
+    );
+    assert_eq!(
+        run(
+            "SELECT score FROM scores WHERE id = 124",
+            pinned_bp.as_mut()
+        )
+        .len(),
+        1
+    );
+
+    drop(bp_guard);
+    catalog.drop_index("idx_hash").unwrap();
+    assert_eq!(
+        catalog.find_index_for_column("scores", "id"),
+        Some(btree_oid)
+    );
+}
+
+#[test]
+fn test_partial_and_expression_indexes() {
+    let (bp, mut catalog) = Catalog::open_in_memory();
+
+    let schema = TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "id".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "email".into(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "status".into(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: false,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let table_oid = catalog.create_table("tickets", schema.clone()).unwrap();
+
+    let row = |i: u32| {
+        let status = if i % 10 == 0 { "open" } else { "closed" };
+        Tuple::new(vec![
+            AttributeValue::U32(i),
+            AttributeValue::Varchar(format!("User{}@Example.com", i)),
+            AttributeValue::Varchar(status.into()),
+        ])
+    };
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for i in 0..100u32 {
+            catalog
+                .insert_tuple(table_oid, &row(i), &schema, pinned_bp.as_mut())
+                .unwrap();
+        }
+    }
+
+    let ast = parse("CREATE INDEX idx_open ON tickets(id) WHERE status = 'open'").unwrap();
+    match ast {
+        AstStatement::CreateIndex { key, predicate, .. } => {
+            assert_eq!(key, AstExpr::Column("id".into()));
+            assert_eq!(
+                predicate,
+                vec![(
+                    AstExpr::Column("status".into()),
+                    AstValue::Varchar("open".into())
+                )]
+            );
+        }
+        other => panic!("Unexpected statement {:?}", other),
+    }
+    let ast = parse("CREATE INDEX idx_email ON tickets(LOWER(email))").unwrap();
+    assert!(matches!(
+        ast,
+        AstStatement::CreateIndex {
+            key: AstExpr::Lower(_),
+            ..
+        }
+    ));
+
+    let open_oid = catalog
+        .create_index_with(
+            "idx_open",
+            "tickets",
+            "id",
+            &IndexOptions {
+                predicate: vec![("status".into(), AttributeValue::Varchar("open".into()))],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let email_oid = catalog
+        .create_index_with(
+            "idx_email",
+            "tickets",
+            "email",
+            &IndexOptions {
+                key_expr: KeyExpr::Lower,
+                method: IndexMethod::Hash,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(
+        catalog
+            .create_index_with(
+                "idx_bad",
+                "tickets",
+                "id",
+                &IndexOptions {
+                    key_expr: KeyExpr::Lower,
+                    ..Default::default()
+                },
+            )
+            .is_err()
+    );
+
+    let mut bp_guard = bp.lock().unwrap();
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+
+    // Only the qualifying rows are in the partial index
+    {
+        let mut lookup = |id: u32| {
+            catalog
+                .index_lookup(open_oid, &AttributeValue::U32(id), pinned_bp.as_mut())
+                .unwrap()
+        };
+        assert_eq!(lookup(20).len(), 1);
+        assert!(lookup(21).is_empty());
+    }
+
+    let terms = |sql: &str| match parse(sql).unwrap() {
+        AstStatement::Select { filter, .. } => filter,
+        other => panic!("Unexpected statement {:?}", other),
+    };
+    assert_eq!(
+        terms("SELECT * FROM tickets WHERE id = 1 AND status = 'open'").len(),
+        2
+    );
+
+    let run = |sql: &str, mut pinned_bp: Pin<&mut BufferPool>| {
+        let mut exec = Planner::new(&catalog).plan(parse(sql).unwrap()).unwrap();
+        exec.init();
+        let mut rows = Vec::new();
+        while let Some(row) = exec.next(pinned_bp.as_mut()).unwrap() {
+            rows.push(row);
+        }
+        rows
+    };
+    let ids = |rows: Vec<Tuple>| {
+        rows.into_iter()
+            .map(|r| r.values[0].clone())
+            .collect::<Vec<_>>()
+    };
+
+    // The partial index only answers queries that repeat its predicate
+    let id_is = |id: u32| EqualityTerm {
+        column_idx: 0,
+        key_expr: KeyExpr::Column,
+        value: AttributeValue::U32(id),
+    };
+    let open = EqualityTerm {
+        column_idx: 2,
+        key_expr: KeyExpr::Column,
+        value: AttributeValue::Varchar("open".into()),
+    };
+    assert_eq!(
+        catalog.find_index("tickets", &[id_is(30), open.clone()]),
+        Some((open_oid, 0))
+    );
+    assert_eq!(catalog.find_index("tickets", &[id_is(30)]), None);
+
+    let rows = run(
+        "SELECT * FROM tickets WHERE id = 30 AND status = 'open'",
+        pinned_bp.as_mut(),
+    );
+    assert_eq!(ids(rows), vec![AttributeValue::U32(30)]);
+    assert!(
+        run(
+            "SELECT * FROM tickets WHERE id = 31 AND status = 'open'",
+            pinned_bp.as_mut()
+        )
+        .is_empty()
+    );
+    // Without the predicate the seq scan still finds non-qualifying rows
+    assert_eq!(
+        ids(run(
+            "SELECT * FROM tickets WHERE id = 31",
+            pinned_bp.as_mut()
+        )),
+        vec![AttributeValue::U32(31)]
+    );
+
+    // Expression index lookups
+    let lower = EqualityTerm {
+        column_idx: 1,
+        key_expr: KeyExpr::Lower,
+        value: AttributeValue::Varchar("user42@example.com".into()),
+    };
+    assert_eq!(
+        catalog.find_index("tickets", &[lower]),
+        Some((email_oid, 0))
+    );
+    let rows = run(
+        "SELECT * FROM tickets WHERE LOWER(email) = 'user42@example.com'",
+        pinned_bp.as_mut(),
+    );
+    assert_eq!(ids(rows), vec![AttributeValue::U32(42)]);
+    assert!(
+        run(
+            "SELECT * FROM tickets WHERE LOWER(email) = 'User42@Example.com'",
+            pinned_bp.as_mut()
+        )
+        .is_empty()
+    );
+
+    // Maintenance: a row updated into the predicate joins the partial index, deletes leave it
+    run(
+        "UPDATE tickets SET status = 'open' WHERE id = 31",
+        pinned_bp.as_mut(),
+    );
+    assert_eq!(
+        ids(run(
+            "SELECT * FROM tickets WHERE id = 31 AND status = 'open'",
+            pinned_bp.as_mut()
+        )),
+        vec![AttributeValue::U32(31)]
+    );
+    run(
+        "DELETE FROM tickets WHERE LOWER(email) = 'user31@example.com'",
+        pinned_bp.as_mut(),
+    );
+    assert!(
+        run(
+            "SELECT * FROM tickets WHERE id = 31 AND status = 'open'",
+            pinned_bp.as_mut()
+        )
+        .is_empty()
+    );
+    assert!(
+        run(
+            "SELECT * FROM tickets WHERE LOWER(email) = 'user31@example.com'",
+            pinned_bp.as_mut()
+        )
+        .is_empty()
+    );
+
+    let late = row(500);
+    catalog
+        .insert_tuple(table_oid, &late, &schema, pinned_bp.as_mut())
+        .unwrap();
+    assert_eq!(
+        ids(run(
+            "SELECT * FROM tickets WHERE id = 500 AND status = 'open'",
+            pinned_bp.as_mut()
+        )),
+        vec![AttributeValue::U32(500)]
+    );
+}
+
+#[test]
+fn test_partial_index_predicate_types() {
+    let (bp, mut catalog) = Catalog::open_in_memory();
+    let column = |name: &str, kind| TableAttribute {
+        name: name.into(),
+        kind,
+        nullable: false,
+        is_internal: false,
+    };
+    let schema = TableType {
+        attributes: vec![
+            column("id", AttributeKind::U32),
+            column("shard", AttributeKind::U64),
+            column("active", AttributeKind::Bool),
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let table_oid = catalog.create_table("jobs", schema.clone()).unwrap();
+    let partial = |predicate| IndexOptions {
+        predicate,
+        ..Default::default()
+    };
+    let index_oid = catalog
+        .create_index_with(
+            "idx_active",
+            "jobs",
+            "id",
+            &partial(vec![
+                ("shard".into(), AttributeValue::U64(1 << 40)),
+                ("active".into(), AttributeValue::Bool(true)),
+            ]),
+        )
+        .unwrap();
+    let err = catalog
+        .create_index_with(
+            "idx_bad",
+            "jobs",
+            "id",
+            &partial(vec![("shard".into(), AttributeValue::U32(1))]),
+        )
+        .unwrap_err();
+    assert!(err.contains("wrong type"), "{}", err);
+
+    let mut bp_guard = bp.lock().unwrap();
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    for id in 0..8u32 {
+        let row = Tuple::new(vec![
+            AttributeValue::U32(id),
+            AttributeValue::U64(if id % 2 == 0 { 1 << 40 } else { 1 }),
+            AttributeValue::Bool(id % 4 < 2),
+        ]);
+        catalog
+            .insert_tuple(table_oid, &row, &schema, pinned_bp.as_mut())
+            .unwrap();
+    }
+    let indexed: Vec<u32> = (0..8)
+        .filter(|&id| {
+            !catalog
+                .index_lookup(index_oid, &AttributeValue::U32(id), pinned_bp.as_mut())
+                .unwrap()
+                .is_empty()
+        })
+        .collect();
+    assert_eq!(indexed, vec![0, 4]);
 }
 
-// #[test]
-// fn test_page_recycling_maintains_file_size() {
-//     let db_file = "test_db/test_reuse_size.db";
-//     let (bp, mut catalog) = setup_catalog(db_file); // setup_catalog creates test_db dir and clears the file
-
-//     // 1. Create Table with a Varchar (to make tuples easily fit on a page)
-//     let schema = TableType {
-//         attributes: vec![
-//             TableAttribute {
-//                 name: "id".into(),
-//                 kind: AttributeKind::U32,
-//                 nullable: false,
-//                 is_internal: false,
-//             },
-//             TableAttribute {
-//                 name: "data".into(),
-//                 kind: AttributeKind::Varchar,
-//                 nullable: false,
-//                 is_internal: false,
-//             },
-//         ],
-//         layout: TableLayout {
-//             size: 0,
-//             attr_layouts: vec![],
-//         },
-//     };
-//     let table_oid = catalog.create_table("reusables", schema).unwrap();
-
-//     let mut bp_guard = bp.lock().unwrap();
-//     let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
-
-//     // --- PHASE 1: Fill Page and Capture Size (Should allocate multiple pages) ---
-//     // Insert enough rows (e.g. 50, well beyond 1 page limit) to force file growth.
-//     let initial_inserts: Vec<Tuple> = (1..=50)
-//         .map(|i| {
-//             Tuple::new(vec![
-//                 AttributeValue::U32(i),
-//                 AttributeValue::Varchar(format!("Data_{}", i)),
-//             ])
-//         })
-//         .collect();
-
-//     let mut insert_exec = InsertExecutor::new(
-//         Box::new(ValuesExecutor::new(initial_inserts)),
-//         &catalog,
-//         table_oid,
-//     )
-//     .unwrap();
-//     insert_exec.init();
-//     insert_exec.next(pinned_bp.as_mut());
-
-//     // Flush all pages to disk to accurately measure file size
-//     pinned_bp.as_mut().flush_all().unwrap();
-//     let size_after_fill = get_file_size(db_file);
-//     assert!(
-//         size_after_fill > 4 * 4096,
-//         "File size must be significantly larger than initial directory pages."
-//     );
-
-//     // --- PHASE 2: Delete Half of the Rows (Frees up space) ---
-//     // Delete rows 1 through 25 (This frees up space on the first pages).
-//     let delete_ids: Vec<u32> = (1..=25).collect();
-//     for id in delete_ids {
-//         // Use SeqScan + Filter to find and delete each tuple
-//         let scan = Box::new(SeqScanExecutor::new(&catalog, table_oid).unwrap());
-//         let filter = Box::new(FilterExecutor::new(scan, move |t| {
-//             if let AttributeValue::U32(i) = t.values[0] {
-//                 i == id
-//             } else {
-//                 false
-//             }
-//         }));
-//         let mut delete_exec = DeleteExecutor::new(filter, &catalog, table_oid);
-//         delete_exec.init();
-//         delete_exec.next(pinned_bp.as_mut());
-//     }
-
-//     // --- PHASE 3: Re-insert Same Number of Rows (Triggers Recycling) ---
-//     // Insert rows 101 through 125 (same volume as deleted rows).
-//     let new_inserts: Vec<Tuple> = (101..=125)
-//         .map(|i| {
-//             Tuple::new(vec![
-//                 AttributeValue::U32(i),
-//                 AttributeValue::Varchar(format!("New_Data_{}", i)),
-//             ])
-//         })
-//         .collect();
-
-//     let mut insert_exec_new = InsertExecutor::new(
-//         Box::new(ValuesExecutor::new(new_inserts)),
-//         &catalog,
-//         table_oid,
-//     )
-//     .unwrap();
-//     insert_exec_new.init();
-//     insert_exec_new.next(pinned_bp.as_mut());
-
-//     // Flush and check final size
-//     pinned_bp.as_mut().flush_all().unwrap();
-//     let size_after_reuse = get_file_size(db_file);
-
-//     // ASSERTION: The final size must be very close to (or equal to) the size after the initial fill.
-//     // If recycling failed, the file size would have grown by roughly 25 more tuples worth of pages.
-//     assert_eq!(
-//         size_after_fill, size_after_reuse,
-//         "File size increased, indicating page space recycling failed."
-//     );
-
-//     let _ = fs::remove_file(db_file);
-// }
+#[test]
+fn test_indexes_keep_rows_with_equal_keys() {
+    let (bp, mut catalog) = Catalog::open_in_memory();
+
+    let schema = TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "id".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "name".into(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "team".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let table_oid = catalog.create_table("people", schema.clone()).unwrap();
+    let people = [(1, "Bob", 7), (2, "bob", 7), (3, "Alice", 8)];
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for (id, name, team) in people {
+            let row = Tuple::new(vec![
+                AttributeValue::U32(id),
+                AttributeValue::Varchar(name.into()),
+                AttributeValue::U32(team),
+            ]);
+            catalog
+                .insert_tuple(table_oid, &row, &schema, pinned_bp.as_mut())
+                .unwrap();
+        }
+    }
+
+    let lower_oid = catalog
+        .create_index_with(
+            "idx_name",
+            "people",
+            "name",
+            &IndexOptions {
+                key_expr: KeyExpr::Lower,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    catalog
+        .create_covering_index("idx_team_id", "people", "team", &["id".to_string()])
+        .unwrap();
+    let hash_oid = catalog
+        .create_index_with(
+            "idx_team_hash",
+            "people",
+            "team",
+            &IndexOptions {
+                method: IndexMethod::Hash,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let bob = EqualityTerm {
+        column_idx: 1,
+        key_expr: KeyExpr::Lower,
+        value: AttributeValue::Varchar("bob".into()),
+    };
+    assert_eq!(catalog.find_index("people", &[bob]), Some((lower_oid, 0)));
+
+    let mut bp_guard = bp.lock().unwrap();
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    let ids = |sql: &str, mut pinned_bp: Pin<&mut BufferPool>| {
+        let mut exec = Planner::new(&catalog).plan(parse(sql).unwrap()).unwrap();
+        exec.init();
+        let mut ids = Vec::new();
+        while let Some(row) = exec.next(pinned_bp.as_mut()).unwrap() {
+            if let Some(AttributeValue::U32(id)) = row.values.first() {
+                ids.push(*id);
+            }
+        }
+        ids.sort_unstable();
+        ids
+    };
+
+    // 'Bob' and 'bob' share a LOWER key, and both rows of team 7 share a key in the
+    // covering B+ tree and the hash index
+    let bobs = "SELECT * FROM people WHERE LOWER(name) = 'bob'";
+    assert_eq!(ids(bobs, pinned_bp.as_mut()), vec![1, 2]);
+    let team_7 = "SELECT id FROM people WHERE team = 7";
+    assert_eq!(ids(team_7, pinned_bp.as_mut()), vec![1, 2]);
+    let team_7_rows = "SELECT * FROM people WHERE team = 7";
+    assert_eq!(ids(team_7_rows, pinned_bp.as_mut()), vec![1, 2]);
+    assert_eq!(
+        catalog
+            .index_lookup(hash_oid, &AttributeValue::U32(7), pinned_bp.as_mut())
+            .unwrap()
+            .len(),
+        2
+    );
+
+    // Removing one row leaves the other's entries in place
+    ids("DELETE FROM people WHERE id = 1", pinned_bp.as_mut());
+    assert_eq!(ids(bobs, pinned_bp.as_mut()), vec![2]);
+    assert_eq!(ids(team_7, pinned_bp.as_mut()), vec![2]);
+    assert_eq!(ids(team_7_rows, pinned_bp.as_mut()), vec![2]);
+
+    // Moving a row onto a key another row has keeps both
+    ids(
+        "UPDATE people SET team = 8 WHERE LOWER(name) = 'bob'",
+        pinned_bp.as_mut(),
+    );
+    assert_eq!(
+        ids("SELECT id FROM people WHERE team = 8", pinned_bp.as_mut()),
+        vec![2, 3]
+    );
+    assert_eq!(
+        ids("SELECT * FROM people WHERE team = 8", pinned_bp.as_mut()),
+        vec![2, 3]
+    );
+    assert!(ids(team_7_rows, pinned_bp.as_mut()).is_empty());
+
+    drop(bp_guard);
+    assert!(catalog.verify_index("idx_name").unwrap().is_ok());
+    assert!(catalog.verify_index("idx_team_id").unwrap().is_ok());
+}
+
+#[test]
+fn test_verify_index() {
+    let (bp, mut catalog) = Catalog::open_in_memory();
+
+    let schema = TableType {
+        attributes: vec![TableAttribute {
+            name: "id".into(),
+            kind: AttributeKind::U32,
+            nullable: false,
+            is_internal: false,
+        }],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let table_oid = catalog.create_table("items", schema.clone()).unwrap();
+    catalog.create_index("idx_id", "items", "id").unwrap();
+    let options = IndexOptions {
+        method: IndexMethod::Hash,
+        ..Default::default()
+    };
+    catalog
+        .create_index_with("idx_id_hash", "items", "id", &options)
+        .unwrap();
+
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for i in 0..1000u32 {
+            let tuple = Tuple::new(vec![AttributeValue::U32(i)]);
+            catalog
+                .insert_tuple(table_oid, &tuple, &schema, pinned_bp.as_mut())
+                .unwrap();
+        }
+    }
+
+    let report = catalog.verify_index("idx_id").unwrap();
+    assert!(report.is_ok(), "{:?}", report.problems);
+    assert_eq!(report.entries, 1000);
+    assert!(report.height >= 2);
+    assert!(catalog.verify_index("idx_id_hash").is_err());
+
+    // Delete a row behind the index's back, leaving a dangling entry
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        let root = catalog.get_table_root_page(table_oid).unwrap();
+        let (rid, _) = HeapIterator::new(pinned_bp.as_mut(), root)
+            .next()
+            .unwrap()
+            .unwrap();
+        HeapFile::new(0, 0).delete(pinned_bp.as_mut(), rid).unwrap();
+    }
+
+    let report = catalog.verify_index("idx_id").unwrap();
+    assert_eq!(report.problems.len(), 1, "{:?}", report.problems);
+    assert!(report.problems[0].contains("no live row"));
+}
+
+#[test]
+fn test_vacuum_keeps_rows_and_indexes() {
+    let (bp, mut catalog) = Catalog::open_in_memory();
+
+    let schema = TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "id".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "data".into(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: false,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let table_oid = catalog.create_table("churn", schema.clone()).unwrap();
+    let index_oid = catalog.create_index("idx_churn", "churn", "id").unwrap();
+
+    let mut bp_guard = bp.lock().unwrap();
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    for i in 0..600u32 {
+        let tuple = Tuple::new(vec![
+            AttributeValue::U32(i),
+            AttributeValue::Varchar(format!("{:0>60}", i)),
+        ]);
+        catalog
+            .insert_tuple(table_oid, &tuple, &schema, pinned_bp.as_mut())
+            .unwrap();
+    }
+
+    // Delete all but every third row
+    let root = catalog.get_table_root_page(table_oid).unwrap();
+    let mut doomed = Vec::new();
+    {
+        let mut iter = HeapIterator::new(pinned_bp.as_mut(), root);
+        while let Some(Ok((rid, bytes))) = iter.next() {
+            let tuple = Tuple::from_bytes(&bytes, &schema).unwrap();
+            if !matches!(tuple.values[0], AttributeValue::U32(i) if i % 3 == 0) {
+                doomed.push(rid);
+            }
+        }
+    }
+    for rid in doomed {
+        catalog
+            .delete_tuple(table_oid, rid, pinned_bp.as_mut())
+            .unwrap();
+    }
+    drop(bp_guard);
+
+    assert!(matches!(
+        parse("VACUUM churn;"),
+        Ok(AstStatement::Vacuum { table_name: Some(name) }) if name == "churn"
+    ));
+    let stats = catalog.vacuum(None).unwrap();
+    assert!(stats.bytes_reclaimed > 0);
+
+    // Rows kept their RowIds, so the index still finds every survivor
+    let mut bp_guard = bp.lock().unwrap();
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    for i in (0..600u32).step_by(3) {
+        let rids = catalog
+            .index_lookup(index_oid, &AttributeValue::U32(i), pinned_bp.as_mut())
+            .unwrap();
+        assert_eq!(rids.len(), 1);
+        let bytes = HeapFile::get(pinned_bp.as_mut(), rids[0]).unwrap();
+        let tuple = Tuple::from_bytes(&bytes, &schema).unwrap();
+        assert_eq!(tuple.values[0], AttributeValue::U32(i));
+    }
+    drop(bp_guard);
+    assert!(catalog.verify_index("idx_churn").unwrap().is_ok());
+}
+
+fn get_file_size(file_path: &str) -> u64 {
+    metadata(file_path).unwrap().len()
+}
+
+#[test]
+fn test_page_recycling_maintains_file_size() {
+    let db_file = "test_db/test_reuse_size.db";
+    let (bp, mut catalog) = setup_catalog("test_reuse_size.db");
+
+    let schema = TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "id".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "data".into(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: false,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+
+    // The table and its index each live in a segment file of their own that goes away
+    // with them, so building the same table again must not grow the database file
+    let mut sizes = Vec::new();
+    for _ in 0..2 {
+        let table_oid = catalog.create_table("reusables", schema.clone()).unwrap();
+        catalog
+            .create_index("idx_reusables", "reusables", "id")
+            .unwrap();
+        let index_oid = catalog.get_index_oid("idx_reusables").unwrap();
+        {
+            let mut bp_guard = bp.lock().unwrap();
+            let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            for i in 0..500u32 {
+                let tuple = Tuple::new(vec![
+                    AttributeValue::U32(i),
+                    AttributeValue::Varchar(format!("Data_{:0>40}", i)),
+                ]);
+                catalog
+                    .insert_tuple(table_oid, &tuple, &schema, pinned_bp.as_mut())
+                    .unwrap();
+            }
+            pinned_bp.as_mut().flush_all().unwrap();
+        }
+        let segments = [table_oid, index_oid].map(|oid| format!("{}.{}", db_file, oid));
+        assert!(
+            get_file_size(&segments[0]) > 4 * 4096,
+            "The table should span several pages"
+        );
+        assert!(get_file_size(&segments[1]) > 4096);
+        sizes.push(get_file_size(db_file));
+
+        catalog.drop_table("reusables").unwrap();
+        for segment in &segments {
+            assert!(metadata(segment).is_err(), "{} was not removed", segment);
+        }
+    }
+
+    assert_eq!(sizes[0], sizes[1], "The database file grew");
+
+    // Deleting the first half of the rows leaves whole pages empty. VACUUM puts them on
+    // the free list, and as many new rows take them back without growing the segment.
+    let table_oid = catalog.create_table("reusables", schema.clone()).unwrap();
+    let segment = format!("{}.{}", db_file, table_oid);
+    let insert_rows = |ids: std::ops::Range<u32>| {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for i in ids {
+            let tuple = Tuple::new(vec![
+                AttributeValue::U32(i),
+                AttributeValue::Varchar(format!("Data_{:0>40}", i)),
+            ]);
+            catalog
+                .insert_tuple(table_oid, &tuple, &schema, pinned_bp.as_mut())
+                .unwrap();
+        }
+        pinned_bp.as_mut().flush_all().unwrap();
+    };
+    insert_rows(0..500);
+    let sizes = [get_file_size(db_file), get_file_size(&segment)];
+
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        let root = catalog.get_table_root_page(table_oid).unwrap();
+        let mut doomed = Vec::new();
+        let mut iter = HeapIterator::new(pinned_bp.as_mut(), root);
+        while let Some(Ok((rid, bytes))) = iter.next() {
+            let tuple = Tuple::from_bytes(&bytes, &schema).unwrap();
+            if matches!(tuple.values[0], AttributeValue::U32(i) if i < 250) {
+                doomed.push(rid);
+            }
+        }
+        drop(iter);
+        for rid in doomed {
+            catalog
+                .delete_tuple(table_oid, rid, pinned_bp.as_mut())
+                .unwrap();
+        }
+    }
+    assert!(catalog.vacuum(Some("reusables")).unwrap().pages_freed > 0);
+
+    insert_rows(500..750);
+    assert_eq!(sizes[0], get_file_size(db_file), "The database file grew");
+    assert_eq!(
+        sizes[1],
+        get_file_size(&segment),
+        "The table's segment grew"
+    );
+
+    let mut bp_guard = bp.lock().unwrap();
+    let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    let root = catalog.get_table_root_page(table_oid).unwrap();
+    let mut iter = HeapIterator::new(pinned_bp, root);
+    let mut rows = 0;
+    while let Some(Ok(_)) = iter.next() {
+        rows += 1;
+    }
+    assert_eq!(rows, 500);
+    drop(iter);
+    drop(bp_guard);
+
+    let _ = FileManager::remove_database(db_file);
+}
+
+#[test]
+fn test_vacuumed_space_is_reused() {
+    let (bp, mut catalog) = Catalog::open_in_memory();
+    let schema = TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "id".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "data".into(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: false,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let table_oid = catalog.create_table("churn", schema.clone()).unwrap();
+    let root = catalog.get_table_root_page(table_oid).unwrap();
+    let insert_rows = |ids: std::ops::Range<u32>| {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for i in ids {
+            let tuple = Tuple::new(vec![
+                AttributeValue::U32(i),
+                AttributeValue::Varchar(format!("Data_{:0>40}", i)),
+            ]);
+            catalog
+                .insert_tuple(table_oid, &tuple, &schema, pinned_bp.as_mut())
+                .unwrap();
+        }
+    };
+    // (heap pages, odd rows)
+    let survey = || {
+        let mut bp_guard = bp.lock().unwrap();
+        let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        let mut pages = std::collections::BTreeSet::new();
+        let mut odd = Vec::new();
+        let mut iter = HeapIterator::new(pinned_bp, root);
+        while let Some(Ok((rid, bytes))) = iter.next() {
+            pages.insert(rid.page_id());
+            let tuple = Tuple::from_bytes(&bytes, &schema).unwrap();
+            if matches!(tuple.values[0], AttributeValue::U32(i) if i % 2 == 1) {
+                odd.push(rid);
+            }
+        }
+        (pages.len(), odd)
+    };
+
+    insert_rows(0..600);
+    let (pages, odd) = survey();
+    assert!(pages > 4);
+
+    // Every page keeps half its rows, so VACUUM frees none and only makes room in each
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for rid in odd {
+            catalog
+                .delete_tuple(table_oid, rid, pinned_bp.as_mut())
+                .unwrap();
+        }
+    }
+    let stats = catalog.vacuum(Some("churn")).unwrap();
+    assert_eq!(stats.pages_freed, 0);
+    assert!(stats.bytes_reclaimed > 0);
+
+    insert_rows(600..900);
+    assert_eq!(
+        survey().0,
+        pages,
+        "The heap grew instead of refilling its pages"
+    );
+}
+
+#[test]
+fn test_order_by_index() {
+    let (bp, mut catalog) = Catalog::open_in_memory();
+
+    let schema = TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "id".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "kind".into(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: false,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let table_oid = catalog.create_table("events", schema.clone()).unwrap();
+    catalog
+        .create_index("idx_events_id", "events", "id")
+        .unwrap();
+
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        // Out of key order, so heap order and index order differ
+        for i in 0..500u32 {
+            let id = (i * 7) % 500;
+            let kind = if id % 2 == 0 { "even" } else { "odd" };
+            let tuple = Tuple::new(vec![
+                AttributeValue::U32(id),
+                AttributeValue::Varchar(kind.into()),
+            ]);
+            catalog
+                .insert_tuple(table_oid, &tuple, &schema, pinned_bp.as_mut())
+                .unwrap();
+        }
+        // Three more rows with a value that is already there
+        for _ in 0..3 {
+            let tuple = Tuple::new(vec![
+                AttributeValue::U32(250),
+                AttributeValue::Varchar("again".into()),
+            ]);
+            catalog
+                .insert_tuple(table_oid, &tuple, &schema, pinned_bp.as_mut())
+                .unwrap();
+        }
+    }
+
+    match parse("SELECT id FROM events ORDER BY id DESC LIMIT 5").unwrap() {
+        AstStatement::Select {
+            order_by, limit, ..
+        } => {
+            let order_by = order_by.unwrap();
+            assert_eq!(order_by.column, "id");
+            assert!(order_by.descending);
+            assert_eq!(limit, Some(5));
+        }
+        other => panic!("Unexpected statement {:?}", other),
+    }
+    assert!(parse("SELECT * FROM events ORDER BY id, kind").is_err());
+
+    let mut bp_guard = bp.lock().unwrap();
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    let mut run = |sql: &str| {
+        let mut exec = Planner::new(&catalog).plan(parse(sql).unwrap()).unwrap();
+        exec.init();
+        let mut ids = Vec::new();
+        while let Some(row) = exec.next(pinned_bp.as_mut()).unwrap() {
+            ids.push(row.values[0].clone());
+        }
+        ids
+    };
+    let u32s = |ids: &[u32]| {
+        ids.iter()
+            .map(|&i| AttributeValue::U32(i))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        run("SELECT id FROM events ORDER BY id DESC LIMIT 5"),
+        u32s(&[499, 498, 497, 496, 495])
+    );
+    assert_eq!(
+        run("SELECT * FROM events ORDER BY id LIMIT 3"),
+        u32s(&[0, 1, 2])
+    );
+    assert_eq!(
+        run("SELECT id FROM events WHERE kind = 'odd' ORDER BY id DESC LIMIT 3"),
+        u32s(&[499, 497, 495])
+    );
+    // Rows with equal values each keep their place in the order
+    let mut expected: Vec<u32> = (0..500).collect();
+    expected.splice(250..250, [250; 3]);
+    let ascending = run("SELECT id FROM events ORDER BY id ASC");
+    assert_eq!(ascending, u32s(&expected));
+    let descending = run("SELECT id FROM events ORDER BY id DESC");
+    assert_eq!(descending, ascending.into_iter().rev().collect::<Vec<_>>());
+    assert_eq!(
+        run("SELECT id FROM events WHERE kind = 'again' ORDER BY id DESC"),
+        u32s(&[250, 250, 250])
+    );
+    assert_eq!(
+        run("SELECT id FROM events ORDER BY id LIMIT 254").len(),
+        254
+    );
+
+    // Without an index on the column there is nothing to take the order from
+    assert!(
+        Planner::new(&catalog)
+            .plan(parse("SELECT * FROM events ORDER BY kind").unwrap())
+            .is_err()
+    );
+}
+
+#[test]
+fn test_update_keeps_row_ids() {
+    let (bp, mut catalog) = Catalog::open_in_memory();
+
+    let schema = TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "id".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "hits".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "body".into(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: false,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let table_oid = catalog.create_table("pages", schema.clone()).unwrap();
+    let idx_oid = catalog.create_index("idx_pages_id", "pages", "id").unwrap();
+
+    let tuples = (0..20)
+        .map(|i| {
+            Tuple::new(vec![
+                AttributeValue::U32(i),
+                AttributeValue::U32(0),
+                AttributeValue::Varchar("x".repeat(300)),
+            ])
+        })
+        .collect();
+    let mut bp_guard = bp.lock().unwrap();
+    let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+    let mut insert_exec =
+        InsertExecutor::new(Box::new(ValuesExecutor::new(tuples)), &catalog, table_oid).unwrap();
+    insert_exec.init();
+    insert_exec.next(pinned_bp.as_mut()).unwrap();
+
+    let scan_rows = |bpm: Pin<&mut BufferPool>| {
+        let mut bpm = bpm;
+        let mut scan = SeqScanExecutor::new(&catalog, table_oid).unwrap();
+        scan.init();
+        let mut rows = Vec::new();
+        while let Some(t) = scan.next(bpm.as_mut()).unwrap() {
+            rows.push((t.rid.unwrap(), t.values));
+        }
+        rows
+    };
+    let before = scan_rows(pinned_bp.as_mut());
+    assert_eq!(before.len(), 20);
+
+    // The hot path: bump a counter on every row
+    let scan = Box::new(SeqScanExecutor::new(&catalog, table_oid).unwrap());
+    let mut update_exec = UpdateExecutor::new(scan, &catalog, table_oid, |old| {
+        let mut values = old.values.clone();
+        if let AttributeValue::U32(hits) = values[1] {
+            values[1] = AttributeValue::U32(hits + 1);
+        }
+        Tuple::new(values)
+    })
+    .unwrap();
+    update_exec.init();
+    let res = update_exec.next(pinned_bp.as_mut()).unwrap().unwrap();
+    assert_eq!(res.values[0], AttributeValue::U32(20));
+
+    let after = scan_rows(pinned_bp.as_mut());
+    assert_eq!(
+        after.iter().map(|(rid, _)| *rid).collect::<Vec<_>>(),
+        before.iter().map(|(rid, _)| *rid).collect::<Vec<_>>()
+    );
+    assert!(after.iter().all(|(_, v)| v[1] == AttributeValue::U32(1)));
+    // Written over the old versions, so nothing for VACUUM
+    assert_eq!(catalog.get_table_stats(table_oid).unwrap().dead_tuples, 0);
+
+    // Grow one row past what its page can hold: it moves but keeps its RowId
+    let key = AttributeValue::U32(3);
+    let scan = Box::new(IndexScanExecutor::new(&catalog, idx_oid, key.clone()).unwrap());
+    let mut update_exec = UpdateExecutor::new(scan, &catalog, table_oid, |old| {
+        let mut values = old.values.clone();
+        values[2] = AttributeValue::Varchar("y".repeat(2000));
+        Tuple::new(values)
+    })
+    .unwrap();
+    update_exec.init();
+    update_exec.next(pinned_bp.as_mut()).unwrap().unwrap();
+
+    let rid_3 = before[3].0;
+    assert_eq!(
+        catalog
+            .index_lookup(idx_oid, &key, pinned_bp.as_mut())
+            .unwrap(),
+        vec![rid_3]
+    );
+    let mut idx_scan = IndexScanExecutor::new(&catalog, idx_oid, key).unwrap();
+    idx_scan.init();
+    let tuple = idx_scan.next(pinned_bp.as_mut()).unwrap().unwrap();
+    assert_eq!(tuple.values[2], AttributeValue::Varchar("y".repeat(2000)));
+
+    let after = scan_rows(pinned_bp.as_mut());
+    assert_eq!(after.len(), 20, "A moved row is seen once");
+    assert_eq!(after[3].0, rid_3);
+    assert_eq!(after[3].1[2], AttributeValue::Varchar("y".repeat(2000)));
+}
+
+#[test]
+fn test_tablespace_holds_table_and_index_segments() {
+    let db_file = "test_db/test_tablespace.db";
+    let dir = std::env::temp_dir().join(format!("nimbus_tablespace_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let location = dir.to_str().unwrap().to_string();
+
+    let sql = format!("CREATE TABLESPACE fast LOCATION '{}';", location);
+    match parse(&sql).unwrap() {
+        AstStatement::CreateTablespace { name, location: l } => {
+            assert_eq!((name.as_str(), l.as_str()), ("fast", location.as_str()))
+        }
+        other => panic!("Expected CREATE TABLESPACE, got {:?}", other),
+    }
+    match parse("CREATE TABLE hot (id INT, name VARCHAR) TABLESPACE fast").unwrap() {
+        AstStatement::CreateTable {
+            columns,
+            tablespace,
+            ..
+        } => {
+            assert_eq!(columns.len(), 2);
+            assert_eq!(tablespace.as_deref(), Some("fast"));
+        }
+        other => panic!("Expected CREATE TABLE, got {:?}", other),
+    }
+    match parse("CREATE INDEX idx_hot ON hot(id) TABLESPACE fast").unwrap() {
+        AstStatement::CreateIndex { tablespace, .. } => {
+            assert_eq!(tablespace.as_deref(), Some("fast"))
+        }
+        other => panic!("Expected CREATE INDEX, got {:?}", other),
+    }
+    assert!(parse("CREATE TABLE hot (id INT) TABLESPACE").is_err());
+
+    let schema = TableType {
+        attributes: vec![TableAttribute {
+            name: "id".into(),
+            kind: AttributeKind::U32,
+            nullable: false,
+            is_internal: false,
+        }],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let (table_oid, index_oid) = {
+        let (bp, mut catalog) = setup_catalog("test_tablespace.db");
+        assert!(catalog.create_tablespace("rel", "relative/dir").is_err());
+        catalog.create_tablespace("fast", &location).unwrap();
+        assert!(catalog.create_tablespace("fast", &location).is_err());
+        let in_tablespace = |name: &str| TableOptions {
+            tablespace: Some(name.to_string()),
+            ..Default::default()
+        };
+        assert!(
+            catalog
+                .create_table_with("t", schema.clone(), &in_tablespace("slow"))
+                .is_err()
+        );
+
+        let table_oid = catalog
+            .create_table_with("hot", schema.clone(), &in_tablespace("fast"))
+            .unwrap();
+        let options = IndexOptions {
+            tablespace: Some("fast".to_string()),
+            ..Default::default()
+        };
+        let index_oid = catalog
+            .create_index_with("idx_hot", "hot", "id", &options)
+            .unwrap();
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for i in 0..300u32 {
+            let tuple = Tuple::new(vec![AttributeValue::U32(i)]);
+            catalog
+                .insert_tuple(table_oid, &tuple, &schema, pinned_bp.as_mut())
+                .unwrap();
+        }
+        pinned_bp.flush_all().unwrap();
+        (table_oid, index_oid)
+    };
+
+    let segments =
+        [table_oid, index_oid].map(|oid| dir.join(format!("test_tablespace.db.{}", oid)));
+    for (segment, oid) in segments.iter().zip([table_oid, index_oid]) {
+        assert!(segment.exists(), "{:?} is missing", segment);
+        assert!(metadata(format!("{}.{}", db_file, oid)).is_err());
+    }
+
+    // Reopened, the catalog finds the segments in the tablespace again
+    let (bp, mut catalog) = Catalog::open(db_file).unwrap();
+    assert_eq!(
+        catalog.get_tablespace_location("fast"),
+        Some(location.as_str())
+    );
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        let mut exec = Planner::new(&catalog)
+            .plan(parse("SELECT * FROM hot WHERE id = 42").unwrap())
+            .unwrap();
+        exec.init();
+        let row = exec.next(pinned_bp.as_mut()).unwrap().unwrap();
+        assert_eq!(row.values, vec![AttributeValue::U32(42)]);
+        assert!(exec.next(pinned_bp.as_mut()).unwrap().is_none());
+    }
+
+    catalog.drop_table("hot").unwrap();
+    for segment in &segments {
+        assert!(!segment.exists(), "{:?} was not removed", segment);
+    }
+
+    let _ = FileManager::remove_database(db_file);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_compressed_table_is_smaller_and_reads_back() {
+    let db_file = "test_db/test_compression.db";
+    match parse("CREATE TABLE logs (id INT, msg VARCHAR) WITH (compression = 'lz4')").unwrap() {
+        AstStatement::CreateTable { compression, .. } => {
+            assert_eq!(compression.as_deref(), Some("lz4"))
+        }
+        other => panic!("Expected CREATE TABLE, got {:?}", other),
+    }
+    assert!(parse("CREATE TABLE logs (id INT) WITH (fillfactor = 50)").is_err());
+
+    let schema = TableType {
+        attributes: vec![
+            TableAttribute {
+                name: "id".into(),
+                kind: AttributeKind::U32,
+                nullable: false,
+                is_internal: false,
+            },
+            TableAttribute {
+                name: "msg".into(),
+                kind: AttributeKind::Varchar,
+                nullable: false,
+                is_internal: false,
+            },
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let message = |i: u32| {
+        format!(
+            "2024-01-01 12:00:00 INFO request {} served from cache",
+            i % 10
+        )
+    };
+    let (plain_oid, logs_oid) = {
+        let (bp, mut catalog) = setup_catalog("test_compression.db");
+        let plain_oid = catalog.create_table("plain", schema.clone()).unwrap();
+        let options = TableOptions {
+            compression: Compression::Lz4,
+            ..Default::default()
+        };
+        let logs_oid = catalog
+            .create_table_with("logs", schema.clone(), &options)
+            .unwrap();
+
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for i in 0..3000u32 {
+            let tuple = Tuple::new(vec![
+                AttributeValue::U32(i),
+                AttributeValue::Varchar(message(i)),
+            ]);
+            for oid in [plain_oid, logs_oid] {
+                catalog
+                    .insert_tuple(oid, &tuple, &schema, pinned_bp.as_mut())
+                    .unwrap();
+            }
+            // Pages are written while they fill up, so they outgrow their slots and move
+            if i % 500 == 0 {
+                pinned_bp.as_mut().checkpoint().unwrap();
+            }
+        }
+        pinned_bp.as_mut().checkpoint().unwrap();
+        (plain_oid, logs_oid)
+    };
+
+    let size = |oid: u32| metadata(format!("{}.{}", db_file, oid)).unwrap().len();
+    assert!(
+        size(logs_oid) * 3 < size(plain_oid),
+        "compressed {} bytes, plain {} bytes",
+        size(logs_oid),
+        size(plain_oid)
+    );
+
+    // Reopened, every row comes back from the slots recorded in the directory
+    let (bp, mut catalog) = Catalog::open(db_file).unwrap();
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        let mut exec = Planner::new(&catalog)
+            .plan(parse("SELECT * FROM logs").unwrap())
+            .unwrap();
+        exec.init();
+        let mut count = 0;
+        while let Some(row) = exec.next(pinned_bp.as_mut()).unwrap() {
+            let AttributeValue::U32(id) = row.values[0] else {
+                panic!("Expected an id, got {:?}", row.values);
+            };
+            assert_eq!(row.values[1], AttributeValue::Varchar(message(id)));
+            count += 1;
+        }
+        assert_eq!(count, 3000);
+    }
+
+    catalog.drop_table("logs").unwrap();
+    assert!(metadata(format!("{}.{}", db_file, logs_oid)).is_err());
+    catalog.drop_table("plain").unwrap();
+    let _ = FileManager::remove_database(db_file);
+    let _ = fs::remove_file(CheckpointRecord::path_for(db_file));
+}
+
+#[test]
+#[cfg(feature = "encryption")]
+fn test_encrypted_database_hides_its_pages() {
+    let db_file = "test_db/test_encryption.db";
+    let schema = TableType {
+        attributes: vec![TableAttribute {
+            name: "secret".into(),
+            kind: AttributeKind::Varchar,
+            nullable: false,
+            is_internal: false,
+        }],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let secret = |i: u32| format!("launch code {:04} is classified", i);
+    let oids = {
+        let _ = fs::create_dir_all("test_db");
+        let _ = FileManager::remove_database(db_file);
+        // Few key derivation rounds keep the test fast, they are stored with the file
+        let fm = FileManager::new(db_file.to_string()).unwrap();
+        let device = EncryptedDevice::open(Box::new(fm), "hunter2", 10).unwrap();
+        let bp = Arc::new(Mutex::new(BufferPool::new(
+            Box::new(device),
+            Box::new(FifoEvictor::new()),
+            Box::new(DirectoryPageLocator::new()),
+        )));
+        let mut catalog = Catalog::new(bp.clone());
+        let plain = catalog.create_table("plain", schema.clone()).unwrap();
+        let options = TableOptions {
+            compression: Compression::Lz4,
+            ..Default::default()
+        };
+        let packed = catalog
+            .create_table_with("packed", schema.clone(), &options)
+            .unwrap();
+
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for i in 0..500u32 {
+            let tuple = Tuple::new(vec![AttributeValue::Varchar(secret(i))]);
+            for oid in [plain, packed] {
+                catalog
+                    .insert_tuple(oid, &tuple, &schema, pinned_bp.as_mut())
+                    .unwrap();
+            }
+        }
+        pinned_bp.as_mut().checkpoint().unwrap();
+        [plain, packed]
+    };
+
+    // Neither the database file nor the segments give the rows away
+    let files = [db_file.to_string()]
+        .into_iter()
+        .chain(oids.iter().map(|oid| format!("{}.{}", db_file, oid)));
+    for file in files {
+        let bytes = fs::read(&file).unwrap();
+        assert!(
+            !bytes.windows(10).any(|w| w == b"classified"),
+            "{} holds plaintext",
+            file
+        );
+    }
+
+    assert!(Catalog::open(db_file).is_err());
+    assert!(Catalog::open_encrypted(db_file, "hunter3").is_err());
+
+    let (bp, catalog) = Catalog::open_encrypted(db_file, "hunter2").unwrap();
+    {
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for table in ["plain", "packed"] {
+            let mut exec = Planner::new(&catalog)
+                .plan(parse(&format!("SELECT * FROM {}", table)).unwrap())
+                .unwrap();
+            exec.init();
+            let mut count = 0;
+            while let Some(row) = exec.next(pinned_bp.as_mut()).unwrap() {
+                assert_eq!(row.values[0], AttributeValue::Varchar(secret(count)));
+                count += 1;
+            }
+            assert_eq!(count, 500, "rows of {}", table);
+        }
+    }
+    drop(catalog);
+    drop(bp);
+    let _ = FileManager::remove_database(db_file);
+    let _ = fs::remove_file(CheckpointRecord::path_for(db_file));
+}
+
+#[test]
+fn test_old_files_are_upgraded_and_newer_ones_refused() {
+    use nimbus::constants::storage::PAGE_SIZE;
+
+    let db_file = "test_db/test_superblock.db";
+    let _ = FileManager::remove_database(db_file);
+    let schema = TableType {
+        attributes: vec![TableAttribute {
+            name: "id".into(),
+            kind: AttributeKind::U32,
+            nullable: false,
+            is_internal: false,
+        }],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    let old_oid = {
+        let (bp, mut catalog) = Catalog::open(db_file).unwrap();
+        let oid = catalog.create_table("old", schema.clone()).unwrap();
+        let mut bp_guard = bp.lock().unwrap();
+        let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        let tuple = Tuple::new(vec![AttributeValue::U32(7)]);
+        catalog
+            .insert_tuple(oid, &tuple, &schema, pinned_bp)
+            .unwrap();
+        oid
+    };
+
+    // Back to the layout from before the superblock: the first directory page, which
+    // follows the superblock in a new file, at offset 0
+    let mut bytes = fs::read(db_file).unwrap();
+    assert_eq!(&bytes[32..40], b"NIMBUSDB");
+    bytes.copy_within(PAGE_SIZE..2 * PAGE_SIZE, 0);
+    fs::write(db_file, &bytes).unwrap();
+
+    {
+        let (bp, mut catalog) = Catalog::open(db_file).unwrap();
+        let new_oid = catalog.create_table("new", schema.clone()).unwrap();
+        assert!(new_oid > old_oid);
+        let mut bp_guard = bp.lock().unwrap();
+        let pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        let root = catalog.get_table_root_page(old_oid).unwrap();
+        let mut iter = HeapIterator::new(pinned_bp, root);
+        assert!(iter.next().is_some_and(|row| row.is_ok()));
+        assert!(iter.next().is_none());
+    }
+    let bytes = fs::read(db_file).unwrap();
+    assert_eq!(&bytes[32..40], b"NIMBUSDB");
+    let (_, catalog) = Catalog::open(db_file).unwrap();
+    assert!(catalog.get_table_oid("new").is_some());
+    drop(catalog);
+
+    // A file from a newer build is refused rather than misread
+    let mut newer = bytes.clone();
+    newer[40..44].copy_from_slice(&99u32.to_le_bytes());
+    fs::write(db_file, &newer).unwrap();
+    let err = Catalog::open(db_file).err().unwrap();
+    assert!(err.contains("format version 99"), "{}", err);
+
+    fs::write(db_file, vec![b'x'; PAGE_SIZE]).unwrap();
+    let err = Catalog::open(db_file).err().unwrap();
+    assert!(err.contains("not a nimbus database"), "{}", err);
+
+    let _ = FileManager::remove_database(db_file);
+}
+
+#[test]
+fn test_baseline_file_indexes_are_rebuilt() {
+    // Written by the code from before the superblock: table items(id, name) with ids
+    // 0..170 and a second row with id 7, indexed on id while keys were unique
+    let db_file = "test_db/test_baseline_upgrade.db";
+    let _ = FileManager::remove_database(db_file);
+    fs::copy("test_db/baseline_index.db", db_file).unwrap();
+
+    for _ in 0..2 {
+        let (bp, catalog) = Catalog::open(db_file).unwrap();
+        let index_oid = catalog.get_index_oid("items_id").unwrap();
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        let root = catalog.get_index_meta(index_oid).unwrap().root_page_id;
+        assert_ne!(pinned_bp.as_mut().segment_of(root).unwrap(), 0);
+        // The pages of the old tree were freed
+        for page_id in pinned_bp.as_mut().file_page_ids(0).unwrap() {
+            let frame = pinned_bp
+                .as_mut()
+                .fetch_page(page_id, AccessStrategy::Normal)
+                .unwrap();
+            let fid = frame.fid();
+            let kind = frame.page_view().header().page_kind();
+            pinned_bp.as_mut().unpin_frame(fid).unwrap();
+            assert!(!matches!(kind, PageKind::BPlusInner | PageKind::BPlusLeaf));
+        }
+
+        let lookup = |value, bpm: Pin<&mut BufferPool>| {
+            catalog
+                .index_lookup(index_oid, &AttributeValue::U32(value), bpm)
+                .unwrap()
+                .len()
+        };
+        assert_eq!(lookup(7, pinned_bp.as_mut()), 2);
+        assert_eq!(lookup(169, pinned_bp.as_mut()), 1);
+        assert_eq!(lookup(170, pinned_bp.as_mut()), 0);
+
+        let mut scan = IndexScanExecutor::new(&catalog, index_oid, AttributeValue::U32(7)).unwrap();
+        scan.init();
+        let mut names = Vec::new();
+        while let Some(tuple) = scan.next(pinned_bp.as_mut()).unwrap() {
+            names.push(tuple.values[1].clone());
+        }
+        names.sort_by_key(|name| format!("{:?}", name));
+        assert_eq!(
+            names,
+            vec![
+                AttributeValue::Varchar("again".into()),
+                AttributeValue::Varchar("item 7".into()),
+            ]
+        );
+        drop(bp_guard);
+        let report = catalog.verify_index("items_id").unwrap();
+        assert_eq!(report.entries, 171);
+    }
+
+    let _ = FileManager::remove_database(db_file);
+}
+
+#[test]
+fn test_page_ids_are_not_handed_out_again_after_reopening() {
+    use nimbus::constants::storage::PAGE_SIZE;
+    use nimbus::storage::page::Superblock;
+
+    let db_file = "test_db/test_page_ids.db";
+    let _ = FileManager::remove_database(db_file);
+    let column = |name: &str, kind| TableAttribute {
+        name: name.into(),
+        kind,
+        nullable: false,
+        is_internal: false,
+    };
+    let schema = TableType {
+        attributes: vec![
+            column("id", AttributeKind::U32),
+            column("payload", AttributeKind::Varchar),
+        ],
+        layout: TableLayout {
+            size: 0,
+            attr_layouts: vec![],
+        },
+    };
+    // Enough rows for a table to take several heap pages and its index a split
+    let create = |name: &str| {
+        let (bp, mut catalog) = Catalog::open(db_file).unwrap();
+        let oid = catalog.create_table(name, schema.clone()).unwrap();
+        {
+            let mut bp_guard = bp.lock().unwrap();
+            let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+            for id in 0..300 {
+                let tuple = Tuple::new(vec![
+                    AttributeValue::U32(id),
+                    AttributeValue::Varchar(format!("{}-{:0>60}", name, id)),
+                ]);
+                catalog
+                    .insert_tuple(oid, &tuple, &schema, pinned_bp.as_mut())
+                    .unwrap();
+            }
+        }
+        catalog
+            .create_index(&format!("{}_id", name), name, "id")
+            .unwrap();
+    };
+    let check = |names: &[&str]| {
+        let (bp, catalog) = Catalog::open(db_file).unwrap();
+        let mut bp_guard = bp.lock().unwrap();
+        let mut pinned_bp = unsafe { Pin::new_unchecked(&mut *bp_guard) };
+        for name in names {
+            let oid = catalog.get_table_oid(name).unwrap();
+            let root = catalog.get_table_root_page(oid).unwrap();
+            let mut iter = HeapIterator::new(pinned_bp.as_mut(), root);
+            let mut rows = 0;
+            while let Some(row) = iter.next() {
+                let tuple = Tuple::from_bytes(&row.unwrap().1, &schema).unwrap();
+                let AttributeValue::Varchar(payload) = &tuple.values[1] else {
+                    panic!("unexpected row {:?}", tuple.values);
+                };
+                assert!(payload.starts_with(&format!("{}-", name)), "{}", payload);
+                rows += 1;
+            }
+            assert_eq!(rows, 300, "{}", name);
+        }
+    };
+
+    create("first");
+    create("second");
+    check(&["first", "second"]);
+
+    // A file that does not record the counter has it recovered from the directory
+    let mut bytes = fs::read(db_file).unwrap();
+    let page: &mut [u8; PAGE_SIZE] = (&mut bytes[..PAGE_SIZE]).try_into().unwrap();
+    let mut superblock = Superblock::read_from(page).unwrap();
+    assert!(superblock.next_page_id > 0);
+    superblock.next_page_id = 0;
+    superblock.write_to(page);
+    fs::write(db_file, &bytes).unwrap();
+
+    create("third");
+    check(&["first", "second", "third"]);
+    let bytes = fs::read(db_file).unwrap();
+    let page: &[u8; PAGE_SIZE] = bytes[..PAGE_SIZE].try_into().unwrap();
+    assert!(Superblock::read_from(page).unwrap().next_page_id > 0);
+
+    let _ = FileManager::remove_database(db_file);
+}